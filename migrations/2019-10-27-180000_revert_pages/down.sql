@@ -0,0 +1,26 @@
+-- Revisions which reverted pages can't be represented without this change type,
+-- so refuse to revert rather than silently deleting them.
+-- To go back anyway, remove or convert the affected revisions first.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM revisions WHERE change_type = 'revert') THEN
+        RAISE EXCEPTION 'Cannot revert: % revision(s) have change type revert',
+            (SELECT COUNT(*) FROM revisions WHERE change_type = 'revert');
+    END IF;
+END $$;
+
+DROP TABLE title_history;
+
+ALTER TABLE revisions DROP CONSTRAINT revisions_target_revision_check;
+ALTER TABLE revisions DROP COLUMN target_revision_id;
+
+ALTER TABLE revisions DROP CONSTRAINT revisions_change_type_check;
+ALTER TABLE revisions ADD CONSTRAINT revisions_change_type_check CHECK (
+    change_type IN (
+        'create',
+        'modify',
+        'delete',
+        'rename',
+        'tags'
+    )
+);
@@ -0,0 +1,24 @@
+-- Allow reverting pages to earlier revisions
+
+ALTER TABLE revisions DROP CONSTRAINT revisions_change_type_check;
+ALTER TABLE revisions ADD CONSTRAINT revisions_change_type_check CHECK (
+    change_type IN (
+        'create',
+        'modify',
+        'delete',
+        'rename',
+        'tags',
+        'revert'
+    )
+);
+
+ALTER TABLE revisions ADD COLUMN target_revision_id BIGINT REFERENCES revisions(revision_id);
+ALTER TABLE revisions ADD CONSTRAINT revisions_target_revision_check CHECK (
+    (change_type = 'revert') = (target_revision_id IS NOT NULL)
+);
+
+CREATE TABLE title_history (
+    revision_id BIGSERIAL REFERENCES revisions(revision_id) PRIMARY KEY,
+    title TEXT NOT NULL,
+    alt_title TEXT
+);
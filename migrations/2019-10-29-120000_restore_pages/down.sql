@@ -0,0 +1,22 @@
+-- Revisions which restored pages can't be represented without this change type,
+-- so refuse to revert rather than silently deleting them.
+-- To go back anyway, remove or convert the affected revisions first.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM revisions WHERE change_type = 'restore') THEN
+        RAISE EXCEPTION 'Cannot revert: % revision(s) have change type restore',
+            (SELECT COUNT(*) FROM revisions WHERE change_type = 'restore');
+    END IF;
+END $$;
+
+ALTER TABLE revisions DROP CONSTRAINT revisions_change_type_check;
+ALTER TABLE revisions ADD CONSTRAINT revisions_change_type_check CHECK (
+    change_type IN (
+        'create',
+        'modify',
+        'delete',
+        'rename',
+        'tags',
+        'revert'
+    )
+);
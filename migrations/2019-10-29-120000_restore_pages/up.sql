@@ -0,0 +1,14 @@
+-- Allow restoring deleted pages
+
+ALTER TABLE revisions DROP CONSTRAINT revisions_change_type_check;
+ALTER TABLE revisions ADD CONSTRAINT revisions_change_type_check CHECK (
+    change_type IN (
+        'create',
+        'modify',
+        'delete',
+        'restore',
+        'rename',
+        'tags',
+        'revert'
+    )
+);
@@ -0,0 +1,15 @@
+-- Audit log of permanently purged pages and revisions
+
+CREATE TABLE purges (
+    purge_id BIGSERIAL PRIMARY KEY,
+    wiki_id BIGINT NOT NULL REFERENCES wikis(wiki_id),
+    page_id BIGINT NOT NULL,
+    slug TEXT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(user_id),
+    reason TEXT NOT NULL,
+    page_removed BOOLEAN NOT NULL,
+    revision_ids BIGINT[] NOT NULL,
+    git_commits TEXT[] NOT NULL,
+    commits_rewritten INTEGER NOT NULL CHECK (commits_rewritten >= 0),
+    purged_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- Redirects left behind by renamed pages
+
+CREATE TABLE redirects (
+    wiki_id BIGINT NOT NULL REFERENCES wikis(wiki_id),
+    slug TEXT NOT NULL CHECK (slug ~ '^[a-z0-9:_-]+$'),
+    page_id BIGINT NOT NULL REFERENCES pages(page_id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (wiki_id, slug)
+);
+
+CREATE INDEX redirects_page_id_idx ON redirects(page_id);
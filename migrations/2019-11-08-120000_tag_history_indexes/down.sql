@@ -0,0 +1,2 @@
+DROP INDEX tag_history_removed_tags_idx;
+DROP INDEX tag_history_added_tags_idx;
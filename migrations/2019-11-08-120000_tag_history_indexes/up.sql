@@ -0,0 +1,4 @@
+-- Look up tag changes by tag, for auditing when tags were added or removed
+
+CREATE INDEX tag_history_added_tags_idx ON tag_history USING GIN (added_tags);
+CREATE INDEX tag_history_removed_tags_idx ON tag_history USING GIN (removed_tags);
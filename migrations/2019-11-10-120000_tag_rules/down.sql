@@ -0,0 +1,2 @@
+DROP TABLE tag_groups;
+DROP TABLE tag_rules;
@@ -0,0 +1,15 @@
+-- Per-wiki rules for which tags pages may have
+
+CREATE TABLE tag_rules (
+    wiki_id BIGINT PRIMARY KEY REFERENCES wikis(wiki_id),
+    allowed_tags TEXT[],
+    staff_tags BOOLEAN NOT NULL
+);
+
+CREATE TABLE tag_groups (
+    wiki_id BIGINT NOT NULL REFERENCES wikis(wiki_id),
+    name TEXT NOT NULL,
+    tags TEXT[] NOT NULL,
+    required BOOLEAN NOT NULL,
+    PRIMARY KEY (wiki_id, name)
+);
@@ -0,0 +1,3 @@
+DROP TRIGGER page_search_document_update ON page_search;
+DROP FUNCTION page_search_document;
+DROP TABLE page_search;
@@ -0,0 +1,38 @@
+-- Full-text search index over page titles and contents
+--
+-- Page contents only live in the revision stores, so a copy of each live page's
+-- current text is kept here for building snippets. The document is computed
+-- by a trigger whenever a row is written. Rows are removed along with their page.
+
+CREATE TABLE page_search (
+    page_id BIGINT PRIMARY KEY REFERENCES pages(page_id) ON DELETE CASCADE,
+    wiki_id BIGINT NOT NULL REFERENCES wikis(wiki_id),
+    title TEXT NOT NULL,
+    alt_title TEXT,
+    content TEXT NOT NULL,
+    document TSVECTOR NOT NULL
+);
+
+CREATE INDEX page_search_wiki_id_idx ON page_search (wiki_id);
+CREATE INDEX page_search_document_idx ON page_search USING GIN (document);
+
+CREATE FUNCTION page_search_document() RETURNS trigger AS $$
+BEGIN
+    NEW.document :=
+        setweight(to_tsvector('english', NEW.title), 'A') ||
+        setweight(to_tsvector('english', COALESCE(NEW.alt_title, '')), 'A') ||
+        setweight(to_tsvector('english', NEW.content), 'C');
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER page_search_document_update
+    BEFORE INSERT OR UPDATE ON page_search
+    FOR EACH ROW EXECUTE PROCEDURE page_search_document();
+
+-- Index the title of every existing page. Their contents are added
+-- when the index is rebuilt from the revision stores.
+INSERT INTO page_search (page_id, wiki_id, title, alt_title, content, document)
+    SELECT page_id, wiki_id, title, alt_title, '', ''
+    FROM pages
+    WHERE deleted_at IS NULL;
@@ -0,0 +1,4 @@
+DELETE FROM wiki_membership WHERE applied_at IS NULL OR joined_at IS NULL;
+
+ALTER TABLE wiki_membership ALTER COLUMN applied_at SET NOT NULL;
+ALTER TABLE wiki_membership ALTER COLUMN joined_at SET NOT NULL;
@@ -0,0 +1,8 @@
+-- Allow membership rows for pending applications and bans
+--
+-- A null joined_at means the user has applied but not been accepted yet,
+-- and a null applied_at means they never applied, such as when a user
+-- is banned without having been a member.
+
+ALTER TABLE wiki_membership ALTER COLUMN applied_at DROP NOT NULL;
+ALTER TABLE wiki_membership ALTER COLUMN joined_at DROP NOT NULL;
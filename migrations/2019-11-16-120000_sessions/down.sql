@@ -0,0 +1,8 @@
+DROP TABLE sessions;
+
+CREATE TABLE sessions (
+    user_id BIGSERIAL PRIMARY KEY REFERENCES users(user_id),
+    token VARCHAR(64) NOT NULL UNIQUE,
+    ip_address INET NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,19 @@
+-- Allow users to have several sessions, each of which expires
+--
+-- Existing sessions are dropped, so everyone will need to log in again.
+
+DROP TABLE sessions;
+
+CREATE TABLE sessions (
+    session_id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(user_id),
+    token VARCHAR(64) NOT NULL UNIQUE,
+    ip_address INET NOT NULL,
+    user_agent TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,4 @@
+DELETE FROM sessions;
+
+ALTER TABLE sessions DROP COLUMN token_hash;
+ALTER TABLE sessions ADD COLUMN token VARCHAR(64) NOT NULL UNIQUE;
@@ -0,0 +1,8 @@
+-- Store session tokens as a keyed hash instead of in plaintext
+--
+-- Plaintext tokens cannot be converted, so all existing sessions are invalidated.
+
+DELETE FROM sessions;
+
+ALTER TABLE sessions DROP COLUMN token;
+ALTER TABLE sessions ADD COLUMN token_hash BYTEA NOT NULL;
@@ -0,0 +1,12 @@
+-- Argon2id hashes cannot be checked without the algorithm column,
+-- so refuse to revert rather than silently deleting those passwords.
+-- To go back anyway, reset or remove the affected passwords first.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM passwords WHERE algorithm != 'scrypt') THEN
+        RAISE EXCEPTION 'Cannot revert: % password(s) are hashed with argon2id',
+            (SELECT COUNT(*) FROM passwords WHERE algorithm != 'scrypt');
+    END IF;
+END $$;
+
+ALTER TABLE passwords DROP COLUMN algorithm;
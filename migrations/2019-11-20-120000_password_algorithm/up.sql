@@ -0,0 +1,15 @@
+-- Record which algorithm produced each password hash
+--
+-- Existing hashes were all made with scrypt. The parameter columns are interpreted per algorithm:
+--   scrypt:   logn = log2(N), param_r = r, param_p = p
+--   argon2id: logn = log2(memory in KiB), param_r = iterations, param_p = lanes
+
+ALTER TABLE passwords
+    ADD COLUMN algorithm VARCHAR(8) NOT NULL DEFAULT 'scrypt' CHECK (
+        algorithm IN (
+            'scrypt',
+            'argon2id'
+        )
+    );
+
+ALTER TABLE passwords ALTER COLUMN algorithm DROP DEFAULT;
@@ -0,0 +1,7 @@
+-- Record the slug a page was moved from in its rename revisions
+--
+-- Existing renames are left empty, and are still found from the repository contents.
+
+ALTER TABLE revisions ADD COLUMN old_slug TEXT CHECK (
+    old_slug IS NULL OR change_type = 'rename'
+);
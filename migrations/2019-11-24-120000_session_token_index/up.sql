@@ -0,0 +1,3 @@
+-- Look up sessions by their token hash, which must be unique
+
+CREATE UNIQUE INDEX sessions_token_hash_idx ON sessions (token_hash);
@@ -18,10 +18,13 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
+use crate::page::TagRuleViolation;
+use crate::revision::MergeConflict;
+use crate::role::Permission;
 use diesel::result::{ConnectionError, Error as DieselError};
 use serde_json as json;
 use std::io;
-use subprocess::PopenError;
+use std::path::PathBuf;
 
 #[must_use = "should handle errors"]
 #[derive(Debug, Error)]
@@ -38,15 +41,9 @@ pub enum Error {
     #[error("error connecting to database: {0}")]
     DatabaseConnection(#[from] ConnectionError),
 
-    #[error("error running subprocess: {0}")]
-    Subprocess(#[from] PopenError),
-
     #[error("error serializing JSON: {0}")]
     JsonSerialize(#[from] json::Error),
 
-    #[error("command failed: {0}")]
-    CommandFailed(String),
-
     #[error("invalid username or password")]
     AuthenticationFailed,
 
@@ -56,9 +53,15 @@ pub enum Error {
     #[error("invalid password: {0}")]
     NewPasswordInvalid(&'static str),
 
+    #[error("revision directory is not a valid git repository: {}", .0.display())]
+    InvalidRevisionStore(PathBuf),
+
     #[error("the given wiki was not found")]
     WikiNotFound,
 
+    #[error("the given wiki already has pages")]
+    WikiNotEmpty,
+
     #[error("the given page was not found")]
     PageNotFound,
 
@@ -74,6 +77,45 @@ pub enum Error {
     #[error("a user with the given email already exists")]
     UserEmailExists,
 
+    #[error("the given user is not a member of the wiki")]
+    NotMember,
+
+    #[error("the given user is already a member of the wiki")]
+    AlreadyMember,
+
+    #[error("the given user is banned from the wiki")]
+    UserBanned,
+
+    #[error("the given user has not applied to join the wiki")]
+    ApplicationNotFound,
+
+    #[error("the given role was not found")]
+    RoleNotFound,
+
+    #[error("a role with the given name already exists")]
+    RoleExists,
+
+    #[error("user does not have the {0:?} permission")]
+    PermissionDenied(Permission),
+
     #[error("the given revision was not found")]
     RevisionNotFound,
+
+    #[error("only edits, tag changes and reverts can be purged without the whole page")]
+    CannotPurgeRevision,
+
+    #[error("revision shares its commit with changes to other pages")]
+    SharedCommit,
+
+    #[error("a batch can only change each page once")]
+    DuplicateBatchPage,
+
+    #[error("the given tag is already in use")]
+    TagExists,
+
+    #[error("tags break the wiki's tagging rules ({} violations)", .0.len())]
+    TagRulesViolated(Vec<TagRuleViolation>),
+
+    #[error("edit conflicts with changes made since its base revision ({} regions)", .0.len())]
+    EditConflict(Vec<MergeConflict>),
 }
@@ -20,6 +20,7 @@
 
 #![deny(missing_debug_implementations)]
 
+extern crate argon2;
 extern crate arrayvec;
 extern crate chrono;
 extern crate crypto;
@@ -27,6 +28,7 @@ extern crate crypto;
 #[macro_use]
 extern crate diesel;
 extern crate either;
+extern crate flate2;
 extern crate ipnetwork;
 
 #[macro_use]
@@ -41,7 +43,6 @@ extern crate regex;
 #[macro_use]
 extern crate serde;
 extern crate serde_json;
-extern crate subprocess;
 
 #[macro_use]
 extern crate thiserror;
@@ -52,10 +53,12 @@ mod macros;
 
 mod author;
 mod error;
+mod membership;
 mod page;
 mod password;
 mod rating;
 mod revision;
+mod role;
 mod schema;
 mod server;
 mod session;
@@ -69,22 +72,36 @@ mod test;
 pub mod prelude {
     pub use crate::id::*;
     pub use crate::model::*;
-    pub use crate::page::PageCommit;
+    pub use crate::page::{BatchChange, PageCommit};
+    pub use crate::revision::RevisionBackend;
     pub use crate::server::{Server, ServerConfig};
     pub use crate::user::UserMetadata;
     pub use crate::{Error, Result, StdResult};
 }
 
 pub mod id {
-    pub use crate::page::{PageId, RevisionId};
+    pub use crate::page::{PageId, PurgeId, RevisionId};
+    pub use crate::role::RoleId;
+    pub use crate::session::SessionId;
     pub use crate::user::UserId;
     pub use crate::wiki::WikiId;
 }
 
 pub mod model {
-    pub use crate::page::Page;
+    pub use crate::membership::Membership;
+    pub use crate::page::{
+        ChangeType, ConsistencyIssue, ConsistencyReport, HistoryEntry, HistoryPage, HistoryQuery,
+        Inconsistency, Page, PageList, PageOrder, PageQuery, PurgeRecord, RebuildIssue,
+        RebuildReport, Redirect, SearchResult, SearchResults, TagAction, TagEvent, TagGroup,
+        TagRuleViolation, TagRules, TagUpdatePage, TagUpdateReport,
+    };
     pub use crate::rating::Rating;
-    pub use crate::revision::{Blame, GitHash};
+    pub use crate::revision::{
+        Blame, Diff, DiffChunk, DiffFile, DiffHunk, DiffLine, GitHash, MergeConflict,
+    };
+    pub use crate::role::{Permission, Permset, Role};
+    pub use crate::server::PageContents;
+    pub use crate::session::Session;
     pub use crate::user::User;
     pub use crate::wiki::Wiki;
 }
@@ -109,4 +126,5 @@ pub type StdResult<T, E> = std::result::Result<T, E>;
 pub type Result<T> = StdResult<T, Error>;
 
 pub use self::error::Error;
+pub use self::revision::RevisionBackend;
 pub use self::server::{Server, ServerConfig};
@@ -0,0 +1,380 @@
+/*
+ * membership/service.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::schema::wiki_membership;
+use crate::service_prelude::*;
+use crate::utils::rows_to_result;
+use diesel::dsl::now;
+use diesel::expression::BoxableExpression;
+use diesel::pg::Pg;
+use diesel::sql_types::Bool;
+
+/// A user's standing in a wiki.
+///
+/// Users who applied but haven't been accepted have no `joined_at`, and
+/// banning a user removes their membership or application. Bans with a
+/// `banned_until` in the past no longer apply.
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone, PartialEq, Eq)]
+pub struct Membership {
+    wiki_id: WikiId,
+    user_id: UserId,
+    applied_at: Option<DateTime<Utc>>,
+    joined_at: Option<DateTime<Utc>>,
+    banned_at: Option<DateTime<Utc>>,
+    banned_until: Option<DateTime<Utc>>,
+}
+
+impl Membership {
+    #[inline]
+    pub fn wiki_id(&self) -> WikiId {
+        self.wiki_id
+    }
+
+    #[inline]
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    #[inline]
+    pub fn applied_at(&self) -> Option<DateTime<Utc>> {
+        self.applied_at
+    }
+
+    #[inline]
+    pub fn joined_at(&self) -> Option<DateTime<Utc>> {
+        self.joined_at
+    }
+
+    #[inline]
+    pub fn banned_at(&self) -> Option<DateTime<Utc>> {
+        self.banned_at
+    }
+
+    #[inline]
+    pub fn banned_until(&self) -> Option<DateTime<Utc>> {
+        self.banned_until
+    }
+}
+
+type MembershipFilter = Box<dyn BoxableExpression<wiki_membership::table, Pg, SqlType = Bool>>;
+
+/// Matches bans which are still in effect.
+fn active_ban() -> MembershipFilter {
+    use self::wiki_membership::dsl;
+
+    Box::new(
+        dsl::banned_at
+            .is_not_null()
+            .and(dsl::banned_until.is_null().or(dsl::banned_until.gt(now))),
+    )
+}
+
+/// Matches applications which haven't been accepted or rejected yet.
+fn pending_application() -> MembershipFilter {
+    use self::wiki_membership::dsl;
+
+    Box::new(dsl::applied_at.is_not_null().and(dsl::joined_at.is_null()))
+}
+
+pub struct MembershipService {
+    conn: Arc<PgConnection>,
+}
+
+impl MembershipService {
+    #[inline]
+    pub fn new(conn: &Arc<PgConnection>) -> Self {
+        let conn = Arc::clone(conn);
+
+        MembershipService { conn }
+    }
+
+    pub fn get(&self, wiki_id: WikiId, user_id: UserId) -> Result<Option<Membership>> {
+        debug!(
+            "Getting membership for user ID {} in wiki ID {}",
+            user_id, wiki_id
+        );
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let membership = wiki_membership::table
+            .find((wiki_id, user_id))
+            .first::<Membership>(&*self.conn)
+            .optional()?;
+
+        Ok(membership)
+    }
+
+    pub fn is_member(&self, wiki_id: WikiId, user_id: UserId) -> Result<bool> {
+        let membership = self.get(wiki_id, user_id)?;
+
+        Ok(membership.and_then(|membership| membership.joined_at).is_some())
+    }
+
+    /// Determines if a user is currently banned from a wiki.
+    pub fn is_banned(&self, wiki_id: WikiId, user_id: UserId) -> Result<bool> {
+        debug!(
+            "Checking if user ID {} is banned from wiki ID {}",
+            user_id, wiki_id
+        );
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let result = wiki_membership::table
+            .find((wiki_id, user_id))
+            .filter(active_ban())
+            .select(wiki_membership::dsl::user_id)
+            .first::<UserId>(&*self.conn)
+            .optional()?;
+
+        Ok(result.is_some())
+    }
+
+    /// Applies for a user to join a wiki.
+    /// Returns false if they already have an application waiting.
+    pub fn apply(&self, wiki_id: WikiId, user_id: UserId) -> Result<bool> {
+        use self::wiki_membership::dsl;
+
+        info!("User ID {} applying to join wiki ID {}", user_id, wiki_id);
+
+        self.conn.transaction::<_, Error, _>(|| {
+            if self.is_banned(wiki_id, user_id)? {
+                return Err(Error::UserBanned);
+            }
+
+            let wiki: i64 = wiki_id.into();
+            let user: i64 = user_id.into();
+
+            match self.get(wiki_id, user_id)? {
+                Some(membership) if membership.joined_at.is_some() => {
+                    return Err(Error::AlreadyMember);
+                }
+                Some(membership) if membership.applied_at.is_some() => return Ok(false),
+
+                // Only an expired ban is left
+                Some(_) => {
+                    trace!("Replacing expired ban with application");
+                    diesel::update(dsl::wiki_membership.find((wiki, user)))
+                        .set((
+                            dsl::applied_at.eq(now),
+                            dsl::banned_at.eq(None::<DateTime<Utc>>),
+                            dsl::banned_until.eq(None::<DateTime<Utc>>),
+                        ))
+                        .execute(&*self.conn)?;
+                }
+                None => {
+                    trace!("Inserting application into wiki membership table");
+                    diesel::insert_into(dsl::wiki_membership)
+                        .values((
+                            dsl::wiki_id.eq(wiki),
+                            dsl::user_id.eq(user),
+                            dsl::applied_at.eq(now),
+                        ))
+                        .execute(&*self.conn)?;
+                }
+            }
+
+            Ok(true)
+        })
+    }
+
+    pub fn accept(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        use self::wiki_membership::dsl;
+
+        info!("Accepting user ID {} into wiki ID {}", user_id, wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::update(
+            dsl::wiki_membership
+                .find((wiki_id, user_id))
+                .filter(pending_application()),
+        )
+        .set(dsl::joined_at.eq(now))
+        .execute(&*self.conn)?;
+
+        if rows_to_result(rows) {
+            Ok(())
+        } else {
+            Err(Error::ApplicationNotFound)
+        }
+    }
+
+    pub fn reject(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        use self::wiki_membership::dsl;
+
+        info!(
+            "Rejecting application of user ID {} to wiki ID {}",
+            user_id, wiki_id
+        );
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::delete(
+            dsl::wiki_membership
+                .find((wiki_id, user_id))
+                .filter(pending_application()),
+        )
+        .execute(&*self.conn)?;
+
+        if rows_to_result(rows) {
+            Ok(())
+        } else {
+            Err(Error::ApplicationNotFound)
+        }
+    }
+
+    pub fn leave(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        use self::wiki_membership::dsl;
+
+        info!("User ID {} leaving wiki ID {}", user_id, wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::delete(
+            dsl::wiki_membership
+                .find((wiki_id, user_id))
+                .filter(dsl::joined_at.is_not_null()),
+        )
+        .execute(&*self.conn)?;
+
+        if rows_to_result(rows) {
+            Ok(())
+        } else {
+            Err(Error::NotMember)
+        }
+    }
+
+    /// Bans a user from a wiki until the given time, or forever if there is none.
+    /// Any membership or application they had is removed.
+    pub fn ban(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        use self::wiki_membership::dsl;
+
+        info!(
+            "Banning user ID {} from wiki ID {} until {:?}",
+            user_id, wiki_id, until,
+        );
+
+        if let Some(until) = until {
+            if until <= Utc::now() {
+                return Err(Error::StaticMsg("ban must end in the future"));
+            }
+        }
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let none = None::<DateTime<Utc>>;
+
+        diesel::insert_into(dsl::wiki_membership)
+            .values((
+                dsl::wiki_id.eq(wiki_id),
+                dsl::user_id.eq(user_id),
+                dsl::banned_at.eq(now),
+                dsl::banned_until.eq(until),
+            ))
+            .on_conflict((dsl::wiki_id, dsl::user_id))
+            .do_update()
+            .set((
+                dsl::applied_at.eq(none),
+                dsl::joined_at.eq(none),
+                dsl::banned_at.eq(now),
+                dsl::banned_until.eq(until),
+            ))
+            .execute(&*self.conn)?;
+
+        Ok(())
+    }
+
+    /// Lifts a user's ban from a wiki. Returns false if they weren't banned.
+    pub fn unban(&self, wiki_id: WikiId, user_id: UserId) -> Result<bool> {
+        info!("Unbanning user ID {} from wiki ID {}", user_id, wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::delete(
+            wiki_membership::table
+                .find((wiki_id, user_id))
+                .filter(active_ban()),
+        )
+        .execute(&*self.conn)?;
+
+        Ok(rows_to_result(rows))
+    }
+
+    /// Gets everyone who is a member of a wiki, in the order they joined.
+    pub fn get_members(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        use self::wiki_membership::dsl;
+
+        debug!("Getting members of wiki ID {}", wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let members = dsl::wiki_membership
+            .filter(dsl::wiki_id.eq(wiki_id))
+            .filter(dsl::joined_at.is_not_null())
+            .order_by((dsl::joined_at.asc(), dsl::user_id.asc()))
+            .load::<Membership>(&*self.conn)?;
+
+        Ok(members)
+    }
+
+    /// Gets everyone waiting to join a wiki, in the order they applied.
+    pub fn get_applicants(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        use self::wiki_membership::dsl;
+
+        debug!("Getting applicants to wiki ID {}", wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let applicants = dsl::wiki_membership
+            .filter(dsl::wiki_id.eq(wiki_id))
+            .filter(pending_application())
+            .order_by((dsl::applied_at.asc(), dsl::user_id.asc()))
+            .load::<Membership>(&*self.conn)?;
+
+        Ok(applicants)
+    }
+
+    /// Gets everyone currently banned from a wiki, most recently banned first.
+    pub fn get_banned(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        use self::wiki_membership::dsl;
+
+        debug!("Getting banned users in wiki ID {}", wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let banned = dsl::wiki_membership
+            .filter(dsl::wiki_id.eq(wiki_id))
+            .filter(active_ban())
+            .order_by((dsl::banned_at.desc(), dsl::user_id.asc()))
+            .load::<Membership>(&*self.conn)?;
+
+        Ok(banned)
+    }
+}
+
+impl Debug for MembershipService {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MembershipService")
+            .field("conn", &"PgConnection { .. }")
+            .finish()
+    }
+}
@@ -0,0 +1,311 @@
+/*
+ * page/batch.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    ChangeType, CommitMessage, NewPage, NewRedirect, NewRevision, PageId, PageService, RevisionId,
+    UpdatePage,
+};
+use crate::revision::{CommitInfo, PageChange};
+use crate::schema::{pages, redirects, revisions};
+use crate::service_prelude::*;
+use crate::user::User;
+use crate::wiki::WikiId;
+use serde_json as json;
+
+/// A change to a single page, made as part of a batch.
+///
+/// See [`PageService::batch`] for how batches are applied.
+///
+/// [`PageService::batch`]: ./struct.PageService.html#method.batch
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BatchChange<'a> {
+    /// Creates a new page with the given contents and metadata.
+    Create {
+        slug: &'a str,
+        content: &'a [u8],
+        tags: &'a [&'a str],
+        title: &'a str,
+        alt_title: Option<&'a str>,
+    },
+
+    /// Edits an existing page, optionally changing its title or alternate title.
+    Edit {
+        slug: &'a str,
+        content: Option<&'a [u8]>,
+        title: Option<&'a str>,
+        alt_title: Option<Nullable<&'a str>>,
+    },
+
+    /// Replaces the tags of an existing page.
+    Tags { slug: &'a str, tags: &'a [&'a str] },
+
+    /// Moves a page to a different slug, optionally leaving a redirect behind.
+    Rename {
+        old_slug: &'a str,
+        new_slug: &'a str,
+        redirect: bool,
+    },
+
+    /// Removes an existing page.
+    Remove { slug: &'a str },
+}
+
+impl PageService {
+    /// Applies changes to several pages as a single commit and database transaction.
+    ///
+    /// Each page gets its own revision, all sharing the commit and message.
+    /// Changes are applied in order, so a page can be created at a slug another page
+    /// was renamed away from earlier in the batch, but each page may only be changed
+    /// once. If any of the changes fail, none of them are made.
+    ///
+    /// Returns the page and revision IDs for each change, in the same order.
+    pub fn batch(
+        &self,
+        wiki_id: WikiId,
+        changes: &[BatchChange],
+        message: &str,
+        user: &User,
+    ) -> Result<Vec<(PageId, RevisionId)>> {
+        use diesel::dsl::now;
+
+        info!(
+            "Starting transaction for batch of {} page changes",
+            changes.len(),
+        );
+
+        if changes.is_empty() {
+            return Err(Error::StaticMsg("batch has no changes"));
+        }
+
+        self.transaction(wiki_id, || {
+            let user_id = user.id();
+            let mut commits = Vec::with_capacity(changes.len());
+            let mut store_changes = Vec::with_capacity(changes.len());
+
+            for change in changes {
+                trace!("Applying batch change {:?}", change);
+
+                let (page_id, change_type, slug) = match *change {
+                    BatchChange::Create {
+                        slug,
+                        content,
+                        title,
+                        alt_title,
+                        ..
+                    } => {
+                        let model = NewPage {
+                            wiki_id: wiki_id.into(),
+                            slug,
+                            title,
+                            alt_title,
+                        };
+
+                        trace!("Checking for existing page");
+                        if self.get_page_id(wiki_id, slug)?.is_some() {
+                            return Err(Error::PageExists);
+                        }
+
+                        self.remove_redirect(wiki_id, slug)?;
+
+                        trace!("Inserting {:?} into pages table", &model);
+                        let page_id = diesel::insert_into(pages::table)
+                            .values(&model)
+                            .returning(pages::dsl::page_id)
+                            .get_result::<PageId>(&*self.conn)?;
+
+                        store_changes.push(PageChange::Write { slug, content });
+                        (page_id, ChangeType::Create, slug)
+                    }
+                    BatchChange::Edit {
+                        slug,
+                        content,
+                        title,
+                        alt_title,
+                    } => {
+                        let page_id = self
+                            .get_page_id(wiki_id, slug)?
+                            .ok_or(Error::PageNotFound)?;
+
+                        if title.is_some() || alt_title.is_some() {
+                            use self::pages::dsl;
+
+                            let model = UpdatePage {
+                                slug: None,
+                                title,
+                                alt_title,
+                            };
+
+                            trace!("Updating {:?} in pages table", &model);
+                            let id: i64 = page_id.into();
+                            diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                                .set(&model)
+                                .execute(&*self.conn)?;
+                        }
+
+                        if let Some(content) = content {
+                            store_changes.push(PageChange::Write { slug, content });
+                        }
+
+                        (page_id, ChangeType::Modify, slug)
+                    }
+                    BatchChange::Tags { slug, .. } => {
+                        let page_id = self
+                            .get_page_id(wiki_id, slug)?
+                            .ok_or(Error::PageNotFound)?;
+
+                        (page_id, ChangeType::Tags, slug)
+                    }
+                    BatchChange::Rename {
+                        old_slug,
+                        new_slug,
+                        redirect,
+                    } => {
+                        use self::pages::dsl;
+
+                        let page_id = self
+                            .get_page_id(wiki_id, old_slug)?
+                            .ok_or(Error::PageNotFound)?;
+
+                        trace!("Moving page to '{}' in pages table", new_slug);
+                        let id: i64 = page_id.into();
+                        diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                            .set(dsl::slug.eq(new_slug))
+                            .execute(&*self.conn)?;
+
+                        self.remove_redirect(wiki_id, new_slug)?;
+
+                        if redirect {
+                            let model = NewRedirect {
+                                wiki_id: wiki_id.into(),
+                                slug: old_slug,
+                                page_id: id,
+                            };
+
+                            trace!("Inserting {:?} into redirects table", &model);
+                            diesel::insert_into(redirects::table)
+                                .values(&model)
+                                .execute(&*self.conn)?;
+                        }
+
+                        store_changes.push(PageChange::Rename { old_slug, new_slug });
+                        (page_id, ChangeType::Rename, new_slug)
+                    }
+                    BatchChange::Remove { slug } => {
+                        use self::pages::dsl;
+
+                        let page_id = self
+                            .get_page_id(wiki_id, slug)?
+                            .ok_or(Error::PageNotFound)?;
+
+                        trace!("Marking page as deleted in table");
+                        let id: i64 = page_id.into();
+                        diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                            .set(dsl::deleted_at.eq(now))
+                            .execute(&*self.conn)?;
+
+                        store_changes.push(PageChange::Remove { slug });
+                        (page_id, ChangeType::Delete, slug)
+                    }
+                };
+
+                if commits
+                    .iter()
+                    .any(|commit: &CommitMessage| commit.page_id == page_id)
+                {
+                    return Err(Error::DuplicateBatchPage);
+                }
+
+                let old_slug = match *change {
+                    BatchChange::Rename { old_slug, .. } => Some(old_slug.to_owned()),
+                    _ => None,
+                };
+
+                commits.push(CommitMessage {
+                    wiki_id,
+                    page_id,
+                    user_id,
+                    change_type,
+                    target_commit: None,
+                    slug: Some(slug.to_owned()),
+                    old_slug,
+                });
+            }
+
+            let commit = json::to_string(&commits)?;
+            let info = CommitInfo {
+                username: user.name(),
+                message: &commit,
+            };
+
+            let hash = self.get_store::<_, GitHash>(wiki_id, |store| {
+                trace!("Committing batch to repository");
+                store.commit_batch(&store_changes, info)
+            })?;
+
+            let mut revisions = Vec::with_capacity(changes.len());
+            for (change, commit) in changes.iter().zip(&commits) {
+                let page_id = commit.page_id;
+                let model = NewRevision {
+                    page_id: page_id.into(),
+                    user_id: user_id.into(),
+                    message,
+                    git_commit: hash.as_ref(),
+                    change_type: commit.change_type.into(),
+                    target_revision_id: None,
+                    old_slug: commit.old_slug.as_deref(),
+                };
+
+                trace!("Inserting revision {:?} into revisions table", &model);
+                let revision_id = diesel::insert_into(revisions::table)
+                    .values(&model)
+                    .returning(revisions::dsl::revision_id)
+                    .get_result::<RevisionId>(&*self.conn)?;
+
+                match *change {
+                    BatchChange::Create { tags, .. } => {
+                        self.record_title(page_id, revision_id)?;
+
+                        let mut tags = tags.to_vec();
+                        self.set_tags(wiki_id, page_id, revision_id, &mut tags, user)?;
+                    }
+                    BatchChange::Edit {
+                        title, alt_title, ..
+                    } if title.is_some() || alt_title.is_some() => {
+                        self.record_title(page_id, revision_id)?
+                    }
+                    BatchChange::Tags { tags, .. } => {
+                        let mut tags = tags.to_vec();
+                        self.set_tags(wiki_id, page_id, revision_id, &mut tags, user)?;
+                    }
+                    _ => (),
+                }
+
+                // Tag changes don't affect the search index
+                if commit.change_type != ChangeType::Tags {
+                    self.index_page(wiki_id, page_id)?;
+                }
+
+                revisions.push((page_id, revision_id));
+            }
+
+            Ok(revisions)
+        })
+    }
+}
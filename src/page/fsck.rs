@@ -0,0 +1,102 @@
+/*
+ * page/fsck.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{ChangeType, PageId, RevisionId};
+use crate::revision::GitHash;
+
+/// A disagreement between the `revisions` table and a wiki's git history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// A revision refers to a commit which is not in the history.
+    MissingCommit {
+        revision_id: RevisionId,
+        git_commit: GitHash,
+    },
+
+    /// A commit in the history has no revision referring to it.
+    UntrackedCommit {
+        git_commit: GitHash,
+        page_id: PageId,
+        change_type: ChangeType,
+    },
+
+    /// A commit's message could not be read, or belongs to a different wiki.
+    InvalidCommit { git_commit: GitHash },
+
+    /// A revision and its commit disagree on which page was changed.
+    PageMismatch {
+        revision_id: RevisionId,
+        git_commit: GitHash,
+        database: PageId,
+        git: PageId,
+    },
+
+    /// A revision and its commit disagree on the kind of change made.
+    ChangeTypeMismatch {
+        revision_id: RevisionId,
+        git_commit: GitHash,
+        database: ChangeType,
+        git: ChangeType,
+    },
+
+    /// A page marked as deleted still has its file in the repository.
+    DeletedPageExists { page_id: PageId, slug: String },
+}
+
+/// One problem found while checking a wiki, and whether it was fixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyIssue {
+    pub inconsistency: Inconsistency,
+    pub repaired: bool,
+}
+
+/// The results of checking a wiki's revisions against its git history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    pub commits_checked: usize,
+    pub revisions_checked: usize,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+impl ConsistencyReport {
+    /// Determines if no problems were found.
+    #[inline]
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Determines if every problem found was repaired.
+    #[inline]
+    pub fn is_repaired(&self) -> bool {
+        self.issues.iter().all(|issue| issue.repaired)
+    }
+
+    pub(crate) fn push(&mut self, inconsistency: Inconsistency, repaired: bool) {
+        warn!(
+            "Consistency issue found (repaired: {}): {:?}",
+            repaired, inconsistency,
+        );
+
+        self.issues.push(ConsistencyIssue {
+            inconsistency,
+            repaired,
+        });
+    }
+}
@@ -0,0 +1,235 @@
+/*
+ * page/history.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{ChangeType, PageId, PageService, RevisionId};
+use crate::revision::GitHash;
+use crate::schema::{pages, revisions, tag_history};
+use crate::service_prelude::*;
+use crate::user::UserId;
+use crate::wiki::WikiId;
+use std::collections::HashSet;
+
+/// The number of revisions returned when a history query has no limit.
+pub const DEFAULT_HISTORY_LIMIT: u32 = 50;
+
+/// The largest number of revisions a history query may return at once.
+pub const MAX_HISTORY_LIMIT: u32 = 500;
+
+/// Which revisions of a page to list, newest first.
+///
+/// All filters are optional. To get the next set of results, run the
+/// query again with `cursor` set to the `next` value from the last set.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+    pub user_id: Option<UserId>,
+    pub change_type: Option<ChangeType>,
+
+    /// Only include revisions made at or after this time.
+    pub start: Option<DateTime<Utc>>,
+
+    /// Only include revisions made before this time.
+    pub end: Option<DateTime<Utc>>,
+
+    /// Only include revisions older than this one.
+    pub cursor: Option<RevisionId>,
+
+    /// How many revisions to return, or zero for the default.
+    pub limit: u32,
+}
+
+/// A single revision of a page, as shown in its history.
+///
+/// The tag fields are only non-empty for revisions which changed tags,
+/// and `target_revision_id` is only set for reverts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub revision_id: RevisionId,
+    pub user_id: UserId,
+    pub message: String,
+    pub change_type: ChangeType,
+    pub git_commit: GitHash,
+    pub created_at: DateTime<Utc>,
+    pub target_revision_id: Option<RevisionId>,
+    pub added_tags: Vec<String>,
+    pub removed_tags: Vec<String>,
+}
+
+/// One set of results from a history query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+
+    /// The cursor for the next set of results, if there are any more.
+    pub next: Option<RevisionId>,
+}
+
+/// Whether a tag was added to or removed from a page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TagAction {
+    Added,
+    Removed,
+}
+
+/// A single tag being added to or removed from a page by one of its revisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagEvent {
+    pub page_id: PageId,
+    pub revision_id: RevisionId,
+    pub user_id: UserId,
+    pub tag: String,
+    pub action: TagAction,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PageService {
+    /// Gets every time tags were added to or removed from a page, oldest first.
+    /// If `tag` is given, only changes to that tag are included.
+    pub fn get_tag_history(&self, page_id: PageId, tag: Option<&str>) -> Result<Vec<TagEvent>> {
+        use self::tag_history::dsl::{added_tags, removed_tags};
+
+        info!(
+            "Getting tag history for page ID {} (tag: {:?})",
+            page_id, tag
+        );
+
+        let id: i64 = page_id.into();
+        let mut statement = tag_history::table
+            .inner_join(revisions::table)
+            .filter(revisions::dsl::page_id.eq(id))
+            .select((
+                revisions::dsl::revision_id,
+                revisions::dsl::user_id,
+                revisions::dsl::created_at,
+                added_tags,
+                removed_tags,
+            ))
+            .into_boxed();
+
+        if let Some(tag) = tag {
+            statement = statement.filter(
+                added_tags
+                    .contains(vec![tag])
+                    .or(removed_tags.contains(vec![tag])),
+            );
+        }
+
+        let rows = statement
+            .order_by(revisions::dsl::revision_id.asc())
+            .load::<(RevisionId, UserId, DateTime<Utc>, Vec<String>, Vec<String>)>(&*self.conn)?;
+
+        let mut events = Vec::new();
+        for (revision_id, user_id, created_at, added, removed) in rows {
+            let changes = added
+                .into_iter()
+                .map(|name| (name, TagAction::Added))
+                .chain(removed.into_iter().map(|name| (name, TagAction::Removed)));
+
+            for (name, action) in changes {
+                if tag.is_some() && tag != Some(name.as_str()) {
+                    continue;
+                }
+
+                events.push(TagEvent {
+                    page_id,
+                    revision_id,
+                    user_id,
+                    tag: name,
+                    action,
+                    created_at,
+                });
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Gets the pages in a wiki which had the given tag at a particular time.
+    ///
+    /// The tags are replayed from the tag history, so pages are included even if
+    /// they have since been untagged, renamed or deleted. Pages which were deleted
+    /// at the time are left out.
+    pub fn get_tagged_pages_at(
+        &self,
+        wiki_id: WikiId,
+        tag: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<PageId>> {
+        use self::tag_history::dsl::{added_tags, removed_tags};
+
+        info!(
+            "Getting pages in wiki ID {} tagged '{}' at {}",
+            wiki_id, tag, time,
+        );
+
+        let id: i64 = wiki_id.into();
+        let tags = vec![tag];
+        let changes = tag_history::table
+            .inner_join(revisions::table.inner_join(pages::table))
+            .filter(pages::dsl::wiki_id.eq(id))
+            .filter(revisions::dsl::created_at.le(time))
+            .filter(added_tags.contains(&tags).or(removed_tags.contains(&tags)))
+            .order_by(revisions::dsl::revision_id.asc())
+            .select((revisions::dsl::page_id, added_tags))
+            .load::<(PageId, Vec<String>)>(&*self.conn)?;
+
+        // Each change either added or removed the tag, the latest one wins
+        let mut tagged = HashSet::new();
+        for (page_id, added) in changes {
+            if added.iter().any(|name| name == tag) {
+                tagged.insert(page_id);
+            } else {
+                tagged.remove(&page_id);
+            }
+        }
+
+        trace!("Checking which of {} tagged pages existed", tagged.len());
+        let page_ids = tagged
+            .iter()
+            .map(|&page_id| page_id.into())
+            .collect::<Vec<i64>>();
+        let lifecycle: [&str; 3] = [
+            ChangeType::Create.into(),
+            ChangeType::Delete.into(),
+            ChangeType::Restore.into(),
+        ];
+
+        let revisions = revisions::table
+            .filter(revisions::dsl::page_id.eq_any(&page_ids))
+            .filter(revisions::dsl::change_type.eq_any(&lifecycle[..]))
+            .filter(revisions::dsl::created_at.le(time))
+            .order_by(revisions::dsl::revision_id.asc())
+            .select((revisions::dsl::page_id, revisions::dsl::change_type))
+            .load::<(PageId, String)>(&*self.conn)?;
+
+        let delete: &str = ChangeType::Delete.into();
+        let mut deleted = HashSet::new();
+        for (page_id, change_type) in revisions {
+            if change_type == delete {
+                deleted.insert(page_id);
+            } else {
+                deleted.remove(&page_id);
+            }
+        }
+
+        let mut page_ids = tagged.difference(&deleted).copied().collect::<Vec<_>>();
+        page_ids.sort();
+        Ok(page_ids)
+    }
+}
@@ -18,8 +18,26 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
+mod batch;
+mod fsck;
+mod history;
 mod models;
+mod purge;
+mod query;
+mod rebuild;
+mod redirect;
+mod search;
 mod service;
+mod tags;
 
+pub use self::batch::*;
+pub use self::fsck::*;
+pub use self::history::*;
 pub use self::models::*;
+pub use self::purge::*;
+pub use self::query::*;
+pub use self::rebuild::*;
+pub use self::redirect::*;
+pub use self::search::*;
 pub use self::service::*;
+pub use self::tags::*;
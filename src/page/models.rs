@@ -18,19 +18,26 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-use crate::schema::{pages, revisions, tag_history};
+use super::PageId;
+use crate::schema::{page_search, pages, revisions, tag_history, title_history};
+use crate::user::UserId;
+use crate::wiki::WikiId;
 use crate::StdResult;
+use serde_json as json;
 use std::convert::TryFrom;
 
 type Nullable<T> = Option<T>;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum ChangeType {
     Create,
     Modify,
     Delete,
+    Restore,
     Rename,
     Tags,
+    Revert,
 }
 
 impl Into<&'static str> for ChangeType {
@@ -41,8 +48,10 @@ impl Into<&'static str> for ChangeType {
             Create => "create",
             Modify => "modify",
             Delete => "delete",
+            Restore => "restore",
             Rename => "rename",
             Tags => "tags",
+            Revert => "revert",
         }
     }
 }
@@ -55,8 +64,10 @@ impl TryFrom<&'_ str> for ChangeType {
             "create" => ChangeType::Create,
             "modify" => ChangeType::Modify,
             "delete" => ChangeType::Delete,
+            "restore" => ChangeType::Restore,
             "rename" => ChangeType::Rename,
             "tags" => ChangeType::Tags,
+            "revert" => ChangeType::Revert,
             _ => return Err(()),
         };
 
@@ -64,6 +75,42 @@ impl TryFrom<&'_ str> for ChangeType {
     }
 }
 
+/// The data stored as the message of each git commit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommitMessage {
+    pub wiki_id: WikiId,
+    pub page_id: PageId,
+    pub user_id: UserId,
+    pub change_type: ChangeType,
+
+    /// For reverts, the commit the page was restored to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_commit: Option<String>,
+
+    /// For batch commits, the page's slug after the change.
+    /// Single-page commits can find it from the files the commit changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+
+    /// For renames, the page's slug before the change.
+    /// Older commits don't have it, and must be matched by file contents instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_slug: Option<String>,
+}
+
+impl CommitMessage {
+    /// Reads the message of a git commit.
+    ///
+    /// Batch commits store a list with one message per page they changed,
+    /// other commits a single message.
+    pub fn parse(message: &str) -> json::Result<Vec<Self>> {
+        match json::from_str::<Vec<Self>>(message) {
+            Ok(messages) => Ok(messages),
+            Err(_) => json::from_str::<Self>(message).map(|message| vec![message]),
+        }
+    }
+}
+
 #[derive(Debug, Insertable)]
 #[table_name = "pages"]
 pub struct NewPage<'a> {
@@ -73,6 +120,16 @@ pub struct NewPage<'a> {
     pub alt_title: Option<&'a str>,
 }
 
+#[derive(Debug, Insertable)]
+#[table_name = "page_search"]
+pub struct NewPageSearch<'a> {
+    pub page_id: i64,
+    pub wiki_id: i64,
+    pub title: &'a str,
+    pub alt_title: Option<&'a str>,
+    pub content: &'a str,
+}
+
 #[derive(Debug, AsChangeset)]
 #[table_name = "pages"]
 pub struct UpdatePage<'a> {
@@ -89,6 +146,8 @@ pub struct NewRevision<'a> {
     pub message: &'a str,
     pub git_commit: &'a str,
     pub change_type: &'a str,
+    pub target_revision_id: Option<i64>,
+    pub old_slug: Option<&'a str>,
 }
 
 #[derive(Debug, Insertable)]
@@ -98,3 +157,11 @@ pub struct NewTagChange<'a> {
     pub added_tags: &'a [&'a str],
     pub removed_tags: &'a [&'a str],
 }
+
+#[derive(Debug, Insertable)]
+#[table_name = "title_history"]
+pub struct NewTitleChange<'a> {
+    pub revision_id: i64,
+    pub title: &'a str,
+    pub alt_title: Option<&'a str>,
+}
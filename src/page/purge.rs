@@ -0,0 +1,69 @@
+/*
+ * page/purge.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{PageId, RevisionId};
+use crate::revision::GitHash;
+use crate::schema::purges;
+use crate::user::UserId;
+use crate::wiki::WikiId;
+use chrono::prelude::*;
+
+mod purge_id {
+    make_id_type!(PurgeId);
+}
+
+pub use self::purge_id::PurgeId;
+
+/// The audit record left behind when a page or some of its revisions are purged.
+///
+/// `git_commits` are the hashes of the commits which were dropped, as they were
+/// before history was rewritten. The purged content itself is not kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurgeRecord {
+    pub purge_id: PurgeId,
+    pub wiki_id: WikiId,
+    pub page_id: PageId,
+    pub slug: String,
+    pub user_id: UserId,
+    pub reason: String,
+
+    /// Whether the whole page was purged, rather than only some revisions.
+    pub page_removed: bool,
+    pub revision_ids: Vec<RevisionId>,
+    pub git_commits: Vec<GitHash>,
+
+    /// How many later commits had their hashes changed by the rewrite.
+    pub commits_rewritten: u32,
+    pub purged_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "purges"]
+pub struct NewPurge<'a> {
+    pub wiki_id: i64,
+    pub page_id: i64,
+    pub slug: &'a str,
+    pub user_id: i64,
+    pub reason: &'a str,
+    pub page_removed: bool,
+    pub revision_ids: &'a [i64],
+    pub git_commits: &'a [&'a str],
+    pub commits_rewritten: i32,
+}
@@ -0,0 +1,278 @@
+/*
+ * page/query.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{Page, PageId, PageService};
+use crate::author::AuthorType;
+use crate::rating::Rating;
+use crate::schema::{authors, pages, parents};
+use crate::service_prelude::*;
+use crate::user::UserId;
+use crate::wiki::WikiId;
+
+/// The number of pages returned when a page query has no limit.
+pub const DEFAULT_PAGE_QUERY_LIMIT: u32 = 20;
+
+/// The largest number of pages a page query may return at once.
+pub const MAX_PAGE_QUERY_LIMIT: u32 = 250;
+
+/// The category pages without a `category:` prefix in their slug belong to.
+pub const DEFAULT_CATEGORY: &str = "_default";
+
+/// What to sort the results of a page query by.
+///
+/// Ties are broken by page ID, so results are stable between queries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageOrder {
+    Rating,
+    CreatedAt,
+    Title,
+    Votes,
+}
+
+impl Default for PageOrder {
+    #[inline]
+    fn default() -> Self {
+        PageOrder::CreatedAt
+    }
+}
+
+/// Which pages in a wiki to list, similar to Wikidot's ListPages module.
+///
+/// All filters are optional, and deleted pages are never included.
+/// To get the next set of results, run the query again with `offset`
+/// moved forward by the number of pages returned.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PageQuery<'a> {
+    /// Only include pages in this category, the part of the slug before `:`.
+    /// Pages without one are in the `_default` category.
+    pub category: Option<&'a str>,
+
+    /// Only include pages which have all of these tags.
+    pub all_tags: &'a [&'a str],
+
+    /// Only include pages which have at least one of these tags.
+    pub any_tags: &'a [&'a str],
+
+    /// Only include pages which have none of these tags.
+    pub no_tags: &'a [&'a str],
+
+    /// Only include pages this user is credited as an author of.
+    pub created_by: Option<UserId>,
+
+    /// Only include pages created at or after this time.
+    pub start: Option<DateTime<Utc>>,
+
+    /// Only include pages created before this time.
+    pub end: Option<DateTime<Utc>>,
+
+    /// Only include pages with at least this score.
+    pub min_rating: Option<i64>,
+
+    /// Only include pages with at most this score.
+    pub max_rating: Option<i64>,
+
+    /// Only include direct children of this page.
+    pub parent: Option<PageId>,
+
+    pub order: PageOrder,
+    pub descending: bool,
+
+    /// How many matching pages to skip.
+    pub offset: u32,
+
+    /// How many pages to return, or zero for the default.
+    pub limit: u32,
+}
+
+/// One set of results from a page query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageList {
+    pub pages: Vec<(Page, Rating)>,
+
+    /// The number of pages matching the query, ignoring `offset` and `limit`.
+    pub total: u64,
+}
+
+// Correlated subqueries for a page's rating, matching RatingService::get_rating()
+const RATING_SCORE_SQL: &str =
+    "(SELECT COALESCE(SUM(ratings.rating), 0) FROM ratings WHERE ratings.page_id = pages.page_id)";
+const RATING_VOTES_SQL: &str =
+    "(SELECT COUNT(*) FROM ratings WHERE ratings.page_id = pages.page_id)";
+
+impl PageService {
+    /// Lists the pages in a wiki matching the given query.
+    pub fn list_pages(&self, wiki_id: WikiId, query: PageQuery) -> Result<PageList> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+        use std::convert::TryInto;
+
+        info!("Listing pages for wiki ID {}: {:?}", wiki_id, query);
+
+        let limit = match query.limit {
+            0 => DEFAULT_PAGE_QUERY_LIMIT,
+            limit => limit.min(MAX_PAGE_QUERY_LIMIT),
+        };
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let total = self
+                .filter_pages(wiki_id, &query)
+                .count()
+                .get_result::<i64>(&*self.conn)?;
+
+            let mut statement = self
+                .filter_pages(wiki_id, &query)
+                .select((
+                    pages::all_columns,
+                    sql::<BigInt>(RATING_SCORE_SQL),
+                    sql::<BigInt>(RATING_VOTES_SQL),
+                ))
+                .offset(i64::from(query.offset))
+                .limit(i64::from(limit));
+
+            statement = match (query.order, query.descending) {
+                (PageOrder::Rating, false) => {
+                    statement.order_by(sql::<BigInt>(RATING_SCORE_SQL).asc())
+                }
+                (PageOrder::Rating, true) => {
+                    statement.order_by(sql::<BigInt>(RATING_SCORE_SQL).desc())
+                }
+                (PageOrder::Votes, false) => {
+                    statement.order_by(sql::<BigInt>(RATING_VOTES_SQL).asc())
+                }
+                (PageOrder::Votes, true) => {
+                    statement.order_by(sql::<BigInt>(RATING_VOTES_SQL).desc())
+                }
+                (PageOrder::CreatedAt, false) => statement.order_by(pages::dsl::created_at.asc()),
+                (PageOrder::CreatedAt, true) => statement.order_by(pages::dsl::created_at.desc()),
+                (PageOrder::Title, false) => statement.order_by(pages::dsl::title.asc()),
+                (PageOrder::Title, true) => statement.order_by(pages::dsl::title.desc()),
+            };
+
+            statement = if query.descending {
+                statement.then_order_by(pages::dsl::page_id.desc())
+            } else {
+                statement.then_order_by(pages::dsl::page_id.asc())
+            };
+
+            let rows = statement.load::<(Page, i64, i64)>(&*self.conn)?;
+            let mut pages = Vec::with_capacity(rows.len());
+            for (page, score, votes) in rows {
+                let votes = votes
+                    .try_into()
+                    .map_err(|_| Error::StaticMsg("number of votes doesn't fit into u32"))?;
+
+                pages.push((page, Rating::new(score, votes)));
+            }
+
+            Ok(PageList {
+                pages,
+                total: total as u64,
+            })
+        })
+    }
+
+    fn filter_pages<'a>(
+        &self,
+        wiki_id: WikiId,
+        query: &PageQuery<'a>,
+    ) -> pages::BoxedQuery<'a, diesel::pg::Pg> {
+        use diesel::dsl::{not, sql};
+        use diesel::sql_types::{BigInt, Bool, Text};
+
+        let wiki_id: i64 = wiki_id.into();
+        let mut statement = pages::table
+            .filter(pages::dsl::wiki_id.eq(wiki_id))
+            .filter(pages::dsl::deleted_at.is_null())
+            .into_boxed();
+
+        match query.category {
+            Some(DEFAULT_CATEGORY) => {
+                statement = statement.filter(sql::<Bool>("strpos(pages.slug, ':') = 0"));
+            }
+            Some(category) => {
+                statement = statement.filter(
+                    sql::<Bool>("split_part(pages.slug, ':', 1) = ")
+                        .bind::<Text, _>(category)
+                        .sql(" AND strpos(pages.slug, ':') > 0"),
+                );
+            }
+            None => (),
+        }
+
+        if !query.all_tags.is_empty() {
+            statement = statement.filter(pages::dsl::tags.contains(query.all_tags.to_vec()));
+        }
+
+        if !query.any_tags.is_empty() {
+            statement = statement.filter(pages::dsl::tags.overlaps_with(query.any_tags.to_vec()));
+        }
+
+        if !query.no_tags.is_empty() {
+            statement =
+                statement.filter(not(pages::dsl::tags.overlaps_with(query.no_tags.to_vec())));
+        }
+
+        if let Some(user_id) = query.created_by {
+            let user_id: i64 = user_id.into();
+            let author_type: &str = AuthorType::Author.into();
+
+            statement = statement.filter(
+                pages::dsl::page_id.eq_any(
+                    authors::table
+                        .filter(authors::dsl::user_id.eq(user_id))
+                        .filter(authors::dsl::author_type.eq(author_type))
+                        .select(authors::dsl::page_id),
+                ),
+            );
+        }
+
+        if let Some(start) = query.start {
+            statement = statement.filter(pages::dsl::created_at.ge(start));
+        }
+
+        if let Some(end) = query.end {
+            statement = statement.filter(pages::dsl::created_at.lt(end));
+        }
+
+        if let Some(min) = query.min_rating {
+            statement = statement
+                .filter(sql::<Bool>(&format!("{} >= ", RATING_SCORE_SQL)).bind::<BigInt, _>(min));
+        }
+
+        if let Some(max) = query.max_rating {
+            statement = statement
+                .filter(sql::<Bool>(&format!("{} <= ", RATING_SCORE_SQL)).bind::<BigInt, _>(max));
+        }
+
+        if let Some(parent) = query.parent {
+            let parent: i64 = parent.into();
+
+            statement = statement.filter(
+                pages::dsl::page_id.eq_any(
+                    parents::table
+                        .filter(parents::dsl::parent_page_id.eq(parent))
+                        .select(parents::dsl::page_id),
+                ),
+            );
+        }
+
+        statement
+    }
+}
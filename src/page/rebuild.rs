@@ -0,0 +1,69 @@
+/*
+ * page/rebuild.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{PageId, RevisionId};
+use crate::revision::GitHash;
+use crate::user::UserId;
+
+/// Something which could not be recovered while rebuilding a wiki from its git history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebuildIssue {
+    /// Titles are not stored in git, so the page was given an empty one.
+    MissingTitle { page_id: PageId, slug: String },
+
+    /// Tag changes are not stored in git, so this revision has no tag history
+    /// and the page's current tags are unknown.
+    MissingTags {
+        page_id: PageId,
+        revision_id: RevisionId,
+    },
+
+    /// A commit's message could not be read, or belongs to a different wiki.
+    InvalidCommit { git_commit: GitHash },
+
+    /// A commit refers to a user which does not exist, so it was skipped.
+    MissingUser {
+        git_commit: GitHash,
+        user_id: UserId,
+    },
+
+    /// A commit refers to a page which was never created, or its slug could not be determined.
+    /// The commit was skipped.
+    UnknownPage {
+        git_commit: GitHash,
+        page_id: PageId,
+    },
+}
+
+/// The results of rebuilding a wiki's pages and revisions from its git history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RebuildReport {
+    pub pages: usize,
+    pub revisions: usize,
+    pub issues: Vec<RebuildIssue>,
+}
+
+impl RebuildReport {
+    pub(crate) fn push(&mut self, issue: RebuildIssue) {
+        warn!("Unable to fully rebuild from git history: {:?}", issue);
+
+        self.issues.push(issue);
+    }
+}
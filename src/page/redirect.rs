@@ -0,0 +1,66 @@
+/*
+ * page/redirect.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::PageId;
+use crate::schema::redirects;
+use crate::wiki::WikiId;
+use chrono::prelude::*;
+
+/// A slug which a page was renamed away from, which now leads to that page.
+///
+/// Redirects always point directly at a page rather than at another slug,
+/// so renaming a page several times never creates a chain of redirects.
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    wiki_id: WikiId,
+    slug: String,
+    page_id: PageId,
+    created_at: DateTime<Utc>,
+}
+
+impl Redirect {
+    #[inline]
+    pub fn wiki_id(&self) -> WikiId {
+        self.wiki_id
+    }
+
+    #[inline]
+    pub fn slug(&self) -> &str {
+        &self.slug
+    }
+
+    #[inline]
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    #[inline]
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "redirects"]
+pub struct NewRedirect<'a> {
+    pub wiki_id: i64,
+    pub slug: &'a str,
+    pub page_id: i64,
+}
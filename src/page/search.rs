@@ -0,0 +1,274 @@
+/*
+ * page/search.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{NewPageSearch, PageId, PageService};
+use crate::schema::{page_search, pages};
+use crate::service_prelude::*;
+use crate::wiki::WikiId;
+use diesel::sql_types::{BigInt, Float, Nullable, Text};
+
+/// The number of results returned when a search has no limit.
+pub const DEFAULT_SEARCH_LIMIT: u32 = 20;
+
+/// The largest number of results a search may return at once.
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+
+/// A page matching a search, with the best matches first.
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    #[sql_type = "BigInt"]
+    pub page_id: PageId,
+
+    #[sql_type = "Text"]
+    pub slug: String,
+
+    #[sql_type = "Text"]
+    pub title: String,
+
+    #[sql_type = "Nullable<Text>"]
+    pub alt_title: Option<String>,
+
+    #[sql_type = "Float"]
+    pub rank: f32,
+
+    /// Parts of the page's contents around the matches. It is HTML-escaped,
+    /// with each match wrapped in `<b>` and `</b>`.
+    #[sql_type = "Text"]
+    pub snippet: String,
+}
+
+/// One set of results from a search.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResults {
+    pub results: Vec<SearchResult>,
+
+    /// The number of pages matching the search, ignoring `offset` and `limit`.
+    pub total: u64,
+}
+
+/// Converts a search into a Postgres `tsquery`, or `None` if it has no words.
+///
+/// Every word must match. Text in double quotes must match as a phrase, and
+/// a word ending in `*` matches any word starting with it. Anything besides
+/// letters and numbers only separates words, so the query is always valid.
+pub fn build_tsquery(search: &str) -> Option<String> {
+    let mut terms = Vec::new();
+
+    // Every other part is inside quotes
+    for (i, part) in search.split('"').enumerate() {
+        if i % 2 == 1 {
+            let words = split_words(part).collect::<Vec<_>>();
+            if !words.is_empty() {
+                terms.push(words.join(" <-> "));
+            }
+
+            continue;
+        }
+
+        for token in part.split_whitespace() {
+            let mut words = split_words(token).collect::<Vec<_>>();
+            if words.is_empty() {
+                continue;
+            }
+
+            if token.ends_with('*') {
+                if let Some(last) = words.last_mut() {
+                    last.push_str(":*");
+                }
+            }
+
+            terms.push(words.join(" <-> "));
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" & "))
+    }
+}
+
+fn split_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl PageService {
+    /// Updates the search index entry for a page to match its current title and contents.
+    /// Deleted pages are removed from the index.
+    pub(super) fn index_page(&self, wiki_id: WikiId, page_id: PageId) -> Result<()> {
+        debug!("Updating search index for page ID {}", page_id);
+
+        let id: i64 = page_id.into();
+        let (slug, title, alt_title, deleted_at) = pages::table
+            .find(id)
+            .select((
+                pages::dsl::slug,
+                pages::dsl::title,
+                pages::dsl::alt_title,
+                pages::dsl::deleted_at,
+            ))
+            .first::<(String, String, Option<String>, Option<DateTime<Utc>>)>(&*self.conn)?;
+
+        if deleted_at.is_some() {
+            trace!("Removing deleted page from search index");
+            diesel::delete(page_search::table.find(id)).execute(&*self.conn)?;
+
+            return Ok(());
+        }
+
+        let content = self
+            .get_store(wiki_id, |store| store.get_page(&slug))?
+            .unwrap_or_default();
+
+        let content = String::from_utf8_lossy(&content);
+        let model = NewPageSearch {
+            page_id: id,
+            wiki_id: wiki_id.into(),
+            title: &title,
+            alt_title: alt_title.as_deref(),
+            content: &content,
+        };
+
+        trace!("Inserting page into search index");
+        diesel::insert_into(page_search::table)
+            .values(&model)
+            .on_conflict(page_search::dsl::page_id)
+            .do_update()
+            .set((
+                page_search::dsl::title.eq(model.title),
+                page_search::dsl::alt_title.eq(model.alt_title),
+                page_search::dsl::content.eq(model.content),
+            ))
+            .execute(&*self.conn)?;
+
+        Ok(())
+    }
+
+    /// Recreates the search index for a wiki from the contents in its revision store.
+    ///
+    /// Returns the number of pages indexed.
+    pub fn reindex(&self, wiki_id: WikiId) -> Result<usize> {
+        info!("Rebuilding search index for wiki ID {}", wiki_id);
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let id: i64 = wiki_id.into();
+
+            trace!("Clearing search index");
+            diesel::delete(page_search::table.filter(page_search::dsl::wiki_id.eq(id)))
+                .execute(&*self.conn)?;
+
+            let page_ids = pages::table
+                .filter(pages::dsl::wiki_id.eq(id))
+                .filter(pages::dsl::deleted_at.is_null())
+                .select(pages::dsl::page_id)
+                .load::<PageId>(&*self.conn)?;
+
+            for page_id in page_ids.iter().copied() {
+                self.index_page(wiki_id, page_id)?;
+            }
+
+            Ok(page_ids.len())
+        })
+    }
+
+    /// Finds pages in a wiki whose title or contents match the search, best matches first.
+    ///
+    /// Every word must match, text in double quotes must match as a phrase,
+    /// and words ending in `*` match any word starting with them.
+    pub fn search(
+        &self,
+        wiki_id: WikiId,
+        search: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<SearchResults> {
+        use diesel::sql_types::{BigInt, Text};
+
+        #[derive(QueryableByName)]
+        struct Count {
+            #[sql_type = "BigInt"]
+            count: i64,
+        }
+
+        info!("Searching pages in wiki ID {} for '{}'", wiki_id, search);
+
+        let limit = match limit {
+            0 => DEFAULT_SEARCH_LIMIT,
+            limit => limit.min(MAX_SEARCH_LIMIT),
+        };
+
+        let query = match build_tsquery(search) {
+            Some(query) => query,
+            None => return Ok(SearchResults::default()),
+        };
+
+        debug!("Built search query '{}'", query);
+
+        let id: i64 = wiki_id.into();
+        self.conn.transaction::<_, Error, _>(|| {
+            let Count { count } = diesel::sql_query(
+                "SELECT COUNT(*) AS count
+                 FROM page_search
+                 WHERE wiki_id = $1
+                   AND document @@ to_tsquery('english', $2)",
+            )
+            .bind::<BigInt, _>(id)
+            .bind::<Text, _>(&query)
+            .get_result::<Count>(&*self.conn)?;
+
+            // Contents are escaped first, since snippets are highlighted with HTML tags
+            let results = diesel::sql_query(
+                "SELECT
+                    page_search.page_id,
+                    pages.slug,
+                    page_search.title,
+                    page_search.alt_title,
+                    ts_rank_cd(page_search.document, query) AS rank,
+                    ts_headline(
+                        'english',
+                        replace(replace(replace(page_search.content,
+                            '&', '&amp;'), '<', '&lt;'), '>', '&gt;'),
+                        query,
+                        'MaxFragments=2'
+                    ) AS snippet
+                 FROM page_search
+                 JOIN pages ON pages.page_id = page_search.page_id,
+                    to_tsquery('english', $2) query
+                 WHERE page_search.wiki_id = $1
+                   AND page_search.document @@ query
+                 ORDER BY rank DESC, page_search.page_id
+                 OFFSET $3
+                 LIMIT $4",
+            )
+            .bind::<BigInt, _>(id)
+            .bind::<Text, _>(&query)
+            .bind::<BigInt, _>(i64::from(offset))
+            .bind::<BigInt, _>(i64::from(limit))
+            .load::<SearchResult>(&*self.conn)?;
+
+            Ok(SearchResults {
+                results,
+                total: count as u64,
+            })
+        })
+    }
+}
@@ -18,18 +18,29 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-use super::{ChangeType, NewPage, NewRevision, NewTagChange, UpdatePage};
-use crate::revision::{CommitInfo, GitHash, RevisionStore};
-use crate::schema::{pages, revisions, tag_history};
+use super::{
+    ChangeType, CommitMessage, ConsistencyReport, HistoryEntry, HistoryPage, HistoryQuery,
+    Inconsistency, NewPage, NewPurge, NewRedirect, NewRevision, NewTagChange, NewTitleChange,
+    PurgeId, PurgeRecord, RebuildIssue, RebuildReport, Redirect, TagGroup, TagRules, UpdatePage,
+    DEFAULT_HISTORY_LIMIT, MAX_HISTORY_LIMIT,
+};
+use crate::revision::{
+    merge, CommitInfo, Diff, GitHash, GitStore, MemoryStore, RevisionBackend, RevisionStore,
+};
+use crate::role::{Permission, RoleService};
+use crate::schema::{
+    authors, files, pages, parents, purges, ratings, ratings_history, redirects, revisions,
+    tag_groups, tag_history, tag_rules, title_history,
+};
 use crate::service_prelude::*;
 use crate::user::{User, UserId};
 use crate::wiki::{Wiki, WikiId};
+use diesel::connection::TransactionManager;
 use either::*;
 use serde_json as json;
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
 
 mod page_id {
     make_id_type!(PageId);
@@ -48,6 +59,10 @@ pub struct PageCommit<'a> {
     pub slug: &'a str,
     pub message: &'a str,
     pub user: &'a User,
+
+    /// The revision the changes were based on, if known.
+    /// Edits made on top of an older revision are merged with the latest version.
+    pub base: Option<RevisionId>,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Clone, PartialEq, Eq)]
@@ -110,20 +125,24 @@ impl Page {
 }
 
 pub struct PageService {
-    conn: Arc<PgConnection>,
-    directory: PathBuf,
-    stores: RwLock<HashMap<WikiId, RevisionStore>>,
+    pub(super) conn: Arc<PgConnection>,
+    backend: RevisionBackend,
+    stores: RwLock<HashMap<WikiId, Box<dyn RevisionStore>>>,
+    roles: RoleService,
 }
 
 impl PageService {
     #[inline]
-    pub fn new(conn: &Arc<PgConnection>, directory: PathBuf) -> Self {
+    pub fn new(conn: &Arc<PgConnection>, backend: RevisionBackend) -> Self {
         let conn = Arc::clone(conn);
 
+        let roles = RoleService::new(&conn);
+
         PageService {
             conn,
-            directory,
+            backend,
             stores: RwLock::new(HashMap::new()),
+            roles,
         }
     }
 
@@ -134,46 +153,199 @@ impl PageService {
         user_id: UserId,
         change_type: ChangeType,
     ) -> Result<String> {
-        #[derive(Debug, Serialize)]
-        struct CommitMessage {
-            wiki_id: WikiId,
-            page_id: PageId,
-            user_id: UserId,
-            change_type: &'static str,
-        }
-
         let message = CommitMessage {
             wiki_id,
             page_id,
             user_id,
-            change_type: change_type.into(),
+            change_type,
+            target_commit: None,
+            slug: None,
+            old_slug: None,
         };
 
         json::to_string(&message).map_err(Error::from)
     }
 
     pub fn add_store(&self, wiki: &Wiki) -> Result<()> {
-        let repo = self.directory.join(wiki.slug());
-        fs::create_dir(&repo)?;
+        let store: Box<dyn RevisionStore> = match self.backend {
+            RevisionBackend::Git(ref directory) => {
+                let repo = directory.join(wiki.slug());
+                fs::create_dir(&repo)?;
+
+                Box::new(GitStore::new(repo, wiki.domain()))
+            }
+            RevisionBackend::Memory => Box::new(MemoryStore::new(wiki.domain())),
+        };
 
-        let store = RevisionStore::new(repo, wiki.domain());
         store.initial_commit()?;
 
-        let mut guard = self.stores.write();
-        guard.insert(wiki.id(), store);
+        self.insert_store(wiki.id(), store);
+        Ok(())
+    }
+
+    /// Opens the revision store for an existing wiki.
+    ///
+    /// Repositories which are missing or were never initialized are created,
+    /// but a non-empty directory which isn't a git repository is an error.
+    /// In-memory stores always start out empty.
+    pub fn load_store(&self, wiki: &Wiki) -> Result<()> {
+        let directory = match self.backend {
+            RevisionBackend::Git(ref directory) => directory,
+            RevisionBackend::Memory => {
+                info!(
+                    "Creating in-memory revision store for wiki ID {}",
+                    wiki.id()
+                );
+
+                let store = MemoryStore::new(wiki.domain());
+                store.initial_commit()?;
+
+                self.insert_store(wiki.id(), Box::new(store));
+                return Ok(());
+            }
+        };
+
+        let repo = directory.join(wiki.slug());
+
+        info!(
+            "Loading revision store for wiki ID {} from {}",
+            wiki.id(),
+            repo.display(),
+        );
+
+        let store = GitStore::new(&repo, wiki.domain());
+
+        if !repo.exists() {
+            warn!(
+                "Revision directory for wiki '{}' is missing, creating new repository",
+                wiki.slug(),
+            );
+
+            fs::create_dir_all(&repo)?;
+            store.initial_commit()?;
+        } else if !store.is_initialized().map_err(|error| {
+            error!(
+                "Unable to read git repository in revision directory {}: {}",
+                repo.display(),
+                error,
+            );
+
+            Error::InvalidRevisionStore(repo.clone())
+        })? {
+            if !repo.join(".git").is_dir() && fs::read_dir(&repo)?.next().is_some() {
+                error!(
+                    "Revision directory {} has files but is not a git repository",
+                    repo.display(),
+                );
+
+                return Err(Error::InvalidRevisionStore(repo));
+            }
+
+            warn!(
+                "Revision directory for wiki '{}' is not initialized, creating initial commit",
+                wiki.slug(),
+            );
 
+            store.initial_commit()?;
+        }
+
+        self.recover_store(&store)?;
+        self.insert_store(wiki.id(), Box::new(store));
         Ok(())
     }
 
-    fn get_store<F, T>(&self, wiki_id: WikiId, f: F) -> Result<T>
+    /// Runs a database transaction which makes commits to the given wiki's revision store.
+    ///
+    /// If the transaction fails, the store is reset to where it was beforehand.
+    /// For outermost transactions the starting commit is also journaled, so that
+    /// changes can be undone on startup if the process exits partway through.
+    pub fn transaction<F, T>(&self, wiki_id: WikiId, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let manager = self.conn.transaction_manager();
+        let outermost = TransactionManager::<PgConnection>::get_transaction_depth(manager) == 0;
+        let head = self.get_store(wiki_id, |store| {
+            if outermost {
+                store.begin_journal()?;
+            }
+
+            store.head()
+        })?;
+
+        let result = self.conn.transaction::<_, Error, _>(f);
+
+        self.get_store(wiki_id, |store| {
+            if let (Err(error), Some(head)) = (&result, &head) {
+                warn!(
+                    "Transaction failed ({}), resetting revision store to {}",
+                    error, head,
+                );
+
+                store.reset(head)?;
+            }
+
+            if outermost {
+                store.end_journal()?;
+            }
+
+            Ok(())
+        })?;
+
+        result
+    }
+
+    /// Undoes any changes left behind by a transaction which never finished.
+    ///
+    /// If the latest commit was recorded in the database, the transaction was
+    /// committed and only the journal needs to be cleared.
+    fn recover_store(&self, store: &dyn RevisionStore) -> Result<()> {
+        let previous = match store.pending_journal()? {
+            Some(hash) => hash,
+            None => return Ok(()),
+        };
+
+        let head = store.head()?;
+        if head.as_ref() != Some(&previous) {
+            let committed = match head {
+                Some(ref hash) => revisions::table
+                    .filter(revisions::dsl::git_commit.eq(hash.as_str()))
+                    .select(revisions::dsl::revision_id)
+                    .first::<RevisionId>(&*self.conn)
+                    .optional()?
+                    .is_some(),
+                None => false,
+            };
+
+            if committed {
+                info!("Unfinished change was committed, keeping commit");
+            } else {
+                warn!(
+                    "Unfinished change was not committed, resetting to {}",
+                    previous,
+                );
+
+                store.reset(&previous)?;
+            }
+        }
+
+        store.end_journal()
+    }
+
+    fn insert_store(&self, wiki_id: WikiId, store: Box<dyn RevisionStore>) {
+        let mut guard = self.stores.write();
+        guard.insert(wiki_id, store);
+    }
+
+    pub(super) fn get_store<F, T>(&self, wiki_id: WikiId, f: F) -> Result<T>
     where
-        F: FnOnce(&RevisionStore) -> Result<T>,
+        F: FnOnce(&dyn RevisionStore) -> Result<T>,
     {
         trace!("Getting revision store for wiki ID {}", wiki_id);
 
         let guard = self.stores.read();
         let store = match guard.get(&wiki_id) {
-            Some(store) => store,
+            Some(store) => store.as_ref(),
             None => {
                 error!("No revision store found for wiki ID {}", wiki_id);
 
@@ -204,6 +376,7 @@ impl PageService {
         let page_id = pages::table
             .filter(pages::dsl::wiki_id.eq(wiki_id))
             .filter(pages::dsl::slug.eq(slug))
+            .filter(pages::dsl::deleted_at.is_null())
             .select(pages::dsl::page_id)
             .first::<PageId>(&*self.conn)
             .optional()?;
@@ -215,6 +388,7 @@ impl PageService {
         &self,
         commit: PageCommit,
         content: &[u8],
+        tags: &[&str],
         title: &str,
         alt_title: Option<&str>,
     ) -> Result<(PageId, RevisionId)> {
@@ -225,9 +399,10 @@ impl PageService {
             slug,
             message,
             user,
+            ..
         } = commit;
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.transaction(wiki_id, || {
             let model = NewPage {
                 wiki_id: wiki_id.into(),
                 slug,
@@ -240,6 +415,9 @@ impl PageService {
                 return Err(Error::PageExists);
             }
 
+            // A real page takes the place of any redirect
+            self.remove_redirect(wiki_id, slug)?;
+
             trace!("Inserting {:?} into pages table", &model);
             let page_id = diesel::insert_into(pages::table)
                 .values(&model)
@@ -262,6 +440,8 @@ impl PageService {
                 message,
                 git_commit: hash.as_ref(),
                 change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: None,
             };
 
             trace!("Inserting revision {:?} into revisions table", &model);
@@ -270,6 +450,10 @@ impl PageService {
                 .returning(revisions::dsl::revision_id)
                 .get_result::<RevisionId>(&*self.conn)?;
 
+            self.record_title(page_id, revision_id)?;
+            self.set_tags(wiki_id, page_id, revision_id, &mut tags.to_vec(), user)?;
+            self.index_page(wiki_id, page_id)?;
+
             Ok((page_id, revision_id))
         })
     }
@@ -288,9 +472,10 @@ impl PageService {
             slug,
             message,
             user,
+            base,
         } = commit;
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.transaction(wiki_id, || {
             let model = UpdatePage {
                 slug: None,
                 title,
@@ -301,6 +486,13 @@ impl PageService {
                 .get_page_id(wiki_id, slug)?
                 .ok_or(Error::PageNotFound)?;
 
+            let content = match (base, content) {
+                (Some(base), Some(content)) => {
+                    Some(self.merge_edit(wiki_id, page_id, slug, base, content)?)
+                }
+                (_, content) => content.map(Cow::Borrowed),
+            };
+
             trace!("Updating {:?} in pages table", &model);
             {
                 use self::pages::dsl;
@@ -320,13 +512,15 @@ impl PageService {
                 message: &commit,
             };
 
-            let hash = self.raw_commit(wiki_id, slug, content, info)?;
+            let hash = self.raw_commit(wiki_id, slug, content.as_deref(), info)?;
             let model = NewRevision {
                 page_id: page_id.into(),
                 user_id: user_id.into(),
                 message,
                 git_commit: hash.as_ref(),
                 change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: None,
             };
 
             trace!("Inserting revision {:?} into revisions table", &model);
@@ -335,10 +529,90 @@ impl PageService {
                 .returning(revisions::dsl::revision_id)
                 .get_result::<RevisionId>(&*self.conn)?;
 
+            if title.is_some() || alt_title.is_some() {
+                self.record_title(page_id, revision_id)?;
+            }
+
+            self.index_page(wiki_id, page_id)?;
+
             Ok(revision_id)
         })
     }
 
+    /// Merges an edit based on an older revision of a page with its latest version.
+    ///
+    /// If the base is the latest revision, the content is used unchanged.
+    fn merge_edit<'a>(
+        &self,
+        wiki_id: WikiId,
+        page_id: PageId,
+        slug: &str,
+        base: RevisionId,
+        content: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>> {
+        use self::revisions::dsl;
+
+        debug!(
+            "Checking edit of page ID {} based on revision ID {}",
+            page_id, base,
+        );
+
+        let id: i64 = page_id.into();
+        let base_id: i64 = base.into();
+        let base_hash = dsl::revisions
+            .filter(dsl::revision_id.eq(base_id))
+            .filter(dsl::page_id.eq(id))
+            .select(dsl::git_commit)
+            .first::<String>(&*self.conn)
+            .optional()?
+            .ok_or(Error::RevisionNotFound)?;
+
+        // Tag changes leave the contents alone, so they don't need to be merged with
+        let tags: &str = ChangeType::Tags.into();
+        let latest = dsl::revisions
+            .filter(dsl::page_id.eq(id))
+            .filter(dsl::change_type.ne(tags))
+            .order_by(dsl::revision_id.desc())
+            .select(dsl::revision_id)
+            .first::<RevisionId>(&*self.conn)?;
+
+        if latest <= base {
+            return Ok(Cow::Borrowed(content));
+        }
+
+        info!(
+            "Page was changed since revision ID {}, merging with revision ID {}",
+            base, latest,
+        );
+
+        // The page may have been renamed since the base revision
+        let (_, base_slug) = self.slug_at(page_id, base)?;
+        let base_hash = GitHash::from_checked(base_hash);
+        let (original, current) = self.get_store(wiki_id, |store| {
+            let original = store.get_page_version(&base_slug, &base_hash)?;
+            let current = store.get_page(slug)?;
+
+            Ok((original, current))
+        })?;
+
+        let original = original.unwrap_or_default();
+        let current = current.unwrap_or_default();
+
+        match merge(&original, &current, content) {
+            Ok(merged) => Ok(Cow::Owned(merged)),
+            Err(conflicts) => {
+                warn!("Edit has {} conflicting regions", conflicts.len());
+
+                Err(Error::EditConflict(conflicts))
+            }
+        }
+    }
+
+    /// Moves a page to a different slug.
+    ///
+    /// If `redirect` is set, the old slug is kept as a redirect to the page.
+    /// Any redirect at the new slug is removed, so a page renamed back to
+    /// one of its earlier slugs never redirects to itself.
     pub fn rename(
         &self,
         wiki_id: WikiId,
@@ -346,10 +620,11 @@ impl PageService {
         new_slug: &str,
         message: &str,
         user: &User,
+        redirect: bool,
     ) -> Result<RevisionId> {
         info!("Starting transaction for page rename");
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.transaction(wiki_id, || {
             let model = UpdatePage {
                 slug: Some(new_slug),
                 title: None,
@@ -373,7 +648,16 @@ impl PageService {
             let user_id = user.id();
             let change_type = ChangeType::Rename;
 
-            let commit = self.commit_data(wiki_id, page_id, user_id, change_type)?;
+            let commit = json::to_string(&CommitMessage {
+                wiki_id,
+                page_id,
+                user_id,
+                change_type,
+                target_commit: None,
+                slug: None,
+                old_slug: Some(old_slug.to_owned()),
+            })?;
+
             let info = CommitInfo {
                 username: user.name(),
                 message: &commit,
@@ -384,12 +668,29 @@ impl PageService {
                 store.rename(old_slug, new_slug, info)
             })?;
 
+            self.remove_redirect(wiki_id, new_slug)?;
+
+            if redirect {
+                let model = NewRedirect {
+                    wiki_id: wiki_id.into(),
+                    slug: old_slug,
+                    page_id: page_id.into(),
+                };
+
+                trace!("Inserting {:?} into redirects table", &model);
+                diesel::insert_into(redirects::table)
+                    .values(&model)
+                    .execute(&*self.conn)?;
+            }
+
             let model = NewRevision {
                 page_id: page_id.into(),
                 user_id: user_id.into(),
                 message,
                 git_commit: hash.as_ref(),
                 change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: Some(old_slug),
             };
 
             trace!("Inserting revision {:?} into revisions table", &model);
@@ -398,6 +699,8 @@ impl PageService {
                 .returning(revisions::dsl::revision_id)
                 .get_result::<RevisionId>(&*self.conn)?;
 
+            self.index_page(wiki_id, page_id)?;
+
             Ok(revision_id)
         })
     }
@@ -410,9 +713,10 @@ impl PageService {
             slug,
             message,
             user,
+            ..
         } = commit;
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.transaction(wiki_id, || {
             use diesel::dsl::now;
 
             let page_id = self
@@ -452,6 +756,8 @@ impl PageService {
                 message,
                 git_commit: hash.as_ref(),
                 change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: None,
             };
 
             trace!("Inserting revision {:?} into revisions table", &model);
@@ -460,35 +766,262 @@ impl PageService {
                 .returning(revisions::dsl::revision_id)
                 .get_result::<RevisionId>(&*self.conn)?;
 
+            self.index_page(wiki_id, page_id)?;
+
             Ok(revision_id)
         })
     }
 
-    pub fn tags(&self, commit: PageCommit, tags: &mut [&str]) -> Result<RevisionId> {
-        info!("Starting transaction for page tags");
+    /// Brings back a deleted page, with the contents it had when it was removed.
+    pub fn restore(&self, page_id: PageId, message: &str, user: &User) -> Result<RevisionId> {
+        info!("Starting transaction for page restoration");
+
+        let id: i64 = page_id.into();
+        let (wiki_id, slug, deleted_at) = pages::table
+            .find(id)
+            .select((
+                pages::dsl::wiki_id,
+                pages::dsl::slug,
+                pages::dsl::deleted_at,
+            ))
+            .first::<(WikiId, String, Option<DateTime<Utc>>)>(&*self.conn)
+            .optional()?
+            .ok_or(Error::PageNotFound)?;
+
+        if deleted_at.is_none() {
+            return Err(Error::PageExists);
+        }
+
+        self.transaction(wiki_id, || {
+            trace!("Checking for page with the same slug");
+            if self.get_page_id(wiki_id, &slug)?.is_some() {
+                return Err(Error::PageExists);
+            }
+
+            // The last commit before the removal still has the page's file
+            let delete: &str = ChangeType::Delete.into();
+            let raw_hash = revisions::table
+                .filter(revisions::dsl::page_id.eq(id))
+                .filter(revisions::dsl::change_type.ne(delete))
+                .order_by(revisions::dsl::revision_id.desc())
+                .select(revisions::dsl::git_commit)
+                .first::<String>(&*self.conn)
+                .optional()?
+                .ok_or(Error::RevisionNotFound)?;
+
+            let hash = GitHash::from_checked(raw_hash);
+            let content = self
+                .get_store(wiki_id, |store| store.get_page_version(&slug, &hash))?
+                .ok_or(Error::RevisionNotFound)?;
+
+            self.remove_redirect(wiki_id, &slug)?;
+
+            trace!("Unmarking page as deleted in table");
+            {
+                use self::pages::dsl;
+
+                diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                    .set(dsl::deleted_at.eq(None::<DateTime<Utc>>))
+                    .execute(&*self.conn)?;
+            }
+
+            let user_id = user.id();
+            let change_type = ChangeType::Restore;
+
+            let commit = self.commit_data(wiki_id, page_id, user_id, change_type)?;
+            let info = CommitInfo {
+                username: user.name(),
+                message: &commit,
+            };
+
+            let hash = self.raw_commit(wiki_id, &slug, Some(&content), info)?;
+            let model = NewRevision {
+                page_id: id,
+                user_id: user_id.into(),
+                message,
+                git_commit: hash.as_ref(),
+                change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: None,
+            };
+
+            trace!("Inserting revision {:?} into revisions table", &model);
+            let revision_id = diesel::insert_into(revisions::table)
+                .values(&model)
+                .returning(revisions::dsl::revision_id)
+                .get_result::<RevisionId>(&*self.conn)?;
+
+            self.index_page(wiki_id, page_id)?;
+
+            Ok(revision_id)
+        })
+    }
+
+    /// Restores a page to how it was at an earlier revision.
+    ///
+    /// The title and tags are restored along with the content. Revisions from
+    /// before titles were tracked leave the current title in place.
+    pub fn revert(
+        &self,
+        commit: PageCommit,
+        target: Either<RevisionId, &GitHash>,
+    ) -> Result<RevisionId> {
+        info!("Starting transaction for page revert");
 
         let PageCommit {
             wiki_id,
             slug,
             message,
             user,
+            ..
         } = commit;
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.transaction(wiki_id, || {
             let page_id = self
                 .get_page_id(wiki_id, slug)?
                 .ok_or(Error::PageNotFound)?;
 
-            trace!("Getting tag difference");
-            let current_tags = {
-                let id: i64 = page_id.into();
-                pages::table
-                    .find(id)
-                    .select(pages::dsl::tags)
-                    .first::<Vec<String>>(&*self.conn)?
+            let (target_id, target_hash) = self.page_revision(page_id, target)?;
+
+            // The page may have had a different slug at that revision
+            let (_, target_slug) = self.slug_at(page_id, target_id)?;
+            let content = self
+                .get_store(wiki_id, |store| {
+                    store.get_page_version(&target_slug, &target_hash)
+                })?
+                .ok_or(Error::RevisionNotFound)?;
+
+            let id: i64 = page_id.into();
+            let target: i64 = target_id.into();
+
+            trace!("Getting title as of revision ID {}", target_id);
+            let title = title_history::table
+                .inner_join(revisions::table)
+                .filter(revisions::dsl::page_id.eq(id))
+                .filter(revisions::dsl::revision_id.le(target))
+                .order_by(revisions::dsl::revision_id.desc())
+                .select((title_history::dsl::title, title_history::dsl::alt_title))
+                .first::<(String, Option<String>)>(&*self.conn)
+                .optional()?;
+
+            trace!("Getting tags as of revision ID {}", target_id);
+            let tag_changes = tag_history::table
+                .inner_join(revisions::table)
+                .filter(revisions::dsl::page_id.eq(id))
+                .filter(revisions::dsl::revision_id.le(target))
+                .order_by(revisions::dsl::revision_id.asc())
+                .select((tag_history::dsl::added_tags, tag_history::dsl::removed_tags))
+                .load::<(Vec<String>, Vec<String>)>(&*self.conn)?;
+
+            let mut target_tags = HashSet::new();
+            for (added, removed) in tag_changes {
+                for tag in removed {
+                    target_tags.remove(&tag);
+                }
+
+                target_tags.extend(added);
+            }
+
+            let current_tags = pages::table
+                .find(id)
+                .select(pages::dsl::tags)
+                .first::<Vec<String>>(&*self.conn)?;
+
+            // Restoring tags counts as changing them, and the rules are checked when they're set
+            let tags_changed =
+                current_tags.iter().collect::<HashSet<_>>() != target_tags.iter().collect();
+
+            if tags_changed
+                && !self
+                    .roles
+                    .has_permission(wiki_id, user.id(), Permission::Tag)?
+            {
+                warn!(
+                    "User ID {} tried to revert tags on page ID {} without permission",
+                    user.id(),
+                    page_id,
+                );
+
+                return Err(Error::PermissionDenied(Permission::Tag));
+            }
+
+            // Create commit
+            let user_id = user.id();
+            let change_type = ChangeType::Revert;
+
+            let commit = json::to_string(&CommitMessage {
+                wiki_id,
+                page_id,
+                user_id,
+                change_type,
+                target_commit: Some(target_hash.to_string()),
+                slug: None,
+                old_slug: None,
+            })?;
+
+            let info = CommitInfo {
+                username: user.name(),
+                message: &commit,
+            };
+
+            let hash = self.raw_commit(wiki_id, slug, Some(&content), info)?;
+            let model = NewRevision {
+                page_id: page_id.into(),
+                user_id: user_id.into(),
+                message,
+                git_commit: hash.as_ref(),
+                change_type: change_type.into(),
+                target_revision_id: Some(target),
+                old_slug: None,
             };
 
-            let (added_tags, removed_tags) = tag_diff(&current_tags, tags);
+            trace!("Inserting revision {:?} into revisions table", &model);
+            let revision_id = diesel::insert_into(revisions::table)
+                .values(&model)
+                .returning(revisions::dsl::revision_id)
+                .get_result::<RevisionId>(&*self.conn)?;
+
+            if let Some((title, alt_title)) = title {
+                use self::pages::dsl;
+
+                trace!("Restoring title for page");
+                diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                    .set((dsl::title.eq(title), dsl::alt_title.eq(alt_title)))
+                    .execute(&*self.conn)?;
+
+                self.record_title(page_id, revision_id)?;
+            }
+
+            if tags_changed {
+                let mut tags = target_tags
+                    .iter()
+                    .map(|tag| tag.as_str())
+                    .collect::<Vec<_>>();
+
+                self.set_tags(wiki_id, page_id, revision_id, &mut tags, user)?;
+            }
+
+            self.index_page(wiki_id, page_id)?;
+
+            Ok(revision_id)
+        })
+    }
+
+    pub fn tags(&self, commit: PageCommit, tags: &mut [&str]) -> Result<RevisionId> {
+        info!("Starting transaction for page tags");
+
+        let PageCommit {
+            wiki_id,
+            slug,
+            message,
+            user,
+            ..
+        } = commit;
+
+        self.transaction(wiki_id, || {
+            let page_id = self
+                .get_page_id(wiki_id, slug)?
+                .ok_or(Error::PageNotFound)?;
 
             // Create commit
             let user_id = user.id();
@@ -511,6 +1044,8 @@ impl PageService {
                 message,
                 git_commit: hash.as_ref(),
                 change_type: change_type.into(),
+                target_revision_id: None,
+                old_slug: None,
             };
 
             trace!("Inserting revision {:?} into revisions table", &model);
@@ -519,35 +1054,537 @@ impl PageService {
                 .returning(revisions::dsl::revision_id)
                 .get_result::<RevisionId>(&*self.conn)?;
 
-            let model = NewTagChange {
-                revision_id: revision_id.into(),
-                added_tags: &added_tags,
-                removed_tags: &removed_tags,
-            };
+            self.set_tags(wiki_id, page_id, revision_id, tags, user)?;
 
-            trace!("Inserting tag change {:?} into tag history table", &model);
-            diesel::insert_into(tag_history::table)
-                .values(&model)
+            Ok(revision_id)
+        })
+    }
+
+    /// Gets the tagging rules for a wiki.
+    /// Wikis which have never had rules set allow any tags.
+    pub fn get_tag_rules(&self, wiki_id: WikiId) -> Result<TagRules> {
+        debug!("Getting tag rules for wiki ID {}", wiki_id);
+
+        let id: i64 = wiki_id.into();
+        let (allowed_tags, staff_tags) = tag_rules::table
+            .find(id)
+            .select((tag_rules::dsl::allowed_tags, tag_rules::dsl::staff_tags))
+            .first::<(Option<Vec<String>>, bool)>(&*self.conn)
+            .optional()?
+            .unwrap_or((None, false));
+
+        let groups = tag_groups::table
+            .filter(tag_groups::dsl::wiki_id.eq(id))
+            .order_by(tag_groups::dsl::name.asc())
+            .select((
+                tag_groups::dsl::name,
+                tag_groups::dsl::tags,
+                tag_groups::dsl::required,
+            ))
+            .load::<(String, Vec<String>, bool)>(&*self.conn)?
+            .into_iter()
+            .map(|(name, tags, required)| TagGroup {
+                name,
+                tags,
+                required,
+            })
+            .collect();
+
+        Ok(TagRules {
+            allowed_tags,
+            staff_tags,
+            groups,
+        })
+    }
+
+    /// Replaces the tagging rules for a wiki.
+    /// Existing pages aren't checked until the next time their tags change.
+    pub fn set_tag_rules(&self, wiki_id: WikiId, rules: &TagRules) -> Result<()> {
+        info!("Setting tag rules for wiki ID {}: {:?}", wiki_id, rules);
+
+        let id: i64 = wiki_id.into();
+
+        self.conn.transaction::<_, Error, _>(|| {
+            diesel::delete(tag_groups::table.filter(tag_groups::dsl::wiki_id.eq(id)))
                 .execute(&*self.conn)?;
 
-            tags.sort();
+            diesel::delete(tag_rules::table.filter(tag_rules::dsl::wiki_id.eq(id)))
+                .execute(&*self.conn)?;
 
-            trace!("Updating tags for page");
-            diesel::update(pages::table)
-                .set(pages::dsl::tags.eq(&*tags))
+            diesel::insert_into(tag_rules::table)
+                .values((
+                    tag_rules::dsl::wiki_id.eq(id),
+                    tag_rules::dsl::allowed_tags.eq(rules.allowed_tags.as_ref()),
+                    tag_rules::dsl::staff_tags.eq(rules.staff_tags),
+                ))
                 .execute(&*self.conn)?;
 
-            Ok(revision_id)
+            for group in &rules.groups {
+                trace!("Inserting tag group {:?}", group);
+                diesel::insert_into(tag_groups::table)
+                    .values((
+                        tag_groups::dsl::wiki_id.eq(id),
+                        tag_groups::dsl::name.eq(&group.name),
+                        tag_groups::dsl::tags.eq(&group.tags),
+                        tag_groups::dsl::required.eq(group.required),
+                    ))
+                    .execute(&*self.conn)?;
+            }
+
+            Ok(())
         })
     }
 
+    /// Checks new tags for a page against its wiki's rules.
+    fn check_tags(
+        &self,
+        wiki_id: WikiId,
+        old_tags: &[String],
+        tags: &[&str],
+        user: &User,
+    ) -> Result<()> {
+        debug!(
+            "Checking tags {:?} against rules for wiki ID {}",
+            tags, wiki_id
+        );
+
+        let rules = self.get_tag_rules(wiki_id)?;
+        let staff = self
+            .roles
+            .has_permission(wiki_id, user.id(), Permission::ManageTags)?;
+        let violations = rules.check(old_tags, tags, staff);
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            warn!("Tags break {} rules", violations.len());
+
+            Err(Error::TagRulesViolated(violations))
+        }
+    }
+
+    /// Changes the tags for a page, recording the change as part of the given revision.
+    ///
+    /// Every change to a page's tags goes through here, so this is where the new tags
+    /// are checked against the wiki's rules. Nothing is recorded if the tags are unchanged.
+    pub(super) fn set_tags(
+        &self,
+        wiki_id: WikiId,
+        page_id: PageId,
+        revision_id: RevisionId,
+        tags: &mut [&str],
+        user: &User,
+    ) -> Result<()> {
+        use self::pages::dsl;
+
+        trace!("Getting tag difference");
+        let id: i64 = page_id.into();
+        let current_tags = dsl::pages
+            .find(id)
+            .select(dsl::tags)
+            .first::<Vec<String>>(&*self.conn)?;
+
+        self.check_tags(wiki_id, &current_tags, tags, user)?;
+
+        let (added_tags, removed_tags) = tag_diff(&current_tags, tags);
+        if added_tags.is_empty() && removed_tags.is_empty() {
+            return Ok(());
+        }
+
+        let model = NewTagChange {
+            revision_id: revision_id.into(),
+            added_tags: &added_tags,
+            removed_tags: &removed_tags,
+        };
+
+        trace!("Inserting tag change {:?} into tag history table", &model);
+        diesel::insert_into(tag_history::table)
+            .values(&model)
+            .execute(&*self.conn)?;
+
+        tags.sort();
+
+        trace!("Updating tags for page");
+        diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+            .set(dsl::tags.eq(&*tags))
+            .execute(&*self.conn)?;
+
+        Ok(())
+    }
+
+    /// Saves the page's current title as of the given revision.
+    pub(super) fn record_title(&self, page_id: PageId, revision_id: RevisionId) -> Result<()> {
+        let id: i64 = page_id.into();
+        let (title, alt_title) = pages::table
+            .find(id)
+            .select((pages::dsl::title, pages::dsl::alt_title))
+            .first::<(String, Option<String>)>(&*self.conn)?;
+
+        let model = NewTitleChange {
+            revision_id: revision_id.into(),
+            title: &title,
+            alt_title: alt_title.as_deref(),
+        };
+
+        trace!(
+            "Inserting title change {:?} into title history table",
+            &model
+        );
+        diesel::insert_into(title_history::table)
+            .values(&model)
+            .execute(&*self.conn)?;
+
+        Ok(())
+    }
+
+    /// Permanently removes a page, or some of its revisions, from the database
+    /// and the wiki's history.
+    ///
+    /// If `revisions` is `None` the whole page is purged, along with its ratings,
+    /// authors, files and parent links. Otherwise only the given edits, tag changes
+    /// and reverts are removed, along with any reverts back to them, and the page's
+    /// title and tags are recomputed from the revisions which remain.
+    ///
+    /// Later commits are rewritten and their revisions updated to match. Objects
+    /// which are no longer reachable are deleted once the outermost transaction
+    /// is committed. The user must have the `Purge` permission in the page's wiki.
+    pub fn purge(
+        &self,
+        page_id: PageId,
+        revisions: Option<&[RevisionId]>,
+        user: &User,
+        reason: &str,
+    ) -> Result<PurgeId> {
+        info!("Starting transaction for page purge");
+
+        let id: i64 = page_id.into();
+        let (wiki_id, slug) = pages::table
+            .find(id)
+            .select((pages::dsl::wiki_id, pages::dsl::slug))
+            .first::<(WikiId, String)>(&*self.conn)
+            .optional()?
+            .ok_or(Error::PageNotFound)?;
+
+        if !self
+            .roles
+            .has_permission(wiki_id, user.id(), Permission::Purge)?
+        {
+            warn!(
+                "User ID {} tried to purge page ID {} without permission",
+                user.id(),
+                page_id,
+            );
+
+            return Err(Error::PermissionDenied(Permission::Purge));
+        }
+
+        let manager = self.conn.transaction_manager();
+        let outermost = TransactionManager::<PgConnection>::get_transaction_depth(manager) == 0;
+
+        let purge_id = self.transaction(wiki_id, || {
+            let rows = revisions::table
+                .filter(revisions::dsl::page_id.eq(id))
+                .order_by(revisions::dsl::revision_id.asc())
+                .select((
+                    revisions::dsl::revision_id,
+                    revisions::dsl::git_commit,
+                    revisions::dsl::change_type,
+                    revisions::dsl::target_revision_id,
+                ))
+                .load::<(RevisionId, String, String, Option<RevisionId>)>(&*self.conn)?;
+
+            let mut purged = HashSet::new();
+            match revisions {
+                None => purged.extend(rows.iter().map(|(revision_id, ..)| *revision_id)),
+                Some(revisions) => {
+                    if revisions.is_empty() {
+                        return Err(Error::RevisionNotFound);
+                    }
+
+                    for revision_id in revisions {
+                        let (_, _, change_type, _) = rows
+                            .iter()
+                            .find(|(id, ..)| id == revision_id)
+                            .ok_or(Error::RevisionNotFound)?;
+
+                        match ChangeType::try_from(change_type.as_str()) {
+                            Ok(ChangeType::Modify)
+                            | Ok(ChangeType::Tags)
+                            | Ok(ChangeType::Revert) => (),
+                            _ => return Err(Error::CannotPurgeRevision),
+                        }
+
+                        purged.insert(*revision_id);
+                    }
+
+                    // Reverts back to purged revisions would bring the content back
+                    for (revision_id, _, _, target) in &rows {
+                        if let Some(target) = target {
+                            if purged.contains(target) {
+                                purged.insert(*revision_id);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let mut revision_ids = Vec::new();
+            let mut dropped = HashSet::new();
+            for (revision_id, git_commit, ..) in &rows {
+                if purged.contains(revision_id) {
+                    revision_ids.push(revision_id.to_i64());
+                    dropped.insert(GitHash::from_checked(git_commit.as_str()));
+                }
+            }
+
+            // Also catch commits for the page which never made it into the database
+            if revisions.is_none() {
+                for entry in self.get_store(wiki_id, |store| store.get_log())? {
+                    let messages = match CommitMessage::parse(&entry.message) {
+                        Ok(messages) => messages,
+                        Err(_) => continue,
+                    };
+
+                    if messages.iter().any(|message| message.page_id == page_id) {
+                        if messages.len() > 1 {
+                            return Err(Error::SharedCommit);
+                        }
+
+                        dropped.insert(entry.hash);
+                    }
+                }
+            }
+
+            // Dropping a batch commit would take other pages' changes with it
+            trace!("Checking for other revisions sharing purged commits");
+            let dropped_hashes = dropped.iter().map(GitHash::as_str).collect::<Vec<_>>();
+            let shared = revisions::table
+                .filter(revisions::dsl::git_commit.eq_any(&dropped_hashes))
+                .filter(revisions::dsl::revision_id.ne_all(&revision_ids))
+                .select(revisions::dsl::revision_id)
+                .first::<RevisionId>(&*self.conn)
+                .optional()?;
+
+            if shared.is_some() {
+                return Err(Error::SharedCommit);
+            }
+
+            let renames = self.get_renames(wiki_id)?;
+            let rewritten = self.get_store(wiki_id, |store| store.rewrite(&dropped, &renames))?;
+
+            trace!("Deleting {} purged revisions", revision_ids.len());
+            diesel::delete(
+                tag_history::table.filter(tag_history::dsl::revision_id.eq_any(&revision_ids)),
+            )
+            .execute(&*self.conn)?;
+
+            diesel::delete(
+                title_history::table.filter(title_history::dsl::revision_id.eq_any(&revision_ids)),
+            )
+            .execute(&*self.conn)?;
+
+            diesel::delete(
+                revisions::table.filter(revisions::dsl::revision_id.eq_any(&revision_ids)),
+            )
+            .execute(&*self.conn)?;
+
+            if revisions.is_none() {
+                trace!("Deleting purged page and everything attached to it");
+                diesel::delete(ratings::table.filter(ratings::dsl::page_id.eq(id)))
+                    .execute(&*self.conn)?;
+                diesel::delete(ratings_history::table.filter(ratings_history::dsl::page_id.eq(id)))
+                    .execute(&*self.conn)?;
+                diesel::delete(authors::table.filter(authors::dsl::page_id.eq(id)))
+                    .execute(&*self.conn)?;
+                diesel::delete(files::table.filter(files::dsl::page_id.eq(id)))
+                    .execute(&*self.conn)?;
+                diesel::delete(redirects::table.filter(redirects::dsl::page_id.eq(id)))
+                    .execute(&*self.conn)?;
+                diesel::delete(
+                    parents::table.filter(
+                        parents::dsl::page_id
+                            .eq(id)
+                            .or(parents::dsl::parent_page_id.eq(id)),
+                    ),
+                )
+                .execute(&*self.conn)?;
+                diesel::delete(pages::table.find(id)).execute(&*self.conn)?;
+            } else {
+                self.recompute_page(page_id)?;
+                self.index_page(wiki_id, page_id)?;
+            }
+
+            trace!("Updating {} rewritten commits", rewritten.len());
+            let wiki: i64 = wiki_id.into();
+            let wiki_pages = pages::table
+                .filter(pages::dsl::wiki_id.eq(wiki))
+                .select(pages::dsl::page_id);
+
+            for (old_hash, new_hash) in &rewritten {
+                diesel::update(
+                    revisions::table
+                        .filter(revisions::dsl::git_commit.eq(old_hash.as_str()))
+                        .filter(revisions::dsl::page_id.eq_any(wiki_pages)),
+                )
+                .set(revisions::dsl::git_commit.eq(new_hash.as_str()))
+                .execute(&*self.conn)?;
+            }
+
+            let mut git_commits = dropped.iter().map(|hash| hash.as_str()).collect::<Vec<_>>();
+            git_commits.sort();
+
+            let model = NewPurge {
+                wiki_id: wiki,
+                page_id: id,
+                slug: &slug,
+                user_id: user.id().into(),
+                reason,
+                page_removed: revisions.is_none(),
+                revision_ids: &revision_ids,
+                git_commits: &git_commits,
+                commits_rewritten: rewritten.len() as i32,
+            };
+
+            trace!("Inserting purge {:?} into purges table", &model);
+            let purge_id = diesel::insert_into(purges::table)
+                .values(&model)
+                .returning(purges::dsl::purge_id)
+                .get_result::<PurgeId>(&*self.conn)?;
+
+            Ok(purge_id)
+        })?;
+
+        if outermost {
+            if let Err(error) = self.prune(wiki_id) {
+                warn!(
+                    "Unable to prune objects after purge, purged content is still on disk: {}",
+                    error,
+                );
+            }
+        }
+
+        Ok(purge_id)
+    }
+
+    /// Sets a page's title and tags to what its remaining history says they should be.
+    /// Pages without any recorded titles keep their current one.
+    fn recompute_page(&self, page_id: PageId) -> Result<()> {
+        use self::pages::dsl;
+
+        let id: i64 = page_id.into();
+        let title = title_history::table
+            .inner_join(revisions::table)
+            .filter(revisions::dsl::page_id.eq(id))
+            .order_by(revisions::dsl::revision_id.desc())
+            .select((title_history::dsl::title, title_history::dsl::alt_title))
+            .first::<(String, Option<String>)>(&*self.conn)
+            .optional()?;
+
+        if let Some((title, alt_title)) = title {
+            diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+                .set((dsl::title.eq(title), dsl::alt_title.eq(alt_title)))
+                .execute(&*self.conn)?;
+        }
+
+        let tag_changes = tag_history::table
+            .inner_join(revisions::table)
+            .filter(revisions::dsl::page_id.eq(id))
+            .order_by(revisions::dsl::revision_id.asc())
+            .select((tag_history::dsl::added_tags, tag_history::dsl::removed_tags))
+            .load::<(Vec<String>, Vec<String>)>(&*self.conn)?;
+
+        let mut tags = HashSet::new();
+        for (added, removed) in tag_changes {
+            for tag in removed {
+                tags.remove(&tag);
+            }
+
+            tags.extend(added);
+        }
+
+        let mut tags = tags.into_iter().collect::<Vec<_>>();
+        tags.sort();
+
+        diesel::update(dsl::pages.filter(dsl::page_id.eq(id)))
+            .set(dsl::tags.eq(tags))
+            .execute(&*self.conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes objects in the wiki's revision store which are no longer in its history.
+    pub fn prune(&self, wiki_id: WikiId) -> Result<usize> {
+        info!("Pruning revision store for wiki ID {}", wiki_id);
+
+        self.get_store(wiki_id, |store| store.prune())
+    }
+
+    pub fn get_purge_log(&self, wiki_id: WikiId) -> Result<Vec<PurgeRecord>> {
+        info!("Getting purge log for wiki ID {}", wiki_id);
+
+        let id: i64 = wiki_id.into();
+        let rows = purges::table
+            .filter(purges::dsl::wiki_id.eq(id))
+            .order_by(purges::dsl::purge_id.desc())
+            .load::<(
+                PurgeId,
+                WikiId,
+                PageId,
+                String,
+                UserId,
+                String,
+                bool,
+                Vec<i64>,
+                Vec<String>,
+                i32,
+                DateTime<Utc>,
+            )>(&*self.conn)?;
+
+        let records = rows
+            .into_iter()
+            .map(|row| {
+                let (
+                    purge_id,
+                    wiki_id,
+                    page_id,
+                    slug,
+                    user_id,
+                    reason,
+                    page_removed,
+                    revision_ids,
+                    git_commits,
+                    commits_rewritten,
+                    purged_at,
+                ) = row;
+
+                PurgeRecord {
+                    purge_id,
+                    wiki_id,
+                    page_id,
+                    slug,
+                    user_id,
+                    reason,
+                    page_removed,
+                    revision_ids: revision_ids.into_iter().map(RevisionId::from_raw).collect(),
+                    git_commits: git_commits.into_iter().map(GitHash::from_checked).collect(),
+                    commits_rewritten: commits_rewritten as u32,
+                    purged_at,
+                }
+            })
+            .collect();
+
+        Ok(records)
+    }
+
     pub fn check_page(&self, wiki_id: WikiId, slug: &str) -> Result<bool> {
         info!(
             "Checking if page for exists in wiki ID {}, slug {} exists",
             wiki_id, slug,
         );
 
+        let wiki_id: i64 = wiki_id.into();
         let result = pages::table
+            .filter(pages::wiki_id.eq(wiki_id))
             .filter(pages::slug.eq(slug))
             .filter(pages::deleted_at.is_null())
             .select(pages::page_id)
@@ -571,6 +1608,66 @@ impl PageService {
         Ok(page)
     }
 
+    /// Gets the redirect at the given slug, if it leads to a page which exists.
+    pub fn get_redirect(&self, wiki_id: WikiId, slug: &str) -> Result<Option<Redirect>> {
+        info!("Getting redirect for wiki ID {}, slug {}", wiki_id, slug);
+
+        let wiki_id: i64 = wiki_id.into();
+        let redirect = redirects::table
+            .inner_join(pages::table)
+            .filter(redirects::dsl::wiki_id.eq(wiki_id))
+            .filter(redirects::dsl::slug.eq(slug))
+            .filter(pages::dsl::deleted_at.is_null())
+            .select(redirects::all_columns)
+            .first::<Redirect>(&*self.conn)
+            .optional()?;
+
+        Ok(redirect)
+    }
+
+    /// Lists all the redirects in a wiki, including ones to deleted pages.
+    pub fn get_redirects(&self, wiki_id: WikiId) -> Result<Vec<Redirect>> {
+        info!("Getting redirects for wiki ID {}", wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let redirects = redirects::table
+            .filter(redirects::dsl::wiki_id.eq(wiki_id))
+            .order_by(redirects::dsl::slug.asc())
+            .load::<Redirect>(&*self.conn)?;
+
+        Ok(redirects)
+    }
+
+    /// Deletes the redirect at the given slug.
+    /// Returns `false` if there wasn't one.
+    pub fn remove_redirect(&self, wiki_id: WikiId, slug: &str) -> Result<bool> {
+        debug!("Removing redirect for wiki ID {}, slug {}", wiki_id, slug);
+
+        let wiki_id: i64 = wiki_id.into();
+        let count = diesel::delete(
+            redirects::table
+                .filter(redirects::dsl::wiki_id.eq(wiki_id))
+                .filter(redirects::dsl::slug.eq(slug)),
+        )
+        .execute(&*self.conn)?;
+
+        Ok(count > 0)
+    }
+
+    /// Lists the deleted pages in a wiki, most recently deleted first.
+    pub fn get_deleted_pages(&self, wiki_id: WikiId) -> Result<Vec<Page>> {
+        info!("Getting deleted pages for wiki ID {}", wiki_id);
+
+        let wiki_id: i64 = wiki_id.into();
+        let pages = pages::table
+            .filter(pages::wiki_id.eq(wiki_id))
+            .filter(pages::deleted_at.is_not_null())
+            .order_by(pages::deleted_at.desc())
+            .load::<Page>(&*self.conn)?;
+
+        Ok(pages)
+    }
+
     pub fn get_page_by_id(&self, page_id: PageId) -> Result<Option<Page>> {
         info!("Getting page for page ID {}", page_id);
 
@@ -663,6 +1760,38 @@ impl PageService {
         }
     }
 
+    /// Finds a revision of the given page, returning its ID and commit.
+    fn page_revision(
+        &self,
+        page_id: PageId,
+        revision: Either<RevisionId, &GitHash>,
+    ) -> Result<(RevisionId, GitHash)> {
+        use self::revisions::dsl;
+
+        debug!("Getting revision {:?} for page ID {}", revision, page_id);
+
+        let id: i64 = page_id.into();
+        let query = dsl::revisions
+            .filter(dsl::page_id.eq(id))
+            .select((dsl::revision_id, dsl::git_commit))
+            .into_boxed();
+
+        let query = match revision {
+            Left(revision_id) => {
+                let revision_id: i64 = revision_id.into();
+                query.filter(dsl::revision_id.eq(revision_id))
+            }
+            Right(hash) => query.filter(dsl::git_commit.eq(hash.as_str())),
+        };
+
+        let (revision_id, git_commit) = query
+            .first::<(RevisionId, String)>(&*self.conn)
+            .optional()?
+            .ok_or(Error::RevisionNotFound)?;
+
+        Ok((revision_id, GitHash::from_checked(git_commit)))
+    }
+
     fn commit_hash<'a>(
         &self,
         revision: Either<RevisionId, &'a GitHash>,
@@ -698,13 +1827,173 @@ impl PageService {
         slug: &str,
         first: Either<RevisionId, &GitHash>,
         second: Either<RevisionId, &GitHash>,
-    ) -> Result<Box<[u8]>> {
+    ) -> Result<Diff> {
         info!("Getting diff for wiki ID {}, slug {}", wiki_id, slug);
 
         let first = self.commit_hash(first)?;
         let second = self.commit_hash(second)?;
+        let diff = self.get_store(wiki_id, |store| {
+            store.get_diff((slug, &first), (slug, &second))
+        })?;
+
+        Diff::from_porcelain(&diff)
+    }
+
+    /// Gets the slug a page had as of the given revision, following its renames back.
+    fn slug_at(&self, page_id: PageId, revision_id: RevisionId) -> Result<(WikiId, String)> {
+        debug!(
+            "Getting slug for page ID {} at revision ID {}",
+            page_id, revision_id,
+        );
+
+        let id: i64 = page_id.into();
+        let (wiki_id, mut slug) = pages::table
+            .find(id)
+            .select((pages::dsl::wiki_id, pages::dsl::slug))
+            .first::<(WikiId, String)>(&*self.conn)
+            .optional()?
+            .ok_or(Error::PageNotFound)?;
+
+        let rename: &str = ChangeType::Rename.into();
+        let revision_id: i64 = revision_id.into();
+        let renames = revisions::table
+            .filter(revisions::dsl::page_id.eq(id))
+            .filter(revisions::dsl::change_type.eq(rename))
+            .filter(revisions::dsl::revision_id.gt(revision_id))
+            .order_by(revisions::dsl::revision_id.desc())
+            .select((revisions::dsl::git_commit, revisions::dsl::old_slug))
+            .load::<(String, Option<String>)>(&*self.conn)?;
+
+        for (hash, old_slug) in renames {
+            // Renames from before the old slug was recorded are matched by file contents
+            let old_slug = match old_slug {
+                Some(old_slug) => old_slug,
+                None => {
+                    let hash = GitHash::from_checked(hash);
+
+                    self.get_store(wiki_id, |store| store.get_rename(&hash, &slug))?
+                        .ok_or(Error::StaticMsg("rename commit does not match page slug"))?
+                }
+            };
+
+            trace!("Following rename from '{}' to '{}'", old_slug, slug);
+            slug = old_slug;
+        }
+
+        Ok((wiki_id, slug))
+    }
+
+    /// Gets every rename in a wiki as `(old_slug, new_slug)` pairs, by the commit which made it.
+    fn get_renames(&self, wiki_id: WikiId) -> Result<HashMap<GitHash, Vec<(String, String)>>> {
+        debug!("Getting all renames for wiki ID {}", wiki_id);
+
+        let wiki: i64 = wiki_id.into();
+        let rename: &str = ChangeType::Rename.into();
+        let rows = revisions::table
+            .inner_join(pages::table)
+            .filter(pages::dsl::wiki_id.eq(wiki))
+            .filter(revisions::dsl::change_type.eq(rename))
+            .order_by((
+                revisions::dsl::page_id.asc(),
+                revisions::dsl::revision_id.desc(),
+            ))
+            .select((
+                revisions::dsl::page_id,
+                pages::dsl::slug,
+                revisions::dsl::git_commit,
+                revisions::dsl::old_slug,
+            ))
+            .load::<(i64, String, String, Option<String>)>(&*self.conn)?;
+
+        // Each page's renames are followed back from its current slug, like in slug_at()
+        let mut renames = HashMap::new();
+        let mut previous: Option<(i64, String)> = None;
+
+        for (page_id, page_slug, hash, old_slug) in rows {
+            let new_slug = match previous {
+                Some((id, ref slug)) if id == page_id => slug.clone(),
+                _ => page_slug,
+            };
+
+            let hash = GitHash::from_checked(hash);
+            let old_slug = match old_slug {
+                Some(old_slug) => old_slug,
+                None => self
+                    .get_store(wiki_id, |store| store.get_rename(&hash, &new_slug))?
+                    .ok_or(Error::StaticMsg("rename commit does not match page slug"))?,
+            };
+
+            previous = Some((page_id, old_slug.clone()));
+            renames
+                .entry(hash)
+                .or_insert_with(Vec::new)
+                .push((old_slug, new_slug));
+        }
+
+        Ok(renames)
+    }
+
+    /// Gets the contents of a page at one of its revisions, even if it has since been renamed.
+    pub fn get_page_version_by_id(
+        &self,
+        page_id: PageId,
+        revision: Either<RevisionId, &GitHash>,
+    ) -> Result<Option<Box<[u8]>>> {
+        info!("Getting specific page version for page ID {}", page_id);
 
-        self.get_store(wiki_id, |store| store.get_diff(slug, &first, &second))
+        let (revision_id, hash) = self.page_revision(page_id, revision)?;
+        let (wiki_id, slug) = self.slug_at(page_id, revision_id)?;
+
+        self.get_store(wiki_id, |store| store.get_page_version(&slug, &hash))
+    }
+
+    /// Gets the diff between two revisions of a page, even if it was renamed in between.
+    pub fn get_diff_by_id(
+        &self,
+        page_id: PageId,
+        first: Either<RevisionId, &GitHash>,
+        second: Either<RevisionId, &GitHash>,
+    ) -> Result<Diff> {
+        info!("Getting diff for page ID {}", page_id);
+
+        let (first_id, first) = self.page_revision(page_id, first)?;
+        let (second_id, second) = self.page_revision(page_id, second)?;
+        let (wiki_id, first_slug) = self.slug_at(page_id, first_id)?;
+        let (_, second_slug) = self.slug_at(page_id, second_id)?;
+
+        let diff = self.get_store(wiki_id, |store| {
+            store.get_diff((&first_slug, &first), (&second_slug, &second))
+        })?;
+
+        Diff::from_porcelain(&diff)
+    }
+
+    /// Gets the blame for a page as of one of its revisions.
+    /// Renames are followed, so lines keep their authors after a page is moved.
+    pub fn get_blame_at(
+        &self,
+        page_id: PageId,
+        revision: Either<RevisionId, &GitHash>,
+    ) -> Result<Option<Blame>> {
+        info!("Getting blame for page ID {} at {:?}", page_id, revision);
+
+        let (revision_id, hash) = self.page_revision(page_id, revision)?;
+        let (wiki_id, slug) = self.slug_at(page_id, revision_id)?;
+
+        self.get_store(wiki_id, |store| store.get_blame(&slug, Some(hash)))
+    }
+
+    /// Gets the wiki a revision was made in, and the user who made it.
+    pub fn get_revision_owner(&self, revision_id: RevisionId) -> Result<(WikiId, UserId)> {
+        let id: i64 = revision_id.into();
+        let result = revisions::table
+            .inner_join(pages::table)
+            .filter(revisions::dsl::revision_id.eq(id))
+            .select((pages::dsl::wiki_id, revisions::dsl::user_id))
+            .first::<(WikiId, UserId)>(&*self.conn)
+            .optional()?;
+
+        result.ok_or(Error::RevisionNotFound)
     }
 
     pub fn edit_revision(&self, revision_id: RevisionId, message: &str) -> Result<()> {
@@ -720,6 +2009,572 @@ impl PageService {
         Ok(())
     }
 
+    pub fn get_history(&self, page_id: PageId, query: HistoryQuery) -> Result<HistoryPage> {
+        info!("Getting history for page ID {}: {:?}", page_id, query);
+
+        let limit = match query.limit {
+            0 => DEFAULT_HISTORY_LIMIT,
+            limit => limit.min(MAX_HISTORY_LIMIT),
+        };
+
+        let id: i64 = page_id.into();
+        let mut statement = revisions::table
+            .left_join(tag_history::table)
+            .filter(revisions::dsl::page_id.eq(id))
+            .select((
+                revisions::dsl::revision_id,
+                revisions::dsl::user_id,
+                revisions::dsl::message,
+                revisions::dsl::git_commit,
+                revisions::dsl::change_type,
+                revisions::dsl::created_at,
+                revisions::dsl::target_revision_id,
+                tag_history::dsl::added_tags.nullable(),
+                tag_history::dsl::removed_tags.nullable(),
+            ))
+            .into_boxed();
+
+        if let Some(user_id) = query.user_id {
+            let user_id: i64 = user_id.into();
+            statement = statement.filter(revisions::dsl::user_id.eq(user_id));
+        }
+
+        if let Some(change_type) = query.change_type {
+            let change_type: &str = change_type.into();
+            statement = statement.filter(revisions::dsl::change_type.eq(change_type));
+        }
+
+        if let Some(start) = query.start {
+            statement = statement.filter(revisions::dsl::created_at.ge(start));
+        }
+
+        if let Some(end) = query.end {
+            statement = statement.filter(revisions::dsl::created_at.lt(end));
+        }
+
+        if let Some(cursor) = query.cursor {
+            let cursor: i64 = cursor.into();
+            statement = statement.filter(revisions::dsl::revision_id.lt(cursor));
+        }
+
+        // Fetch one extra row to tell if there are more results
+        let rows = statement
+            .order_by(revisions::dsl::revision_id.desc())
+            .limit(i64::from(limit) + 1)
+            .load::<(
+                RevisionId,
+                UserId,
+                String,
+                String,
+                String,
+                DateTime<Utc>,
+                Option<RevisionId>,
+                Option<Vec<String>>,
+                Option<Vec<String>>,
+            )>(&*self.conn)?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let (
+                revision_id,
+                user_id,
+                message,
+                git_commit,
+                change_type,
+                created_at,
+                target_revision_id,
+                added,
+                removed,
+            ) = row;
+
+            let change_type = ChangeType::try_from(change_type.as_str())
+                .map_err(|_| Error::StaticMsg("invalid change type in revisions table"))?;
+
+            entries.push(HistoryEntry {
+                revision_id,
+                user_id,
+                message,
+                change_type,
+                git_commit: GitHash::from_checked(git_commit),
+                created_at,
+                target_revision_id,
+                added_tags: added.unwrap_or_default(),
+                removed_tags: removed.unwrap_or_default(),
+            });
+        }
+
+        let next = if entries.len() > limit as usize {
+            entries.truncate(limit as usize);
+            entries.last().map(|entry| entry.revision_id)
+        } else {
+            None
+        };
+
+        Ok(HistoryPage { entries, next })
+    }
+
+    fn user_exists(&self, user_id: UserId) -> Result<bool> {
+        let id: i64 = user_id.into();
+        let result = users::table
+            .find(id)
+            .select(users::dsl::user_id)
+            .first::<UserId>(&*self.conn)
+            .optional()?;
+
+        Ok(result.is_some())
+    }
+
+    /// Compares the revisions of a wiki against its git history, reporting any disagreements.
+    ///
+    /// If `repair` is set, problems which can be fixed without guessing are: change types
+    /// are corrected to match their commits, untracked commits for existing pages are given
+    /// revisions, and files left behind by deleted pages are removed. Revisions whose commits
+    /// are missing can only be reported.
+    pub fn check_consistency(&self, wiki_id: WikiId, repair: bool) -> Result<ConsistencyReport> {
+        const UNTRACKED_MESSAGE: &str = "Revision restored from git history";
+        const DELETED_MESSAGE: &str = "Removed file left behind by deleted page";
+
+        info!(
+            "Checking consistency for wiki ID {} (repair: {})",
+            wiki_id, repair,
+        );
+
+        self.transaction(wiki_id, || {
+            let log = self.get_store(wiki_id, |store| store.get_log())?;
+            let id: i64 = wiki_id.into();
+
+            trace!("Getting all revisions for wiki");
+            let revisions = revisions::table
+                .inner_join(pages::table)
+                .filter(pages::dsl::wiki_id.eq(id))
+                .order_by(revisions::dsl::revision_id.asc())
+                .select((
+                    revisions::dsl::revision_id,
+                    revisions::dsl::page_id,
+                    revisions::dsl::git_commit,
+                    revisions::dsl::change_type,
+                ))
+                .load::<(RevisionId, PageId, String, String)>(&*self.conn)?;
+
+            let mut report = ConsistencyReport {
+                commits_checked: log.len(),
+                revisions_checked: revisions.len(),
+                issues: Vec::new(),
+            };
+
+            trace!("Reading commit messages");
+            let mut commits = HashMap::new();
+            for (i, entry) in log.iter().enumerate() {
+                match CommitMessage::parse(&entry.message) {
+                    Ok(messages)
+                        if !messages.is_empty()
+                            && messages.iter().all(|message| message.wiki_id == wiki_id) =>
+                    {
+                        commits.insert(&entry.hash, messages);
+                    }
+                    // The initial commit isn't for any page
+                    _ if i == 0 => (),
+                    _ => {
+                        let git_commit = entry.hash.clone();
+                        report.push(Inconsistency::InvalidCommit { git_commit }, false);
+                    }
+                }
+            }
+
+            trace!("Checking revisions against commits");
+            let mut tracked = HashSet::new();
+            for (revision_id, page_id, git_commit, change_type) in revisions {
+                let git_commit = GitHash::from_checked(git_commit);
+                let change_type = ChangeType::try_from(change_type.as_str())
+                    .map_err(|_| Error::StaticMsg("invalid change type in revisions table"))?;
+
+                let messages = match commits.get(&git_commit) {
+                    Some(messages) => messages,
+                    None => {
+                        // Invalid commits have already been reported
+                        if !log.iter().any(|entry| entry.hash == git_commit) {
+                            let inconsistency = Inconsistency::MissingCommit {
+                                revision_id,
+                                git_commit,
+                            };
+
+                            report.push(inconsistency, false);
+                        }
+
+                        continue;
+                    }
+                };
+
+                // Batch commits have a message for each page they changed
+                let message = messages
+                    .iter()
+                    .find(|message| message.page_id == page_id)
+                    .unwrap_or(&messages[0]);
+
+                tracked.insert((git_commit.clone(), message.page_id));
+
+                if message.page_id != page_id {
+                    let inconsistency = Inconsistency::PageMismatch {
+                        revision_id,
+                        git_commit: git_commit.clone(),
+                        database: page_id,
+                        git: message.page_id,
+                    };
+
+                    report.push(inconsistency, false);
+                }
+
+                if message.change_type != change_type {
+                    if repair {
+                        use self::revisions::dsl;
+
+                        let id: i64 = revision_id.into();
+                        let value: &str = message.change_type.into();
+                        diesel::update(dsl::revisions.filter(dsl::revision_id.eq(id)))
+                            .set(dsl::change_type.eq(value))
+                            .execute(&*self.conn)?;
+                    }
+
+                    let inconsistency = Inconsistency::ChangeTypeMismatch {
+                        revision_id,
+                        git_commit,
+                        database: change_type,
+                        git: message.change_type,
+                    };
+
+                    report.push(inconsistency, repair);
+                }
+            }
+
+            trace!("Checking for commits without revisions");
+            for entry in &log {
+                let messages = match commits.get(&entry.hash) {
+                    Some(messages) => messages,
+                    None => continue,
+                };
+
+                for message in messages {
+                    if tracked.contains(&(entry.hash.clone(), message.page_id)) {
+                        continue;
+                    }
+
+                    let page_exists = self
+                        .get_page_by_id(message.page_id)?
+                        .map(|page| page.wiki_id)
+                        == Some(wiki_id);
+
+                    let repaired = repair && page_exists && self.user_exists(message.user_id)?;
+                    if repaired {
+                        let change_type: &str = message.change_type.into();
+                        let page_id: i64 = message.page_id.into();
+                        let user_id: i64 = message.user_id.into();
+
+                        trace!("Inserting revision for untracked commit {}", entry.hash);
+                        diesel::insert_into(revisions::table)
+                            .values((
+                                revisions::dsl::created_at.eq(entry.created_at),
+                                revisions::dsl::page_id.eq(page_id),
+                                revisions::dsl::user_id.eq(user_id),
+                                revisions::dsl::message.eq(UNTRACKED_MESSAGE),
+                                revisions::dsl::git_commit.eq(entry.hash.as_str()),
+                                revisions::dsl::change_type.eq(change_type),
+                            ))
+                            .execute(&*self.conn)?;
+                    }
+
+                    let inconsistency = Inconsistency::UntrackedCommit {
+                        git_commit: entry.hash.clone(),
+                        page_id: message.page_id,
+                        change_type: message.change_type,
+                    };
+
+                    report.push(inconsistency, repaired);
+                }
+            }
+
+            trace!("Checking for files belonging to deleted pages");
+            let deleted_pages = pages::table
+                .filter(pages::dsl::wiki_id.eq(id))
+                .filter(pages::dsl::deleted_at.is_not_null())
+                .order_by(pages::dsl::page_id.asc())
+                .select((pages::dsl::page_id, pages::dsl::slug))
+                .load::<(PageId, String)>(&*self.conn)?;
+
+            let mut checked_slugs = HashSet::new();
+            for (page_id, slug) in deleted_pages {
+                // The slug may have been reused by a new page
+                if !checked_slugs.insert(slug.clone()) || self.get_page(wiki_id, &slug)?.is_some() {
+                    continue;
+                }
+
+                if self
+                    .get_store(wiki_id, |store| store.get_page(&slug))?
+                    .is_none()
+                {
+                    continue;
+                }
+
+                if repair {
+                    let page: i64 = page_id.into();
+                    let (user_id, username) = revisions::table
+                        .inner_join(users::table)
+                        .filter(revisions::dsl::page_id.eq(page))
+                        .order_by(revisions::dsl::revision_id.desc())
+                        .select((users::dsl::user_id, users::dsl::name))
+                        .first::<(UserId, String)>(&*self.conn)?;
+
+                    let change_type = ChangeType::Delete;
+                    let commit = self.commit_data(wiki_id, page_id, user_id, change_type)?;
+                    let info = CommitInfo {
+                        username: &username,
+                        message: &commit,
+                    };
+
+                    let hash = self.get_store::<_, GitHash>(wiki_id, |store| {
+                        trace!("Committing removal of leftover file to repository");
+                        match store.remove(&slug, info)? {
+                            Some(hash) => Ok(hash),
+                            None => Err(Error::PageNotFound),
+                        }
+                    })?;
+
+                    let model = NewRevision {
+                        page_id: page,
+                        user_id: user_id.into(),
+                        message: DELETED_MESSAGE,
+                        git_commit: hash.as_ref(),
+                        change_type: change_type.into(),
+                        target_revision_id: None,
+                        old_slug: None,
+                    };
+
+                    trace!("Inserting revision {:?} into revisions table", &model);
+                    diesel::insert_into(revisions::table)
+                        .values(&model)
+                        .execute(&*self.conn)?;
+                }
+
+                report.push(Inconsistency::DeletedPageExists { page_id, slug }, repair);
+            }
+
+            Ok(report)
+        })
+    }
+
+    /// Recreates the pages and revisions of a wiki by replaying its git history.
+    ///
+    /// This is intended for disaster recovery, so the wiki must not have any pages.
+    /// Page IDs are kept, but revisions are given new IDs. Anything which is only
+    /// stored in the database is not guessed at: missing titles and tags are reported,
+    /// and revision messages are left empty.
+    pub fn rebuild(&self, wiki_id: WikiId) -> Result<RebuildReport> {
+        info!("Rebuilding pages and revisions for wiki ID {}", wiki_id);
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let id: i64 = wiki_id.into();
+
+            trace!("Checking that wiki has no pages");
+            let existing = pages::table
+                .filter(pages::dsl::wiki_id.eq(id))
+                .select(pages::dsl::page_id)
+                .first::<PageId>(&*self.conn)
+                .optional()?;
+
+            if existing.is_some() {
+                return Err(Error::WikiNotEmpty);
+            }
+
+            let log = self.get_store(wiki_id, |store| store.get_log())?;
+            let mut report = RebuildReport::default();
+            let mut slugs = HashMap::new();
+            let mut revision_ids = HashMap::new();
+
+            for (i, entry) in log.iter().enumerate() {
+                let git_commit = || entry.hash.clone();
+                let messages = match CommitMessage::parse(&entry.message) {
+                    Ok(messages)
+                        if !messages.is_empty()
+                            && messages.iter().all(|message| message.wiki_id == wiki_id) =>
+                    {
+                        messages
+                    }
+                    // The initial commit isn't for any page
+                    _ if i == 0 => continue,
+                    _ => {
+                        let git_commit = git_commit();
+                        report.push(RebuildIssue::InvalidCommit { git_commit });
+                        continue;
+                    }
+                };
+
+                for message in messages {
+                    let CommitMessage {
+                        page_id,
+                        user_id,
+                        change_type,
+                        target_commit,
+                        slug,
+                        old_slug,
+                        ..
+                    } = message;
+
+                    if !self.user_exists(user_id)? {
+                        let git_commit = git_commit();
+                        report.push(RebuildIssue::MissingUser {
+                            git_commit,
+                            user_id,
+                        });
+                        continue;
+                    }
+
+                    // Created or renamed pages should have exactly one new file,
+                    // unless the commit was a batch which records each page's slug
+                    let new_slug = match (slug, entry.added.as_slice()) {
+                        (Some(slug), _) => Some(slug),
+                        (None, [slug]) => Some(slug.clone()),
+                        (None, _) => None,
+                    };
+
+                    let page: i64 = page_id.into();
+                    let known = match (change_type, new_slug.as_ref()) {
+                        (ChangeType::Create, Some(slug)) if !slugs.contains_key(&page_id) => {
+                            trace!("Inserting page ID {} with slug '{}'", page_id, slug);
+                            diesel::insert_into(pages::table)
+                                .values((
+                                    pages::dsl::page_id.eq(page),
+                                    pages::dsl::wiki_id.eq(id),
+                                    pages::dsl::slug.eq(slug),
+                                    pages::dsl::title.eq(""),
+                                    pages::dsl::created_at.eq(entry.created_at),
+                                ))
+                                .execute(&*self.conn)?;
+
+                            slugs.insert(page_id, slug.clone());
+                            report.pages += 1;
+                            report.push(RebuildIssue::MissingTitle {
+                                page_id,
+                                slug: slug.clone(),
+                            });
+                            true
+                        }
+                        (ChangeType::Create, _) => false,
+                        _ if !slugs.contains_key(&page_id) => false,
+                        (ChangeType::Rename, Some(slug)) => {
+                            use self::pages::dsl;
+
+                            trace!("Renaming page ID {} to '{}'", page_id, slug);
+                            diesel::update(dsl::pages.filter(dsl::page_id.eq(page)))
+                                .set(dsl::slug.eq(slug))
+                                .execute(&*self.conn)?;
+
+                            slugs.insert(page_id, slug.clone());
+                            true
+                        }
+                        (ChangeType::Rename, None) => false,
+                        (ChangeType::Delete, _) => {
+                            use self::pages::dsl;
+
+                            trace!("Marking page ID {} as deleted", page_id);
+                            diesel::update(dsl::pages.filter(dsl::page_id.eq(page)))
+                                .set(dsl::deleted_at.eq(entry.created_at))
+                                .execute(&*self.conn)?;
+                            true
+                        }
+                        (ChangeType::Restore, Some(slug)) => {
+                            use self::pages::dsl;
+
+                            trace!("Restoring page ID {} as '{}'", page_id, slug);
+                            diesel::update(dsl::pages.filter(dsl::page_id.eq(page)))
+                                .set((
+                                    dsl::slug.eq(slug),
+                                    dsl::deleted_at.eq(None::<DateTime<Utc>>),
+                                ))
+                                .execute(&*self.conn)?;
+
+                            slugs.insert(page_id, slug.clone());
+                            true
+                        }
+                        (ChangeType::Restore, None) => false,
+                        (ChangeType::Modify, _)
+                        | (ChangeType::Tags, _)
+                        | (ChangeType::Revert, _) => true,
+                    };
+
+                    if !known {
+                        let git_commit = git_commit();
+                        report.push(RebuildIssue::UnknownPage {
+                            git_commit,
+                            page_id,
+                        });
+                        continue;
+                    }
+
+                    // Reverts refer to the revision they restored, which must have been rebuilt
+                    let target = match target_commit {
+                        Some(hash) => match revision_ids.get(&(hash, page_id)) {
+                            Some(&revision_id) => Some(revision_id),
+                            None => {
+                                let git_commit = git_commit();
+                                report.push(RebuildIssue::InvalidCommit { git_commit });
+                                continue;
+                            }
+                        },
+                        None if change_type == ChangeType::Revert => {
+                            let git_commit = git_commit();
+                            report.push(RebuildIssue::InvalidCommit { git_commit });
+                            continue;
+                        }
+                        None => None,
+                    };
+
+                    let user: i64 = user_id.into();
+                    let change: &str = change_type.into();
+                    let target: Option<i64> = target.map(RevisionId::into);
+                    let old_slug = old_slug.filter(|_| change_type == ChangeType::Rename);
+
+                    trace!("Inserting revision for commit {}", entry.hash);
+                    let revision_id = diesel::insert_into(revisions::table)
+                        .values((
+                            revisions::dsl::created_at.eq(entry.created_at),
+                            revisions::dsl::page_id.eq(page),
+                            revisions::dsl::user_id.eq(user),
+                            revisions::dsl::message.eq(""),
+                            revisions::dsl::git_commit.eq(entry.hash.as_str()),
+                            revisions::dsl::change_type.eq(change),
+                            revisions::dsl::target_revision_id.eq(target),
+                            revisions::dsl::old_slug.eq(old_slug),
+                        ))
+                        .returning(revisions::dsl::revision_id)
+                        .get_result::<RevisionId>(&*self.conn)?;
+
+                    revision_ids.insert((entry.hash.to_string(), page_id), revision_id);
+                    report.revisions += 1;
+
+                    if change_type == ChangeType::Tags {
+                        report.push(RebuildIssue::MissingTags {
+                            page_id,
+                            revision_id,
+                        });
+                    }
+                }
+            }
+
+            // Page IDs were inserted directly, so the sequence needs to catch up
+            trace!("Updating page ID sequence");
+            diesel::sql_query(
+                "SELECT setval(
+                    pg_get_serial_sequence('pages', 'page_id'),
+                    GREATEST(MAX(page_id), nextval(pg_get_serial_sequence('pages', 'page_id')))
+                ) FROM pages",
+            )
+            .execute(&*self.conn)?;
+
+            self.reindex(wiki_id)?;
+
+            Ok(report)
+        })
+    }
+
     pub fn set_domain(&self, wiki_id: WikiId, new_domain: &str) -> Result<()> {
         self.get_store(wiki_id, |store| {
             store.set_domain(new_domain);
@@ -0,0 +1,313 @@
+/*
+ * page/tags.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{BatchChange, PageId, PageService, RevisionId};
+use crate::schema::pages;
+use crate::service_prelude::*;
+use crate::user::User;
+use crate::wiki::WikiId;
+
+/// Tags starting with this character are staff-only on wikis which enable it.
+pub const STAFF_TAG_PREFIX: char = '_';
+
+/// The pages changed by a wiki-wide tag operation, such as renaming a tag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagUpdateReport {
+    /// Whether this was a dry run, in which case nothing was changed.
+    pub dry_run: bool,
+
+    /// Each page which had its tags changed, in order of page ID.
+    pub pages: Vec<TagUpdatePage>,
+}
+
+/// A page which had its tags changed by a wiki-wide tag operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagUpdatePage {
+    pub page_id: PageId,
+    pub slug: String,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+
+    /// The revision recording the change, or `None` for dry runs.
+    pub revision_id: Option<RevisionId>,
+}
+
+/// The tagging policy for a wiki, checked whenever pages are created or have their tags set.
+///
+/// The default allows any tags at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagRules {
+    /// If set, the only tags pages may have.
+    pub allowed_tags: Option<Vec<String>>,
+
+    /// Whether tags starting with `_` may only be added or removed by staff,
+    /// meaning users with the `ManageTags` permission.
+    pub staff_tags: bool,
+
+    /// Sets of tags which pages may not have more than one of.
+    pub groups: Vec<TagGroup>,
+}
+
+/// A named set of mutually exclusive tags, such as object classes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagGroup {
+    pub name: String,
+    pub tags: Vec<String>,
+
+    /// Whether pages must have exactly one of the tags, rather than at most one.
+    pub required: bool,
+}
+
+/// A tagging rule broken by a change to a page's tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagRuleViolation {
+    /// The tag isn't in the wiki's list of allowed tags.
+    NotAllowed { tag: String },
+
+    /// The tag is staff-only, but was added or removed by someone else.
+    StaffOnly { tag: String },
+
+    /// None of the tags in a required group are present.
+    MissingFromGroup { group: String },
+
+    /// More than one tag in a group is present.
+    ConflictingTags { group: String, tags: Vec<String> },
+}
+
+impl TagRules {
+    /// Checks a change to a page's tags, returning every rule which is broken.
+    ///
+    /// Staff-only tags a page already had are left alone, so only tags
+    /// which were added or removed need `staff` to be set.
+    pub fn check(
+        &self,
+        old_tags: &[String],
+        new_tags: &[&str],
+        staff: bool,
+    ) -> Vec<TagRuleViolation> {
+        let mut violations = Vec::new();
+
+        if let Some(ref allowed) = self.allowed_tags {
+            for &tag in new_tags {
+                if !allowed.iter().any(|name| name == tag) {
+                    violations.push(TagRuleViolation::NotAllowed { tag: tag.into() });
+                }
+            }
+        }
+
+        if self.staff_tags && !staff {
+            let added = new_tags
+                .iter()
+                .copied()
+                .filter(|&tag| !old_tags.iter().any(|name| name == tag));
+
+            let removed = old_tags
+                .iter()
+                .map(String::as_str)
+                .filter(|tag| !new_tags.contains(tag));
+
+            for tag in added.chain(removed) {
+                if tag.starts_with(STAFF_TAG_PREFIX) {
+                    violations.push(TagRuleViolation::StaffOnly { tag: tag.into() });
+                }
+            }
+        }
+
+        for group in &self.groups {
+            let present = group
+                .tags
+                .iter()
+                .filter(|tag| new_tags.contains(&tag.as_str()))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            match present.len() {
+                0 if group.required => {
+                    violations.push(TagRuleViolation::MissingFromGroup {
+                        group: group.name.clone(),
+                    });
+                }
+                0 | 1 => (),
+                _ => {
+                    violations.push(TagRuleViolation::ConflictingTags {
+                        group: group.name.clone(),
+                        tags: present,
+                    });
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+impl PageService {
+    /// Renames a tag on every page in a wiki which has it.
+    /// Fails if any page already has the new tag, use [`merge_tags`] for that instead.
+    ///
+    /// [`merge_tags`]: #method.merge_tags
+    pub fn rename_tag(
+        &self,
+        wiki_id: WikiId,
+        old_tag: &str,
+        new_tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        info!(
+            "Renaming tag '{}' to '{}' in wiki ID {} (dry run: {})",
+            old_tag, new_tag, wiki_id, dry_run,
+        );
+
+        self.transaction(wiki_id, || {
+            if old_tag == new_tag || !self.tagged_pages(wiki_id, &[new_tag])?.is_empty() {
+                return Err(Error::TagExists);
+            }
+
+            self.update_tags(wiki_id, &[old_tag], Some(new_tag), message, user, dry_run)
+        })
+    }
+
+    /// Replaces several tags with a single one on every page in a wiki which has any of them.
+    /// The target tag may already be in use.
+    pub fn merge_tags(
+        &self,
+        wiki_id: WikiId,
+        old_tags: &[&str],
+        new_tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        info!(
+            "Merging tags {:?} into '{}' in wiki ID {} (dry run: {})",
+            old_tags, new_tag, wiki_id, dry_run,
+        );
+
+        self.update_tags(wiki_id, old_tags, Some(new_tag), message, user, dry_run)
+    }
+
+    /// Removes a tag from every page in a wiki which has it.
+    pub fn delete_tag(
+        &self,
+        wiki_id: WikiId,
+        tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        info!(
+            "Deleting tag '{}' in wiki ID {} (dry run: {})",
+            tag, wiki_id, dry_run,
+        );
+
+        self.update_tags(wiki_id, &[tag], None, message, user, dry_run)
+    }
+
+    /// Gets the ID, slug and tags of every existing page with any of the given tags.
+    fn tagged_pages(
+        &self,
+        wiki_id: WikiId,
+        tags: &[&str],
+    ) -> Result<Vec<(PageId, String, Vec<String>)>> {
+        use self::pages::dsl;
+
+        let id: i64 = wiki_id.into();
+        let pages = dsl::pages
+            .filter(dsl::wiki_id.eq(id))
+            .filter(dsl::deleted_at.is_null())
+            .filter(dsl::tags.overlaps_with(tags))
+            .order_by(dsl::page_id.asc())
+            .select((dsl::page_id, dsl::slug, dsl::tags))
+            .load::<(PageId, String, Vec<String>)>(&*self.conn)?;
+
+        Ok(pages)
+    }
+
+    /// Replaces the given tags on every page which has any of them,
+    /// committing all of the changes together as one batch.
+    fn update_tags(
+        &self,
+        wiki_id: WikiId,
+        old_tags: &[&str],
+        new_tag: Option<&str>,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        self.transaction(wiki_id, || {
+            let mut report = TagUpdateReport {
+                dry_run,
+                pages: Vec::new(),
+            };
+
+            for (page_id, slug, old) in self.tagged_pages(wiki_id, old_tags)? {
+                let mut new = old
+                    .iter()
+                    .filter(|tag| !old_tags.contains(&tag.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                if let Some(tag) = new_tag {
+                    if !new.iter().any(|name| name == tag) {
+                        new.push(tag.to_owned());
+                    }
+                }
+
+                new.sort();
+                report.pages.push(TagUpdatePage {
+                    page_id,
+                    slug,
+                    old_tags: old,
+                    new_tags: new,
+                    revision_id: None,
+                });
+            }
+
+            if dry_run || report.pages.is_empty() {
+                return Ok(report);
+            }
+
+            let tags = report
+                .pages
+                .iter()
+                .map(|page| page.new_tags.iter().map(String::as_str).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+
+            let changes = report
+                .pages
+                .iter()
+                .zip(&tags)
+                .map(|(page, tags)| BatchChange::Tags {
+                    slug: &page.slug,
+                    tags,
+                })
+                .collect::<Vec<_>>();
+
+            let revisions = self.batch(wiki_id, &changes, message, user)?;
+            for (page, (_, revision_id)) in report.pages.iter_mut().zip(revisions) {
+                page.revision_id = Some(revision_id);
+            }
+
+            Ok(report)
+        })
+    }
+}
@@ -22,30 +22,61 @@ use super::models::*;
 use super::Password;
 use crate::user::UserId;
 use crate::Result;
+use argon2::{Config, ThreadMode, Variant, Version};
 use crypto::scrypt::{scrypt, ScryptParams};
 use crypto::util::fixed_time_eq;
 use rand::{rngs::OsRng, RngCore};
 
-const PARAM_LOGN: u8 = 6;
-const PARAM_R: u32 = 8;
-const PARAM_P: u32 = 16;
-
 type Hash = [u8; 32];
 type Salt = [u8; 16];
 
-lazy_static! {
-    static ref PARAMS: ScryptParams = ScryptParams::new(PARAM_LOGN, PARAM_R, PARAM_P);
+/// The algorithm and cost parameters used to produce a password hash.
+///
+/// The meaning of each parameter depends on the algorithm:
+/// * scrypt: `logn` is log<sub>2</sub>(N), `param_r` is r, `param_p` is p.
+/// * Argon2id: `logn` is log<sub>2</sub> of the memory cost in KiB,
+///   `param_r` is the number of iterations, `param_p` is the number of lanes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Params {
+    pub algorithm: Algorithm,
+    pub logn: u8,
+    pub param_r: u32,
+    pub param_p: u32,
 }
 
+/// The parameters used for hashing passwords outside of tests.
+pub const PRODUCTION_PARAMS: Params = Params {
+    algorithm: Algorithm::Argon2id,
+    logn: 16,
+    param_r: 3,
+    param_p: 4,
+};
+
+/// The parameters all new passwords are hashed with.
+///
+/// Existing hashes using anything else are replaced on the user's next successful login.
+#[cfg(not(test))]
+pub const CURRENT_PARAMS: Params = PRODUCTION_PARAMS;
+
+// Debug builds of argon2 are slow, so tests use much lower costs
+#[cfg(test)]
+pub const CURRENT_PARAMS: Params = Params {
+    algorithm: Algorithm::Argon2id,
+    logn: 8,
+    param_r: 1,
+    param_p: 1,
+};
+
 #[inline]
 fn make_model<'a>(user_id: UserId, hash: &'a [u8], salt: &'a [u8]) -> NewPassword<'a> {
     NewPassword {
         user_id: user_id.into(),
         hash,
         salt,
-        logn: PARAM_LOGN.into(),
-        param_r: PARAM_R as i32,
-        param_p: PARAM_P as i32,
+        logn: CURRENT_PARAMS.logn.into(),
+        param_r: CURRENT_PARAMS.param_r as i32,
+        param_p: CURRENT_PARAMS.param_p as i32,
+        algorithm: CURRENT_PARAMS.algorithm.into(),
     }
 }
 
@@ -60,6 +91,47 @@ fn new_hash() -> Hash {
     [0; 32]
 }
 
+/// Hashes the password with the given parameters.
+/// Returns false if the parameters are not valid for the algorithm.
+pub fn hash_password(params: &Params, password: &[u8], salt: &[u8], hash: &mut Hash) -> bool {
+    match params.algorithm {
+        Algorithm::Scrypt => {
+            let scrypt_params = ScryptParams::new(params.logn, params.param_r, params.param_p);
+
+            scrypt(password, salt, &scrypt_params, hash);
+            true
+        }
+        Algorithm::Argon2id => {
+            let mem_cost = match 1u32.checked_shl(params.logn.into()) {
+                Some(mem_cost) => mem_cost,
+                None => return false,
+            };
+
+            let config = Config {
+                variant: Variant::Argon2id,
+                version: Version::Version13,
+                mem_cost,
+                time_cost: params.param_r,
+                lanes: params.param_p,
+                thread_mode: ThreadMode::from_threads(params.param_p),
+                hash_length: hash.len() as u32,
+                ..Config::default()
+            };
+
+            match argon2::hash_raw(password, salt, &config) {
+                Ok(output) => {
+                    hash.copy_from_slice(&output);
+                    true
+                }
+                Err(error) => {
+                    warn!("Invalid Argon2 parameters {:?}: {}", params, error);
+                    false
+                }
+            }
+        }
+    }
+}
+
 pub fn new_password<F>(user_id: UserId, password: &[u8], f: F) -> Result<()>
 where
     F: FnOnce(NewPassword<'_>) -> Result<()>,
@@ -69,15 +141,17 @@ where
     let salt = random_salt();
     let mut hash = new_hash();
 
-    scrypt(password, &salt, &*PARAMS, &mut hash);
+    let valid = hash_password(&CURRENT_PARAMS, password, &salt, &mut hash);
+    assert!(valid, "Current password hashing parameters are invalid");
 
     trace!("Handing password model to consumer");
     let model = make_model(user_id, &hash, &salt);
     f(model)
 }
 
-pub fn check_password(record: &Password, password: &[u8]) -> bool {
-    let params = ScryptParams::new(record.logn(), record.param_r(), record.param_p());
+/// Checks the password against the stored hash.
+/// Fails if the record names an algorithm this version doesn't know.
+pub fn check_password(record: &Password, password: &[u8]) -> Result<bool> {
     let mut calculated = new_hash();
 
     // If the hash length ever changes we'll need to use a dynamically-allocated Vec instead.
@@ -88,6 +162,15 @@ pub fn check_password(record: &Password, password: &[u8]) -> bool {
     );
 
     debug!("Checking password validity");
-    scrypt(password, record.salt(), &params, &mut calculated);
-    fixed_time_eq(record.hash(), &calculated)
+    let params = record.params()?;
+    let valid = hash_password(&params, password, record.salt(), &mut calculated)
+        && fixed_time_eq(record.hash(), &calculated);
+
+    Ok(valid)
+}
+
+/// Determines if this password was hashed with an outdated algorithm or parameters.
+#[inline]
+pub fn needs_rehash(record: &Password) -> Result<bool> {
+    Ok(record.params()? != CURRENT_PARAMS)
 }
@@ -30,4 +30,5 @@ pub use self::service::PasswordService;
 
 use self::blacklist::build_blacklist;
 use self::crypto::*;
+use self::models::Algorithm;
 use self::service::Password;
@@ -19,6 +19,37 @@
  */
 
 use crate::schema::passwords;
+use crate::StdResult;
+use std::convert::TryFrom;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    Scrypt,
+    Argon2id,
+}
+
+impl From<Algorithm> for &'static str {
+    fn from(algorithm: Algorithm) -> &'static str {
+        match algorithm {
+            Algorithm::Scrypt => "scrypt",
+            Algorithm::Argon2id => "argon2id",
+        }
+    }
+}
+
+impl TryFrom<&'_ str> for Algorithm {
+    type Error = ();
+
+    fn try_from(value: &str) -> StdResult<Self, ()> {
+        let case = match value {
+            "scrypt" => Algorithm::Scrypt,
+            "argon2id" => Algorithm::Argon2id,
+            _ => return Err(()),
+        };
+
+        Ok(case)
+    }
+}
 
 #[derive(Debug, Insertable, AsChangeset)]
 #[table_name = "passwords"]
@@ -29,4 +60,5 @@ pub struct NewPassword<'a> {
     pub logn: i16,
     pub param_r: i32,
     pub param_p: i32,
+    pub algorithm: &'static str,
 }
@@ -18,7 +18,7 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-use super::{build_blacklist, check_password, new_password};
+use super::{build_blacklist, check_password, needs_rehash, new_password, Algorithm, Params};
 use crate::schema::passwords;
 use crate::service_prelude::*;
 use std::collections::HashSet;
@@ -35,6 +35,7 @@ pub struct Password {
     logn: i16,
     param_r: i32,
     param_p: i32,
+    algorithm: String,
 }
 
 impl Password {
@@ -47,6 +48,7 @@ impl Password {
         logn: i16,
         param_r: i32,
         param_p: i32,
+        algorithm: &str,
     ) -> Self {
         Password {
             user_id,
@@ -55,6 +57,7 @@ impl Password {
             logn,
             param_r,
             param_p,
+            algorithm: algorithm.into(),
         }
     }
 
@@ -88,6 +91,24 @@ impl Password {
             .try_into()
             .expect("Stored param_r field is out of bounds")
     }
+
+    #[inline]
+    pub fn algorithm(&self) -> Result<Algorithm> {
+        let value = self.algorithm.as_str();
+
+        Algorithm::try_from(value)
+            .map_err(|_| Error::StaticMsg("password algorithm in database invalid"))
+    }
+
+    #[inline]
+    pub fn params(&self) -> Result<Params> {
+        Ok(Params {
+            algorithm: self.algorithm()?,
+            logn: self.logn(),
+            param_r: self.param_r(),
+            param_p: self.param_p(),
+        })
+    }
 }
 
 pub struct PasswordService {
@@ -128,7 +149,10 @@ impl PasswordService {
 
     pub fn set(&self, user_id: UserId, password: &str) -> Result<()> {
         self.verify_password(password)?;
+        self.store(user_id, password)
+    }
 
+    fn store(&self, user_id: UserId, password: &str) -> Result<()> {
         new_password(user_id, password.as_bytes(), |model| {
             diesel::insert_into(passwords::table)
                 .values(&model)
@@ -168,11 +192,28 @@ impl PasswordService {
             .optional()?;
 
         let record = record.ok_or(Error::AuthenticationFailed)?;
-        if check_password(&record, password.as_bytes()) {
-            Ok(())
-        } else {
-            Err(Error::AuthenticationFailed)
+        if !check_password(&record, password.as_bytes())? {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        // Now that we have the plaintext, upgrade old hashes to the current parameters.
+        // This isn't fatal, the user can still log in with the old hash.
+        if needs_rehash(&record)? {
+            info!(
+                "Rehashing password for user ID {} (was {:?})",
+                user_id,
+                record.params()?,
+            );
+
+            if let Err(error) = self.store(user_id, password) {
+                warn!(
+                    "Unable to rehash password for user ID {}: {}",
+                    user_id, error
+                );
+            }
         }
+
+        Ok(())
     }
 }
 
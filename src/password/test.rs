@@ -18,8 +18,12 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-use super::{check_password, new_password, Password};
+use super::{
+    check_password, hash_password, needs_rehash, new_password, Password, PRODUCTION_PARAMS,
+};
 use crate::user::UserId;
+use crate::Error;
+use crypto::scrypt::{scrypt, ScryptParams};
 
 #[test]
 fn crypto() {
@@ -31,6 +35,7 @@ fn crypto() {
     let mut hash = Vec::new();
     let mut salt = Vec::new();
     let (mut logn, mut param_r, mut param_p) = (0, 0, 0);
+    let mut algorithm = "";
 
     new_password(user, b"apples and bananas", |model| {
         hash.extend_from_slice(model.hash);
@@ -39,17 +44,20 @@ fn crypto() {
         logn = model.logn;
         param_r = model.param_r;
         param_p = model.param_p;
+        algorithm = model.algorithm;
 
         Ok(())
     })
     .unwrap();
 
-    let record = Password::new(user, hash, salt, logn, param_r, param_p);
+    let record = Password::new(user, hash, salt, logn, param_r, param_p, algorithm);
+    assert_eq!(algorithm, "argon2id");
+    assert!(!needs_rehash(&record).unwrap());
 
     macro_rules! check {
         ($password:expr, $expected:expr) => {{
             println!("Checking password: '{}'", $password);
-            let actual = check_password(&record, $password.as_bytes());
+            let actual = check_password(&record, $password.as_bytes()).unwrap();
             assert_eq!(actual, $expected, "Password result mismatch");
         }};
     }
@@ -58,3 +66,64 @@ fn crypto() {
     check!("apples and bananas", true);
     check!("apples and banana", false);
 }
+
+#[test]
+fn crypto_scrypt() {
+    // Hashes from before Argon2id was added
+    let user = UserId::from_raw(0);
+    let salt = vec![7; 16];
+    let mut hash = vec![0; 32];
+
+    scrypt(
+        b"apples and bananas",
+        &salt,
+        &ScryptParams::new(6, 8, 16),
+        &mut hash,
+    );
+
+    let record = Password::new(user, hash, salt, 6, 8, 16, "scrypt");
+    assert!(needs_rehash(&record).unwrap());
+    assert!(check_password(&record, b"apples and bananas").unwrap());
+    assert!(!check_password(&record, b"apples and banana").unwrap());
+}
+
+#[test]
+fn crypto_unknown_algorithm() {
+    let user = UserId::from_raw(0);
+    let record = Password::new(user, vec![0; 32], vec![7; 16], 6, 8, 16, "md5");
+
+    match check_password(&record, b"apples and bananas") {
+        Err(Error::StaticMsg(_)) => (),
+        result => panic!("Unknown algorithm was accepted: {:?}", result),
+    }
+
+    match needs_rehash(&record) {
+        Err(Error::StaticMsg(_)) => (),
+        result => panic!("Unknown algorithm was accepted: {:?}", result),
+    }
+}
+
+#[test]
+fn crypto_production() {
+    // Tests normally use cheaper parameters, so make sure the real ones work too
+    let user = UserId::from_raw(0);
+    let salt = vec![7; 16];
+    let mut hash = [0; 32];
+
+    let valid = hash_password(&PRODUCTION_PARAMS, b"apples and bananas", &salt, &mut hash);
+    assert!(valid, "Production password hashing parameters are invalid");
+
+    let record = Password::new(
+        user,
+        hash.to_vec(),
+        salt,
+        PRODUCTION_PARAMS.logn.into(),
+        PRODUCTION_PARAMS.param_r as i32,
+        PRODUCTION_PARAMS.param_p as i32,
+        PRODUCTION_PARAMS.algorithm.into(),
+    );
+
+    assert_eq!(record.params().unwrap(), PRODUCTION_PARAMS);
+    assert!(check_password(&record, b"apples and bananas").unwrap());
+    assert!(!check_password(&record, b"apples and banana").unwrap());
+}
@@ -31,6 +31,11 @@ pub struct Rating {
 }
 
 impl Rating {
+    #[inline]
+    pub(crate) fn new(score: i64, votes: u32) -> Self {
+        Rating { score, votes }
+    }
+
     #[inline]
     pub fn score(&self) -> i64 {
         self.score
@@ -0,0 +1,32 @@
+/*
+ * revision/backend.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::path::PathBuf;
+
+/// Which storage backend to use for page revisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevisionBackend {
+    /// Stores each wiki as a git repository within the given directory.
+    Git(PathBuf),
+
+    /// Keeps all revisions in memory, discarding them when the server exits.
+    /// Intended for tests and ephemeral staging instances.
+    Memory,
+}
@@ -0,0 +1,249 @@
+/*
+ * revision/blame/build.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+use crate::revision::git::{Commit, ObjectStore, Signature};
+use crate::revision::myers::{self, Edit};
+use crate::revision::GitHash;
+use crate::{Error, Result};
+
+/// A file as it existed at a particular commit.
+#[derive(Debug)]
+struct Origin {
+    hash: GitHash,
+    commit: Commit,
+    path: String,
+    blob: GitHash,
+    lines: Vec<Box<[u8]>>,
+}
+
+/// Where each line of the final file came from.
+#[derive(Debug, Copy, Clone)]
+struct Attribution {
+    origin: usize,
+    old_index: usize,
+}
+
+impl Blame {
+    /// Builds the blame for a file by walking the first-parent history from the given commit.
+    /// Whole-file renames are followed, like `git blame` does.
+    ///
+    /// Returns `None` if the commit does not exist or the file is not present in it.
+    pub fn build<S>(objects: &S, start: &GitHash, path: &str) -> Result<Option<Self>>
+    where
+        S: ObjectStore + ?Sized,
+    {
+        debug!("Building blame for '{}' at commit {}", path, start);
+
+        let mut origin = match load_origin(objects, start, path)? {
+            Some(origin) => origin,
+            None => return Ok(None),
+        };
+
+        let final_lines = origin.lines.clone();
+
+        // Pairs of (index in current origin, index in final file)
+        let mut pending: Vec<(usize, usize)> = (0..final_lines.len()).map(|i| (i, i)).collect();
+        let mut attributions: Vec<Option<Attribution>> = vec![None; final_lines.len()];
+        let mut origins = Vec::new();
+        let mut previous = Vec::new();
+
+        while !pending.is_empty() {
+            let parent = match origin.commit.parent() {
+                Some(hash) => find_parent_origin(objects, &origin, hash)?,
+                None => None,
+            };
+
+            let origin_index = origins.len();
+            let parent = match parent {
+                Some(parent) => parent,
+                None => {
+                    // File was created here, so it's responsible for everything left
+                    for &(old_index, final_index) in &pending {
+                        attributions[final_index] = Some(Attribution {
+                            origin: origin_index,
+                            old_index,
+                        });
+                    }
+
+                    origins.push(origin);
+                    previous.push(None);
+                    break;
+                }
+            };
+
+            if parent.blob == origin.blob {
+                trace!("File unchanged in commit {}", origin.hash);
+
+                origin = parent;
+                continue;
+            }
+
+            // Lines not present in the parent were introduced by this commit
+            let mut parent_index = vec![None; origin.lines.len()];
+            for edit in myers::diff(&parent.lines, &origin.lines) {
+                if let Edit::Equal { old, new } = edit {
+                    parent_index[new] = Some(old);
+                }
+            }
+
+            let mut blamed = false;
+            pending.retain(|&(old_index, final_index)| {
+                if parent_index[old_index].is_some() {
+                    return true;
+                }
+
+                attributions[final_index] = Some(Attribution {
+                    origin: origin_index,
+                    old_index,
+                });
+
+                blamed = true;
+                false
+            });
+
+            for (old_index, _) in &mut pending {
+                *old_index = parent_index[*old_index].unwrap();
+            }
+
+            let parent_hash = parent.hash.clone();
+            let previous_origin = std::mem::replace(&mut origin, parent);
+            if blamed {
+                origins.push(previous_origin);
+                previous.push(Some(parent_hash));
+            }
+        }
+
+        // Group contiguous lines from the same commit
+        let mut groups: Vec<BlameGroup> = Vec::new();
+        let mut last: Option<Attribution> = None;
+
+        for (final_index, line) in final_lines.into_iter().enumerate() {
+            let attribution = attributions[final_index].expect("Line was never attributed");
+            let origin = &origins[attribution.origin];
+            let blame_line = BlameLine {
+                commit: origin.hash.clone(),
+                old_lineno: attribution.old_index as u32 + 1,
+                new_lineno: final_index as u32 + 1,
+                line,
+            };
+
+            match last {
+                Some(prev)
+                    if prev.origin == attribution.origin
+                        && prev.old_index + 1 == attribution.old_index =>
+                {
+                    groups.last_mut().unwrap().lines.push(blame_line);
+                }
+                _ => groups.push(BlameGroup {
+                    author: blame_author(&origin.commit.author)?,
+                    committer: blame_author(&origin.commit.committer)?,
+                    summary: origin.commit.summary().to_string(),
+                    previous: previous[attribution.origin].clone(),
+                    lines: vec![blame_line],
+                }),
+            }
+
+            last = Some(attribution);
+        }
+
+        Ok(Some(Blame { groups }))
+    }
+}
+
+fn load_origin<S>(objects: &S, hash: &GitHash, path: &str) -> Result<Option<Origin>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commit = match objects.read_commit(hash)? {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+
+    let tree = objects.read_tree(&commit.tree)?;
+    let blob = match tree.get(path) {
+        Some(entry) => entry.hash.clone(),
+        None => return Ok(None),
+    };
+
+    let lines = split_lines(&objects.read_blob(&blob)?);
+
+    Ok(Some(Origin {
+        hash: hash.clone(),
+        commit,
+        path: path.to_string(),
+        blob,
+        lines,
+    }))
+}
+
+/// Finds the file in the parent commit, following an exact rename if needed.
+fn find_parent_origin<S>(objects: &S, origin: &Origin, parent: &GitHash) -> Result<Option<Origin>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commit = objects
+        .read_commit(parent)?
+        .ok_or(Error::StaticMsg("parent commit missing from repository"))?;
+
+    let tree = objects.read_tree(&commit.tree)?;
+    if tree.get(&origin.path).is_some() {
+        return load_origin(objects, parent, &origin.path);
+    }
+
+    let current_tree = objects.read_tree(&origin.commit.tree)?;
+    let renamed = tree
+        .entries()
+        .iter()
+        .find(|entry| entry.hash == origin.blob && current_tree.get(&entry.name).is_none());
+
+    match renamed {
+        Some(entry) => {
+            trace!(
+                "Following rename from '{}' to '{}'",
+                entry.name,
+                origin.path
+            );
+
+            load_origin(objects, parent, &entry.name)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Splits the file into lines, without their line terminators.
+fn split_lines(content: &[u8]) -> Vec<Box<[u8]>> {
+    if content.is_empty() {
+        return Vec::new();
+    }
+
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+
+    content.split(|&c| c == b'\n').map(Box::from).collect()
+}
+
+fn blame_author(signature: &Signature) -> Result<BlameAuthor> {
+    // Porcelain output includes the angle brackets
+    Ok(BlameAuthor {
+        name: signature.name.clone(),
+        email: format!("<{}>", signature.email),
+        time: signature.datetime()?,
+    })
+}
@@ -0,0 +1,35 @@
+/*
+ * revision/change.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// A single change to a page, made as part of a batch commit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageChange<'a> {
+    /// Creates or edits a page to have the specified contents.
+    Write { slug: &'a str, content: &'a [u8] },
+
+    /// Moves a page to a slug which isn't in use.
+    Rename {
+        old_slug: &'a str,
+        new_slug: &'a str,
+    },
+
+    /// Removes an existing page.
+    Remove { slug: &'a str },
+}
@@ -0,0 +1,99 @@
+/*
+ * revision/diff/object.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// A piece of a line in a word diff.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", content = "text", rename_all = "lowercase")]
+pub enum DiffChunk {
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+impl DiffChunk {
+    #[inline]
+    pub fn text(&self) -> &str {
+        match self {
+            DiffChunk::Context(text) | DiffChunk::Added(text) | DiffChunk::Removed(text) => text,
+        }
+    }
+
+    /// Determines if this chunk's text is in the old version of the file.
+    #[inline]
+    pub fn is_old(&self) -> bool {
+        !matches!(self, DiffChunk::Added(_))
+    }
+
+    /// Determines if this chunk's text is in the new version of the file.
+    #[inline]
+    pub fn is_new(&self) -> bool {
+        !matches!(self, DiffChunk::Removed(_))
+    }
+}
+
+/// A line of a word diff, as it would be displayed.
+///
+/// The line numbers are where the line starts in each version of the file,
+/// or `None` if it has no text from that version. Word diffs do not record which
+/// old line unchanged words within a changed block came from, so `old_line` is
+/// approximate when lines are split or joined.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub old_line: Option<u32>,
+    pub new_line: Option<u32>,
+    pub chunks: Vec<DiffChunk>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub heading: Option<String>,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    pub path: String,
+
+    /// Abbreviated hash of the old blob, or `None` if the file was created.
+    pub old_blob: Option<String>,
+
+    /// Abbreviated hash of the new blob, or `None` if the file was removed.
+    pub new_blob: Option<String>,
+
+    pub binary: bool,
+    pub hunks: Vec<DiffHunk>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub files: Vec<DiffFile>,
+}
+
+impl Diff {
+    /// Determines if there were no changes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
@@ -0,0 +1,242 @@
+/*
+ * revision/diff/parse.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+use crate::{Error, Result};
+use regex::bytes::Regex;
+use std::str;
+
+lazy_static! {
+    static ref HUNK_REGEX: Regex = Regex::new(
+        r"(?x)
+        ^@@
+        \s-(?P<old_start>[0-9]+)(,(?P<old_lines>[0-9]+))?
+        \s\+(?P<new_start>[0-9]+)(,(?P<new_lines>[0-9]+))?
+        \s@@
+        (\s(?P<heading>.*))?
+        $
+    "
+    )
+    .unwrap();
+    static ref INDEX_REGEX: Regex = Regex::new(
+        r"(?x)
+        ^index
+        \s(?P<old_blob>[0-9a-f]+)\.\.(?P<new_blob>[0-9a-f]+)
+        (\s[0-7]+)?
+        $
+    "
+    )
+    .unwrap();
+}
+
+/// Tracks the current position within a hunk.
+#[derive(Debug)]
+struct HunkState {
+    hunk: DiffHunk,
+    old_line: u32,
+    new_line: u32,
+    chunks: Vec<DiffChunk>,
+    last_prefix: u8,
+}
+
+impl HunkState {
+    fn new(hunk: DiffHunk) -> Self {
+        // Empty ranges give the line before where the hunk would be
+        let start = |start, lines| if lines == 0 { start + 1 } else { start };
+
+        HunkState {
+            old_line: start(hunk.old_start, hunk.old_lines),
+            new_line: start(hunk.new_start, hunk.new_lines),
+            hunk,
+            chunks: Vec::new(),
+            last_prefix: b' ',
+        }
+    }
+
+    fn push_chunk(&mut self, prefix: u8, text: &[u8]) {
+        let text = String::from_utf8_lossy(text).into_owned();
+        let chunk = match prefix {
+            b'+' => DiffChunk::Added(text),
+            b'-' => DiffChunk::Removed(text),
+            _ => DiffChunk::Context(text),
+        };
+
+        self.chunks.push(chunk);
+        self.last_prefix = prefix;
+    }
+
+    fn end_line(&mut self) {
+        // Lines without any text take the side of whatever came before them
+        let (old, new) = if self.chunks.is_empty() {
+            (self.last_prefix != b'+', self.last_prefix != b'-')
+        } else {
+            (
+                self.chunks.iter().any(DiffChunk::is_old),
+                self.chunks.iter().any(DiffChunk::is_new),
+            )
+        };
+
+        self.hunk.lines.push(DiffLine {
+            old_line: if old { Some(self.old_line) } else { None },
+            new_line: if new { Some(self.new_line) } else { None },
+            chunks: self.chunks.drain(..).collect(),
+        });
+
+        // The newline belongs to the chunk it was printed after
+        match self.last_prefix {
+            b'+' => self.new_line += 1,
+            b'-' => self.old_line += 1,
+            _ => {
+                self.old_line += 1;
+                self.new_line += 1;
+            }
+        }
+    }
+
+    fn finish(mut self) -> DiffHunk {
+        if !self.chunks.is_empty() {
+            self.end_line();
+        }
+
+        self.hunk
+    }
+}
+
+// Diff implementation
+
+impl Diff {
+    pub fn from_porcelain(raw_bytes: &[u8]) -> Result<Self> {
+        const DIFF_ERROR: Error =
+            Error::StaticMsg("unexpected or mismatched input line in diff data");
+
+        macro_rules! number {
+            ($captures:expr, $name:expr) => {
+                match $captures.name($name) {
+                    // Unwrap is safe because the value is regex-verified
+                    Some(mtch) => str::from_utf8(mtch.as_bytes()).unwrap().parse().unwrap(),
+                    None => 1,
+                }
+            };
+        }
+
+        debug!("Parsing git diff porcelain ({} bytes)", raw_bytes.len());
+
+        let mut files = Vec::new();
+        let mut file: Option<DiffFile> = None;
+        let mut hunk: Option<HunkState> = None;
+
+        let finish_file = |file: &mut Option<DiffFile>, hunk: &mut Option<HunkState>| {
+            if let Some(mut file) = file.take() {
+                file.hunks.extend(hunk.take().map(HunkState::finish));
+                Some(file)
+            } else {
+                None
+            }
+        };
+
+        for line in raw_bytes.split(|&b| b == b'\n') {
+            if let Some(rest) = line.strip_prefix(b"diff --git a/") {
+                trace!("Starting new file in diff");
+                files.extend(finish_file(&mut file, &mut hunk));
+
+                // Both paths are the same, separated by " b/"
+                if rest.len() < 3 || rest.len() % 2 == 0 {
+                    return Err(DIFF_ERROR);
+                }
+
+                let path = &rest[..(rest.len() - 3) / 2];
+                file = Some(DiffFile {
+                    path: String::from_utf8_lossy(path).into_owned(),
+                    old_blob: None,
+                    new_blob: None,
+                    binary: false,
+                    hunks: Vec::new(),
+                });
+                continue;
+            }
+
+            let file = match file.as_mut() {
+                Some(file) => file,
+                None if line.is_empty() => continue,
+                None => return Err(DIFF_ERROR),
+            };
+
+            if let Some(captures) = HUNK_REGEX.captures(line) {
+                trace!("Starting new hunk in diff");
+                file.hunks.extend(hunk.take().map(HunkState::finish));
+
+                let heading = captures
+                    .name("heading")
+                    .map(|mtch| String::from_utf8_lossy(mtch.as_bytes()).into_owned());
+
+                hunk = Some(HunkState::new(DiffHunk {
+                    old_start: number!(captures, "old_start"),
+                    old_lines: number!(captures, "old_lines"),
+                    new_start: number!(captures, "new_start"),
+                    new_lines: number!(captures, "new_lines"),
+                    heading,
+                    lines: Vec::new(),
+                }));
+                continue;
+            }
+
+            if let Some(state) = hunk.as_mut() {
+                match line.first() {
+                    Some(&b'~') => state.end_line(),
+                    Some(&prefix) if b" +-".contains(&prefix) => {
+                        state.push_chunk(prefix, &line[1..]);
+                    }
+                    Some(&b'\\') => trace!("Skipping end of file marker"),
+                    None => (),
+                    Some(_) => return Err(DIFF_ERROR),
+                }
+
+                continue;
+            }
+
+            // File headers
+            if let Some(captures) = INDEX_REGEX.captures(line) {
+                let blob = |name| {
+                    let hash = str::from_utf8(&captures[name]).unwrap();
+
+                    if hash.bytes().all(|c| c == b'0') {
+                        None
+                    } else {
+                        Some(String::from(hash))
+                    }
+                };
+
+                file.old_blob = blob("old_blob");
+                file.new_blob = blob("new_blob");
+            } else if line.starts_with(b"Binary files ") {
+                file.binary = true;
+            } else {
+                trace!(
+                    "Skipping diff header line: {}",
+                    String::from_utf8_lossy(line),
+                );
+            }
+        }
+
+        files.extend(finish_file(&mut file, &mut hunk));
+
+        Ok(Diff { files })
+    }
+}
@@ -0,0 +1,86 @@
+/*
+ * revision/git/index.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Writes the git index (staging area) so that the working tree looks clean to stock git.
+//!
+//! Only the file size and modification time are recorded. Git treats any other
+//! stat mismatches as "possibly changed" and compares contents instead.
+
+use crate::revision::GitHash;
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub path: String,
+    pub mode: u32,
+    pub hash: GitHash,
+    pub size: u32,
+    pub mtime: Option<SystemTime>,
+}
+
+pub fn serialize_index(entries: &mut [IndexEntry]) -> Vec<u8> {
+    entries.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+
+    let mut data = Vec::new();
+    data.extend_from_slice(b"DIRC");
+    data.extend_from_slice(&2u32.to_be_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+    for entry in entries.iter() {
+        let start = data.len();
+        let (seconds, nanos) = entry
+            .mtime
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|duration| (duration.as_secs() as u32, duration.subsec_nanos()))
+            .unwrap_or((0, 0));
+
+        // ctime and mtime
+        for _ in 0..2 {
+            data.extend_from_slice(&seconds.to_be_bytes());
+            data.extend_from_slice(&nanos.to_be_bytes());
+        }
+
+        // dev, ino
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&entry.mode.to_be_bytes());
+
+        // uid, gid
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&entry.size.to_be_bytes());
+        data.extend_from_slice(&entry.hash.to_bytes());
+
+        let flags = entry.path.len().min(0xfff) as u16;
+        data.extend_from_slice(&flags.to_be_bytes());
+        data.extend_from_slice(entry.path.as_bytes());
+
+        // Entries are NUL-padded to a multiple of eight bytes
+        let padding = 8 - (data.len() - start) % 8;
+        data.resize(data.len() + padding, 0);
+    }
+
+    let mut hasher = Sha1::new();
+    let mut checksum = [0; 20];
+    hasher.input(&data);
+    hasher.result(&mut checksum);
+    data.extend_from_slice(&checksum);
+    data
+}
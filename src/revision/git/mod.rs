@@ -0,0 +1,34 @@
+/*
+ * revision/git/mod.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+mod index;
+mod object;
+mod odb;
+mod pack;
+mod repo;
+mod store;
+
+pub use self::object::*;
+pub use self::repo::Repository;
+pub use self::store::{MemoryObjectStore, ObjectStore};
+
+use self::index::{serialize_index, IndexEntry};
+use self::odb::ObjectDatabase;
+use self::pack::Pack;
@@ -0,0 +1,384 @@
+/*
+ * revision/git/object.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::revision::GitHash;
+use crate::{Error, Result, StdResult};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use std::convert::TryFrom;
+use std::str;
+
+pub const MODE_FILE: u32 = 0o100_644;
+pub const MODE_DIRECTORY: u32 = 0o040_000;
+
+const OBJECT_ERROR: Error = Error::StaticMsg("malformed git object");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    /// Gets the kind from the type number used in packfiles.
+    pub fn from_pack_type(value: u8) -> Option<Self> {
+        let kind = match value {
+            1 => ObjectKind::Commit,
+            2 => ObjectKind::Tree,
+            3 => ObjectKind::Blob,
+            4 => ObjectKind::Tag,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
+impl From<ObjectKind> for &'static str {
+    fn from(kind: ObjectKind) -> &'static str {
+        match kind {
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tag => "tag",
+        }
+    }
+}
+
+impl TryFrom<&'_ str> for ObjectKind {
+    type Error = ();
+
+    fn try_from(value: &str) -> StdResult<Self, ()> {
+        let kind = match value {
+            "commit" => ObjectKind::Commit,
+            "tree" => ObjectKind::Tree,
+            "blob" => ObjectKind::Blob,
+            "tag" => ObjectKind::Tag,
+            _ => return Err(()),
+        };
+
+        Ok(kind)
+    }
+}
+
+/// Prepends the `<kind> <length>\0` header git stores before object contents.
+pub fn object_header(kind: ObjectKind, length: usize) -> Vec<u8> {
+    let kind: &str = kind.into();
+
+    format!("{} {}\0", kind, length).into_bytes()
+}
+
+/// Computes the hash of an object without storing it.
+pub fn hash_object(kind: ObjectKind, data: &[u8]) -> GitHash {
+    let mut hasher = Sha1::new();
+    let mut digest = [0; 20];
+
+    hasher.input(&object_header(kind, data.len()));
+    hasher.input(data);
+    hasher.result(&mut digest);
+
+    GitHash::from_bytes(&digest).unwrap()
+}
+
+// Trees
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: u32,
+    pub name: String,
+    pub hash: GitHash,
+}
+
+impl TreeEntry {
+    #[inline]
+    pub fn is_directory(&self) -> bool {
+        self.mode == MODE_DIRECTORY
+    }
+
+    // Git sorts directories as if their names had a trailing slash.
+    fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_bytes().to_vec();
+
+        if self.is_directory() {
+            key.push(b'/');
+        }
+
+        key
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    #[inline]
+    pub fn new() -> Self {
+        Tree::default()
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut data = data;
+
+        while !data.is_empty() {
+            let space = find(data, b' ').ok_or(OBJECT_ERROR)?;
+            let mode = str::from_utf8(&data[..space]).map_err(|_| OBJECT_ERROR)?;
+            let mode = u32::from_str_radix(mode, 8).map_err(|_| OBJECT_ERROR)?;
+            data = &data[space + 1..];
+
+            let nul = find(data, b'\0').ok_or(OBJECT_ERROR)?;
+            let name = str::from_utf8(&data[..nul]).map_err(|_| OBJECT_ERROR)?;
+            let name = name.to_string();
+            data = &data[nul + 1..];
+
+            if data.len() < 20 {
+                return Err(OBJECT_ERROR);
+            }
+
+            let hash = GitHash::from_bytes(&data[..20]).unwrap();
+            data = &data[20..];
+
+            entries.push(TreeEntry { mode, name, hash });
+        }
+
+        Ok(Tree { entries })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for entry in &self.entries {
+            data.extend_from_slice(format!("{:o} {}\0", entry.mode, entry.name).as_bytes());
+            data.extend_from_slice(&entry.hash.to_bytes());
+        }
+
+        data
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[TreeEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TreeEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Adds or replaces a file entry, keeping git's required ordering.
+    pub fn insert(&mut self, name: &str, hash: GitHash) {
+        self.remove(name);
+        self.entries.push(TreeEntry {
+            mode: MODE_FILE,
+            name: name.to_string(),
+            hash,
+        });
+
+        self.entries.sort_by_key(|entry| entry.sort_key());
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<TreeEntry> {
+        let index = self.entries.iter().position(|entry| entry.name == name)?;
+
+        Some(self.entries.remove(index))
+    }
+}
+
+// Commits
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub time: i64,
+    pub offset: i32,
+}
+
+impl Signature {
+    /// Creates a signature for the current time, in UTC.
+    pub fn now(name: &str, email: &str) -> Self {
+        // Names containing these would produce an unparseable commit
+        let name = name.replace(&['<', '>', '\n'][..], "");
+
+        Signature {
+            name,
+            email: email.to_string(),
+            time: Utc::now().timestamp(),
+            offset: 0,
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        let open = value.find('<').ok_or(OBJECT_ERROR)?;
+        let close = value.find('>').ok_or(OBJECT_ERROR)?;
+
+        let name = value[..open].trim_end();
+        let email = &value[open + 1..close];
+        let mut parts = value[close + 1..].split_whitespace();
+
+        let time = parts
+            .next()
+            .and_then(|part| part.parse().ok())
+            .ok_or(OBJECT_ERROR)?;
+
+        let offset = parts.next().ok_or(OBJECT_ERROR)?;
+        if offset.len() != 5 {
+            return Err(OBJECT_ERROR);
+        }
+
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let hours: i32 = offset[1..3].parse().map_err(|_| OBJECT_ERROR)?;
+        let minutes: i32 = offset[3..5].parse().map_err(|_| OBJECT_ERROR)?;
+
+        Ok(Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            offset: sign * (hours * 60 + minutes),
+        })
+    }
+
+    fn serialize(&self) -> String {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.abs();
+
+        format!(
+            "{} <{}> {} {}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            sign,
+            offset / 60,
+            offset % 60,
+        )
+    }
+
+    pub fn datetime(&self) -> Result<DateTime<FixedOffset>> {
+        const TIME_ERROR: Error = Error::StaticMsg("commit timestamp out of range");
+
+        let offset = FixedOffset::east_opt(self.offset * 60).ok_or(TIME_ERROR)?;
+
+        offset
+            .timestamp_opt(self.time, 0)
+            .single()
+            .ok_or(TIME_ERROR)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: GitHash,
+    pub parents: Vec<GitHash>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl Commit {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let data = str::from_utf8(data).map_err(|_| OBJECT_ERROR)?;
+        let (headers, message) = match data.find("\n\n") {
+            Some(idx) => (&data[..idx], &data[idx + 2..]),
+            None => (data, ""),
+        };
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+
+        for line in headers.lines() {
+            // Continuation lines, such as in "gpgsig"
+            if line.starts_with(' ') {
+                continue;
+            }
+
+            let (key, value) = match line.find(' ') {
+                Some(idx) => (&line[..idx], &line[idx + 1..]),
+                None => (line, ""),
+            };
+
+            match key {
+                "tree" => tree = GitHash::try_from(value).ok(),
+                "parent" => {
+                    let hash = GitHash::try_from(value).map_err(|_| OBJECT_ERROR)?;
+                    parents.push(hash);
+                }
+                "author" => author = Some(Signature::parse(value)?),
+                "committer" => committer = Some(Signature::parse(value)?),
+                _ => trace!("Ignoring commit header '{}'", key),
+            }
+        }
+
+        Ok(Commit {
+            tree: tree.ok_or(OBJECT_ERROR)?,
+            parents,
+            author: author.ok_or(OBJECT_ERROR)?,
+            committer: committer.ok_or(OBJECT_ERROR)?,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = format!("tree {}\n", self.tree);
+
+        for parent in &self.parents {
+            data.push_str(&format!("parent {}\n", parent));
+        }
+
+        data.push_str(&format!("author {}\n", self.author.serialize()));
+        data.push_str(&format!("committer {}\n", self.committer.serialize()));
+        data.push('\n');
+        data.push_str(&self.message);
+        data.into_bytes()
+    }
+
+    #[inline]
+    pub fn parent(&self) -> Option<&GitHash> {
+        self.parents.first()
+    }
+
+    /// Gets the first line of the commit message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+/// Normalizes a commit message the way `git commit --message` does,
+/// removing trailing whitespace and ending it with a single newline.
+pub fn clean_message(message: &str) -> String {
+    let mut cleaned = String::new();
+
+    for line in message.trim_matches('\n').lines() {
+        cleaned.push_str(line.trim_end());
+        cleaned.push('\n');
+    }
+
+    cleaned
+}
+
+#[inline]
+fn find(data: &[u8], byte: u8) -> Option<usize> {
+    data.iter().position(|&b| b == byte)
+}
@@ -0,0 +1,261 @@
+/*
+ * revision/git/odb.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{hash_object, object_header, ObjectKind, ObjectStore, Pack};
+use crate::revision::GitHash;
+use crate::{Error, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use parking_lot::RwLock;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::SystemTime;
+
+const LOOSE_ERROR: Error = Error::StaticMsg("malformed loose git object");
+
+/// The packs that were present when the `pack` directory was last read.
+#[derive(Debug)]
+struct PackList {
+    modified: Option<SystemTime>,
+    packs: Vec<Pack>,
+}
+
+/// The `objects` directory of a repository.
+#[derive(Debug)]
+pub struct ObjectDatabase {
+    directory: PathBuf,
+    packs: RwLock<Option<PackList>>,
+}
+
+impl ObjectDatabase {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        ObjectDatabase {
+            directory: directory.into(),
+            packs: RwLock::new(None),
+        }
+    }
+
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn loose_path(&self, hash: &GitHash) -> PathBuf {
+        let (prefix, rest) = hash.as_str().split_at(2);
+
+        self.directory.join(prefix).join(rest)
+    }
+
+    /// Determines if the given object is present, loose or packed.
+    pub fn contains(&self, hash: &GitHash) -> Result<bool> {
+        if self.loose_path(hash).is_file() {
+            return Ok(true);
+        }
+
+        let found = self.with_packs(|packs| {
+            let found = packs.iter().any(|pack| pack.contains(hash));
+
+            Ok(if found { Some(()) } else { None })
+        })?;
+
+        Ok(found.is_some())
+    }
+
+    /// Determines if any pack files are present.
+    pub fn has_packs(&self) -> Result<bool> {
+        let entries = match fs::read_dir(self.directory.join("pack")) {
+            Ok(entries) => entries,
+            Err(error) => {
+                return match error.kind() {
+                    io::ErrorKind::NotFound => Ok(false),
+                    _ => Err(Error::from(error)),
+                };
+            }
+        };
+
+        for entry in entries {
+            if entry?.path().extension() == Some("pack".as_ref()) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Deletes every loose object which isn't in `keep`.
+    /// Packed objects are left alone. Returns the number of objects removed.
+    pub fn prune(&self, keep: &HashSet<GitHash>) -> Result<usize> {
+        let mut removed = 0;
+
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let prefix = entry.file_name();
+            let prefix = prefix.to_string_lossy();
+
+            if prefix.len() != 2 || !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            for object in fs::read_dir(entry.path())? {
+                let object = object?;
+                let suffix = object.file_name();
+                let suffix = suffix.to_string_lossy();
+
+                // Skip anything else in the directory, such as temporary files from failed writes
+                if suffix.len() != 38 || !suffix.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    trace!("Skipping non-object file {}/{}", prefix, suffix);
+                    continue;
+                }
+
+                let name = format!("{}{}", prefix, suffix);
+                let hash = match GitHash::try_from(name.as_str()) {
+                    Ok(hash) => hash,
+                    Err(_) => continue,
+                };
+
+                if !keep.contains(&hash) {
+                    trace!("Removing unreachable object {}", hash);
+
+                    fs::remove_file(object.path())?;
+                    removed += 1;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    fn read_loose(&self, hash: &GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        let file = match File::open(self.loose_path(hash)) {
+            Ok(file) => file,
+            Err(error) => {
+                return match error.kind() {
+                    io::ErrorKind::NotFound => Ok(None),
+                    _ => Err(Error::from(error)),
+                };
+            }
+        };
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(file).read_to_end(&mut raw)?;
+
+        let nul = raw.iter().position(|&b| b == b'\0').ok_or(LOOSE_ERROR)?;
+        let header = str::from_utf8(&raw[..nul]).map_err(|_| LOOSE_ERROR)?;
+        let mut parts = header.splitn(2, ' ');
+
+        let kind = parts.next().ok_or(LOOSE_ERROR)?;
+        let kind = ObjectKind::try_from(kind).map_err(|_| LOOSE_ERROR)?;
+        let length: usize = parts
+            .next()
+            .and_then(|length| length.parse().ok())
+            .ok_or(LOOSE_ERROR)?;
+
+        let data = raw.split_off(nul + 1);
+        if data.len() != length {
+            return Err(LOOSE_ERROR);
+        }
+
+        Ok(Some((kind, data)))
+    }
+
+    /// Runs a lookup against the loaded packs.
+    /// If nothing is found and the pack directory has changed, such as
+    /// after a `git gc`, the packs are reloaded and the lookup retried.
+    fn with_packs<F, T>(&self, f: F) -> Result<Option<T>>
+    where
+        F: Fn(&[Pack]) -> Result<Option<T>>,
+    {
+        let pack_directory = self.directory.join("pack");
+        let modified = fs::metadata(&pack_directory)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        {
+            let guard = self.packs.read();
+            if let Some(ref list) = *guard {
+                if let Some(value) = f(&list.packs)? {
+                    return Ok(Some(value));
+                }
+
+                if list.modified == modified {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut guard = self.packs.write();
+        let packs = Pack::load_all(&pack_directory)?;
+        *guard = Some(PackList { modified, packs });
+        f(&guard.as_ref().unwrap().packs)
+    }
+}
+
+impl ObjectStore for ObjectDatabase {
+    fn read(&self, hash: &GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        if let Some(object) = self.read_loose(hash)? {
+            return Ok(Some(object));
+        }
+
+        self.with_packs(|packs| {
+            for pack in packs {
+                if let Some(object) = pack.read(hash, |base| self.read_loose(base))? {
+                    return Ok(Some(object));
+                }
+            }
+
+            Ok(None)
+        })
+    }
+
+    /// Stores the object as a loose object.
+    /// Objects which already exist are not rewritten.
+    fn write(&self, kind: ObjectKind, data: &[u8]) -> Result<GitHash> {
+        let hash = hash_object(kind, data);
+
+        if self.contains(&hash)? {
+            trace!("Object {} already exists", hash);
+            return Ok(hash);
+        }
+
+        let path = self.loose_path(&hash);
+        let parent = path.parent().unwrap();
+        fs::create_dir_all(parent)?;
+
+        trace!("Writing {} bytes to loose object {}", data.len(), hash);
+
+        // Write to a temporary file first, so a partial object is never visible
+        let temp_path = parent.join(format!("tmp_obj_{}", hash));
+        {
+            let file = File::create(&temp_path)?;
+            let mut encoder = ZlibEncoder::new(file, Compression::default());
+            encoder.write_all(&object_header(kind, data.len()))?;
+            encoder.write_all(data)?;
+            encoder.finish()?.sync_all()?;
+        }
+
+        fs::rename(&temp_path, &path)?;
+        Ok(hash)
+    }
+}
@@ -0,0 +1,293 @@
+/*
+ * revision/git/pack.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Read-only support for packfiles.
+//!
+//! We never write packs ourselves, but repositories which were previously
+//! managed by the git binary may have had `git gc` pack their objects.
+
+use super::ObjectKind;
+use crate::revision::GitHash;
+use crate::{Error, Result};
+use flate2::read::ZlibDecoder;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const PACK_ERROR: Error = Error::StaticMsg("malformed git packfile");
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+const TYPE_OFS_DELTA: u8 = 6;
+const TYPE_REF_DELTA: u8 = 7;
+
+/// A parsed `.idx` file and the path to its corresponding `.pack`.
+#[derive(Debug)]
+pub struct Pack {
+    pack_path: PathBuf,
+    hashes: Vec<[u8; 20]>,
+    offsets: Vec<u64>,
+}
+
+impl Pack {
+    /// Loads all the pack indices in the given `objects/pack` directory.
+    pub fn load_all(directory: &Path) -> Result<Vec<Pack>> {
+        let mut packs = Vec::new();
+
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(packs),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension().map(|ext| ext == "idx").unwrap_or(false) {
+                debug!("Loading pack index {}", path.display());
+
+                packs.push(Pack::load(&path)?);
+            }
+        }
+
+        Ok(packs)
+    }
+
+    fn load(idx_path: &Path) -> Result<Self> {
+        let data = fs::read(idx_path)?;
+
+        if data.len() < 8 + 256 * 4 || data[..4] != IDX_MAGIC || read_u32(&data, 4)? != 2 {
+            return Err(Error::StaticMsg("unsupported git pack index version"));
+        }
+
+        let count = read_u32(&data, 8 + 255 * 4)? as usize;
+        let hashes_start = 8 + 256 * 4;
+        let offsets_start = hashes_start + count * 24;
+        let large_start = offsets_start + count * 4;
+
+        let mut hashes = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = hashes_start + i * 20;
+            let hash = data.get(start..start + 20).ok_or(PACK_ERROR)?;
+            hashes.push(hash.try_into().unwrap());
+        }
+
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(&data, offsets_start + i * 4)?;
+
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(u64::from(offset));
+            } else {
+                let index = (offset & 0x7fff_ffff) as usize;
+                let high = u64::from(read_u32(&data, large_start + index * 8)?);
+                let low = u64::from(read_u32(&data, large_start + index * 8 + 4)?);
+                offsets.push((high << 32) | low);
+            }
+        }
+
+        Ok(Pack {
+            pack_path: idx_path.with_extension("pack"),
+            hashes,
+            offsets,
+        })
+    }
+
+    fn find(&self, hash: &[u8; 20]) -> Option<u64> {
+        self.hashes
+            .binary_search(hash)
+            .ok()
+            .map(|index| self.offsets[index])
+    }
+
+    #[inline]
+    pub fn contains(&self, hash: &GitHash) -> bool {
+        self.find(&hash.to_bytes()).is_some()
+    }
+
+    /// Reads the object with the given hash, if it's in this pack.
+    /// Objects stored as deltas against objects in other packs are resolved using `lookup`.
+    pub fn read<F>(&self, hash: &GitHash, lookup: F) -> Result<Option<(ObjectKind, Vec<u8>)>>
+    where
+        F: Fn(&GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>>,
+    {
+        let offset = match self.find(&hash.to_bytes()) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        let mut file = File::open(&self.pack_path)?;
+        self.read_at(&mut file, offset, &lookup).map(Some)
+    }
+
+    fn read_at<F>(&self, file: &mut File, offset: u64, lookup: &F) -> Result<(ObjectKind, Vec<u8>)>
+    where
+        F: Fn(&GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>>,
+    {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut *file);
+
+        // Object header, a variable-length type and size
+        let mut byte = read_byte(&mut reader)?;
+        let pack_type = (byte >> 4) & 0x07;
+        let mut size = u64::from(byte & 0x0f);
+        let mut shift = 4;
+
+        while byte & 0x80 != 0 {
+            byte = read_byte(&mut reader)?;
+            size |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+
+        match pack_type {
+            TYPE_OFS_DELTA => {
+                let mut byte = read_byte(&mut reader)?;
+                let mut distance = u64::from(byte & 0x7f);
+
+                while byte & 0x80 != 0 {
+                    byte = read_byte(&mut reader)?;
+                    distance = ((distance + 1) << 7) | u64::from(byte & 0x7f);
+                }
+
+                let delta = inflate(reader, size)?;
+                let base_offset = offset.checked_sub(distance).ok_or(PACK_ERROR)?;
+                let (kind, base) = self.read_at(file, base_offset, lookup)?;
+
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            TYPE_REF_DELTA => {
+                let mut base_hash = [0; 20];
+                reader.read_exact(&mut base_hash)?;
+
+                let delta = inflate(reader, size)?;
+                let base_hash = GitHash::from_bytes(&base_hash).unwrap();
+                let (kind, base) = match self.find(&base_hash.to_bytes()) {
+                    Some(base_offset) => self.read_at(file, base_offset, lookup)?,
+                    None => lookup(&base_hash)?.ok_or(PACK_ERROR)?,
+                };
+
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            _ => {
+                let kind = ObjectKind::from_pack_type(pack_type).ok_or(PACK_ERROR)?;
+
+                Ok((kind, inflate(reader, size)?))
+            }
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or(PACK_ERROR)?;
+
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn inflate<R: Read>(reader: R, size: u64) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(size as usize);
+    ZlibDecoder::new(reader).take(size).read_to_end(&mut data)?;
+
+    if data.len() as u64 != size {
+        return Err(PACK_ERROR);
+    }
+
+    Ok(data)
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    fn read_size(delta: &[u8], position: &mut usize) -> Result<usize> {
+        let mut size = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = *delta.get(*position).ok_or(PACK_ERROR)?;
+            *position += 1;
+            size |= usize::from(byte & 0x7f) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(size);
+            }
+        }
+    }
+
+    let mut position = 0;
+    let base_size = read_size(delta, &mut position)?;
+    let result_size = read_size(delta, &mut position)?;
+
+    if base_size != base.len() {
+        return Err(PACK_ERROR);
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+
+        if instruction & 0x80 != 0 {
+            // Copy from base
+            let mut offset = 0;
+            let mut size = 0;
+
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    let byte = *delta.get(position).ok_or(PACK_ERROR)?;
+                    offset |= usize::from(byte) << (i * 8);
+                    position += 1;
+                }
+            }
+
+            for i in 0..3 {
+                if instruction & (0x10 << i) != 0 {
+                    let byte = *delta.get(position).ok_or(PACK_ERROR)?;
+                    size |= usize::from(byte) << (i * 8);
+                    position += 1;
+                }
+            }
+
+            if size == 0 {
+                size = 0x10000;
+            }
+
+            let chunk = base.get(offset..offset + size).ok_or(PACK_ERROR)?;
+            result.extend_from_slice(chunk);
+        } else if instruction != 0 {
+            // Insert literal data
+            let size = usize::from(instruction);
+            let chunk = delta.get(position..position + size).ok_or(PACK_ERROR)?;
+            result.extend_from_slice(chunk);
+            position += size;
+        } else {
+            return Err(PACK_ERROR);
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(PACK_ERROR);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,263 @@
+/*
+ * revision/git/repo.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{serialize_index, IndexEntry, ObjectDatabase, ObjectKind, ObjectStore, Tree};
+use crate::revision::GitHash;
+use crate::{Error, Result};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG: &str = "\
+[core]
+\trepositoryformatversion = 0
+\tfilemode = true
+\tbare = false
+";
+
+/// A non-bare git repository, read and written without the git binary.
+///
+/// This uses the same on-disk layout as stock git, so repositories can
+/// be inspected or backed up using regular git tooling.
+#[derive(Debug)]
+pub struct Repository {
+    workdir: PathBuf,
+    git_dir: PathBuf,
+    odb: ObjectDatabase,
+}
+
+impl Repository {
+    pub fn new<P: Into<PathBuf>>(workdir: P) -> Self {
+        let workdir = workdir.into();
+        let git_dir = workdir.join(".git");
+        let odb = ObjectDatabase::new(git_dir.join("objects"));
+
+        Repository {
+            workdir,
+            git_dir,
+            odb,
+        }
+    }
+
+    #[inline]
+    pub fn workdir(&self) -> &Path {
+        &self.workdir
+    }
+
+    #[inline]
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    /// Determines if any objects are stored in pack files.
+    #[inline]
+    pub fn has_packs(&self) -> Result<bool> {
+        self.odb.has_packs()
+    }
+
+    /// Deletes every loose object which isn't in `keep`.
+    #[inline]
+    pub fn prune(&self, keep: &HashSet<GitHash>) -> Result<usize> {
+        self.odb.prune(keep)
+    }
+
+    /// Determines if `init()` has been run on this repository.
+    #[inline]
+    pub fn exists(&self) -> bool {
+        self.git_dir.join("HEAD").is_file()
+    }
+
+    /// Creates the repository structure, like `git init`.
+    /// Existing files are left alone, so this is safe to run on an existing repository.
+    pub fn init(&self) -> Result<()> {
+        debug!("Initializing git directory {}", self.git_dir.display());
+
+        fs::create_dir_all(self.git_dir.join("refs").join("heads"))?;
+        fs::create_dir_all(self.git_dir.join("refs").join("tags"))?;
+        fs::create_dir_all(self.odb.directory().join("info"))?;
+        fs::create_dir_all(self.odb.directory().join("pack"))?;
+
+        let head = self.git_dir.join("HEAD");
+        if !head.exists() {
+            fs::write(&head, "ref: refs/heads/master\n")?;
+        }
+
+        let config = self.git_dir.join("config");
+        if !config.exists() {
+            fs::write(&config, DEFAULT_CONFIG)?;
+        }
+
+        Ok(())
+    }
+
+    // References
+
+    fn read_ref_file(&self, name: &str) -> Result<Option<String>> {
+        match fs::read_to_string(self.git_dir.join(name)) {
+            Ok(contents) => Ok(Some(contents.trim().to_string())),
+            Err(error) => match error.kind() {
+                io::ErrorKind::NotFound => Ok(None),
+                _ => Err(Error::from(error)),
+            },
+        }
+    }
+
+    fn read_packed_ref(&self, name: &str) -> Result<Option<GitHash>> {
+        let packed = match self.read_ref_file("packed-refs")? {
+            Some(packed) => packed,
+            None => return Ok(None),
+        };
+
+        for line in packed.lines() {
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            let hash = parts.next().unwrap_or("");
+
+            if parts.next() == Some(name) {
+                return Ok(GitHash::try_from(hash).ok());
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Gets the reference `HEAD` points to, or `None` if it is detached.
+    fn head_target(&self) -> Result<Option<String>> {
+        let head = self
+            .read_ref_file("HEAD")?
+            .ok_or(Error::StaticMsg("repository has no HEAD"))?;
+
+        Ok(head.strip_prefix("ref: ").map(String::from))
+    }
+
+    /// Gets the commit `HEAD` points to, or `None` if no commits have been made.
+    pub fn head(&self) -> Result<Option<GitHash>> {
+        let name = match self.head_target()? {
+            Some(name) => name,
+            None => {
+                let head = self
+                    .read_ref_file("HEAD")?
+                    .ok_or(Error::StaticMsg("repository has no HEAD"))?;
+                let hash = GitHash::try_from(head.as_str())
+                    .map_err(|_| Error::StaticMsg("detached HEAD is not a valid hash"))?;
+
+                return Ok(Some(hash));
+            }
+        };
+
+        match self.read_ref_file(&name)? {
+            Some(value) => {
+                let hash = GitHash::try_from(value.as_str())
+                    .map_err(|_| Error::StaticMsg("git reference is not a valid hash"))?;
+
+                Ok(Some(hash))
+            }
+            None => self.read_packed_ref(&name),
+        }
+    }
+
+    /// Moves `HEAD` (or the branch it points to) to the given commit.
+    pub fn set_head(&self, hash: &GitHash) -> Result<()> {
+        let name = self.head_target()?.unwrap_or_else(|| String::from("HEAD"));
+        let path = self.git_dir.join(&name);
+        let lock_path = path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().unwrap().to_string_lossy(),
+        ));
+
+        debug!("Updating reference {} to {}", name, hash);
+
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&lock_path, format!("{}\n", hash))?;
+        fs::rename(&lock_path, &path)?;
+        Ok(())
+    }
+
+    /// Gets the tree of the commit at `HEAD`, or an empty tree if there is none.
+    pub fn head_tree(&self) -> Result<Tree> {
+        let head = self.head()?;
+
+        self.commit_tree(head.as_ref())
+    }
+
+    /// Rewrites the index to match the given tree.
+    pub fn write_index(&self, tree: &Tree) -> Result<()> {
+        let mut entries = Vec::new();
+        self.collect_index_entries(tree, "", &mut entries)?;
+
+        let data = serialize_index(&mut entries);
+        let path = self.git_dir.join("index");
+        let lock_path = self.git_dir.join("index.lock");
+
+        trace!("Writing index with {} entries", entries.len());
+
+        fs::write(&lock_path, data)?;
+        fs::rename(&lock_path, &path)?;
+        Ok(())
+    }
+
+    fn collect_index_entries(
+        &self,
+        tree: &Tree,
+        prefix: &str,
+        entries: &mut Vec<IndexEntry>,
+    ) -> Result<()> {
+        for entry in tree.entries() {
+            let path = format!("{}{}", prefix, entry.name);
+
+            if entry.is_directory() {
+                let subtree = self.read_tree(&entry.hash)?;
+                self.collect_index_entries(&subtree, &format!("{}/", path), entries)?;
+                continue;
+            }
+
+            let metadata = fs::metadata(self.workdir.join(&path)).ok();
+            let size = metadata.as_ref().map(|meta| meta.len() as u32).unwrap_or(0);
+            let mtime = metadata.and_then(|meta| meta.modified().ok());
+
+            entries.push(IndexEntry {
+                path,
+                mode: entry.mode,
+                hash: entry.hash.clone(),
+                size,
+                mtime,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl ObjectStore for Repository {
+    #[inline]
+    fn read(&self, hash: &GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        self.odb.read(hash)
+    }
+
+    #[inline]
+    fn write(&self, kind: ObjectKind, data: &[u8]) -> Result<GitHash> {
+        self.odb.write(kind, data)
+    }
+}
@@ -0,0 +1,154 @@
+/*
+ * revision/git/store.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{hash_object, Commit, ObjectKind, Tree};
+use crate::revision::GitHash;
+use crate::{Error, Result};
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Storage for git objects, addressed by their hashes.
+pub trait ObjectStore: Debug + Send + Sync {
+    /// Reads an object, returning `None` if it does not exist.
+    fn read(&self, hash: &GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>>;
+
+    /// Stores an object, returning its hash.
+    fn write(&self, kind: ObjectKind, data: &[u8]) -> Result<GitHash>;
+
+    /// Reads an object, failing if it is not of the expected kind.
+    fn read_kind(&self, hash: &GitHash, expected: ObjectKind) -> Result<Option<Vec<u8>>> {
+        match self.read(hash)? {
+            Some((kind, data)) if kind == expected => Ok(Some(data)),
+            Some(_) => Err(Error::StaticMsg("git object has unexpected type")),
+            None => Ok(None),
+        }
+    }
+
+    fn read_commit(&self, hash: &GitHash) -> Result<Option<Commit>> {
+        match self.read_kind(hash, ObjectKind::Commit)? {
+            Some(data) => Commit::parse(&data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_tree(&self, hash: &GitHash) -> Result<Tree> {
+        match self.read_kind(hash, ObjectKind::Tree)? {
+            Some(data) => Tree::parse(&data),
+            None => Err(Error::StaticMsg("git tree missing from repository")),
+        }
+    }
+
+    fn read_blob(&self, hash: &GitHash) -> Result<Vec<u8>> {
+        self.read_kind(hash, ObjectKind::Blob)?
+            .ok_or(Error::StaticMsg("git blob missing from repository"))
+    }
+
+    #[inline]
+    fn write_blob(&self, data: &[u8]) -> Result<GitHash> {
+        self.write(ObjectKind::Blob, data)
+    }
+
+    #[inline]
+    fn write_tree(&self, tree: &Tree) -> Result<GitHash> {
+        self.write(ObjectKind::Tree, &tree.serialize())
+    }
+
+    #[inline]
+    fn write_commit(&self, commit: &Commit) -> Result<GitHash> {
+        self.write(ObjectKind::Commit, &commit.serialize())
+    }
+
+    /// Gets the tree of the given commit, or an empty tree if there is none.
+    fn commit_tree(&self, hash: Option<&GitHash>) -> Result<Tree> {
+        let hash = match hash {
+            Some(hash) => hash,
+            None => return Ok(Tree::new()),
+        };
+
+        let commit = self
+            .read_commit(hash)?
+            .ok_or(Error::StaticMsg("commit missing from repository"))?;
+
+        self.read_tree(&commit.tree)
+    }
+
+    /// Gets the blob hash and contents for a file in the given commit's tree.
+    fn read_file(&self, commit: &Commit, path: &str) -> Result<Option<(GitHash, Vec<u8>)>> {
+        let tree = self.read_tree(&commit.tree)?;
+
+        match tree.get(path) {
+            Some(entry) => {
+                let content = self.read_blob(&entry.hash)?;
+
+                Ok(Some((entry.hash.clone(), content)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+type ObjectMap = HashMap<GitHash, (ObjectKind, Arc<[u8]>)>;
+
+/// An object store which is held entirely in memory.
+#[derive(Debug, Default)]
+pub struct MemoryObjectStore {
+    objects: RwLock<ObjectMap>,
+}
+
+impl MemoryObjectStore {
+    #[inline]
+    pub fn new() -> Self {
+        MemoryObjectStore::default()
+    }
+
+    /// Deletes every object which isn't in `keep`.
+    /// Returns the number of objects removed.
+    pub fn prune(&self, keep: &HashSet<GitHash>) -> usize {
+        let mut guard = self.objects.write();
+        let before = guard.len();
+
+        guard.retain(|hash, _| keep.contains(hash));
+        before - guard.len()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn read(&self, hash: &GitHash) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        let guard = self.objects.read();
+        let object = guard.get(hash).map(|(kind, data)| (*kind, data.to_vec()));
+
+        Ok(object)
+    }
+
+    fn write(&self, kind: ObjectKind, data: &[u8]) -> Result<GitHash> {
+        let hash = hash_object(kind, data);
+
+        trace!("Storing {} bytes in memory as object {}", data.len(), hash);
+
+        let mut guard = self.objects.write();
+        guard
+            .entry(hash.clone())
+            .or_insert_with(|| (kind, Arc::from(data)));
+
+        Ok(hash)
+    }
+}
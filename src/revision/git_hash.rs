@@ -24,14 +24,14 @@ use regex::Regex;
 use std::borrow::Borrow;
 use std::convert::TryFrom;
 use std::ffi::OsStr;
-use std::fmt::{self, Debug, Display};
+use std::fmt::{self, Debug, Display, Write};
 use std::str;
 
 lazy_static! {
-    static ref GIT_HASH_REGEX: Regex = Regex::new(r"[a-f0-9]{40}").unwrap();
+    static ref GIT_HASH_REGEX: Regex = Regex::new(r"^[a-f0-9]{40}$").unwrap();
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Hash, PartialEq, Eq)]
 pub struct GitHash(ArrayString<[u8; 40]>);
 
 impl GitHash {
@@ -44,6 +44,33 @@ impl GitHash {
         Self::try_from(hash).expect("Invalid git hash")
     }
 
+    /// Creates a hash from the 20 raw bytes of a SHA-1 digest.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 20 {
+            return None;
+        }
+
+        let mut hash = ArrayString::new();
+        for byte in bytes {
+            write!(&mut hash, "{:02x}", byte).unwrap();
+        }
+
+        Some(GitHash(hash))
+    }
+
+    /// Gets the raw 20-byte digest this hash represents.
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        let hex = self.0.as_bytes();
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let digits = str::from_utf8(&hex[i * 2..i * 2 + 2]).unwrap();
+            *byte = u8::from_str_radix(digits, 16).unwrap();
+        }
+
+        bytes
+    }
+
     #[inline]
     pub fn as_str(&self) -> &str {
         &self.0
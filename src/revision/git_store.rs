@@ -0,0 +1,492 @@
+/*
+ * revision/git_store.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::git::{ObjectStore, Repository, Signature, Tree};
+use super::store::{
+    apply_changes, blame_page, check_normal, diff_page, reachable_objects, read_log,
+    read_page_version, read_rename, rewrite_history, slug_filename, write_commit,
+};
+use super::{Blame, CommitInfo, GitHash, LogEntry, PageChange, RevisionStore};
+use crate::{Error, Result};
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// An object that can't be copied or cloned for a `RwLock`.
+#[derive(Debug)]
+struct RevisionBlock;
+
+/// Represents a git repository to store page contents and their histories.
+#[derive(Debug)]
+pub struct GitStore {
+    lock: RwLock<RevisionBlock>,
+    repo: Repository,
+    domain: RwLock<String>,
+}
+
+impl GitStore {
+    /// Creates a new revision store using the given repository directory and domain name.
+    ///
+    /// The domain name should not be prefixed with a protocol such as `https://` but does
+    /// permit subdomains.
+    #[inline]
+    pub fn new<P, S>(repo: P, domain: S) -> Self
+    where
+        P: Into<PathBuf>,
+        S: Into<String>,
+    {
+        let lock = RwLock::new(RevisionBlock);
+        let repo = repo.into();
+        let domain = domain.into();
+
+        info!(
+            "Creating new revision store for repository {}, domain {}",
+            repo.display(),
+            domain,
+        );
+
+        let repo = Repository::new(repo);
+        let domain = RwLock::new(domain);
+
+        GitStore { lock, repo, domain }
+    }
+
+    // Filesystem helpers
+    fn get_path(&self, slug: &str) -> PathBuf {
+        self.repo.workdir().join(slug_filename(slug))
+    }
+
+    fn read_file(&self, slug: &str) -> Result<Option<Box<[u8]>>> {
+        let path = self.get_path(slug);
+
+        debug!("Reading file from {}", path.display());
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                use std::io::ErrorKind;
+
+                return match error.kind() {
+                    ErrorKind::NotFound => Ok(None),
+                    _ => Err(Error::from(error)),
+                };
+            }
+        };
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        let bytes = content.into_boxed_slice();
+        Ok(Some(bytes))
+    }
+
+    fn write_file(&self, slug: &str, content: &[u8]) -> Result<()> {
+        let path = self.get_path(slug);
+
+        debug!("Writing {} bytes to {}", content.len(), path.display());
+
+        let mut file = File::create(path)?;
+        file.write_all(content)?;
+        Ok(())
+    }
+
+    fn remove_file(&self, slug: &str) -> Result<Option<()>> {
+        let path = self.get_path(slug);
+
+        debug!("Removing file {}", path.display());
+
+        match fs::remove_file(path) {
+            Ok(_) => (),
+            Err(error) => {
+                use std::io::ErrorKind;
+
+                return match error.kind() {
+                    ErrorKind::NotFound => Ok(None),
+                    _ => Err(Error::from(error)),
+                };
+            }
+        }
+
+        Ok(Some(()))
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.repo.git_dir().join("DEEPWELL_JOURNAL")
+    }
+
+    // Git helpers
+    fn signature(&self, name: &str) -> Signature {
+        let domain = self.domain.read();
+        let email = format!("noreply@{}", domain);
+
+        Signature::now(name, &email)
+    }
+
+    /// Commits the given tree on top of `HEAD`, and updates the index to match.
+    fn commit_tree(&self, tree: &Tree, info: CommitInfo) -> Result<GitHash> {
+        let author = self.signature(info.username);
+        let parent = self.repo.head()?;
+        let hash = write_commit(&self.repo, tree, parent, author, info)?;
+
+        self.repo.set_head(&hash)?;
+        self.repo.write_index(tree)?;
+        Ok(hash)
+    }
+
+    /// Moves `HEAD` to the given commit, making the working tree and index match.
+    fn checkout(&self, hash: &GitHash) -> Result<()> {
+        let tree = match self.repo.read_commit(hash)? {
+            Some(commit) => self.repo.read_tree(&commit.tree)?,
+            None => return Err(Error::RevisionNotFound),
+        };
+
+        // Remove pages which didn't exist at the time
+        for entry in fs::read_dir(self.repo.workdir())? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+
+            if name.ends_with(".ftml") && tree.get(&name).is_none() {
+                debug!("Removing file {}", name);
+                fs::remove_file(self.repo.workdir().join(&*name))?;
+            }
+        }
+
+        // Restore the contents of the rest
+        for entry in tree.entries() {
+            let path = self.repo.workdir().join(&entry.name);
+            let content = self.repo.read_blob(&entry.hash)?;
+
+            if fs::read(&path).ok().as_ref() != Some(&content) {
+                debug!("Restoring file {}", entry.name);
+                fs::write(&path, &content)?;
+            }
+        }
+
+        self.repo.set_head(hash)?;
+        self.repo.write_index(&tree)?;
+        Ok(())
+    }
+}
+
+impl RevisionStore for GitStore {
+    /// Determines if the repository exists and has a commit at `HEAD`.
+    /// Returns `false` if either the repository or the initial commit has yet to be created.
+    fn is_initialized(&self) -> Result<bool> {
+        debug!(
+            "Checking if repository {} is initialized",
+            self.repo.workdir().display(),
+        );
+
+        let _guard = self.lock.read();
+        if !self.repo.exists() {
+            return Ok(false);
+        }
+
+        Ok(self.repo.head()?.is_some())
+    }
+
+    #[cold]
+    fn initial_commit(&self) -> Result<()> {
+        info!("Initializing new git repository");
+
+        let _guard = self.lock.write();
+        self.repo.init()?;
+
+        let info = CommitInfo {
+            username: "DEEPWELL",
+            message: "Initial commit",
+        };
+
+        self.commit_tree(&Tree::new(), info)?;
+        Ok(())
+    }
+
+    fn commit(&self, slug: &str, content: Option<&[u8]>, info: CommitInfo) -> Result<GitHash> {
+        info!(
+            "Committing file changes for slug '{}' ({} bytes)",
+            slug,
+            content.map(|b| b.len()).unwrap_or(0),
+        );
+
+        let _guard = self.lock.write();
+        check_normal(slug)?;
+
+        if let Some(content) = content {
+            self.write_file(slug, content)?;
+        }
+
+        let filename = slug_filename(slug);
+        let mut tree = self.repo.head_tree()?;
+
+        match self.read_file(slug)? {
+            Some(content) => {
+                let blob = self.repo.write_blob(&content)?;
+                tree.insert(&filename, blob);
+            }
+            None => {
+                tree.remove(&filename);
+            }
+        }
+
+        self.commit_tree(&tree, info)
+    }
+
+    fn commit_batch(&self, changes: &[PageChange], info: CommitInfo) -> Result<GitHash> {
+        info!("Committing batch of {} file changes", changes.len());
+
+        let _guard = self.lock.write();
+
+        // Check every change against the tree before touching any files
+        let mut tree = self.repo.head_tree()?;
+        apply_changes(&self.repo, &mut tree, changes)?;
+
+        for change in changes {
+            match *change {
+                PageChange::Write { slug, content } => self.write_file(slug, content)?,
+                PageChange::Rename { old_slug, new_slug } => {
+                    fs::rename(self.get_path(old_slug), self.get_path(new_slug))?;
+                }
+                PageChange::Remove { slug } => {
+                    self.remove_file(slug)?;
+                }
+            }
+        }
+
+        self.commit_tree(&tree, info)
+    }
+
+    fn empty_commit(&self, info: CommitInfo) -> Result<GitHash> {
+        info!("Creating empty commit");
+
+        let _guard = self.lock.write();
+        let tree = self.repo.head_tree()?;
+
+        self.commit_tree(&tree, info)
+    }
+
+    fn rename(&self, old_slug: &str, new_slug: &str, info: CommitInfo) -> Result<GitHash> {
+        info!("Renaming file for slug '{}' -> '{}'", old_slug, new_slug);
+
+        let _guard = self.lock.write();
+        check_normal(old_slug)?;
+        check_normal(new_slug)?;
+
+        let new_path = self.get_path(new_slug);
+        if new_path.exists() {
+            return Err(Error::PageExists);
+        }
+
+        let content = self.read_file(old_slug)?.ok_or(Error::PageNotFound)?;
+        fs::rename(self.get_path(old_slug), &new_path)?;
+
+        let blob = self.repo.write_blob(&content)?;
+        let mut tree = self.repo.head_tree()?;
+        tree.remove(&slug_filename(old_slug));
+        tree.insert(&slug_filename(new_slug), blob);
+
+        self.commit_tree(&tree, info)
+    }
+
+    fn remove(&self, slug: &str, info: CommitInfo) -> Result<Option<GitHash>> {
+        info!("Removing file for slug '{}' (info: {:?})", slug, info);
+
+        let _guard = self.lock.write();
+        check_normal(slug)?;
+
+        if self.remove_file(slug)?.is_none() {
+            return Ok(None);
+        }
+
+        let mut tree = self.repo.head_tree()?;
+        tree.remove(&slug_filename(slug));
+
+        self.commit_tree(&tree, info).map(Some)
+    }
+
+    fn get_page(&self, slug: &str) -> Result<Option<Box<[u8]>>> {
+        info!("Getting page content for slug '{}'", slug);
+
+        let _guard = self.lock.read();
+        check_normal(slug)?;
+
+        self.read_file(slug)
+    }
+
+    fn get_page_version(&self, slug: &str, hash: &GitHash) -> Result<Option<Box<[u8]>>> {
+        info!(
+            "Getting page content for slug '{}' at commit {}",
+            slug, hash,
+        );
+
+        let _guard = self.lock.read();
+        check_normal(slug)?;
+
+        read_page_version(&self.repo, slug, hash)
+    }
+
+    fn get_diff(&self, first: (&str, &GitHash), second: (&str, &GitHash)) -> Result<Box<[u8]>> {
+        info!(
+            "Getting diff for slugs '{}' -> '{}' between {}..{}",
+            first.0, second.0, first.1, second.1,
+        );
+
+        let _guard = self.lock.read();
+        check_normal(first.0)?;
+        check_normal(second.0)?;
+
+        diff_page(&self.repo, first, second)
+    }
+
+    fn get_rename(&self, hash: &GitHash, new_slug: &str) -> Result<Option<String>> {
+        debug!("Getting rename to slug '{}' in commit {}", new_slug, hash);
+
+        let _guard = self.lock.read();
+        read_rename(&self.repo, hash, new_slug)
+    }
+
+    fn get_blame(&self, slug: &str, hash: Option<GitHash>) -> Result<Option<Blame>> {
+        info!("Getting blame for slug '{}'", slug);
+
+        let _guard = self.lock.read();
+        check_normal(slug)?;
+
+        let hash = match hash {
+            Some(hash) => Some(hash),
+            None => self.repo.head()?,
+        };
+
+        blame_page(&self.repo, slug, hash)
+    }
+
+    fn head(&self) -> Result<Option<GitHash>> {
+        let _guard = self.lock.read();
+
+        self.repo.head()
+    }
+
+    fn reset(&self, hash: &GitHash) -> Result<()> {
+        info!("Resetting repository to commit {}", hash);
+
+        let _guard = self.lock.write();
+        self.checkout(hash)
+    }
+
+    fn begin_journal(&self) -> Result<()> {
+        let _guard = self.lock.write();
+        let head = self
+            .repo
+            .head()?
+            .ok_or(Error::StaticMsg("repository has no commits"))?;
+
+        debug!("Journaling commit {} before starting change", head);
+
+        let mut file = File::create(self.journal_path())?;
+        writeln!(&mut file, "{}", head)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn end_journal(&self) -> Result<()> {
+        let _guard = self.lock.write();
+
+        match fs::remove_file(self.journal_path()) {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                use std::io::ErrorKind;
+
+                match error.kind() {
+                    ErrorKind::NotFound => Ok(()),
+                    _ => Err(Error::from(error)),
+                }
+            }
+        }
+    }
+
+    fn pending_journal(&self) -> Result<Option<GitHash>> {
+        let _guard = self.lock.read();
+
+        let contents = match fs::read_to_string(self.journal_path()) {
+            Ok(contents) => contents,
+            Err(error) => {
+                use std::io::ErrorKind;
+
+                return match error.kind() {
+                    ErrorKind::NotFound => Ok(None),
+                    _ => Err(Error::from(error)),
+                };
+            }
+        };
+
+        match GitHash::try_from(contents.as_str()) {
+            Ok(hash) => Ok(Some(hash)),
+            Err(_) => Err(Error::StaticMsg("revision journal is corrupt")),
+        }
+    }
+
+    fn get_log(&self) -> Result<Vec<LogEntry>> {
+        info!("Getting commit log");
+
+        let _guard = self.lock.read();
+        read_log(&self.repo, self.repo.head()?)
+    }
+
+    fn rewrite(
+        &self,
+        drop: &HashSet<GitHash>,
+        renames: &HashMap<GitHash, Vec<(String, String)>>,
+    ) -> Result<HashMap<GitHash, GitHash>> {
+        info!("Rewriting history without {} commits", drop.len());
+
+        let _guard = self.lock.write();
+
+        // Packed objects can't be pruned, so dropped content would stay on disk
+        if self.repo.has_packs()? {
+            return Err(Error::StaticMsg(
+                "cannot rewrite history of a packed repository",
+            ));
+        }
+
+        let (head, rewritten) = rewrite_history(&self.repo, self.repo.head()?, drop, renames)?;
+        if let Some(ref head) = head {
+            self.checkout(head)?;
+        }
+
+        Ok(rewritten)
+    }
+
+    fn prune(&self) -> Result<usize> {
+        info!("Pruning unreachable objects");
+
+        let _guard = self.lock.write();
+        let reachable = reachable_objects(&self.repo, self.repo.head()?)?;
+
+        self.repo.prune(&reachable)
+    }
+
+    fn set_domain(&self, new_domain: &str) {
+        trace!("Acquiring domain write lock to change: {}", new_domain);
+
+        let mut guard = self.domain.write();
+        guard.clear();
+        guard.push_str(new_domain);
+    }
+}
@@ -0,0 +1,40 @@
+/*
+ * revision/log.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::GitHash;
+use chrono::prelude::*;
+
+/// A single commit in the history of a revision store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub hash: GitHash,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub message: String,
+
+    /// Slugs of pages which did not exist before this commit.
+    pub added: Vec<String>,
+
+    /// Slugs of pages whose contents were changed by this commit.
+    pub modified: Vec<String>,
+
+    /// Slugs of pages which no longer exist after this commit.
+    pub removed: Vec<String>,
+}
@@ -0,0 +1,312 @@
+/*
+ * revision/memory_store.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::git::{MemoryObjectStore, ObjectStore, Signature, Tree};
+use super::store::{
+    apply_changes, blame_page, check_normal, diff_page, reachable_objects, read_log,
+    read_page_version, read_rename, rewrite_history, slug_filename, write_commit,
+};
+use super::{Blame, CommitInfo, GitHash, LogEntry, PageChange, RevisionStore};
+use crate::{Error, Result};
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+
+/// A revision store which keeps all of its history in memory.
+///
+/// Commits are structured the same as in [`GitStore`], so hashes and diffs
+/// are identical, but nothing touches the filesystem and everything is
+/// lost when the store is dropped. Useful for tests and staging instances.
+///
+/// [`GitStore`]: ./struct.GitStore.html
+#[derive(Debug)]
+pub struct MemoryStore {
+    objects: MemoryObjectStore,
+    head: RwLock<Option<GitHash>>,
+    journal: RwLock<Option<GitHash>>,
+    domain: RwLock<String>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty in-memory revision store with the given domain name.
+    #[inline]
+    pub fn new<S: Into<String>>(domain: S) -> Self {
+        let domain = domain.into();
+
+        info!("Creating new in-memory revision store, domain {}", domain);
+
+        MemoryStore {
+            objects: MemoryObjectStore::new(),
+            head: RwLock::new(None),
+            journal: RwLock::new(None),
+            domain: RwLock::new(domain),
+        }
+    }
+
+    fn signature(&self, name: &str) -> Signature {
+        let domain = self.domain.read();
+        let email = format!("noreply@{}", domain);
+
+        Signature::now(name, &email)
+    }
+
+    /// Modifies the tree at `HEAD` and commits the result.
+    /// The closure returns `None` if no commit should be made.
+    fn update<F>(&self, info: CommitInfo, f: F) -> Result<Option<GitHash>>
+    where
+        F: FnOnce(&mut Tree) -> Result<Option<()>>,
+    {
+        let mut head = self.head.write();
+        let mut tree = self.objects.commit_tree(head.as_ref())?;
+
+        if f(&mut tree)?.is_none() {
+            return Ok(None);
+        }
+
+        let author = self.signature(info.username);
+        let hash = write_commit(&self.objects, &tree, head.clone(), author, info)?;
+
+        *head = Some(hash.clone());
+        Ok(Some(hash))
+    }
+
+    fn head_tree(&self) -> Result<Tree> {
+        let head = self.head.read();
+
+        self.objects.commit_tree(head.as_ref())
+    }
+}
+
+impl RevisionStore for MemoryStore {
+    fn is_initialized(&self) -> Result<bool> {
+        Ok(self.head.read().is_some())
+    }
+
+    #[cold]
+    fn initial_commit(&self) -> Result<()> {
+        info!("Creating initial commit for in-memory store");
+
+        let info = CommitInfo {
+            username: "DEEPWELL",
+            message: "Initial commit",
+        };
+
+        self.update(info, |_| Ok(Some(())))?;
+        Ok(())
+    }
+
+    fn commit(&self, slug: &str, content: Option<&[u8]>, info: CommitInfo) -> Result<GitHash> {
+        info!(
+            "Committing changes for slug '{}' ({} bytes)",
+            slug,
+            content.map(|b| b.len()).unwrap_or(0),
+        );
+
+        check_normal(slug)?;
+
+        let hash = self.update(info, |tree| {
+            if let Some(content) = content {
+                let blob = self.objects.write_blob(content)?;
+                tree.insert(&slug_filename(slug), blob);
+            }
+
+            Ok(Some(()))
+        })?;
+
+        Ok(hash.unwrap())
+    }
+
+    fn commit_batch(&self, changes: &[PageChange], info: CommitInfo) -> Result<GitHash> {
+        info!("Committing batch of {} page changes", changes.len());
+
+        let hash = self.update(info, |tree| {
+            apply_changes(&self.objects, tree, changes)?;
+
+            Ok(Some(()))
+        })?;
+
+        Ok(hash.unwrap())
+    }
+
+    fn empty_commit(&self, info: CommitInfo) -> Result<GitHash> {
+        info!("Creating empty commit");
+
+        let hash = self.update(info, |_| Ok(Some(())))?;
+        Ok(hash.unwrap())
+    }
+
+    fn rename(&self, old_slug: &str, new_slug: &str, info: CommitInfo) -> Result<GitHash> {
+        info!("Renaming slug '{}' -> '{}'", old_slug, new_slug);
+
+        check_normal(old_slug)?;
+        check_normal(new_slug)?;
+
+        let hash = self.update(info, |tree| {
+            if tree.get(&slug_filename(new_slug)).is_some() {
+                return Err(Error::PageExists);
+            }
+
+            let entry = tree
+                .remove(&slug_filename(old_slug))
+                .ok_or(Error::PageNotFound)?;
+
+            tree.insert(&slug_filename(new_slug), entry.hash);
+            Ok(Some(()))
+        })?;
+
+        Ok(hash.unwrap())
+    }
+
+    fn remove(&self, slug: &str, info: CommitInfo) -> Result<Option<GitHash>> {
+        info!("Removing slug '{}' (info: {:?})", slug, info);
+
+        check_normal(slug)?;
+
+        self.update(info, |tree| {
+            let entry = tree.remove(&slug_filename(slug));
+
+            Ok(entry.map(|_| ()))
+        })
+    }
+
+    fn get_page(&self, slug: &str) -> Result<Option<Box<[u8]>>> {
+        info!("Getting page content for slug '{}'", slug);
+
+        check_normal(slug)?;
+
+        let tree = self.head_tree()?;
+        match tree.get(&slug_filename(slug)) {
+            Some(entry) => {
+                let content = self.objects.read_blob(&entry.hash)?;
+
+                Ok(Some(content.into_boxed_slice()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_page_version(&self, slug: &str, hash: &GitHash) -> Result<Option<Box<[u8]>>> {
+        info!(
+            "Getting page content for slug '{}' at commit {}",
+            slug, hash,
+        );
+
+        check_normal(slug)?;
+
+        read_page_version(&self.objects, slug, hash)
+    }
+
+    fn get_diff(&self, first: (&str, &GitHash), second: (&str, &GitHash)) -> Result<Box<[u8]>> {
+        info!(
+            "Getting diff for slugs '{}' -> '{}' between {}..{}",
+            first.0, second.0, first.1, second.1,
+        );
+
+        check_normal(first.0)?;
+        check_normal(second.0)?;
+
+        diff_page(&self.objects, first, second)
+    }
+
+    fn get_rename(&self, hash: &GitHash, new_slug: &str) -> Result<Option<String>> {
+        debug!("Getting rename to slug '{}' in commit {}", new_slug, hash);
+
+        read_rename(&self.objects, hash, new_slug)
+    }
+
+    fn get_blame(&self, slug: &str, hash: Option<GitHash>) -> Result<Option<Blame>> {
+        info!("Getting blame for slug '{}'", slug);
+
+        check_normal(slug)?;
+
+        let hash = hash.or_else(|| self.head.read().clone());
+        blame_page(&self.objects, slug, hash)
+    }
+
+    fn head(&self) -> Result<Option<GitHash>> {
+        Ok(self.head.read().clone())
+    }
+
+    fn reset(&self, hash: &GitHash) -> Result<()> {
+        info!("Resetting to commit {}", hash);
+
+        let mut head = self.head.write();
+        if self.objects.read_commit(hash)?.is_none() {
+            return Err(Error::RevisionNotFound);
+        }
+
+        *head = Some(hash.clone());
+        Ok(())
+    }
+
+    fn begin_journal(&self) -> Result<()> {
+        let head = self.head.read();
+        let mut journal = self.journal.write();
+
+        *journal = head.clone();
+        Ok(())
+    }
+
+    fn end_journal(&self) -> Result<()> {
+        *self.journal.write() = None;
+        Ok(())
+    }
+
+    fn pending_journal(&self) -> Result<Option<GitHash>> {
+        Ok(self.journal.read().clone())
+    }
+
+    fn get_log(&self) -> Result<Vec<LogEntry>> {
+        info!("Getting commit log");
+
+        let head = self.head.read().clone();
+        read_log(&self.objects, head)
+    }
+
+    fn rewrite(
+        &self,
+        drop: &HashSet<GitHash>,
+        renames: &HashMap<GitHash, Vec<(String, String)>>,
+    ) -> Result<HashMap<GitHash, GitHash>> {
+        info!("Rewriting history without {} commits", drop.len());
+
+        let mut head = self.head.write();
+        let (new_head, rewritten) = rewrite_history(&self.objects, head.clone(), drop, renames)?;
+
+        *head = new_head;
+        Ok(rewritten)
+    }
+
+    fn prune(&self) -> Result<usize> {
+        info!("Pruning unreachable objects");
+
+        let head = self.head.read();
+        let reachable = reachable_objects(&self.objects, head.clone())?;
+
+        Ok(self.objects.prune(&reachable))
+    }
+
+    fn set_domain(&self, new_domain: &str) {
+        trace!("Acquiring domain write lock to change: {}", new_domain);
+
+        let mut guard = self.domain.write();
+        guard.clear();
+        guard.push_str(new_domain);
+    }
+}
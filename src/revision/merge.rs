@@ -0,0 +1,135 @@
+/*
+ * revision/merge.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Line-based three-way merging, in the style of `diff3`.
+
+use super::myers::{self, Edit};
+use super::word_diff::split_lines;
+use crate::StdResult;
+
+/// A region which was changed differently in both versions being merged.
+///
+/// Line numbers start from 1, and refer to where the region begins in
+/// the base and current versions of the page respectively.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub base_line: u32,
+    pub current_line: u32,
+    pub base: String,
+    pub current: String,
+    pub edited: String,
+}
+
+/// Merges the changes from `base` to `edited` into `current`.
+///
+/// Regions which only one side changed are taken from that side, as are
+/// regions which both sides changed in the same way. If any region was
+/// changed differently on both sides, all such regions are returned instead.
+pub fn merge(base: &[u8], current: &[u8], edited: &[u8]) -> StdResult<Vec<u8>, Vec<MergeConflict>> {
+    let base = split_lines(base);
+    let current = split_lines(current);
+    let edited = split_lines(edited);
+
+    let current_matches = match_lines(&base, &current);
+    let edited_matches = match_lines(&base, &edited);
+
+    let mut output = Vec::new();
+    let mut conflicts = Vec::new();
+    let (mut i_base, mut i_current, mut i_edited) = (0, 0, 0);
+
+    loop {
+        // Copy lines which are unchanged in both versions
+        while i_base < base.len()
+            && current_matches[i_base] == Some(i_current)
+            && edited_matches[i_base] == Some(i_edited)
+        {
+            output.extend_from_slice(base[i_base]);
+            i_base += 1;
+            i_current += 1;
+            i_edited += 1;
+        }
+
+        if i_base == base.len() && i_current == current.len() && i_edited == edited.len() {
+            break;
+        }
+
+        // Find the end of the changed region, the next line both versions kept
+        let next_base = (i_base..base.len())
+            .find(|&i| current_matches[i].is_some() && edited_matches[i].is_some())
+            .unwrap_or(base.len());
+
+        let (next_current, next_edited) = match next_base {
+            i if i < base.len() => (current_matches[i].unwrap(), edited_matches[i].unwrap()),
+            _ => (current.len(), edited.len()),
+        };
+
+        let base_region = &base[i_base..next_base];
+        let current_region = &current[i_current..next_current];
+        let edited_region = &edited[i_edited..next_edited];
+
+        if current_region == base_region || current_region == edited_region {
+            edited_region
+                .iter()
+                .for_each(|line| output.extend_from_slice(line));
+        } else if edited_region == base_region {
+            current_region
+                .iter()
+                .for_each(|line| output.extend_from_slice(line));
+        } else {
+            conflicts.push(MergeConflict {
+                base_line: i_base as u32 + 1,
+                current_line: i_current as u32 + 1,
+                base: join_lines(base_region),
+                current: join_lines(current_region),
+                edited: join_lines(edited_region),
+            });
+        }
+
+        i_base = next_base;
+        i_current = next_current;
+        i_edited = next_edited;
+    }
+
+    if conflicts.is_empty() {
+        Ok(output)
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// For each line in `base`, finds the index of the same line in `other`,
+/// if it was left unchanged.
+fn match_lines(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+
+    for edit in myers::diff(base, other) {
+        if let Edit::Equal { old, new } = edit {
+            matches[old] = Some(new);
+        }
+    }
+
+    matches
+}
+
+fn join_lines(lines: &[&[u8]]) -> String {
+    let bytes = lines.concat();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
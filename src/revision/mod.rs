@@ -18,17 +18,32 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
+mod backend;
 mod blame;
+mod change;
+mod diff;
+mod git;
 mod git_hash;
+mod git_store;
 mod info;
-mod process;
+mod log;
+mod memory_store;
+mod merge;
+mod myers;
 mod store;
+mod word_diff;
 
 #[cfg(test)]
 mod test;
 
+pub use self::backend::RevisionBackend;
 pub use self::blame::Blame;
+pub use self::change::PageChange;
+pub use self::diff::{Diff, DiffChunk, DiffFile, DiffHunk, DiffLine};
 pub use self::git_hash::GitHash;
+pub use self::git_store::GitStore;
 pub use self::info::CommitInfo;
-pub use self::process::{spawn, spawn_output};
+pub use self::log::LogEntry;
+pub use self::memory_store::MemoryStore;
+pub use self::merge::{merge, MergeConflict};
 pub use self::store::RevisionStore;
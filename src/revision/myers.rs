@@ -0,0 +1,434 @@
+/*
+ * revision/myers.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Linear-space implementation of Myers' difference algorithm.
+//!
+//! This is used for both line and word diffs, as well as for blame.
+//!
+//! After the edit script is computed, groups of changes are slid the same
+//! way git does, so ambiguous diffs are shown the same as with the git binary.
+
+use std::ops::{Index, IndexMut};
+
+/// A single step in transforming the old sequence into the new one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+impl Edit {
+    #[inline]
+    pub fn is_equal(self) -> bool {
+        matches!(self, Edit::Equal { .. })
+    }
+}
+
+/// Computes a minimal edit script between the two sequences.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut vf = V::new(max_d);
+    let mut vb = V::new(max_d);
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+
+    conquer(
+        old,
+        0,
+        old.len(),
+        new,
+        0,
+        new.len(),
+        &mut vf,
+        &mut vb,
+        &mut edits,
+    );
+
+    let mut old_changed = ChangeFlags::new(old.len());
+    let mut new_changed = ChangeFlags::new(new.len());
+
+    for edit in &edits {
+        match *edit {
+            Edit::Equal { .. } => (),
+            Edit::Delete { old } => old_changed.set(old as isize, true),
+            Edit::Insert { new } => new_changed.set(new as isize, true),
+        }
+    }
+
+    compact(old, &mut old_changed, &new_changed);
+    compact(new, &mut new_changed, &old_changed);
+
+    // Rebuild the edit script from the adjusted flags
+    let (mut i, mut j) = (0, 0);
+    edits.clear();
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && old_changed.get(i as isize) {
+            edits.push(Edit::Delete { old: i });
+            i += 1;
+        } else if j < new.len() && new_changed.get(j as isize) {
+            edits.push(Edit::Insert { new: j });
+            j += 1;
+        } else {
+            edits.push(Edit::Equal { old: i, new: j });
+            i += 1;
+            j += 1;
+        }
+    }
+
+    edits
+}
+
+/// Diagonal-indexed storage of the furthest reaching x values.
+#[derive(Debug)]
+struct V {
+    offset: isize,
+    values: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> Self {
+        V {
+            offset: max_d as isize,
+            values: vec![0; 2 * max_d + 1],
+        }
+    }
+}
+
+impl Index<isize> for V {
+    type Output = usize;
+
+    #[inline]
+    fn index(&self, k: isize) -> &usize {
+        &self.values[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for V {
+    #[inline]
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.values[(k + self.offset) as usize]
+    }
+}
+
+fn common_prefix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn find_middle_snake<T: PartialEq>(
+    old: &[T],
+    old_start: usize,
+    old_end: usize,
+    new: &[T],
+    new_start: usize,
+    new_end: usize,
+    vf: &mut V,
+    vb: &mut V,
+) -> Option<(usize, usize)> {
+    let n = old_end - old_start;
+    let m = new_end - new_start;
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+
+    vf[1] = 0;
+    vb[1] = 0;
+
+    let d_max = ((n + m).div_ceil(2) + 1) as isize;
+    for d in 0..d_max {
+        // Forward path
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                vf[k + 1]
+            } else {
+                vf[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+
+            let (x0, y0) = (x, y);
+            if x < n && y < m {
+                x += common_prefix(&old[old_start + x..old_end], &new[new_start + y..new_end]);
+            }
+
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some((x0 + old_start, y0 + new_start));
+            }
+        }
+
+        // Backward path
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                vb[k + 1]
+            } else {
+                vb[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+
+            if x < n && y < m {
+                let advance = common_suffix(
+                    &old[old_start..old_start + n - x],
+                    &new[new_start..new_start + m - y],
+                );
+
+                x += advance;
+                y += advance;
+            }
+
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((n - x + old_start, m - y + new_start));
+            }
+        }
+    }
+
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_start: usize,
+    mut old_end: usize,
+    new: &[T],
+    mut new_start: usize,
+    mut new_end: usize,
+    vf: &mut V,
+    vb: &mut V,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix(&old[old_start..old_end], &new[new_start..new_end]);
+    for i in 0..prefix {
+        edits.push(Edit::Equal {
+            old: old_start + i,
+            new: new_start + i,
+        });
+    }
+
+    old_start += prefix;
+    new_start += prefix;
+
+    let suffix = common_suffix(&old[old_start..old_end], &new[new_start..new_end]);
+    old_end -= suffix;
+    new_end -= suffix;
+
+    if old_start == old_end {
+        edits.extend((new_start..new_end).map(|new| Edit::Insert { new }));
+    } else if new_start == new_end {
+        edits.extend((old_start..old_end).map(|old| Edit::Delete { old }));
+    } else if let Some((x, y)) =
+        find_middle_snake(old, old_start, old_end, new, new_start, new_end, vf, vb)
+    {
+        conquer(old, old_start, x, new, new_start, y, vf, vb, edits);
+        conquer(old, x, old_end, new, y, new_end, vf, vb, edits);
+    } else {
+        edits.extend((old_start..old_end).map(|old| Edit::Delete { old }));
+        edits.extend((new_start..new_end).map(|new| Edit::Insert { new }));
+    }
+
+    for i in 0..suffix {
+        edits.push(Edit::Equal {
+            old: old_end + i,
+            new: new_end + i,
+        });
+    }
+}
+
+/// Which items of a sequence are changed, with unchanged sentinels on either end.
+#[derive(Debug, Clone)]
+struct ChangeFlags {
+    flags: Vec<bool>,
+}
+
+impl ChangeFlags {
+    fn new(len: usize) -> Self {
+        ChangeFlags {
+            flags: vec![false; len + 2],
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> isize {
+        self.flags.len() as isize - 2
+    }
+
+    #[inline]
+    fn get(&self, index: isize) -> bool {
+        self.flags[(index + 1) as usize]
+    }
+
+    #[inline]
+    fn set(&mut self, index: isize, value: bool) {
+        self.flags[(index + 1) as usize] = value;
+    }
+}
+
+/// A run of changed items, which may be empty.
+#[derive(Debug, Copy, Clone)]
+struct Group {
+    start: isize,
+    end: isize,
+}
+
+impl Group {
+    fn first(changed: &ChangeFlags) -> Self {
+        let mut end = 0;
+        while changed.get(end) {
+            end += 1;
+        }
+
+        Group { start: 0, end }
+    }
+
+    #[inline]
+    fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    fn next(&mut self, changed: &ChangeFlags) -> bool {
+        if self.end == changed.len() {
+            return false;
+        }
+
+        self.start = self.end + 1;
+        self.end = self.start;
+        while changed.get(self.end) {
+            self.end += 1;
+        }
+
+        true
+    }
+
+    fn previous(&mut self, changed: &ChangeFlags) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+
+        self.end = self.start - 1;
+        self.start = self.end;
+        while changed.get(self.start - 1) {
+            self.start -= 1;
+        }
+
+        true
+    }
+
+    fn slide_down<T: PartialEq>(&mut self, items: &[T], changed: &mut ChangeFlags) -> bool {
+        if self.end < changed.len() && items[self.start as usize] == items[self.end as usize] {
+            changed.set(self.start, false);
+            changed.set(self.end, true);
+            self.start += 1;
+            self.end += 1;
+
+            while changed.get(self.end) {
+                self.end += 1;
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn slide_up<T: PartialEq>(&mut self, items: &[T], changed: &mut ChangeFlags) -> bool {
+        if self.start > 0 && items[self.start as usize - 1] == items[self.end as usize - 1] {
+            self.start -= 1;
+            self.end -= 1;
+            changed.set(self.start, true);
+            changed.set(self.end, false);
+
+            while changed.get(self.start - 1) {
+                self.start -= 1;
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Moves groups of changes to merge them where possible, and otherwise
+/// to line up with changes in the other sequence, or as far down as they go.
+///
+/// This is a port of `xdl_change_compact()` from git, without the indent heuristic.
+fn compact<T: PartialEq>(items: &[T], changed: &mut ChangeFlags, other: &ChangeFlags) {
+    const SYNC_ERROR: &str = "Change groups out of sync";
+
+    let mut group = Group::first(changed);
+    let mut other_group = Group::first(other);
+
+    loop {
+        if !group.is_empty() {
+            let mut earliest_end;
+            let mut end_matching_other;
+
+            loop {
+                let size = group.end - group.start;
+                end_matching_other = None;
+
+                // Shift the group up as much as possible
+                while group.slide_up(items, changed) {
+                    assert!(other_group.previous(other), "{}", SYNC_ERROR);
+                }
+
+                earliest_end = group.end;
+                if !other_group.is_empty() {
+                    end_matching_other = Some(group.end);
+                }
+
+                // Then shift it down as far as possible
+                while group.slide_down(items, changed) {
+                    assert!(other_group.next(other), "{}", SYNC_ERROR);
+
+                    if !other_group.is_empty() {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+
+            // Line up with a change in the other sequence, if there is one in reach
+            if group.end != earliest_end && end_matching_other.is_some() {
+                while other_group.is_empty() {
+                    assert!(group.slide_up(items, changed), "{}", SYNC_ERROR);
+                    assert!(other_group.previous(other), "{}", SYNC_ERROR);
+                }
+            }
+        }
+
+        if !group.next(changed) {
+            break;
+        }
+
+        assert!(other_group.next(other), "{}", SYNC_ERROR);
+    }
+}
@@ -18,404 +18,518 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-use super::{Blame, CommitInfo, GitHash};
+use super::git::{clean_message, Commit, ObjectStore, Signature, Tree};
+use super::word_diff::write_diff;
+use super::{Blame, CommitInfo, GitHash, LogEntry, PageChange};
 use crate::{Error, Result};
-use parking_lot::RwLock;
+use chrono::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::ffi::{OsStr, OsString};
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::str;
+use std::fmt::Debug;
 use wikidot_normalize::is_normal;
 
-macro_rules! arguments {
-    ($($x:expr), *) => {{
-        use arrayvec::ArrayVec;
+/// A versioned storage backend for page contents and their histories.
+///
+/// Every change produces a commit with a git-style hash, which is what
+/// gets recorded in the `revisions` table.
+pub trait RevisionStore: Debug + Send + Sync {
+    /// Determines if the store has been set up and has an initial commit.
+    fn is_initialized(&self) -> Result<bool>;
 
-        let mut arguments = ArrayVec::<[&OsStr; 16]>::new();
+    /// Create the first commit of the store.
+    /// Should only be called on empty stores.
+    fn initial_commit(&self) -> Result<()>;
 
-        $(
-            arguments.push(OsStr::new($x));
-        )*
+    /// For the given slug, create or edit a page to have the specified contents.
+    /// If no contents are given, the page's current contents are committed.
+    fn commit(&self, slug: &str, content: Option<&[u8]>, info: CommitInfo) -> Result<GitHash>;
 
-        arguments
-    }};
-    ($($x:expr,)*) => (arguments![$($x),*]);
-}
+    /// Applies several page changes in order, producing a single commit.
+    /// Nothing is committed if any of the changes fail.
+    fn commit_batch(&self, changes: &[PageChange], info: CommitInfo) -> Result<GitHash>;
 
-/// An object that can't be copied or cloned for a `RwLock`.
-#[derive(Debug)]
-struct RevisionBlock;
+    /// Creates an empty commit.
+    fn empty_commit(&self, info: CommitInfo) -> Result<GitHash>;
 
-/// Represents a git repository to store page contents and their histories.
-#[derive(Debug)]
-pub struct RevisionStore {
-    lock: RwLock<RevisionBlock>,
-    repo: PathBuf,
-    domain: RwLock<String>,
-}
+    /// Renames the given page.
+    fn rename(&self, old_slug: &str, new_slug: &str, info: CommitInfo) -> Result<GitHash>;
 
-impl RevisionStore {
-    /// Creates a new revision store using the given `git2::Repository` and domain name.
-    ///
-    /// The domain name should not be prefixed with a protocol such as `https://` but does
-    /// permit subdomains.
-    #[inline]
-    pub fn new<P, S>(repo: P, domain: S) -> Self
-    where
-        P: Into<PathBuf>,
-        S: Into<String>,
-    {
-        let lock = RwLock::new(RevisionBlock);
-        let repo = repo.into();
-        let domain = domain.into();
-
-        info!(
-            "Creating new revision store for repository {}, domain {}",
-            repo.display(),
-            domain,
-        );
-
-        let domain = RwLock::new(domain);
-
-        RevisionStore { lock, repo, domain }
-    }
+    /// Remove the given page.
+    /// Returns `None` if the page does not exist.
+    fn remove(&self, slug: &str, info: CommitInfo) -> Result<Option<GitHash>>;
 
-    // Filesystem helpers
-    fn get_path(&self, slug: &str, absolute: bool) -> PathBuf {
-        trace!(
-            "Converting slug '{}' to path (absolute: {})",
-            slug,
-            absolute,
-        );
+    /// Gets the current version of a page.
+    /// Returns `None` if the page does not exist.
+    fn get_page(&self, slug: &str) -> Result<Option<Box<[u8]>>>;
 
-        let filename = {
-            let mut filename = String::new();
+    /// Gets the version of a page at the specified commit.
+    /// Returns `None` if the page did not at exist at the time.
+    fn get_page_version(&self, slug: &str, hash: &GitHash) -> Result<Option<Box<[u8]>>>;
 
-            for part in slug.split(':') {
-                filename.push_str(part);
-                filename.push('$');
-            }
+    /// Gets the diff between two versions of a page, each given as a slug and commit.
+    /// The slugs differ when comparing versions from before and after a rename.
+    ///
+    /// The output is the same as `git diff --word-diff=porcelain`,
+    /// with the file named after the second slug.
+    fn get_diff(&self, first: (&str, &GitHash), second: (&str, &GitHash)) -> Result<Box<[u8]>>;
 
-            filename.pop();
-            filename
-        };
+    /// Gets the blame for a particular page, at the given commit or the latest one.
+    /// Returns `None` if the page does not exist.
+    fn get_blame(&self, slug: &str, hash: Option<GitHash>) -> Result<Option<Blame>>;
 
-        let mut path = PathBuf::new();
+    /// Gets the slug a page had before the given commit renamed it to `new_slug`.
+    /// Returns `None` if the commit did not rename a page to that slug.
+    ///
+    /// This is guessed from the file contents, so it's only meant for older
+    /// renames which didn't record the slug they moved the page from.
+    fn get_rename(&self, hash: &GitHash, new_slug: &str) -> Result<Option<String>>;
 
-        if absolute {
-            path.push(&self.repo);
-        }
+    /// Gets the latest commit, or `None` if there are none.
+    fn head(&self) -> Result<Option<GitHash>>;
 
-        path.push(&filename);
-        path.set_extension("ftml");
-        path
-    }
+    /// Moves the store back to the given commit, discarding any later changes.
+    fn reset(&self, hash: &GitHash) -> Result<()>;
 
-    fn read_file(&self, slug: &str) -> Result<Option<Box<[u8]>>> {
-        let path = self.get_path(slug, true);
+    /// Records the latest commit before starting a change, so that it can be
+    /// undone with [`reset`] if the process exits before the change is finished.
+    ///
+    /// [`reset`]: #tymethod.reset
+    fn begin_journal(&self) -> Result<()>;
 
-        debug!("Reading file from {}", path.display());
+    /// Clears the record of a change in progress.
+    fn end_journal(&self) -> Result<()>;
 
-        let mut file = match File::open(&path) {
-            Ok(file) => file,
-            Err(error) => {
-                use std::io::ErrorKind;
+    /// Gets the commit recorded by a change which was never finished, if any.
+    fn pending_journal(&self) -> Result<Option<GitHash>>;
 
-                return match error.kind() {
-                    ErrorKind::NotFound => Ok(None),
-                    _ => Err(Error::from(error)),
-                };
-            }
-        };
+    /// Gets every commit reachable from the latest one, following first parents.
+    /// The oldest commit appears first.
+    fn get_log(&self) -> Result<Vec<LogEntry>>;
 
-        let mut content = Vec::new();
-        file.read_to_end(&mut content)?;
-        let bytes = content.into_boxed_slice();
-        Ok(Some(bytes))
-    }
+    /// Rewrites history without the given commits, moving `HEAD` to the result.
+    /// Returns the new hashes of the later commits which had to be rewritten.
+    ///
+    /// Every page moved in the remaining history must be listed in `renames`
+    /// by its commit, as an `(old_slug, new_slug)` pair.
+    ///
+    /// The old commits are kept until [`prune`] is called.
+    ///
+    /// [`prune`]: #tymethod.prune
+    fn rewrite(
+        &self,
+        drop: &HashSet<GitHash>,
+        renames: &HashMap<GitHash, Vec<(String, String)>>,
+    ) -> Result<HashMap<GitHash, GitHash>>;
+
+    /// Permanently deletes objects which can't be reached from `HEAD`.
+    /// Returns the number of objects removed.
+    fn prune(&self) -> Result<usize>;
+
+    /// Sets the domain used in commit author emails to a different value.
+    fn set_domain(&self, new_domain: &str);
+}
 
-    fn write_file(&self, slug: &str, content: &[u8]) -> Result<()> {
-        let path = self.get_path(slug, true);
+// Helpers shared between implementations
 
-        debug!("Writing {} bytes to {}", content.len(), path.display());
+pub fn check_normal(slug: &str) -> Result<()> {
+    trace!("Checking slug for normal form: {}", slug);
 
-        let mut file = File::create(path)?;
-        file.write_all(content)?;
+    if is_normal(slug, false) {
         Ok(())
+    } else {
+        Err(Error::StaticMsg("slug not in wikidot normal form"))
     }
+}
 
-    fn remove_file(&self, slug: &str) -> Result<Option<()>> {
-        let path = self.get_path(slug, true);
+/// Gets the filename a page is stored under.
+/// Since colons aren't permitted in filenames everywhere, categories are separated by `$`.
+pub fn slug_filename(slug: &str) -> String {
+    trace!("Converting slug '{}' to filename", slug);
 
-        debug!("Removing file {}", path.display());
+    format!("{}.ftml", slug.replace(':', "$"))
+}
 
-        match fs::remove_file(path) {
-            Ok(_) => (),
-            Err(error) => {
-                use std::io::ErrorKind;
+/// Gets the slug for a page's filename, or `None` if it isn't a page.
+pub fn filename_slug(filename: &str) -> Option<String> {
+    filename
+        .strip_suffix(".ftml")
+        .map(|slug| slug.replace('$', ":"))
+}
 
-                return match error.kind() {
-                    ErrorKind::NotFound => Ok(None),
-                    _ => Err(Error::from(error)),
-                };
-            }
-        }
+/// Writes a commit of the given tree on top of `parent`.
+pub fn write_commit<S>(
+    objects: &S,
+    tree: &Tree,
+    parent: Option<GitHash>,
+    author: Signature,
+    info: CommitInfo,
+) -> Result<GitHash>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commit = Commit {
+        tree: objects.write_tree(tree)?,
+        parents: parent.into_iter().collect(),
+        committer: author.clone(),
+        author,
+        message: clean_message(info.message),
+    };
+
+    let hash = objects.write_commit(&commit)?;
+
+    debug!("Created commit {}", hash);
+    Ok(hash)
+}
 
-        Ok(Some(()))
-    }
+/// Applies the changes of a batch commit to a tree, in order.
+pub fn apply_changes<S>(objects: &S, tree: &mut Tree, changes: &[PageChange]) -> Result<()>
+where
+    S: ObjectStore + ?Sized,
+{
+    for change in changes {
+        trace!("Applying batch change {:?}", change);
 
-    // Argument helpers
-    fn arg_author(&self, name: &str) -> String {
-        let domain = self.domain.read();
+        match *change {
+            PageChange::Write { slug, content } => {
+                check_normal(slug)?;
 
-        format!("--author={} <noreply@{}>", name, domain)
-    }
+                let blob = objects.write_blob(content)?;
+                tree.insert(&slug_filename(slug), blob);
+            }
+            PageChange::Rename { old_slug, new_slug } => {
+                check_normal(old_slug)?;
+                check_normal(new_slug)?;
 
-    fn arg_message(&self, message: &str) -> String {
-        format!("--message={}", message)
-    }
+                if tree.get(&slug_filename(new_slug)).is_some() {
+                    return Err(Error::PageExists);
+                }
 
-    // Process helpers
-    fn repo(&self) -> OsString {
-        self.repo.as_os_str().to_os_string()
-    }
+                let entry = tree
+                    .remove(&slug_filename(old_slug))
+                    .ok_or(Error::PageNotFound)?;
 
-    fn spawn(&self, arguments: &[&OsStr]) -> Result<()> {
-        super::spawn(self.repo(), arguments)
-    }
+                tree.insert(&slug_filename(new_slug), entry.hash);
+            }
+            PageChange::Remove { slug } => {
+                check_normal(slug)?;
 
-    fn spawn_output(&self, arguments: &[&OsStr]) -> Result<Box<[u8]>> {
-        super::spawn_output(self.repo(), arguments)
+                tree.remove(&slug_filename(slug))
+                    .ok_or(Error::PageNotFound)?;
+            }
+        }
     }
 
-    // Git helpers
-    fn get_commit(&self) -> Result<GitHash> {
-        let args = arguments!["git", "rev-parse", "--verify", "HEAD"];
+    Ok(())
+}
 
-        debug!("Getting current HEAD commit");
+pub fn read_page_version<S>(objects: &S, slug: &str, hash: &GitHash) -> Result<Option<Box<[u8]>>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commit = match objects.read_commit(hash)? {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+
+    let blob = objects.read_file(&commit, &slug_filename(slug))?;
+    Ok(blob.map(|(_, content)| content.into_boxed_slice()))
+}
 
-        let digest_bytes = self.spawn_output(&args)?;
-        let digest = str::from_utf8(&digest_bytes)
-            .map_err(|_| Error::StaticMsg("git hash wasn't valid UTF-8"))?;
+pub fn diff_page<S>(
+    objects: &S,
+    (first_slug, first): (&str, &GitHash),
+    (second_slug, second): (&str, &GitHash),
+) -> Result<Box<[u8]>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let read = |slug, hash| -> Result<Option<(GitHash, Vec<u8>)>> {
+        let commit = objects.read_commit(hash)?.ok_or(Error::RevisionNotFound)?;
+
+        objects.read_file(&commit, &slug_filename(slug))
+    };
+
+    let first = read(first_slug, first)?;
+    let second = read(second_slug, second)?;
+    let diff = write_diff(
+        &slug_filename(second_slug),
+        first
+            .as_ref()
+            .map(|(hash, content)| (hash, content.as_slice())),
+        second
+            .as_ref()
+            .map(|(hash, content)| (hash, content.as_slice())),
+    );
+
+    Ok(diff.into_boxed_slice())
+}
 
-        let hash = GitHash::try_from(digest)
-            .map_err(|_| Error::StaticMsg("unable to parse git hash from output"))?;
+/// Finds the page which was moved in the given commit, by looking for a file
+/// which was added with the same contents as one that was removed.
+///
+/// This is only a guess, for older renames which didn't record their old slug.
+pub fn read_rename<S>(objects: &S, hash: &GitHash, new_slug: &str) -> Result<Option<String>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commit = objects.read_commit(hash)?.ok_or(Error::RevisionNotFound)?;
+    let tree = objects.read_tree(&commit.tree)?;
+    let parent = objects.commit_tree(commit.parent())?;
+
+    let filename = slug_filename(new_slug);
+    let entry = match tree.get(&filename) {
+        Some(entry) if parent.get(&filename).is_none() => entry,
+        _ => return Ok(None),
+    };
+
+    let source = parent
+        .entries()
+        .iter()
+        .find(|old| old.hash == entry.hash && tree.get(&old.name).is_none());
+
+    Ok(source.and_then(|old| filename_slug(&old.name)))
+}
 
-        Ok(hash)
+pub fn blame_page<S>(objects: &S, slug: &str, hash: Option<GitHash>) -> Result<Option<Blame>>
+where
+    S: ObjectStore + ?Sized,
+{
+    match hash {
+        Some(hash) => Blame::build(objects, &hash, &slug_filename(slug)),
+        None => Ok(None),
     }
+}
 
-    /// Create the first commit of the repo.
-    /// Should only be called on empty repositories.
-    #[cold]
-    pub fn initial_commit(&self) -> Result<()> {
-        info!("Initializing new git repository");
-
-        let _guard = self.lock.write();
-        let args = arguments!["git", "init"];
-        self.spawn(&args)?;
-
-        let author = self.arg_author("DEEPWELL");
-        let message = self.arg_message("Initial commit");
-        let args = arguments!["git", "commit", "--allow-empty", &author, &message];
-
-        self.spawn(&args)?;
-        Ok(())
+/// Gets every commit reachable from `head` by following first parents, oldest first.
+fn first_parents<S>(objects: &S, head: Option<GitHash>) -> Result<Vec<(GitHash, Commit)>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let mut commits = Vec::new();
+    let mut next = head;
+
+    while let Some(hash) = next {
+        let commit = objects
+            .read_commit(&hash)?
+            .ok_or(Error::StaticMsg("commit missing from repository"))?;
+
+        next = commit.parent().cloned();
+        commits.push((hash, commit));
     }
 
-    /// For the given slug, create or edit a page to have the specified contents.
-    pub fn commit(&self, slug: &str, content: Option<&[u8]>, info: CommitInfo) -> Result<GitHash> {
-        info!(
-            "Committing file changes for slug '{}' ({} bytes)",
-            slug,
-            content.map(|b| b.len()).unwrap_or(0),
-        );
-
-        let _guard = self.lock.write();
-        check_normal(slug)?;
-
-        if let Some(content) = content {
-            self.write_file(slug, content)?;
-        }
-
-        let path = self.get_path(slug, false);
-        let args = arguments!["git", "add", &path];
-        self.spawn(&args)?;
-
-        let author = self.arg_author(info.username);
-        let message = self.arg_message(info.message);
-        let args = arguments![
-            "git",
-            "commit",
-            "--allow-empty",
-            &author,
-            &message,
-            "--",
-            &path,
-        ];
-        self.spawn(&args)?;
-
-        self.get_commit()
-    }
+    commits.reverse();
+    Ok(commits)
+}
 
-    /// Creates an empty commit.
-    pub fn empty_commit(&self, info: CommitInfo) -> Result<GitHash> {
-        info!("Creating empty commit");
+pub fn read_log<S>(objects: &S, head: Option<GitHash>) -> Result<Vec<LogEntry>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let commits = first_parents(objects, head)?;
+    let mut log = Vec::with_capacity(commits.len());
+    let mut previous = Tree::new();
+
+    for (hash, commit) in commits {
+        let tree = objects.read_tree(&commit.tree)?;
+        let mut added = Vec::new();
+        let mut modified = Vec::new();
+        let mut removed = Vec::new();
+
+        for entry in tree.entries() {
+            if let Some(slug) = filename_slug(&entry.name) {
+                match previous.get(&entry.name) {
+                    Some(old) if old.hash == entry.hash => (),
+                    Some(_) => modified.push(slug),
+                    None => added.push(slug),
+                }
+            }
+        }
 
-        let _guard = self.lock.write();
-        let author = self.arg_author(info.username);
-        let message = self.arg_message(info.message);
-        let args = arguments!["git", "commit", "--allow-empty", &author, &message];
-        self.spawn(&args)?;
+        for entry in previous.entries() {
+            if tree.get(&entry.name).is_none() {
+                removed.extend(filename_slug(&entry.name));
+            }
+        }
 
-        self.get_commit()
+        log.push(LogEntry {
+            hash,
+            username: commit.author.name.clone(),
+            created_at: commit.author.datetime()?.with_timezone(&Utc),
+            message: commit.message,
+            added,
+            modified,
+            removed,
+        });
+
+        previous = tree;
     }
 
-    /// Renames the given page in the repository.
-    pub fn rename(&self, old_slug: &str, new_slug: &str, info: CommitInfo) -> Result<GitHash> {
-        info!("Renaming file for slug '{}' -> '{}'", old_slug, new_slug);
-
-        let _guard = self.lock.write();
-        check_normal(old_slug)?;
-        check_normal(new_slug)?;
+    Ok(log)
+}
 
-        let new_path = self.get_path(new_slug, true);
-        if new_path.exists() {
-            return Err(Error::PageExists);
+/// Replays the history leading up to `head`, leaving out the given commits.
+///
+/// Each remaining commit reapplies only the files it changed, so content
+/// written by a dropped commit is never carried forward. Pages moved by one
+/// of the `renames`, given as `(old_slug, new_slug)` pairs for each commit,
+/// keep whatever content they have in the rewritten history.
+/// Commits before the first dropped one come out with the same hashes, and
+/// references to rewritten commits within commit messages are updated.
+///
+/// Returns the new head and a map from old to new hashes for every
+/// remaining commit which changed.
+pub fn rewrite_history<S>(
+    objects: &S,
+    head: Option<GitHash>,
+    drop: &HashSet<GitHash>,
+    renames: &HashMap<GitHash, Vec<(String, String)>>,
+) -> Result<(Option<GitHash>, HashMap<GitHash, GitHash>)>
+where
+    S: ObjectStore + ?Sized,
+{
+    let mut rewritten = HashMap::new();
+    let mut parent = None;
+    let mut previous = Tree::new();
+    let mut tree = Tree::new();
+
+    for (hash, commit) in first_parents(objects, head)? {
+        let old_tree = objects.read_tree(&commit.tree)?;
+
+        if drop.contains(&hash) {
+            debug!("Dropping commit {}", hash);
+
+            previous = old_tree;
+            continue;
         }
 
-        let old_path = self.get_path(old_slug, false);
-        let new_path = self.get_path(new_slug, false);
-        let args = arguments!["git", "mv", "--", &old_path, &new_path];
-        self.spawn(&args)?;
-
-        let author = self.arg_author(info.username);
-        let message = self.arg_message(info.message);
-        let args = arguments!["git", "commit", &author, &message, "--", &new_path];
-        self.spawn(&args)?;
+        let mut changes = Vec::new();
+        for entry in old_tree.entries() {
+            match previous.get(&entry.name) {
+                Some(old) if old.hash == entry.hash => (),
+                Some(_) => changes.push((entry.name.clone(), Some(entry.hash.clone()))),
+                None => {
+                    let source = renames.get(&hash).and_then(|renames| {
+                        renames
+                            .iter()
+                            .find(|(_, new_slug)| slug_filename(new_slug) == entry.name)
+                    });
+
+                    let blob = match source {
+                        Some((old_slug, _)) => tree
+                            .get(&slug_filename(old_slug))
+                            .map(|entry| entry.hash.clone()),
+                        None => Some(entry.hash.clone()),
+                    };
+
+                    changes.push((entry.name.clone(), blob));
+                }
+            }
+        }
 
-        self.get_commit()
-    }
+        for entry in previous.entries() {
+            if old_tree.get(&entry.name).is_none() {
+                changes.push((entry.name.clone(), None));
+            }
+        }
 
-    /// Remove the given page from the repository.
-    /// Returns `None` if the page does not exist.
-    pub fn remove(&self, slug: &str, info: CommitInfo) -> Result<Option<GitHash>> {
-        info!("Removing file for slug '{}' (info: {:?})", slug, info);
+        for (name, blob) in changes {
+            match blob {
+                Some(blob) => tree.insert(&name, blob),
+                None => {
+                    tree.remove(&name);
+                }
+            }
+        }
 
-        let _guard = self.lock.write();
-        check_normal(slug)?;
+        let new_commit = Commit {
+            tree: objects.write_tree(&tree)?,
+            parents: parent.into_iter().collect(),
+            author: commit.author,
+            committer: commit.committer,
+            message: remap_hashes(&commit.message, &rewritten),
+        };
 
-        if self.remove_file(slug)?.is_none() {
-            return Ok(None);
+        let new_hash = objects.write_commit(&new_commit)?;
+        if new_hash != hash {
+            trace!("Rewrote commit {} -> {}", hash, new_hash);
+            rewritten.insert(hash, new_hash.clone());
         }
 
-        let author = self.arg_author(info.username);
-        let message = self.arg_message(info.message);
-        let path = self.get_path(slug, false);
-        let args = arguments!["git", "commit", &author, &message, "--", &path];
-
-        self.spawn(&args)?;
-        self.get_commit().map(Some)
+        parent = Some(new_hash);
+        previous = old_tree;
     }
 
-    /// Gets the current version of a page.
-    /// Returns `None` if the page does not exist.
-    pub fn get_page(&self, slug: &str) -> Result<Option<Box<[u8]>>> {
-        info!("Getting page content for slug '{}'", slug);
-
-        let _guard = self.lock.read();
-        check_normal(slug)?;
+    Ok((parent, rewritten))
+}
 
-        self.read_file(slug)
-    }
+/// Replaces any full commit hashes in the message which were rewritten.
+fn remap_hashes(message: &str, rewritten: &HashMap<GitHash, GitHash>) -> String {
+    let mut output = String::with_capacity(message.len());
+    let mut rest = message;
+
+    while !rest.is_empty() {
+        let end = rest
+            .find(|ch: char| !ch.is_ascii_hexdigit())
+            .unwrap_or(rest.len());
+
+        let (word, tail) = rest.split_at(end);
+        // Longer runs of hex digits aren't hashes
+        let replacement = if word.len() == 40 {
+            GitHash::try_from(word)
+                .ok()
+                .and_then(|hash| rewritten.get(&hash))
+        } else {
+            None
+        };
 
-    /// Gets the version of a page at the specified commit.
-    /// Returns `None` if the page did not at exist at the time.
-    pub fn get_page_version(&self, slug: &str, hash: &GitHash) -> Result<Option<Box<[u8]>>> {
-        info!(
-            "Getting page content for slug '{}' at commit {}",
-            slug, hash,
-        );
-
-        let _guard = self.lock.read();
-        check_normal(slug)?;
-
-        let path = self.get_path(slug, false);
-        let spec = format!("{}:{}", hash, path.display());
-        let args = arguments!["git", "show", "--format=%B", &spec];
-
-        match self.spawn_output(&args) {
-            Ok(bytes) => Ok(Some(bytes)),
-            Err(Error::CommandFailed(_)) => Ok(None),
-            Err(error) => Err(error),
+        match replacement {
+            Some(hash) => output.push_str(hash.as_str()),
+            None => output.push_str(word),
         }
-    }
 
-    /// Gets the diff between commits of a particular page.
-    /// Returns `None` if the page or commits do not exist.
-    pub fn get_diff(&self, slug: &str, first: &GitHash, second: &GitHash) -> Result<Box<[u8]>> {
-        info!(
-            "Getting diff for slug '{}' between {}..{}",
-            slug, first, second,
-        );
-
-        let _guard = self.lock.read();
-        check_normal(slug)?;
-        let path = self.get_path(slug, false);
-
-        let args = arguments![
-            "git",
-            "diff",
-            "--word-diff=porcelain",
-            &first,
-            &second,
-            "--",
-            &path,
-        ];
-        self.spawn_output(&args)
+        let next = tail.chars().next().map_or(0, char::len_utf8);
+        output.push_str(&tail[..next]);
+        rest = &tail[next..];
     }
 
-    /// Gets the blame for a particular page.
-    /// Returns `None` if the page does not exist.
-    pub fn get_blame(&self, slug: &str, hash: Option<GitHash>) -> Result<Option<Blame>> {
-        info!("Getting blame for slug '{}'", slug);
+    output
+}
 
-        let _guard = self.lock.read();
-        check_normal(slug)?;
-        let path = self.get_path(slug, false);
+/// Gets the hashes of every object reachable from `head`.
+pub fn reachable_objects<S>(objects: &S, head: Option<GitHash>) -> Result<HashSet<GitHash>>
+where
+    S: ObjectStore + ?Sized,
+{
+    let mut reachable = HashSet::new();
+    let mut commits = head.into_iter().collect::<Vec<_>>();
+    let mut trees = Vec::new();
+
+    while let Some(hash) = commits.pop() {
+        if !reachable.insert(hash.clone()) {
+            continue;
+        }
 
-        let args = match hash {
-            Some(ref hash) => arguments!["git", "blame", "--porcelain", hash, "--", &path],
-            None => arguments!["git", "blame", "--porcelain", "--", &path],
-        };
+        let commit = objects
+            .read_commit(&hash)?
+            .ok_or(Error::StaticMsg("commit missing from repository"))?;
 
-        let raw_blame = match self.spawn_output(&args) {
-            Ok(bytes) => bytes,
-            Err(Error::CommandFailed(_)) => return Ok(None),
-            Err(error) => return Err(error),
-        };
-
-        let blame = Blame::from_porcelain(&raw_blame)?;
-        Ok(Some(blame))
+        trees.push(commit.tree);
+        commits.extend(commit.parents);
     }
 
-    /// Sets the domain to a different value.
-    pub fn set_domain(&self, new_domain: &str) {
-        trace!("Acquiring domain write lock to change: {}", new_domain);
+    while let Some(hash) = trees.pop() {
+        if !reachable.insert(hash.clone()) {
+            continue;
+        }
 
-        let mut guard = self.domain.write();
-        guard.clear();
-        guard.push_str(new_domain);
+        for entry in objects.read_tree(&hash)?.entries() {
+            if entry.is_directory() {
+                trees.push(entry.hash.clone());
+            } else {
+                reachable.insert(entry.hash.clone());
+            }
+        }
     }
-}
-
-fn check_normal(slug: &str) -> Result<()> {
-    trace!("Checking slug for normal form: {}", slug);
 
-    if is_normal(slug, false) {
-        Ok(())
-    } else {
-        Err(Error::StaticMsg("slug not in wikidot normal form"))
-    }
+    Ok(reachable)
 }
@@ -18,22 +18,30 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
-//! Tests the [`RevisionStore`] by creating a temporary git repository.
+//! Tests the [`RevisionStore`] implementations, mostly by creating a temporary git repository.
 //! Performs several actions in the same test:
 //! * Adds some files
 //! * Delete some files
 //! * Test a diff
 //! * Test a blame
-//! [`RevisionStore`]: ./struct.RevisionStore.html
+//!
+//! [`RevisionStore`]: ./trait.RevisionStore.html
 
 extern crate color_backtrace;
 extern crate tempfile;
 
-use super::{CommitInfo, RevisionStore};
+use super::{
+    merge, Blame, CommitInfo, Diff, DiffChunk, DiffLine, GitHash, GitStore, MemoryStore,
+    MergeConflict, PageChange, RevisionStore,
+};
+use crate::Error;
 use rand::prelude::*;
 use std::cmp;
 use std::fmt::Write as _;
+use std::fs;
 use std::ops::{Bound, Range, RangeBounds};
+use std::path::Path;
+use std::process::Command;
 use std::str;
 use tempfile::tempdir;
 
@@ -230,7 +238,7 @@ fn test_git() {
     // Create revision store
     let directory = tempdir().expect("Unable to create temporary directory");
     let repo = directory.path();
-    let store = RevisionStore::new(repo, "example.org");
+    let store = GitStore::new(repo, "example.org");
     store
         .initial_commit()
         .expect("Unable to create initial commit");
@@ -326,7 +334,7 @@ fn test_git() {
         let second = hashes.pop().unwrap();
         let first = hashes.pop().unwrap();
         let diff = store
-            .get_diff(slug, &first, &second)
+            .get_diff((slug, &first), (slug, &second))
             .expect("Unable to get diff");
 
         println!();
@@ -357,7 +365,7 @@ fn thread() {
     // Create revision store
     let directory = tempdir().expect("Unable to create temporary directory");
     let repo = directory.path();
-    let store = RevisionStore::new(repo, "example.org");
+    let store = GitStore::new(repo, "example.org");
     store
         .initial_commit()
         .expect("Unable to create initial commit");
@@ -389,3 +397,871 @@ fn thread() {
         store.commit("test-3", Some(b"ghi"), info).unwrap();
     });
 }
+
+#[test]
+fn initialized() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let store = GitStore::new(repo, "example.org");
+
+    assert!(!store.is_initialized().unwrap());
+
+    store
+        .initial_commit()
+        .expect("Unable to create initial commit");
+
+    assert!(store.is_initialized().unwrap());
+
+    // Reopening an existing repository
+    let store = GitStore::new(repo, "example.org");
+    assert!(store.is_initialized().unwrap());
+}
+
+/// Runs the git binary in the repository, to check our output against.
+fn git(repo: &Path, arguments: &[&str]) -> Vec<u8> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.org"])
+        .args(arguments)
+        .output()
+        .expect("Unable to run git");
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        arguments,
+        String::from_utf8_lossy(&output.stderr),
+    );
+
+    output.stdout
+}
+
+#[test]
+fn git_compatible() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let store = GitStore::new(repo, "example.org");
+    store
+        .initial_commit()
+        .expect("Unable to create initial commit");
+
+    let info = CommitInfo {
+        username: "Dr Clef",
+        message: "Adding pages  \n\n",
+    };
+
+    store
+        .commit("scp-049", Some(b"plague doctor\n"), info)
+        .unwrap();
+    store
+        .commit("component:image-block", Some(b"[[image]]"), info)
+        .unwrap();
+    store.rename("scp-049", "scp-049-j", info).unwrap();
+    store.remove("component:image-block", info).unwrap();
+    let hash = store.commit("scp-173", Some(b"sculpture\n"), info).unwrap();
+
+    git(repo, &["fsck", "--strict"]);
+    assert_eq!(
+        str::from_utf8(&git(repo, &["rev-parse", "HEAD"]))
+            .unwrap()
+            .trim(),
+        hash.as_str()
+    );
+    assert_eq!(git(repo, &["status", "--porcelain"]), b"");
+    assert_eq!(
+        git(repo, &["show", "HEAD:scp-049-j.ftml"]),
+        b"plague doctor\n"
+    );
+    assert_eq!(
+        git(repo, &["log", "-1", "--format=%an <%ae>%n%B"]),
+        b"Dr Clef <noreply@example.org>\nAdding pages\n\n"
+    );
+    assert_eq!(git(repo, &["rev-list", "--count", "HEAD"]), b"6\n");
+
+    // Objects written by git are readable, including packed ones
+    fs::write(repo.join("scp-173.ftml"), "sculpture\nstatue\n").unwrap();
+    git(
+        repo,
+        &["commit", "--quiet", "--all", "--message=Edit from git"],
+    );
+    git(repo, &["gc", "--quiet", "--aggressive"]);
+
+    let hash = str::from_utf8(&git(repo, &["rev-parse", "HEAD"]))
+        .unwrap()
+        .trim()
+        .to_string();
+    let hash = GitHash::from_checked(hash);
+    let content = store.get_page_version("scp-173", &hash).unwrap();
+    assert_eq!(content.as_deref(), Some(&b"sculpture\nstatue\n"[..]));
+
+    store.commit("scp-173", Some(b"peanut\n"), info).unwrap();
+    git(repo, &["fsck", "--strict"]);
+}
+
+#[test]
+fn diff_matches_git() {
+    color_backtrace::install();
+
+    const VERSIONS: [&str; 6] = [
+        "",
+        "title\n\nThe quick brown fox\njumps over\nthe lazy dog.\n\n1\n2\n3\n4\n5\n6\n7\n8\n9\nfinal line",
+        "title\n\nThe quick red fox\njumps over\nthe lazy dog.\n\n1\n2\n3\n4\n5\n6\n7\n8\n9\nfinal   line\n",
+        "new title\nThe quick red fox\n  jumps over\n\n1\n2\n3\n4\n  5\n6\n7\n8\n9\nfinal   line\nextra\n",
+        "1\n2\n3\n",
+        "",
+    ];
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let store = GitStore::new(repo, "example.org");
+    store
+        .initial_commit()
+        .expect("Unable to create initial commit");
+
+    let info = CommitInfo {
+        username: "Tufto",
+        message: "Editing page",
+    };
+
+    let mut hashes = vec![store.empty_commit(info).unwrap()];
+    for content in &VERSIONS[1..VERSIONS.len() - 1] {
+        let hash = store
+            .commit("scp-4000", Some(content.as_bytes()), info)
+            .unwrap();
+        hashes.push(hash);
+    }
+
+    hashes.push(store.remove("scp-4000", info).unwrap().unwrap());
+
+    for first in &hashes {
+        for second in &hashes {
+            let diff = store
+                .get_diff(("scp-4000", first), ("scp-4000", second))
+                .unwrap();
+            let expected = git(
+                repo,
+                &[
+                    "diff",
+                    "--word-diff=porcelain",
+                    first.as_str(),
+                    second.as_str(),
+                    "--",
+                    "scp-4000.ftml",
+                ],
+            );
+
+            assert_eq!(
+                str::from_utf8(&diff).unwrap(),
+                str::from_utf8(&expected).unwrap(),
+                "Diff mismatch between {} and {}",
+                first,
+                second,
+            );
+            let parsed = Diff::from_porcelain(&diff).expect("Unable to parse diff");
+            assert_eq!(parsed.is_empty(), diff.is_empty());
+
+            for hunk in parsed.files.iter().flat_map(|file| &file.hunks) {
+                let last = hunk.lines.iter().filter_map(|line| line.new_line).max();
+
+                if let Some(last) = last {
+                    assert!(last >= hunk.new_start);
+                    assert!(last < hunk.new_start + hunk.new_lines);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn blame_matches_git() {
+    color_backtrace::install();
+
+    const VERSIONS: [&str; 5] = [
+        "alpha\nbeta\ngamma\n",
+        "alpha\nbeta\ninserted\ngamma\n",
+        "zero\nalpha\nBETA\ninserted\ngamma\n",
+        "zero\nalpha\nBETA\ngamma\ndelta\n",
+        "zero\nalpha\nBETA\ngamma\ndelta\nepsilon\n",
+    ];
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let store = GitStore::new(repo, "example.org");
+    store
+        .initial_commit()
+        .expect("Unable to create initial commit");
+
+    for (i, content) in VERSIONS.iter().enumerate() {
+        let info = CommitInfo {
+            username: TEST_USERNAMES[i],
+            message: "Editing page",
+        };
+
+        store
+            .commit("scp-3000", Some(content.as_bytes()), info)
+            .unwrap();
+
+        // Renames should be followed
+        if i == 2 {
+            store.rename("scp-3000", "scp-3001", info).unwrap();
+        }
+
+        if i >= 2 {
+            let slug = "scp-3001";
+            let blame = store.get_blame(slug, None).unwrap().unwrap();
+            let expected = git(repo, &["blame", "--porcelain", "--", "scp-3001.ftml"]);
+            let expected = Blame::from_porcelain(&expected).unwrap();
+
+            let lines = |blame: &Blame| {
+                blame
+                    .groups
+                    .iter()
+                    .flat_map(|group| group.lines.iter().cloned())
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(lines(&blame), lines(&expected));
+        }
+    }
+}
+
+#[test]
+fn memory_matches_git() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let git_store = GitStore::new(directory.path(), "example.org");
+    let memory_store = MemoryStore::new("example.org");
+    let stores: [&dyn RevisionStore; 2] = [&git_store, &memory_store];
+
+    for store in &stores {
+        assert!(!store.is_initialized().unwrap());
+        store
+            .initial_commit()
+            .expect("Unable to create initial commit");
+        assert!(store.is_initialized().unwrap());
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut hashes = [Vec::new(), Vec::new()];
+
+    for i in 0..30 {
+        let slug = pick(&mut rng, &TEST_SLUGS[..8]);
+        let username = pick(&mut rng, TEST_USERNAMES.as_ref());
+        let info = CommitInfo {
+            username,
+            message: "Editing page",
+        };
+
+        let mut content = String::new();
+        let len = rng.gen_range(16, 512);
+        pick_str(&mut rng, &mut content, &CONTENT_CHARACTERS, len, ..);
+
+        let action = rng.gen_range(0, 10);
+        for (store, hashes) in stores.iter().zip(hashes.iter_mut()) {
+            let hash = match action {
+                0 => store.remove(slug, info).unwrap(),
+                1 => store.rename(slug, "renamed", info).ok(),
+                2 => store.rename("renamed", slug, info).ok(),
+                _ => Some(store.commit(slug, Some(content.as_bytes()), info).unwrap()),
+            };
+
+            hashes.push(hash);
+        }
+
+        assert_eq!(
+            hashes[0][i].is_some(),
+            hashes[1][i].is_some(),
+            "Stores disagree on whether change {} was made",
+            i,
+        );
+
+        for slug in TEST_SLUGS[..8].iter().chain(&["renamed"]) {
+            assert_eq!(
+                git_store.get_page(slug).unwrap(),
+                memory_store.get_page(slug).unwrap(),
+                "Page content mismatch for {}",
+                slug,
+            );
+        }
+    }
+
+    let hashes: Vec<Vec<GitHash>> = hashes
+        .iter()
+        .map(|hashes| hashes.iter().flatten().cloned().collect())
+        .collect();
+
+    for slug in &TEST_SLUGS[..8] {
+        for (git, memory) in hashes[0].windows(2).zip(hashes[1].windows(2)) {
+            assert_eq!(
+                git_store
+                    .get_diff((slug, &git[0]), (slug, &git[1]))
+                    .unwrap(),
+                memory_store
+                    .get_diff((slug, &memory[0]), (slug, &memory[1]))
+                    .unwrap(),
+                "Diff mismatch for {}",
+                slug,
+            );
+
+            assert_eq!(
+                git_store.get_page_version(slug, &git[1]).unwrap(),
+                memory_store.get_page_version(slug, &memory[1]).unwrap(),
+            );
+        }
+
+        // Commit times may differ between stores, so compare commits by position
+        let lines = |blame: Option<Blame>, hashes: &[GitHash]| {
+            blame.map(|blame| {
+                blame
+                    .groups
+                    .iter()
+                    .flat_map(|group| group.lines.iter())
+                    .map(|line| {
+                        let index = hashes.iter().position(|hash| hash == &line.commit);
+
+                        (index, line.old_lineno, line.new_lineno, line.line.clone())
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            lines(git_store.get_blame(slug, None).unwrap(), &hashes[0]),
+            lines(memory_store.get_blame(slug, None).unwrap(), &hashes[1]),
+        );
+    }
+}
+
+#[test]
+fn journal_reset() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let git_store = GitStore::new(repo, "example.org");
+    let memory_store = MemoryStore::new("example.org");
+    let stores: [&dyn RevisionStore; 2] = [&git_store, &memory_store];
+
+    let info = CommitInfo {
+        username: "Kalinin",
+        message: "Editing page",
+    };
+
+    for store in &stores {
+        store
+            .initial_commit()
+            .expect("Unable to create initial commit");
+
+        store.commit("scp-1000", Some(b"Bigfoot"), info).unwrap();
+        let previous = store.head().unwrap().unwrap();
+        assert_eq!(store.pending_journal().unwrap(), None);
+
+        store.begin_journal().unwrap();
+        store.commit("scp-1000", Some(b"Humans"), info).unwrap();
+        store.commit("scp-1001", Some(b"Sequel"), info).unwrap();
+        store.rename("scp-1000", "scp-1002", info).unwrap();
+        assert_eq!(store.pending_journal().unwrap(), Some(previous.clone()));
+
+        store.reset(&previous).unwrap();
+        store.end_journal().unwrap();
+
+        assert_eq!(store.pending_journal().unwrap(), None);
+        assert_eq!(store.head().unwrap(), Some(previous));
+        assert_eq!(
+            store.get_page("scp-1000").unwrap().as_deref(),
+            Some(&b"Bigfoot"[..]),
+        );
+        assert_eq!(store.get_page("scp-1001").unwrap(), None);
+        assert_eq!(store.get_page("scp-1002").unwrap(), None);
+    }
+
+    // The working tree and index should match the reset commit
+    let status = git(repo, &["status", "--porcelain"]);
+    assert_eq!(str::from_utf8(&status).unwrap(), "");
+    assert!(!repo.join("scp-1001.ftml").exists());
+}
+
+#[test]
+fn follow_renames() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let git_store = GitStore::new(directory.path(), "example.org");
+    let memory_store = MemoryStore::new("example.org");
+    let stores: [&dyn RevisionStore; 2] = [&git_store, &memory_store];
+
+    let info = CommitInfo {
+        username: "Kalinin",
+        message: "Editing page",
+    };
+
+    for store in &stores {
+        store
+            .initial_commit()
+            .expect("Unable to create initial commit");
+
+        let first = store.commit("scp-1000", Some(b"Bigfoot\n"), info).unwrap();
+        store.commit("scp-1001", Some(b"Bigfoot\n"), info).unwrap();
+        let renamed = store.rename("scp-1000", "scp-1002", info).unwrap();
+        let last = store
+            .commit("scp-1002", Some(b"Bigfoot\nSasquatch\n"), info)
+            .unwrap();
+
+        assert_eq!(store.get_rename(&first, "scp-1000").unwrap(), None);
+        assert_eq!(store.get_rename(&last, "scp-1002").unwrap(), None);
+        assert_eq!(store.get_rename(&renamed, "scp-1001").unwrap(), None);
+        assert_eq!(
+            store.get_rename(&renamed, "scp-1002").unwrap().as_deref(),
+            Some("scp-1000"),
+        );
+
+        let diff = store
+            .get_diff(("scp-1000", &first), ("scp-1002", &last))
+            .unwrap();
+        let diff = Diff::from_porcelain(&diff).unwrap();
+
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.files[0].path, "scp-1002.ftml");
+        assert!(diff.files[0].old_blob.is_some());
+
+        // Under a single slug, the page appears to be created
+        let diff = store
+            .get_diff(("scp-1002", &first), ("scp-1002", &last))
+            .unwrap();
+        let diff = Diff::from_porcelain(&diff).unwrap();
+
+        assert_eq!(diff.files[0].old_blob, None);
+    }
+}
+
+#[test]
+fn batch_commit() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let git_store = GitStore::new(repo, "example.org");
+    let memory_store = MemoryStore::new("example.org");
+    let stores: [&dyn RevisionStore; 2] = [&git_store, &memory_store];
+
+    let info = CommitInfo {
+        username: "Kalinin",
+        message: "Reorganizing pages",
+    };
+
+    for store in &stores {
+        store
+            .initial_commit()
+            .expect("Unable to create initial commit");
+
+        store.commit("scp-1000", Some(b"Bigfoot"), info).unwrap();
+        store.commit("scp-1001", Some(b"Sequel"), info).unwrap();
+        store.commit("scp-1002", Some(b"Spam"), info).unwrap();
+
+        let changes = [
+            PageChange::Write {
+                slug: "scp-1000",
+                content: b"Sasquatch",
+            },
+            PageChange::Rename {
+                old_slug: "scp-1001",
+                new_slug: "scp-1003",
+            },
+            PageChange::Remove { slug: "scp-1002" },
+            PageChange::Write {
+                slug: "scp-1004",
+                content: b"Yeti",
+            },
+        ];
+
+        let hash = store
+            .commit_batch(&changes, info)
+            .expect("Unable to commit batch");
+
+        let log = store.get_log().unwrap();
+        assert_eq!(log.len(), 5);
+
+        let entry = &log[4];
+        assert_eq!(entry.hash, hash);
+        assert_eq!(entry.added, vec!["scp-1003", "scp-1004"]);
+        assert_eq!(entry.modified, vec!["scp-1000"]);
+        assert_eq!(entry.removed, vec!["scp-1001", "scp-1002"]);
+
+        assert_eq!(
+            store.get_rename(&hash, "scp-1003").unwrap().as_deref(),
+            Some("scp-1001"),
+        );
+        assert_eq!(store.get_rename(&hash, "scp-1004").unwrap(), None);
+        assert_eq!(
+            store.get_page("scp-1000").unwrap().as_deref(),
+            Some(&b"Sasquatch"[..]),
+        );
+        assert_eq!(store.get_page("scp-1002").unwrap(), None);
+
+        // A failing change leaves the earlier ones uncommitted
+        let changes = [
+            PageChange::Write {
+                slug: "scp-1000",
+                content: b"Vandalism",
+            },
+            PageChange::Rename {
+                old_slug: "scp-1003",
+                new_slug: "scp-1004",
+            },
+        ];
+
+        match store.commit_batch(&changes, info) {
+            Err(Error::PageExists) => (),
+            result => panic!("Conflicting batch succeeded: {:?}", result),
+        }
+
+        let changes = [PageChange::Remove { slug: "scp-1002" }];
+        match store.commit_batch(&changes, info) {
+            Err(Error::PageNotFound) => (),
+            result => panic!("Removing missing page succeeded: {:?}", result),
+        }
+
+        assert_eq!(store.head().unwrap(), Some(hash));
+        assert_eq!(
+            store.get_page("scp-1000").unwrap().as_deref(),
+            Some(&b"Sasquatch"[..]),
+        );
+    }
+
+    let status = git(repo, &["status", "--porcelain"]);
+    assert_eq!(str::from_utf8(&status).unwrap(), "");
+}
+
+#[test]
+fn rewrite_history() {
+    color_backtrace::install();
+
+    let directory = tempdir().expect("Unable to create temporary directory");
+    let repo = directory.path();
+    let git_store = GitStore::new(repo, "example.org");
+    let memory_store = MemoryStore::new("example.org");
+    let stores: [&dyn RevisionStore; 2] = [&git_store, &memory_store];
+
+    let info = CommitInfo {
+        username: "Kalinin",
+        message: "Editing page",
+    };
+
+    for store in &stores {
+        store
+            .initial_commit()
+            .expect("Unable to create initial commit");
+
+        let first = store.commit("scp-1000", Some(b"Bigfoot"), info).unwrap();
+        let leaked = store
+            .commit("scp-1000", Some(b"Home address"), info)
+            .unwrap();
+        let other = store.commit("scp-1001", Some(b"Sequel"), info).unwrap();
+        let renamed = store.rename("scp-1000", "scp-1002", info).unwrap();
+
+        let message = format!("Reverting to {}", other);
+        let info = CommitInfo {
+            username: "Kalinin",
+            message: &message,
+        };
+        let last = store.commit("scp-1001", Some(b"Prequel"), info).unwrap();
+
+        let drop = [leaked.clone()].iter().cloned().collect();
+        let renames = [(
+            renamed.clone(),
+            vec![(String::from("scp-1000"), String::from("scp-1002"))],
+        )]
+        .iter()
+        .cloned()
+        .collect();
+
+        let rewritten = store
+            .rewrite(&drop, &renames)
+            .expect("Unable to rewrite history");
+
+        assert_eq!(rewritten.len(), 3);
+        assert!(!rewritten.contains_key(&first));
+        assert!(!rewritten.contains_key(&leaked));
+        assert_ne!(rewritten[&other], other);
+        assert_ne!(rewritten[&renamed], renamed);
+        assert_eq!(store.head().unwrap().as_ref(), Some(&rewritten[&last]));
+
+        // Renamed pages keep their content from the rewritten history
+        assert_eq!(store.get_page("scp-1000").unwrap(), None);
+        assert_eq!(
+            store.get_page("scp-1002").unwrap().as_deref(),
+            Some(&b"Bigfoot"[..]),
+        );
+        assert_eq!(
+            store.get_page("scp-1001").unwrap().as_deref(),
+            Some(&b"Prequel"[..]),
+        );
+
+        let log = store.get_log().unwrap();
+        assert_eq!(log.len(), 5);
+        assert_eq!(log[1].hash, first);
+        assert_eq!(
+            log[4].message,
+            format!("Reverting to {}\n", rewritten[&other]),
+        );
+
+        // Dropped content stays around until pruned
+        assert!(store
+            .get_page_version("scp-1000", &leaked)
+            .unwrap()
+            .is_some());
+        assert!(store.prune().unwrap() > 0);
+        assert_eq!(store.get_page_version("scp-1000", &leaked).unwrap(), None);
+        assert_eq!(store.prune().unwrap(), 0);
+    }
+
+    // Leftovers from an interrupted write aren't objects, and are skipped
+    let temp_dir = repo.join(".git").join("objects").join("ab");
+    let temp_file = temp_dir.join(format!("tmp_obj_ab{}", "0".repeat(38)));
+    fs::create_dir_all(&temp_dir).unwrap();
+    fs::write(&temp_file, b"partial object").unwrap();
+    assert_eq!(git_store.prune().unwrap(), 0);
+    assert!(temp_file.is_file());
+    fs::remove_file(&temp_file).unwrap();
+
+    let status = git(repo, &["status", "--porcelain"]);
+    assert_eq!(str::from_utf8(&status).unwrap(), "");
+
+    git(repo, &["fsck", "--full", "--strict"]);
+    let unreachable = git(repo, &["fsck", "--unreachable", "--no-reflogs"]);
+    assert_eq!(str::from_utf8(&unreachable).unwrap(), "");
+
+    let history = git(repo, &["log", "-p", "--all"]);
+    assert!(!str::from_utf8(&history).unwrap().contains("Home address"));
+}
+
+#[test]
+fn diff_parse() {
+    const PORCELAIN: &str = "\
+diff --git a/scp-4000.ftml b/scp-4000.ftml
+index 4f2a1c3..9be07d2 100644
+--- a/scp-4000.ftml
++++ b/scp-4000.ftml
+@@ -1,4 +1,4 @@
+ title
+~
+ 
+~
+ The quick
+-brown
++red
+  fox
+~
+ jumps over
+~
+@@ -9 +9,2 @@ heading
+-final line
+~
++extra
+~
++lines
+~
+diff --git a/scp-4001.ftml b/scp-4001.ftml
+new file mode 100644
+index 0000000..5d308e1
+Binary files /dev/null and b/scp-4001.ftml differ
+";
+
+    let diff = Diff::from_porcelain(PORCELAIN.as_bytes()).expect("Unable to parse diff");
+    assert_eq!(diff.files.len(), 2);
+
+    let file = &diff.files[0];
+    assert_eq!(file.path, "scp-4000.ftml");
+    assert_eq!(file.old_blob.as_deref(), Some("4f2a1c3"));
+    assert_eq!(file.new_blob.as_deref(), Some("9be07d2"));
+    assert!(!file.binary);
+    assert_eq!(file.hunks.len(), 2);
+
+    let hunk = &file.hunks[0];
+    assert_eq!(
+        (
+            hunk.old_start,
+            hunk.old_lines,
+            hunk.new_start,
+            hunk.new_lines
+        ),
+        (1, 4, 1, 4),
+    );
+    assert_eq!(hunk.heading, None);
+
+    let line = |old_line, new_line, chunks: &[DiffChunk]| DiffLine {
+        old_line,
+        new_line,
+        chunks: chunks.to_vec(),
+    };
+
+    let context = |text: &str| DiffChunk::Context(String::from(text));
+    let added = |text: &str| DiffChunk::Added(String::from(text));
+    let removed = |text: &str| DiffChunk::Removed(String::from(text));
+
+    assert_eq!(
+        hunk.lines,
+        vec![
+            line(Some(1), Some(1), &[context("title")]),
+            line(Some(2), Some(2), &[context("")]),
+            line(
+                Some(3),
+                Some(3),
+                &[
+                    context("The quick"),
+                    removed("brown"),
+                    added("red"),
+                    context(" fox"),
+                ],
+            ),
+            line(Some(4), Some(4), &[context("jumps over")]),
+        ],
+    );
+
+    let hunk = &file.hunks[1];
+    assert_eq!(
+        (
+            hunk.old_start,
+            hunk.old_lines,
+            hunk.new_start,
+            hunk.new_lines
+        ),
+        (9, 1, 9, 2),
+    );
+    assert_eq!(hunk.heading.as_deref(), Some("heading"));
+    assert_eq!(
+        hunk.lines,
+        vec![
+            line(Some(9), None, &[removed("final line")]),
+            line(None, Some(9), &[added("extra")]),
+            line(None, Some(10), &[added("lines")]),
+        ],
+    );
+
+    let file = &diff.files[1];
+    assert_eq!(file.path, "scp-4001.ftml");
+    assert_eq!(file.old_blob, None);
+    assert_eq!(file.new_blob.as_deref(), Some("5d308e1"));
+    assert!(file.binary);
+    assert!(file.hunks.is_empty());
+
+    // Should be usable directly as JSON
+    let json = serde_json::to_value(&diff.files[0].hunks[1].lines[0]).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "old_line": 9,
+            "new_line": null,
+            "chunks": [{ "type": "removed", "text": "final line" }],
+        }),
+    );
+
+    let round_trip: Diff = serde_json::from_value(serde_json::to_value(&diff).unwrap()).unwrap();
+    assert_eq!(round_trip, diff);
+
+    assert!(Diff::from_porcelain(b"").unwrap().is_empty());
+    assert!(Diff::from_porcelain(b"not a diff\n").is_err());
+}
+
+#[test]
+fn three_way_merge() {
+    let base = b"Item #: SCP-XXXX\n\nObject Class: Safe\n\nDescription: A cup.\n";
+    let current = b"Item #: SCP-XXXX\n\nObject Class: Euclid\n\nDescription: A cup.\n";
+    let edited = b"Item #: SCP-XXXX\n\nObject Class: Safe\n\nDescription: A teacup.\n";
+
+    // Changes to different lines
+    let merged = merge(base, current, edited).expect("Clean merge failed");
+    assert_eq!(
+        merged,
+        b"Item #: SCP-XXXX\n\nObject Class: Euclid\n\nDescription: A teacup.\n",
+    );
+
+    // Unchanged sides and identical changes
+    assert_eq!(merge(base, base, edited).unwrap(), &edited[..]);
+    assert_eq!(merge(base, current, base).unwrap(), &current[..]);
+    assert_eq!(merge(base, current, current).unwrap(), &current[..]);
+    assert_eq!(merge(b"", b"", b"new\n").unwrap(), b"new\n");
+
+    // Insertions at either end
+    let merged = merge(b"b\n", b"a\nb\n", b"b\nc\n").unwrap();
+    assert_eq!(merged, b"a\nb\nc\n");
+
+    // Conflicting changes to the same line
+    let edited = b"Item #: SCP-XXXX\n\nObject Class: Keter\n\nDescription: A teacup.\n";
+    let conflicts = merge(base, current, edited).expect_err("Conflicting merge succeeded");
+    assert_eq!(
+        conflicts,
+        vec![MergeConflict {
+            base_line: 3,
+            current_line: 3,
+            base: String::from("Object Class: Safe\n"),
+            current: String::from("Object Class: Euclid\n"),
+            edited: String::from("Object Class: Keter\n"),
+        }],
+    );
+
+    // Conflicting insertions
+    let conflicts = merge(b"a\n", b"a\nb\n", b"a\nc\n").unwrap_err();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].base_line, 2);
+    assert_eq!(conflicts[0].base, "");
+    assert_eq!(conflicts[0].current, "b\n");
+    assert_eq!(conflicts[0].edited, "c\n");
+}
+
+#[test]
+fn commit_dates() {
+    use super::git::Commit;
+
+    let commit = |author: &str| {
+        let data = format!(
+            "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+             author {}\n\
+             committer Test <test@example.com> 1573776000 +0000\n\
+             \n\
+             Message\n",
+            author,
+        );
+
+        Commit::parse(data.as_bytes()).expect("Unable to parse commit")
+    };
+
+    let valid = commit("Test <test@example.com> 1573776000 -0500");
+    let datetime = valid.author.datetime().expect("Valid commit date rejected");
+    assert_eq!(datetime.timestamp(), 1573776000);
+    assert_eq!(datetime.offset().local_minus_utc(), -5 * 3600);
+
+    // Out-of-range offsets and timestamps are errors, not panics
+    let bad_offset = commit("Test <test@example.com> 1573776000 +9959");
+    assert!(matches!(
+        bad_offset.author.datetime(),
+        Err(Error::StaticMsg(_)),
+    ));
+
+    let bad_time = commit("Test <test@example.com> 9223372036854775807 +0000");
+    assert!(matches!(
+        bad_time.author.datetime(),
+        Err(Error::StaticMsg(_)),
+    ));
+}
+
+#[test]
+fn git_hash() {
+    use std::convert::TryFrom;
+
+    let hex = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+    let hash = GitHash::try_from(hex).expect("Valid hash rejected");
+    assert_eq!(hash.as_str(), hex);
+
+    assert!(GitHash::try_from("").is_err());
+    assert!(GitHash::try_from(&hex[1..]).is_err());
+    assert!(GitHash::try_from(format!("{}0", hex).as_str()).is_err());
+    assert!(GitHash::try_from(format!("tmp_obj_{}", hex).as_str()).is_err());
+    assert!(GitHash::try_from(hex.to_uppercase().as_str()).is_err());
+}
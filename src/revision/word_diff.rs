@@ -0,0 +1,386 @@
+/*
+ * revision/word_diff.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Produces diffs in the same format as `git diff --word-diff=porcelain`.
+
+use super::myers::{self, Edit};
+use super::GitHash;
+use std::io::Write;
+use std::ops::Range;
+
+const CONTEXT_LINES: usize = 3;
+const FUNCNAME_LENGTH: usize = 80;
+const BINARY_CHECK_LENGTH: usize = 8000;
+const NULL_HASH: &str = "0000000";
+
+/// One side of a file diff, a blob and its contents.
+pub type DiffSide<'a> = Option<(&'a GitHash, &'a [u8])>;
+
+/// Writes the diff for a single file between two versions.
+/// A side of `None` means the file does not exist in that version.
+///
+/// Returns an empty buffer if the versions are identical.
+pub fn write_diff(path: &str, old: DiffSide, new: DiffSide) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    let old_hash = old.map(|(hash, _)| hash);
+    let new_hash = new.map(|(hash, _)| hash);
+    if old_hash == new_hash {
+        return output;
+    }
+
+    let old_content = old.map(|(_, content)| content).unwrap_or(b"");
+    let new_content = new.map(|(_, content)| content).unwrap_or(b"");
+
+    writeln!(&mut output, "diff --git a/{} b/{}", path, path).unwrap();
+
+    match (old, new) {
+        (None, _) => writeln!(&mut output, "new file mode 100644").unwrap(),
+        (_, None) => writeln!(&mut output, "deleted file mode 100644").unwrap(),
+        _ => (),
+    }
+
+    write!(
+        &mut output,
+        "index {}..{}",
+        abbreviate(old_hash),
+        abbreviate(new_hash),
+    )
+    .unwrap();
+
+    if old.is_some() && new.is_some() {
+        write!(&mut output, " 100644").unwrap();
+    }
+
+    writeln!(&mut output).unwrap();
+
+    let old_name = old.map(|_| format!("a/{}", path));
+    let old_name = old_name.as_deref().unwrap_or("/dev/null");
+    let new_name = new.map(|_| format!("b/{}", path));
+    let new_name = new_name.as_deref().unwrap_or("/dev/null");
+
+    if is_binary(old_content) || is_binary(new_content) {
+        writeln!(
+            &mut output,
+            "Binary files {} and {} differ",
+            old_name, new_name
+        )
+        .unwrap();
+        return output;
+    }
+
+    let old_lines = split_lines(old_content);
+    let new_lines = split_lines(new_content);
+    let edits = myers::diff(&old_lines, &new_lines);
+    let hunks = group_hunks(&edits);
+
+    if hunks.is_empty() {
+        return output;
+    }
+
+    writeln!(&mut output, "--- {}", old_name).unwrap();
+    writeln!(&mut output, "+++ {}", new_name).unwrap();
+
+    for hunk in hunks {
+        write_hunk(&mut output, &old_lines, &new_lines, &edits[hunk]);
+    }
+
+    output
+}
+
+#[inline]
+fn abbreviate(hash: Option<&GitHash>) -> &str {
+    hash.map(|hash| &hash.as_str()[..7]).unwrap_or(NULL_HASH)
+}
+
+fn is_binary(content: &[u8]) -> bool {
+    let length = content.len().min(BINARY_CHECK_LENGTH);
+
+    content[..length].contains(&b'\0')
+}
+
+/// Splits content into lines, including their line terminators.
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (i, &byte) in content.iter().enumerate() {
+        if byte == b'\n' {
+            lines.push(&content[start..=i]);
+            start = i + 1;
+        }
+    }
+
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+
+    lines
+}
+
+/// Groups edits into hunks, each a range of indices into `edits`.
+/// Changes separated by few enough unchanged lines share a hunk.
+fn group_hunks(edits: &[Edit]) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    let mut last_change: Option<usize> = None;
+
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.is_equal() {
+            continue;
+        }
+
+        match last_change {
+            Some(last) if i - last - 1 <= CONTEXT_LINES * 2 => {
+                hunks.last_mut().unwrap().end = i + 1;
+            }
+            _ => hunks.push(i..i + 1),
+        }
+
+        last_change = Some(i);
+    }
+
+    for hunk in &mut hunks {
+        hunk.start = hunk.start.saturating_sub(CONTEXT_LINES);
+        hunk.end = (hunk.end + CONTEXT_LINES).min(edits.len());
+    }
+
+    hunks
+}
+
+fn write_hunk(output: &mut Vec<u8>, old_lines: &[&[u8]], new_lines: &[&[u8]], edits: &[Edit]) {
+    // Hunks start with context unless they are at the beginning of the file
+    let (old_first, new_first) = match edits.first() {
+        Some(&Edit::Equal { old, new }) => (old, new),
+        _ => (0, 0),
+    };
+
+    let old_count = edits.iter().filter(|e| !misses_side(**e, true)).count();
+    let new_count = edits.iter().filter(|e| !misses_side(**e, false)).count();
+
+    write!(output, "@@ -").unwrap();
+    write_range(output, old_first, old_count);
+    write!(output, " +").unwrap();
+    write_range(output, new_first, new_count);
+    write!(output, " @@").unwrap();
+
+    if let Some(funcname) = find_funcname(old_lines, old_first) {
+        output.push(b' ');
+        output.extend_from_slice(funcname);
+    }
+
+    output.push(b'\n');
+
+    // Write lines, word-diffing runs of changes
+    let mut minus = Vec::new();
+    let mut plus = Vec::new();
+
+    for edit in edits {
+        match *edit {
+            Edit::Equal { new, .. } => {
+                write_word_diff(output, &minus, &plus);
+                minus.clear();
+                plus.clear();
+
+                let line = new_lines[new];
+                output.push(b' ');
+                output.extend_from_slice(line);
+                if !line.ends_with(b"\n") {
+                    output.push(b'\n');
+                }
+                output.extend_from_slice(b"~\n");
+            }
+            Edit::Delete { old } => append_line(&mut minus, old_lines[old]),
+            Edit::Insert { new } => append_line(&mut plus, new_lines[new]),
+        }
+    }
+
+    write_word_diff(output, &minus, &plus);
+}
+
+/// Determines if the edit has no line on the given side.
+#[inline]
+fn misses_side(edit: Edit, old: bool) -> bool {
+    match edit {
+        Edit::Equal { .. } => false,
+        Edit::Delete { .. } => !old,
+        Edit::Insert { .. } => old,
+    }
+}
+
+fn write_range(output: &mut Vec<u8>, first: usize, count: usize) {
+    match count {
+        0 => write!(output, "{},0", first).unwrap(),
+        1 => write!(output, "{}", first + 1).unwrap(),
+        _ => write!(output, "{},{}", first + 1, count).unwrap(),
+    }
+}
+
+/// Finds the nearest line before the hunk that looks like a heading,
+/// using git's default rule of starting with a letter, `_`, or `$`.
+fn find_funcname<'a>(old_lines: &[&'a [u8]], first: usize) -> Option<&'a [u8]> {
+    old_lines[..first.min(old_lines.len())]
+        .iter()
+        .rev()
+        .find(|line| match line.first() {
+            Some(&c) => c.is_ascii_alphabetic() || c == b'_' || c == b'$',
+            None => false,
+        })
+        .map(|line| {
+            let line = &line[..line.len().min(FUNCNAME_LENGTH)];
+            let end = line
+                .iter()
+                .rposition(|c| !c.is_ascii_whitespace())
+                .map(|i| i + 1)
+                .unwrap_or(0);
+
+            &line[..end]
+        })
+}
+
+/// Lines in word diffs are always terminated, even at the end of the file.
+fn append_line(buffer: &mut Vec<u8>, line: &[u8]) {
+    buffer.extend_from_slice(line);
+
+    if !line.ends_with(b"\n") {
+        buffer.push(b'\n');
+    }
+}
+
+/// Gets the byte ranges of each whitespace-delimited word.
+fn split_words(text: &[u8]) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.iter().enumerate() {
+        match (c.is_ascii_whitespace(), start) {
+            (true, Some(begin)) => {
+                words.push(begin..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+
+    if let Some(begin) = start {
+        words.push(begin..text.len());
+    }
+
+    words
+}
+
+fn write_word_diff(output: &mut Vec<u8>, minus: &[u8], plus: &[u8]) {
+    if minus.is_empty() && plus.is_empty() {
+        return;
+    }
+
+    // Only removal
+    if plus.is_empty() {
+        write_segments(output, b'-', minus);
+        return;
+    }
+
+    let minus_words = split_words(minus);
+    let plus_words = split_words(plus);
+    let edits = {
+        let minus_slices: Vec<_> = minus_words.iter().map(|r| &minus[r.clone()]).collect();
+        let plus_slices: Vec<_> = plus_words.iter().map(|r| &plus[r.clone()]).collect();
+
+        myers::diff(&minus_slices, &plus_slices)
+    };
+
+    // Bounds of the words in a range, or the end of the preceding word if empty
+    let bounds = |words: &[Range<usize>], range: Range<usize>| {
+        if range.start < range.end {
+            (words[range.start].start, words[range.end - 1].end)
+        } else if range.start == 0 {
+            (0, 0)
+        } else {
+            let end = words[range.start - 1].end;
+            (end, end)
+        }
+    };
+
+    let mut current_plus = 0;
+    let mut minus_index = 0;
+    let mut plus_index = 0;
+    let mut i = 0;
+
+    while i < edits.len() {
+        if let Edit::Equal { .. } = edits[i] {
+            minus_index += 1;
+            plus_index += 1;
+            i += 1;
+            continue;
+        }
+
+        // Gather this run of changed words
+        let minus_first = minus_index;
+        let plus_first = plus_index;
+
+        while i < edits.len() {
+            match edits[i] {
+                Edit::Equal { .. } => break,
+                Edit::Delete { .. } => minus_index += 1,
+                Edit::Insert { .. } => plus_index += 1,
+            }
+
+            i += 1;
+        }
+
+        let (minus_begin, minus_end) = bounds(&minus_words, minus_first..minus_index);
+        let (plus_begin, plus_end) = bounds(&plus_words, plus_first..plus_index);
+
+        if current_plus != plus_begin {
+            write_segments(output, b' ', &plus[current_plus..plus_begin]);
+        }
+
+        if minus_begin != minus_end {
+            write_segments(output, b'-', &minus[minus_begin..minus_end]);
+        }
+
+        if plus_begin != plus_end {
+            write_segments(output, b'+', &plus[plus_begin..plus_end]);
+        }
+
+        current_plus = plus_end;
+    }
+
+    if current_plus != plus.len() {
+        write_segments(output, b' ', &plus[current_plus..]);
+    }
+}
+
+/// Writes text with the given prefix, with each newline becoming a `~` line.
+fn write_segments(output: &mut Vec<u8>, prefix: u8, text: &[u8]) {
+    let mut parts = text.split(|&c| c == b'\n').peekable();
+
+    while let Some(part) = parts.next() {
+        if !part.is_empty() {
+            output.push(prefix);
+            output.extend_from_slice(part);
+            output.push(b'\n');
+        }
+
+        if parts.peek().is_some() {
+            output.extend_from_slice(b"~\n");
+        }
+    }
+}
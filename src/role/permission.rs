@@ -0,0 +1,133 @@
+/*
+ * role/permission.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// An action on a wiki which users need a role granting it to perform.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Creating new pages.
+    Create,
+
+    /// Editing page contents and titles, and reverting pages.
+    Edit,
+
+    /// Moving pages to a different slug.
+    Rename,
+
+    /// Deleting and restoring pages.
+    Delete,
+
+    /// Changing the tags on a page.
+    Tag,
+
+    /// Voting on pages.
+    Rate,
+
+    /// Adding and removing the authors credited for a page.
+    ManageAuthors,
+
+    /// Renaming, merging and deleting tags across the wiki, setting its tag rules,
+    /// and using staff-only tags.
+    ManageTags,
+
+    /// Adding, banning and removing members of the wiki.
+    ManageMembers,
+
+    /// Creating, editing and deleting roles, and assigning them to users.
+    ManageRoles,
+
+    /// Permanently purging pages and revisions, and changing the messages
+    /// of other users' revisions.
+    Purge,
+}
+
+/// The set of permissions granted by a role.
+///
+/// This is stored as a JSON object in the `permset` column of `roles`,
+/// with any missing permissions treated as not granted.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Permset {
+    pub create: bool,
+    pub edit: bool,
+    pub rename: bool,
+    pub delete: bool,
+    pub tag: bool,
+    pub rate: bool,
+    pub manage_authors: bool,
+    pub manage_tags: bool,
+    pub manage_members: bool,
+    pub manage_roles: bool,
+    pub purge: bool,
+}
+
+impl Permset {
+    /// A permission set granting everything.
+    pub fn all() -> Self {
+        Permset {
+            create: true,
+            edit: true,
+            rename: true,
+            delete: true,
+            tag: true,
+            rate: true,
+            manage_authors: true,
+            manage_tags: true,
+            manage_members: true,
+            manage_roles: true,
+            purge: true,
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        use self::Permission::*;
+
+        match permission {
+            Create => self.create,
+            Edit => self.edit,
+            Rename => self.rename,
+            Delete => self.delete,
+            Tag => self.tag,
+            Rate => self.rate,
+            ManageAuthors => self.manage_authors,
+            ManageTags => self.manage_tags,
+            ManageMembers => self.manage_members,
+            ManageRoles => self.manage_roles,
+            Purge => self.purge,
+        }
+    }
+
+    /// Combines two permission sets, granting anything either of them grants.
+    pub fn union(self, other: Permset) -> Self {
+        Permset {
+            create: self.create || other.create,
+            edit: self.edit || other.edit,
+            rename: self.rename || other.rename,
+            delete: self.delete || other.delete,
+            tag: self.tag || other.tag,
+            rate: self.rate || other.rate,
+            manage_authors: self.manage_authors || other.manage_authors,
+            manage_tags: self.manage_tags || other.manage_tags,
+            manage_members: self.manage_members || other.manage_members,
+            manage_roles: self.manage_roles || other.manage_roles,
+            purge: self.purge || other.purge,
+        }
+    }
+}
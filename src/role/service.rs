@@ -0,0 +1,329 @@
+/*
+ * role/service.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{NewRole, Permission, Permset, UpdateRole};
+use crate::schema::{role_membership, roles};
+use crate::service_prelude::*;
+use crate::utils::rows_to_result;
+use serde_json as json;
+
+make_id_type!(RoleId);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Role {
+    role_id: RoleId,
+    wiki_id: WikiId,
+    name: String,
+    permset: Permset,
+}
+
+impl Role {
+    fn from_row(
+        (role_id, wiki_id, name, permset): (RoleId, WikiId, String, json::Value),
+    ) -> Result<Self> {
+        let permset = json::from_value(permset)?;
+
+        Ok(Role {
+            role_id,
+            wiki_id,
+            name,
+            permset,
+        })
+    }
+
+    #[inline]
+    pub fn id(&self) -> RoleId {
+        self.role_id
+    }
+
+    #[inline]
+    pub fn wiki_id(&self) -> WikiId {
+        self.wiki_id
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn permset(&self) -> Permset {
+        self.permset
+    }
+}
+
+pub struct RoleService {
+    conn: Arc<PgConnection>,
+}
+
+impl RoleService {
+    #[inline]
+    pub fn new(conn: &Arc<PgConnection>) -> Self {
+        let conn = Arc::clone(conn);
+
+        RoleService { conn }
+    }
+
+    pub fn create(&self, wiki_id: WikiId, name: &str, permset: Permset) -> Result<RoleId> {
+        info!(
+            "Creating role '{}' in wiki ID {}: {:?}",
+            name, wiki_id, permset
+        );
+
+        self.conn.transaction::<_, Error, _>(|| {
+            if self.name_exists(wiki_id, name)? {
+                return Err(Error::RoleExists);
+            }
+
+            let model = NewRole {
+                wiki_id: wiki_id.into(),
+                name,
+                permset: json::to_value(permset)?,
+            };
+
+            trace!("Inserting {:?} into roles table", &model);
+            let role_id = diesel::insert_into(roles::table)
+                .values(&model)
+                .returning(roles::dsl::role_id)
+                .get_result::<RoleId>(&*self.conn)?;
+
+            Ok(role_id)
+        })
+    }
+
+    pub fn edit(
+        &self,
+        role_id: RoleId,
+        name: Option<&str>,
+        permset: Option<Permset>,
+    ) -> Result<()> {
+        info!(
+            "Editing role ID {} (name: {:?}, permset: {:?})",
+            role_id, name, permset,
+        );
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let role = self.get(role_id)?.ok_or(Error::RoleNotFound)?;
+
+            if let Some(name) = name {
+                if name != role.name() && self.name_exists(role.wiki_id(), name)? {
+                    return Err(Error::RoleExists);
+                }
+            }
+
+            let model = UpdateRole {
+                name,
+                permset: permset.map(json::to_value).transpose()?,
+            };
+
+            if model.name.is_none() && model.permset.is_none() {
+                warn!("Empty role update");
+                return Ok(());
+            }
+
+            trace!("Updating {:?} in roles table", &model);
+            let id: i64 = role_id.into();
+            diesel::update(roles::table.find(id))
+                .set(&model)
+                .execute(&*self.conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Deletes a role, taking it away from everyone who had it.
+    pub fn delete(&self, role_id: RoleId) -> Result<()> {
+        info!("Deleting role ID {}", role_id);
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let id: i64 = role_id.into();
+
+            trace!("Removing role from all members");
+            diesel::delete(role_membership::table.filter(role_membership::dsl::role_id.eq(id)))
+                .execute(&*self.conn)?;
+
+            let rows = diesel::delete(roles::table.find(id)).execute(&*self.conn)?;
+            if !rows_to_result(rows) {
+                return Err(Error::RoleNotFound);
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn get(&self, role_id: RoleId) -> Result<Option<Role>> {
+        debug!("Getting role ID {}", role_id);
+
+        let id: i64 = role_id.into();
+        let row = roles::table
+            .find(id)
+            .first::<(RoleId, WikiId, String, json::Value)>(&*self.conn)
+            .optional()?;
+
+        row.map(Role::from_row).transpose()
+    }
+
+    /// Gets all the roles in a wiki, ordered by name.
+    pub fn get_all(&self, wiki_id: WikiId) -> Result<Vec<Role>> {
+        debug!("Getting all roles for wiki ID {}", wiki_id);
+
+        let id: i64 = wiki_id.into();
+        roles::table
+            .filter(roles::dsl::wiki_id.eq(id))
+            .order_by(roles::dsl::name.asc())
+            .load::<(RoleId, WikiId, String, json::Value)>(&*self.conn)?
+            .into_iter()
+            .map(Role::from_row)
+            .collect()
+    }
+
+    /// Gives a role to a user. Returns false if they already had it.
+    pub fn add_member(&self, role_id: RoleId, user_id: UserId) -> Result<bool> {
+        use diesel::dsl::now;
+
+        info!("Adding user ID {} to role ID {}", user_id, role_id);
+
+        self.conn.transaction::<_, Error, _>(|| {
+            let role = self.get(role_id)?.ok_or(Error::RoleNotFound)?;
+            let wiki_id: i64 = role.wiki_id().into();
+            let role_id: i64 = role_id.into();
+            let user_id: i64 = user_id.into();
+
+            let rows = diesel::insert_into(role_membership::table)
+                .values((
+                    role_membership::dsl::wiki_id.eq(wiki_id),
+                    role_membership::dsl::role_id.eq(role_id),
+                    role_membership::dsl::user_id.eq(user_id),
+                    role_membership::dsl::applied_at.eq(now),
+                ))
+                .on_conflict_do_nothing()
+                .execute(&*self.conn)?;
+
+            Ok(rows_to_result(rows))
+        })
+    }
+
+    /// Takes a role away from a user. Returns false if they didn't have it.
+    pub fn remove_member(&self, role_id: RoleId, user_id: UserId) -> Result<bool> {
+        use self::role_membership::dsl;
+
+        info!("Removing user ID {} from role ID {}", user_id, role_id);
+
+        let role_id: i64 = role_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::delete(
+            dsl::role_membership
+                .filter(dsl::role_id.eq(role_id))
+                .filter(dsl::user_id.eq(user_id)),
+        )
+        .execute(&*self.conn)?;
+
+        Ok(rows_to_result(rows))
+    }
+
+    /// Takes away every role a user has in a wiki.
+    pub fn remove_all(&self, wiki_id: WikiId, user_id: UserId) -> Result<usize> {
+        use self::role_membership::dsl;
+
+        info!(
+            "Removing all roles for user ID {} in wiki ID {}",
+            user_id, wiki_id,
+        );
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        let rows = diesel::delete(
+            dsl::role_membership
+                .filter(dsl::wiki_id.eq(wiki_id))
+                .filter(dsl::user_id.eq(user_id)),
+        )
+        .execute(&*self.conn)?;
+
+        Ok(rows)
+    }
+
+    /// Gets the roles a user has in a wiki, ordered by name.
+    pub fn get_user_roles(&self, wiki_id: WikiId, user_id: UserId) -> Result<Vec<Role>> {
+        debug!(
+            "Getting roles for user ID {} in wiki ID {}",
+            user_id, wiki_id
+        );
+
+        let wiki_id: i64 = wiki_id.into();
+        let user_id: i64 = user_id.into();
+        roles::table
+            .inner_join(role_membership::table)
+            .filter(role_membership::dsl::wiki_id.eq(wiki_id))
+            .filter(role_membership::dsl::user_id.eq(user_id))
+            .order_by(roles::dsl::name.asc())
+            .select(roles::all_columns)
+            .load::<(RoleId, WikiId, String, json::Value)>(&*self.conn)?
+            .into_iter()
+            .map(Role::from_row)
+            .collect()
+    }
+
+    /// Gets everything a user is allowed to do in a wiki, from all of their roles combined.
+    pub fn get_permset(&self, wiki_id: WikiId, user_id: UserId) -> Result<Permset> {
+        let permset = self
+            .get_user_roles(wiki_id, user_id)?
+            .iter()
+            .fold(Permset::default(), |permset, role| {
+                permset.union(role.permset())
+            });
+
+        Ok(permset)
+    }
+
+    pub fn has_permission(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        permission: Permission,
+    ) -> Result<bool> {
+        debug!(
+            "Checking if user ID {} can {:?} in wiki ID {}",
+            user_id, permission, wiki_id,
+        );
+
+        let permset = self.get_permset(wiki_id, user_id)?;
+        Ok(permset.allows(permission))
+    }
+
+    fn name_exists(&self, wiki_id: WikiId, name: &str) -> Result<bool> {
+        let id: i64 = wiki_id.into();
+        let result = roles::table
+            .filter(roles::dsl::wiki_id.eq(id))
+            .filter(roles::dsl::name.eq(name))
+            .select(roles::dsl::role_id)
+            .first::<RoleId>(&*self.conn)
+            .optional()?;
+
+        Ok(result.is_some())
+    }
+}
+
+impl Debug for RoleService {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RoleService")
+            .field("conn", &"PgConnection { .. }")
+            .finish()
+    }
+}
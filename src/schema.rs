@@ -30,6 +30,16 @@ table! {
     }
 }
 
+table! {
+    page_search (page_id) {
+        page_id -> Int8,
+        wiki_id -> Int8,
+        title -> Text,
+        alt_title -> Nullable<Text>,
+        content -> Text,
+    }
+}
+
 table! {
     parents (page_id, parent_page_id) {
         page_id -> Int8,
@@ -47,6 +57,23 @@ table! {
         logn -> Int2,
         param_r -> Int4,
         param_p -> Int4,
+        algorithm -> Varchar,
+    }
+}
+
+table! {
+    purges (purge_id) {
+        purge_id -> Int8,
+        wiki_id -> Int8,
+        page_id -> Int8,
+        slug -> Text,
+        user_id -> Int8,
+        reason -> Text,
+        page_removed -> Bool,
+        revision_ids -> Array<Int8>,
+        git_commits -> Array<Text>,
+        commits_rewritten -> Int4,
+        purged_at -> Timestamptz,
     }
 }
 
@@ -68,6 +95,15 @@ table! {
     }
 }
 
+table! {
+    redirects (wiki_id, slug) {
+        wiki_id -> Int8,
+        slug -> Text,
+        page_id -> Int8,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     revisions (revision_id) {
         revision_id -> Int8,
@@ -77,6 +113,8 @@ table! {
         message -> Text,
         git_commit -> Bpchar,
         change_type -> Varchar,
+        target_revision_id -> Nullable<Int8>,
+        old_slug -> Nullable<Text>,
     }
 }
 
@@ -99,11 +137,24 @@ table! {
 }
 
 table! {
-    sessions (user_id) {
+    sessions (session_id) {
+        session_id -> Int8,
         user_id -> Int8,
-        token -> Varchar,
         ip_address -> Inet,
+        user_agent -> Text,
         created_at -> Timestamptz,
+        last_used_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        token_hash -> Bytea,
+    }
+}
+
+table! {
+    tag_groups (wiki_id, name) {
+        wiki_id -> Int8,
+        name -> Text,
+        tags -> Array<Text>,
+        required -> Bool,
     }
 }
 
@@ -115,6 +166,22 @@ table! {
     }
 }
 
+table! {
+    tag_rules (wiki_id) {
+        wiki_id -> Int8,
+        allowed_tags -> Nullable<Array<Text>>,
+        staff_tags -> Bool,
+    }
+}
+
+table! {
+    title_history (revision_id) {
+        revision_id -> Int8,
+        title -> Text,
+        alt_title -> Nullable<Text>,
+    }
+}
+
 table! {
     users (user_id) {
         user_id -> Int8,
@@ -136,8 +203,8 @@ table! {
     wiki_membership (wiki_id, user_id) {
         wiki_id -> Int8,
         user_id -> Int8,
-        applied_at -> Timestamptz,
-        joined_at -> Timestamptz,
+        applied_at -> Nullable<Timestamptz>,
+        joined_at -> Nullable<Timestamptz>,
         banned_at -> Nullable<Timestamptz>,
         banned_until -> Nullable<Timestamptz>,
     }
@@ -156,11 +223,17 @@ table! {
 joinable!(authors -> pages (page_id));
 joinable!(authors -> users (user_id));
 joinable!(files -> pages (page_id));
+joinable!(page_search -> pages (page_id));
+joinable!(page_search -> wikis (wiki_id));
 joinable!(pages -> wikis (wiki_id));
 joinable!(parents -> users (parented_by));
 joinable!(passwords -> users (user_id));
+joinable!(purges -> users (user_id));
+joinable!(purges -> wikis (wiki_id));
 joinable!(ratings_history -> pages (page_id));
 joinable!(ratings_history -> users (user_id));
+joinable!(redirects -> pages (page_id));
+joinable!(redirects -> wikis (wiki_id));
 joinable!(revisions -> pages (page_id));
 joinable!(revisions -> users (user_id));
 joinable!(role_membership -> roles (role_id));
@@ -168,23 +241,32 @@ joinable!(role_membership -> users (user_id));
 joinable!(role_membership -> wikis (wiki_id));
 joinable!(roles -> wikis (wiki_id));
 joinable!(sessions -> users (user_id));
+joinable!(tag_groups -> wikis (wiki_id));
 joinable!(tag_history -> revisions (revision_id));
+joinable!(tag_rules -> wikis (wiki_id));
+joinable!(title_history -> revisions (revision_id));
 joinable!(wiki_membership -> users (user_id));
 joinable!(wiki_membership -> wikis (wiki_id));
 
 allow_tables_to_appear_in_same_query!(
     authors,
     files,
+    page_search,
     pages,
     parents,
     passwords,
+    purges,
     ratings,
     ratings_history,
+    redirects,
     revisions,
     role_membership,
     roles,
     sessions,
+    tag_groups,
     tag_history,
+    tag_rules,
+    title_history,
     users,
     wiki_membership,
     wikis,
@@ -19,11 +19,14 @@
  */
 
 use crate::author::{Author, AuthorService, AuthorType};
+use crate::membership::MembershipService;
 use crate::page::PageService;
 use crate::password::PasswordService;
 use crate::prelude::*;
 use crate::rating::{RatingHistory, RatingId, RatingService};
-use crate::session::{Session, SessionService};
+use crate::revision::RevisionBackend;
+use crate::role::RoleService;
+use crate::session::{Session, SessionId, SessionService};
 use crate::user::UserService;
 use crate::wiki::{UpdateWiki, WikiService};
 use chrono::prelude::*;
@@ -31,23 +34,32 @@ use diesel::{Connection, PgConnection};
 use either::*;
 use ipnetwork::IpNetwork;
 use std::fmt::{self, Debug};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::sync::Arc;
 use wikidot_normalize::normalize;
 
+/// The name of the role given to the creator of a wiki.
+const ADMIN_ROLE_NAME: &str = "administrator";
+
+/// A page's contents, along with the redirect followed to reach it, if any.
+pub type PageContents = (Box<[u8]>, Option<Redirect>);
+
 #[derive(Debug, Clone)]
 pub struct ServerConfig<'a> {
     pub database_url: &'a str,
-    pub revisions_dir: PathBuf,
+    pub revision_backend: RevisionBackend,
     pub password_blacklist: Option<&'a Path>,
+    pub session_key: &'a [u8],
 }
 
 pub struct Server {
     conn: Arc<PgConnection>,
     author: AuthorService,
+    membership: MembershipService,
     page: PageService,
     password: PasswordService,
     rating: RatingService,
+    role: RoleService,
     session: SessionService,
     user: UserService,
     wiki: WikiService,
@@ -59,8 +71,9 @@ impl Server {
 
         let ServerConfig {
             database_url,
-            revisions_dir,
+            revision_backend,
             password_blacklist,
+            session_key,
         } = config;
 
         let conn = match PgConnection::establish(database_url) {
@@ -73,19 +86,26 @@ impl Server {
         };
 
         let author = AuthorService::new(&conn);
-        let page = PageService::new(&conn, revisions_dir);
+        let membership = MembershipService::new(&conn);
+        let page = PageService::new(&conn, revision_backend);
         let password = PasswordService::new(&conn, password_blacklist)?;
         let rating = RatingService::new(&conn);
-        let session = SessionService::new(&conn);
+        let role = RoleService::new(&conn);
+        let session = SessionService::new(&conn, session_key);
         let user = UserService::new(&conn);
         let wiki = WikiService::new(&conn)?;
 
+        info!("Opening revision stores for existing wikis");
+        wiki.for_each(|wiki| page.load_store(wiki))?;
+
         Ok(Server {
             author,
             conn,
+            membership,
             page,
             password,
             rating,
+            role,
             session,
             user,
             wiki,
@@ -98,10 +118,34 @@ impl Server {
         self.conn.test_transaction::<_, Error, _>(f);
     }
 
+    #[cfg(test)]
+    #[inline]
+    pub fn test_connection(&self) -> &PgConnection {
+        &self.conn
+    }
+
+    /// Reopens a wiki's revision store, the same way as on startup.
+    #[cfg(test)]
+    pub fn test_load_store(&self, wiki_id: WikiId) -> Result<()> {
+        self.wiki.get_by_id(wiki_id, |wiki| {
+            let wiki = wiki.ok_or(Error::WikiNotFound)?;
+
+            self.page.load_store(wiki)
+        })
+    }
+
     /* Wiki methods */
 
     /// Creates a new wiki with the given parameters. Returns its ID.
-    pub fn create_wiki<S1, S2>(&self, name: &str, slug: S1, domain: S2) -> Result<WikiId>
+    /// The creating user is given an administrator role granting every permission,
+    /// so that they can set up the wiki's other roles.
+    pub fn create_wiki<S1, S2>(
+        &self,
+        name: &str,
+        slug: S1,
+        domain: S2,
+        user: &User,
+    ) -> Result<WikiId>
     where
         S1: Into<String>,
         S2: Into<String>,
@@ -109,13 +153,17 @@ impl Server {
         let slug = normalize_slug(slug);
         let domain = to_lowercase(domain);
 
-        let id = self.wiki.create(name, &slug, &domain)?;
+        self.conn.transaction::<_, Error, _>(|| {
+            let id = self.wiki.create(name, &slug, &domain)?;
+            let role_id = self.role.create(id, ADMIN_ROLE_NAME, Permset::all())?;
+            self.role.add_member(role_id, user.id())?;
 
-        self.wiki.get_by_id(id, |wiki| {
-            let wiki = wiki.expect("Can't find wiki object after inserting");
+            self.wiki.get_by_id(id, |wiki| {
+                let wiki = wiki.expect("Can't find wiki object after inserting");
 
-            self.page.add_store(&wiki)?;
-            Ok(id)
+                self.page.add_store(&wiki)?;
+                Ok(id)
+            })
         })
     }
 
@@ -242,18 +290,21 @@ impl Server {
     /* Session methods */
 
     /// Checks if a given token is valid for the given user.
+    /// If it is, the session is extended and returned.
     #[inline]
-    pub fn check_token(&self, user_id: UserId, token: &str) -> Result<()> {
+    pub fn check_token(&self, user_id: UserId, token: &str) -> Result<Session> {
         self.session.check_token(user_id, token)
     }
 
-    /// Creates a session by validating the password and creating a token.
+    /// Creates a new session by validating the password and creating a token.
+    /// Returns the session ID and its token.
     pub fn create_session(
         &self,
         user_id: UserId,
         password: &str,
         ip_address: IpNetwork,
-    ) -> Result<String> {
+        user_agent: &str,
+    ) -> Result<(SessionId, String)> {
         info!(
             "Trying to create session for user ID {} (from {})",
             user_id, ip_address,
@@ -261,41 +312,292 @@ impl Server {
 
         self.password.check(user_id, password)?;
 
-        trace!("Password validated, getting or creating session token");
-        self.conn.transaction::<_, Error, _>(|| {
-            if let Some(token) = self.session.get_token(user_id)? {
-                return Ok(token);
-            }
+        trace!("Password validated, creating session");
+        self.session.create_session(user_id, ip_address, user_agent)
+    }
 
-            self.session.create_token(user_id, ip_address)
-        })
+    /// Gets an existing session object, if it hasn't expired.
+    #[inline]
+    pub fn get_session(&self, session_id: SessionId) -> Result<Option<Session>> {
+        self.session.get_session(session_id)
     }
 
-    /// Gets an existing session object for a given user.
+    /// Gets all of a user's active sessions, most recently used first.
     #[inline]
-    pub fn get_session(&self, user_id: UserId) -> Result<Option<Session>> {
-        self.session.get_session(user_id)
+    pub fn get_sessions(&self, user_id: UserId) -> Result<Vec<Session>> {
+        self.session.get_sessions(user_id)
     }
 
     /// Invalidates a session object manually.
     /// Returns true if there was a session present.
     #[inline]
-    pub fn end_session(&self, user_id: UserId) -> Result<bool> {
-        self.session.revoke_token(user_id)
+    pub fn end_session(&self, session_id: SessionId) -> Result<bool> {
+        self.session.revoke_session(session_id)
+    }
+
+    /// Invalidates all of a user's sessions, optionally keeping the current one.
+    /// Returns the number of sessions ended.
+    #[inline]
+    pub fn end_all_sessions(&self, user_id: UserId, keep: Option<SessionId>) -> Result<usize> {
+        self.session.revoke_all(user_id, keep)
+    }
+
+    /// Deletes all expired sessions, returning how many were removed.
+    #[inline]
+    pub fn purge_expired_sessions(&self) -> Result<usize> {
+        self.session.purge_expired()
+    }
+
+    /* Role methods */
+
+    /// Creates a new role in a wiki, which grants the given permissions to its members.
+    /// Requires the `ManageRoles` permission.
+    pub fn create_role(
+        &self,
+        wiki_id: WikiId,
+        name: &str,
+        permset: Permset,
+        user: &User,
+    ) -> Result<RoleId> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageRoles)?;
+
+        self.role.create(wiki_id, name, permset)
+    }
+
+    /// Renames a role or changes its permissions.
+    /// Requires the `ManageRoles` permission.
+    pub fn edit_role(
+        &self,
+        role_id: RoleId,
+        name: Option<&str>,
+        permset: Option<Permset>,
+        user: &User,
+    ) -> Result<()> {
+        let wiki_id = self.get_role_wiki_id(role_id)?;
+        self.check_permission(wiki_id, user.id(), Permission::ManageRoles)?;
+
+        self.role.edit(role_id, name, permset)
+    }
+
+    /// Deletes a role, taking it away from all of its members.
+    /// Requires the `ManageRoles` permission.
+    pub fn delete_role(&self, role_id: RoleId, user: &User) -> Result<()> {
+        let wiki_id = self.get_role_wiki_id(role_id)?;
+        self.check_permission(wiki_id, user.id(), Permission::ManageRoles)?;
+
+        self.role.delete(role_id)
+    }
+
+    #[inline]
+    pub fn get_role(&self, role_id: RoleId) -> Result<Option<Role>> {
+        self.role.get(role_id)
+    }
+
+    /// Gets all the roles in a wiki, ordered by name.
+    #[inline]
+    pub fn get_roles(&self, wiki_id: WikiId) -> Result<Vec<Role>> {
+        self.role.get_all(wiki_id)
+    }
+
+    /// Gives a role to a user. Returns false if they already had it.
+    /// Requires the `ManageRoles` permission.
+    pub fn add_user_role(&self, role_id: RoleId, user_id: UserId, user: &User) -> Result<bool> {
+        let wiki_id = self.get_role_wiki_id(role_id)?;
+        self.check_permission(wiki_id, user.id(), Permission::ManageRoles)?;
+
+        self.role.add_member(role_id, user_id)
+    }
+
+    /// Takes a role away from a user. Returns false if they didn't have it.
+    /// Requires the `ManageRoles` permission.
+    pub fn remove_user_role(&self, role_id: RoleId, user_id: UserId, user: &User) -> Result<bool> {
+        let wiki_id = self.get_role_wiki_id(role_id)?;
+        self.check_permission(wiki_id, user.id(), Permission::ManageRoles)?;
+
+        self.role.remove_member(role_id, user_id)
+    }
+
+    /// Gets the roles a user has in a wiki, ordered by name.
+    #[inline]
+    pub fn get_user_roles(&self, wiki_id: WikiId, user_id: UserId) -> Result<Vec<Role>> {
+        self.role.get_user_roles(wiki_id, user_id)
+    }
+
+    /// Gets everything a user is allowed to do in a wiki, from all of their roles combined.
+    #[inline]
+    pub fn get_user_permissions(&self, wiki_id: WikiId, user_id: UserId) -> Result<Permset> {
+        self.role.get_permset(wiki_id, user_id)
+    }
+
+    /// Checks that a user is allowed to perform an action in a wiki,
+    /// failing with `Error::PermissionDenied` if none of their roles grant it.
+    ///
+    /// Methods which change pages, tags, ratings, authors, roles, members or revisions
+    /// call this themselves with the acting user, so it only needs to be used for other actions.
+    pub fn check_permission(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        action: Permission,
+    ) -> Result<()> {
+        if self.role.has_permission(wiki_id, user_id, action)? {
+            Ok(())
+        } else {
+            info!(
+                "User ID {} denied permission to {:?} in wiki ID {}",
+                user_id, action, wiki_id,
+            );
+
+            Err(Error::PermissionDenied(action))
+        }
+    }
+
+    /* Membership methods */
+
+    /// Applies for a user to join a wiki.
+    /// Returns false if they already have an application waiting.
+    #[inline]
+    pub fn apply_to_wiki(&self, wiki_id: WikiId, user_id: UserId) -> Result<bool> {
+        self.membership.apply(wiki_id, user_id)
+    }
+
+    /// Accepts a user's application, making them a member of the wiki.
+    /// Requires the `ManageMembers` permission.
+    pub fn accept_wiki_application(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        user: &User,
+    ) -> Result<()> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageMembers)?;
+
+        self.membership.accept(wiki_id, user_id)
+    }
+
+    /// Rejects a user's application to join a wiki.
+    /// Requires the `ManageMembers` permission.
+    pub fn reject_wiki_application(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        user: &User,
+    ) -> Result<()> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageMembers)?;
+
+        self.membership.reject(wiki_id, user_id)
+    }
+
+    /// Removes a user from a wiki's members, along with all of their roles in it.
+    pub fn leave_wiki(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        self.conn.transaction::<_, Error, _>(|| {
+            self.membership.leave(wiki_id, user_id)?;
+            self.role.remove_all(wiki_id, user_id)?;
+
+            Ok(())
+        })
+    }
+
+    /// Bans a user from a wiki until the given time, or indefinitely if there is none.
+    /// Their membership, any pending application, and all of their roles are removed.
+    /// Requires the `ManageMembers` permission.
+    pub fn ban_user(
+        &self,
+        wiki_id: WikiId,
+        user_id: UserId,
+        until: Option<DateTime<Utc>>,
+        user: &User,
+    ) -> Result<()> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageMembers)?;
+
+        self.conn.transaction::<_, Error, _>(|| {
+            self.membership.ban(wiki_id, user_id, until)?;
+            self.role.remove_all(wiki_id, user_id)?;
+
+            Ok(())
+        })
+    }
+
+    /// Lifts a user's ban from a wiki early. Returns false if they weren't banned.
+    /// Requires the `ManageMembers` permission.
+    pub fn unban_user(&self, wiki_id: WikiId, user_id: UserId, user: &User) -> Result<bool> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageMembers)?;
+
+        self.membership.unban(wiki_id, user_id)
+    }
+
+    #[inline]
+    pub fn get_membership(&self, wiki_id: WikiId, user_id: UserId) -> Result<Option<Membership>> {
+        self.membership.get(wiki_id, user_id)
+    }
+
+    /// Gets everyone who is a member of a wiki, in the order they joined.
+    #[inline]
+    pub fn get_wiki_members(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        self.membership.get_members(wiki_id)
+    }
+
+    /// Gets everyone waiting to join a wiki, in the order they applied.
+    #[inline]
+    pub fn get_wiki_applicants(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        self.membership.get_applicants(wiki_id)
+    }
+
+    /// Gets everyone currently banned from a wiki, most recently banned first.
+    /// Bans which have run out are not included.
+    #[inline]
+    pub fn get_wiki_bans(&self, wiki_id: WikiId) -> Result<Vec<Membership>> {
+        self.membership.get_banned(wiki_id)
+    }
+
+    /// Checks that a user is a member of a wiki, failing with `Error::UserBanned`
+    /// or `Error::NotMember` if not. This should be called before edits and ratings
+    /// on wikis which only allow members to make them.
+    pub fn check_membership(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        self.check_not_banned(wiki_id, user_id)?;
+
+        if self.membership.is_member(wiki_id, user_id)? {
+            Ok(())
+        } else {
+            Err(Error::NotMember)
+        }
+    }
+
+    /// Checks that a user isn't banned from a wiki, failing with `Error::UserBanned` if they are.
+    /// This should be called before edits and ratings on every wiki.
+    pub fn check_not_banned(&self, wiki_id: WikiId, user_id: UserId) -> Result<()> {
+        if self.membership.is_banned(wiki_id, user_id)? {
+            info!("User ID {} is banned from wiki ID {}", user_id, wiki_id);
+
+            Err(Error::UserBanned)
+        } else {
+            Ok(())
+        }
     }
 
     /* Page methods */
 
     /// Creates a new page with the given contents and metadata.
+    /// The tags are checked against the wiki's tagging rules.
     pub fn create_page(
         &self,
         commit: PageCommit,
         content: &[u8],
         other_authors: &[UserId],
+        tags: &[&str],
         title: &str,
         alt_title: &str,
     ) -> Result<(PageId, RevisionId)> {
-        let PageCommit { user, .. } = commit;
+        let PageCommit { wiki_id, user, .. } = commit;
+
+        self.check_permission(wiki_id, user.id(), Permission::Create)?;
+
+        if !tags.is_empty() {
+            self.check_permission(wiki_id, user.id(), Permission::Tag)?;
+        }
+
+        if !other_authors.is_empty() {
+            self.check_permission(wiki_id, user.id(), Permission::ManageAuthors)?;
+        }
 
         // Empty string means use default
         let alt_title: Option<&str> = match alt_title {
@@ -303,9 +605,10 @@ impl Server {
             _ => Some(alt_title),
         };
 
-        self.conn.transaction::<_, Error, _>(|| {
+        self.page.transaction(wiki_id, || {
             // Create page
-            let (page_id, revision_id) = self.page.create(commit, content, title, alt_title)?;
+            let (page_id, revision_id) =
+                self.page.create(commit, content, tags, title, alt_title)?;
 
             // Add committing user as author
             self.author
@@ -324,6 +627,10 @@ impl Server {
     /// Edits an existing page to have the given content.
     /// Optionally permits modifying the title or alternate title.
     /// (An empty alternate title signifies that none is used)
+    ///
+    /// If the commit has a base revision which is no longer the latest, the content
+    /// is merged with the latest version, failing with `Error::EditConflict` if the
+    /// same regions were changed in both.
     pub fn edit_page(
         &self,
         commit: PageCommit,
@@ -331,6 +638,8 @@ impl Server {
         title: Option<&str>,
         alt_title: Option<&str>,
     ) -> Result<RevisionId> {
+        self.check_permission(commit.wiki_id, commit.user.id(), Permission::Edit)?;
+
         // Empty string means use default
         let alt_title: Option<Option<&str>> = match alt_title {
             Some("") => Some(None),
@@ -342,7 +651,7 @@ impl Server {
     }
 
     /// Renames a page to use a different slug.
-    #[inline]
+    /// If `redirect` is set, the old slug will lead to the page afterwards.
     pub fn rename_page<S1, S2>(
         &self,
         wiki_id: WikiId,
@@ -350,24 +659,100 @@ impl Server {
         new_slug: S2,
         message: &str,
         user: &User,
+        redirect: bool,
     ) -> Result<RevisionId>
     where
         S1: Into<String>,
         S2: Into<String>,
     {
+        self.check_permission(wiki_id, user.id(), Permission::Rename)?;
+
         let old_slug = normalize_slug(old_slug);
         let new_slug = normalize_slug(new_slug);
 
         self.page
-            .rename(wiki_id, &old_slug, &new_slug, message, user)
+            .rename(wiki_id, &old_slug, &new_slug, message, user, redirect)
     }
 
     /// Removes the given page.
-    #[inline]
     pub fn remove_page(&self, commit: PageCommit) -> Result<RevisionId> {
+        self.check_permission(commit.wiki_id, commit.user.id(), Permission::Delete)?;
+
         self.page.remove(commit)
     }
 
+    /// Restores a deleted page to its contents from before it was removed.
+    /// Fails if another page has since been created with the same slug.
+    pub fn restore_page(&self, page_id: PageId, message: &str, user: &User) -> Result<RevisionId> {
+        let wiki_id = self.get_page_wiki_id(page_id)?;
+        self.check_permission(wiki_id, user.id(), Permission::Delete)?;
+
+        self.page.restore(page_id, message, user)
+    }
+
+    /// Reverts a page to an earlier revision, restoring its contents, title, and tags.
+    /// Requires the `Edit` permission, and also `Tag` if the page's tags would change.
+    /// The restored tags are checked against the wiki's tagging rules.
+    pub fn revert_page(
+        &self,
+        commit: PageCommit,
+        target: Either<RevisionId, &GitHash>,
+    ) -> Result<RevisionId> {
+        self.check_permission(commit.wiki_id, commit.user.id(), Permission::Edit)?;
+
+        self.page.revert(commit, target)
+    }
+
+    /// Applies changes to several pages at once, as a single commit.
+    /// Either every change is made or none of them are.
+    ///
+    /// Slugs must already be normalized. The committing user is added as
+    /// an author of any pages which are created.
+    pub fn commit_batch(
+        &self,
+        wiki_id: WikiId,
+        changes: &[BatchChange],
+        message: &str,
+        user: &User,
+    ) -> Result<Vec<(PageId, RevisionId)>> {
+        let mut actions = Vec::new();
+        for change in changes {
+            let needed: &[Permission] = match *change {
+                BatchChange::Create { tags, .. } if !tags.is_empty() => {
+                    &[Permission::Create, Permission::Tag]
+                }
+                BatchChange::Create { .. } => &[Permission::Create],
+                BatchChange::Edit { .. } => &[Permission::Edit],
+                BatchChange::Tags { .. } => &[Permission::Tag],
+                BatchChange::Rename { .. } => &[Permission::Rename],
+                BatchChange::Remove { .. } => &[Permission::Delete],
+            };
+
+            for &action in needed {
+                if !actions.contains(&action) {
+                    actions.push(action);
+                }
+            }
+        }
+
+        for action in actions {
+            self.check_permission(wiki_id, user.id(), action)?;
+        }
+
+        self.page.transaction(wiki_id, || {
+            let revisions = self.page.batch(wiki_id, changes, message, user)?;
+
+            for (change, &(page_id, _)) in changes.iter().zip(&revisions) {
+                if let BatchChange::Create { .. } = change {
+                    self.author
+                        .add(page_id, user.id(), AuthorType::Author, None)?;
+                }
+            }
+
+            Ok(revisions)
+        })
+    }
+
     /// Determines if a page with the given slug exists.
     #[inline]
     pub fn check_page<S: Into<String>>(&self, wiki_id: WikiId, slug: S) -> Result<bool> {
@@ -378,24 +763,33 @@ impl Server {
 
     /// Gets the metadata for a given page, as well as its rating information.
     /// Uses Wikidot's `ups - downs` formula for scoring.
+    ///
+    /// If no page has the slug but a redirect does, the page it leads to is
+    /// returned along with the redirect which was followed.
     pub fn get_page<S: Into<String>>(
         &self,
         wiki_id: WikiId,
         slug: S,
-    ) -> Result<Option<(Page, Rating)>> {
+    ) -> Result<Option<(Page, Rating, Option<Redirect>)>> {
         debug!("Creating transaction for page and rating");
 
         let slug = normalize_slug(slug);
 
         self.conn.transaction::<_, Error, _>(|| {
-            let page = match self.page.get_page(wiki_id, &slug)? {
-                Some(page) => page,
-                None => return Ok(None),
+            let (page, redirect) = match self.page.get_page(wiki_id, &slug)? {
+                Some(page) => (page, None),
+                None => match self.page.get_redirect(wiki_id, &slug)? {
+                    Some(redirect) => match self.page.get_page_by_id(redirect.page_id())? {
+                        Some(page) => (page, Some(redirect)),
+                        None => return Ok(None),
+                    },
+                    None => return Ok(None),
+                },
             };
 
             let rating = self.rating.get_rating(page.id())?;
 
-            Ok(Some((page, rating)))
+            Ok(Some((page, rating, redirect)))
         })
     }
 
@@ -416,16 +810,54 @@ impl Server {
         })
     }
 
-    /// Gets the contents for a given page.
+    /// Gets all deleted pages in a wiki, most recently deleted first.
+    #[inline]
+    pub fn get_deleted_pages(&self, wiki_id: WikiId) -> Result<Vec<Page>> {
+        self.page.get_deleted_pages(wiki_id)
+    }
+
+    /// Lists the pages in a wiki matching the given query, along with their ratings.
     #[inline]
+    pub fn list_pages(&self, wiki_id: WikiId, query: PageQuery) -> Result<PageList> {
+        self.page.list_pages(wiki_id, query)
+    }
+
+    /// Searches the titles and contents of pages in a wiki, best matches first.
+    ///
+    /// Every word must match, text in double quotes must match as a phrase,
+    /// and words ending in `*` match any word starting with them.
+    #[inline]
+    pub fn search_pages(
+        &self,
+        wiki_id: WikiId,
+        search: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<SearchResults> {
+        self.page.search(wiki_id, search, offset, limit)
+    }
+
+    /// Gets the contents for a given page, following a redirect if needed.
+    /// The redirect is returned along with the contents if one was followed.
     pub fn get_page_contents<S: Into<String>>(
         &self,
         wiki_id: WikiId,
         slug: S,
-    ) -> Result<Option<Box<[u8]>>> {
+    ) -> Result<Option<PageContents>> {
         let slug = normalize_slug(slug);
 
-        self.page.get_page_contents(wiki_id, &slug)
+        if let Some(contents) = self.page.get_page_contents(wiki_id, &slug)? {
+            return Ok(Some((contents, None)));
+        }
+
+        match self.page.get_redirect(wiki_id, &slug)? {
+            Some(redirect) => {
+                let contents = self.page.get_page_contents_by_id(redirect.page_id())?;
+
+                Ok(contents.map(|contents| (contents, Some(redirect))))
+            }
+            None => Ok(None),
+        }
     }
 
     /// Gets the contents for a given page ID.
@@ -434,18 +866,105 @@ impl Server {
         self.page.get_page_contents_by_id(page_id)
     }
 
-    /// Sets all the tags for a given page.
+    /// Lists every redirect in a wiki, ordered by slug.
     #[inline]
+    pub fn get_redirects(&self, wiki_id: WikiId) -> Result<Vec<Redirect>> {
+        self.page.get_redirects(wiki_id)
+    }
+
+    /// Deletes the redirect at the given slug.
+    /// Returns `false` if there wasn't one.
+    #[inline]
+    pub fn remove_redirect<S: Into<String>>(&self, wiki_id: WikiId, slug: S) -> Result<bool> {
+        let slug = normalize_slug(slug);
+
+        self.page.remove_redirect(wiki_id, &slug)
+    }
+
+    /// Sets all the tags for a given page.
+    /// Fails with `Error::TagRulesViolated` if the tags break the wiki's tagging rules.
     pub fn set_page_tags<S: AsRef<str>>(
         &self,
         commit: PageCommit,
         tags: &[S],
     ) -> Result<RevisionId> {
+        self.check_permission(commit.wiki_id, commit.user.id(), Permission::Tag)?;
+
         let mut tags = tags.iter().map(|tag| tag.as_ref()).collect::<Vec<&str>>();
 
         self.page.tags(commit, &mut tags)
     }
 
+    /// Gets the tagging rules for a wiki.
+    #[inline]
+    pub fn get_tag_rules(&self, wiki_id: WikiId) -> Result<TagRules> {
+        self.page.get_tag_rules(wiki_id)
+    }
+
+    /// Replaces the tagging rules for a wiki.
+    /// Pages which break the new rules are left alone until their tags are next changed.
+    pub fn set_tag_rules(&self, wiki_id: WikiId, rules: &TagRules, user: &User) -> Result<()> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageTags)?;
+
+        self.page.set_tag_rules(wiki_id, rules)
+    }
+
+    /// Renames a tag on every page in the wiki, as a single commit.
+    /// Fails with `Error::TagExists` if the new tag is already in use.
+    ///
+    /// With `dry_run` set, the affected pages are reported but nothing is changed.
+    #[inline]
+    pub fn rename_tag(
+        &self,
+        wiki_id: WikiId,
+        old_tag: &str,
+        new_tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageTags)?;
+
+        self.page
+            .rename_tag(wiki_id, old_tag, new_tag, message, user, dry_run)
+    }
+
+    /// Replaces several tags with one on every page in the wiki, as a single commit.
+    ///
+    /// With `dry_run` set, the affected pages are reported but nothing is changed.
+    #[inline]
+    pub fn merge_tags(
+        &self,
+        wiki_id: WikiId,
+        old_tags: &[&str],
+        new_tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageTags)?;
+
+        self.page
+            .merge_tags(wiki_id, old_tags, new_tag, message, user, dry_run)
+    }
+
+    /// Removes a tag from every page in the wiki, as a single commit.
+    ///
+    /// With `dry_run` set, the affected pages are reported but nothing is changed.
+    #[inline]
+    pub fn delete_tag(
+        &self,
+        wiki_id: WikiId,
+        tag: &str,
+        message: &str,
+        user: &User,
+        dry_run: bool,
+    ) -> Result<TagUpdateReport> {
+        self.check_permission(wiki_id, user.id(), Permission::ManageTags)?;
+
+        self.page.delete_tag(wiki_id, tag, message, user, dry_run)
+    }
+
     /* Author methods */
 
     fn get_page_id<S: Into<String>>(&self, page: Either<PageId, (WikiId, S)>) -> Result<PageId> {
@@ -477,11 +996,14 @@ impl Server {
         &self,
         page: Either<PageId, (WikiId, &str)>,
         authors: &[(UserId, AuthorType, Option<NaiveDate>)],
+        user: &User,
     ) -> Result<()> {
         info!("Adding authors to page {:?}: {:?}", page, authors);
 
         self.conn.transaction::<_, Error, _>(|| {
             let page_id = self.get_page_id(page)?;
+            let wiki_id = self.get_page_wiki_id(page_id)?;
+            self.check_permission(wiki_id, user.id(), Permission::ManageAuthors)?;
 
             for &(user_id, author_type, written_at) in authors {
                 self.author.add(page_id, user_id, author_type, written_at)?;
@@ -496,11 +1018,14 @@ impl Server {
         &self,
         page: Either<PageId, (WikiId, &str)>,
         authors: &[(UserId, AuthorType)],
+        user: &User,
     ) -> Result<usize> {
         info!("Removing authors from page {:?}: {:?}", page, authors);
 
         self.conn.transaction::<_, Error, _>(|| {
             let page_id = self.get_page_id(page)?;
+            let wiki_id = self.get_page_wiki_id(page_id)?;
+            self.check_permission(wiki_id, user.id(), Permission::ManageAuthors)?;
             let mut count = 0;
 
             for (user_id, author_type) in authors.iter().copied() {
@@ -516,25 +1041,29 @@ impl Server {
     /* Rating methods */
 
     /// Sets the rating for a given page and user.
-    #[inline]
     pub fn set_rating(&self, page_id: PageId, user_id: UserId, rating: i16) -> Result<RatingId> {
         info!(
             "Setting rating for page ID {} / user ID {}: {}",
             page_id, user_id, rating,
         );
 
+        let wiki_id = self.get_page_wiki_id(page_id)?;
+        self.check_permission(wiki_id, user_id, Permission::Rate)?;
+
         self.rating.set(page_id, user_id, rating)
     }
 
     /// Removes the rating for a given page and user.
     /// Returns `None` if the rating is already deleted.
-    #[inline]
     pub fn remove_rating(&self, page_id: PageId, user_id: UserId) -> Result<Option<RatingId>> {
         info!(
             "Removing rating for page ID {} / user ID {}",
             page_id, user_id,
         );
 
+        let wiki_id = self.get_page_wiki_id(page_id)?;
+        self.check_permission(wiki_id, user_id, Permission::Rate)?;
+
         self.rating.remove(page_id, user_id)
     }
 
@@ -581,6 +1110,17 @@ impl Server {
         self.page.get_page_version(wiki_id, slug, revision)
     }
 
+    /// Get the version of a page at one of its revisions.
+    /// Unlike `get_page_version`, this works for revisions from before the page was renamed.
+    #[inline]
+    pub fn get_page_version_by_id(
+        &self,
+        page_id: PageId,
+        revision: Either<RevisionId, &GitHash>,
+    ) -> Result<Option<Box<[u8]>>> {
+        self.page.get_page_version_by_id(page_id, revision)
+    }
+
     /// Get the blame for a given page, if it exists.
     #[inline]
     pub fn get_page_blame(&self, wiki_id: WikiId, slug: &str) -> Result<Option<Blame>> {
@@ -593,6 +1133,16 @@ impl Server {
         self.page.get_blame_by_id(page_id)
     }
 
+    /// Get the blame for a given page ID as of one of its revisions.
+    #[inline]
+    pub fn get_page_blame_at(
+        &self,
+        page_id: PageId,
+        revision: Either<RevisionId, &GitHash>,
+    ) -> Result<Option<Blame>> {
+        self.page.get_blame_at(page_id, revision)
+    }
+
     /// Get a diff for a given page between the two specified revisions.
     #[inline]
     pub fn get_page_diff<S: Into<String>>(
@@ -601,20 +1151,151 @@ impl Server {
         slug: S,
         first: Either<RevisionId, &GitHash>,
         second: Either<RevisionId, &GitHash>,
-    ) -> Result<Box<[u8]>> {
+    ) -> Result<Diff> {
         let slug = normalize_slug(slug);
 
         self.page.get_diff(wiki_id, &slug, first, second)
     }
 
-    /// Overwrite the revision message for a given change.
+    /// Get a diff between two revisions of a page ID, following any renames in between.
+    #[inline]
+    pub fn get_page_diff_by_id(
+        &self,
+        page_id: PageId,
+        first: Either<RevisionId, &GitHash>,
+        second: Either<RevisionId, &GitHash>,
+    ) -> Result<Diff> {
+        self.page.get_diff_by_id(page_id, first, second)
+    }
+
+    /// Lists the revisions of a page, newest first.
+    pub fn get_page_history(
+        &self,
+        page: Either<PageId, (WikiId, &str)>,
+        query: HistoryQuery,
+    ) -> Result<HistoryPage> {
+        self.conn.transaction::<_, Error, _>(|| {
+            let page_id = self.get_page_id(page)?;
+
+            self.page.get_history(page_id, query)
+        })
+    }
+
+    /// Lists when tags were added to or removed from a page, and by whom, oldest first.
+    /// If `tag` is given, only changes to that tag are listed.
+    pub fn get_page_tag_history(
+        &self,
+        page: Either<PageId, (WikiId, &str)>,
+        tag: Option<&str>,
+    ) -> Result<Vec<TagEvent>> {
+        self.conn.transaction::<_, Error, _>(|| {
+            let page_id = self.get_page_id(page)?;
+
+            self.page.get_tag_history(page_id, tag)
+        })
+    }
+
+    /// Gets the pages in a wiki which had a tag at the given time.
     #[inline]
-    pub fn edit_revision(&self, revision_id: RevisionId, message: &str) -> Result<()> {
+    pub fn get_tagged_pages_at(
+        &self,
+        wiki_id: WikiId,
+        tag: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<PageId>> {
+        self.page.get_tagged_pages_at(wiki_id, tag, time)
+    }
+
+    /// Overwrite the revision message for a given change.
+    /// Users with the `Edit` permission can change the messages of their own revisions,
+    /// but changing anyone else's requires the `Purge` permission.
+    pub fn edit_revision(&self, revision_id: RevisionId, message: &str, user: &User) -> Result<()> {
+        let (wiki_id, author_id) = self.page.get_revision_owner(revision_id)?;
+        let action = if author_id == user.id() {
+            Permission::Edit
+        } else {
+            Permission::Purge
+        };
+
+        self.check_permission(wiki_id, user.id(), action)?;
         self.page.edit_revision(revision_id, message)
     }
 
+    /// Checks that a wiki's revisions agree with its git history.
+    /// If `repair` is set, problems which can be fixed safely are fixed.
+    #[inline]
+    pub fn check_wiki_consistency(
+        &self,
+        wiki_id: WikiId,
+        repair: bool,
+    ) -> Result<ConsistencyReport> {
+        self.page.check_consistency(wiki_id, repair)
+    }
+
+    /// Recreates a wiki's pages and revisions from its git history.
+    /// The wiki must not have any pages.
+    #[inline]
+    pub fn rebuild_wiki_pages(&self, wiki_id: WikiId) -> Result<RebuildReport> {
+        self.page.rebuild(wiki_id)
+    }
+
+    /// Recreates a wiki's search index from the page contents in its git repository.
+    /// Returns the number of pages indexed.
+    #[inline]
+    pub fn rebuild_search_index(&self, wiki_id: WikiId) -> Result<usize> {
+        self.page.reindex(wiki_id)
+    }
+
+    /// Permanently removes a page and all of its history, including from git.
+    /// This cannot be undone, and requires the `Purge` permission.
+    #[inline]
+    pub fn purge_page(&self, page_id: PageId, user: &User, reason: &str) -> Result<PurgeId> {
+        self.page.purge(page_id, None, user, reason)
+    }
+
+    /// Permanently removes some of a page's edits, tag changes or reverts,
+    /// including from git. Reverts back to those revisions are removed too.
+    /// This cannot be undone, and requires the `Purge` permission.
+    #[inline]
+    pub fn purge_revisions(
+        &self,
+        page_id: PageId,
+        revisions: &[RevisionId],
+        user: &User,
+        reason: &str,
+    ) -> Result<PurgeId> {
+        self.page.purge(page_id, Some(revisions), user, reason)
+    }
+
+    /// Gets the audit records of every purge in a wiki, most recent first.
+    #[inline]
+    pub fn get_purge_log(&self, wiki_id: WikiId) -> Result<Vec<PurgeRecord>> {
+        self.page.get_purge_log(wiki_id)
+    }
+
+    /// Deletes content from a wiki's revision store which is no longer in its history.
+    /// Purges do this automatically unless they were run inside another transaction.
+    #[inline]
+    pub fn prune_wiki_objects(&self, wiki_id: WikiId) -> Result<usize> {
+        self.page.prune(wiki_id)
+    }
+
     /* Helper methods */
 
+    fn get_role_wiki_id(&self, role_id: RoleId) -> Result<WikiId> {
+        self.role
+            .get(role_id)?
+            .map(|role| role.wiki_id())
+            .ok_or(Error::RoleNotFound)
+    }
+
+    fn get_page_wiki_id(&self, page_id: PageId) -> Result<WikiId> {
+        self.page
+            .get_page_by_id(page_id)?
+            .map(|page| page.wiki_id())
+            .ok_or(Error::PageNotFound)
+    }
+
     #[inline]
     pub fn transaction<F, T>(&self, f: F) -> Result<T>
     where
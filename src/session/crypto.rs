@@ -0,0 +1,45 @@
+/*
+ * session/crypto.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use std::iter;
+
+const TOKEN_LENGTH: usize = 64;
+
+pub type TokenHash = [u8; 32];
+
+pub fn generate_token() -> String {
+    iter::repeat(())
+        .map(|_| OsRng.sample(Alphanumeric))
+        .take(TOKEN_LENGTH)
+        .collect()
+}
+
+pub fn hash_token(key: &[u8], token: &str) -> TokenHash {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    let mut hash = [0; 32];
+
+    hmac.input(token.as_bytes());
+    hmac.raw_result(&mut hash);
+    hash
+}
@@ -18,9 +18,13 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
+mod crypto;
 mod models;
 mod service;
 
+#[cfg(test)]
+mod test;
+
 pub use self::service::*;
 
 use self::models::*;
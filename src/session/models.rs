@@ -19,12 +19,15 @@
  */
 
 use crate::schema::sessions;
+use chrono::prelude::*;
 use ipnetwork::IpNetwork;
 
 #[derive(Debug, Insertable)]
 #[table_name = "sessions"]
 pub struct NewSession<'a> {
     pub user_id: i64,
-    pub token: &'a str,
     pub ip_address: IpNetwork,
+    pub user_agent: &'a str,
+    pub expires_at: DateTime<Utc>,
+    pub token_hash: &'a [u8],
 }
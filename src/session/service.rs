@@ -18,135 +18,243 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
  */
 
+use super::crypto::{generate_token, hash_token};
 use super::NewSession;
 use crate::schema::sessions;
 use crate::service_prelude::*;
 use crate::utils::rows_to_result;
 use chrono::prelude::*;
+use chrono::Duration;
+use diesel::dsl::now;
 use ipnetwork::IpNetwork;
-use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
-use std::iter;
 
-const TOKEN_LENGTH: usize = 64;
+/// How long a session lasts without being used.
+/// Every time its token is checked, the session is extended by this much.
+const SESSION_DURATION_DAYS: i64 = 30;
 
-// This implementation is extremely primitive -- it just stores a securely-generated
-// random string as the token and then matches it when the user makes calls.
-//
-// In the future we will want distinct session objects which are separated by IP and
-// can be invalidated separately.
-//
-// This also might want to be in-memory instead of persisted.
+make_id_type!(SessionId);
 
-#[derive(Debug, Queryable)]
+/// A logged-in session for a user, such as on one of their devices.
+///
+/// Each session has its own token, which is only available when it is created.
+/// Only a keyed hash of the token is stored.
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, PartialEq, Eq)]
 pub struct Session {
+    session_id: SessionId,
     user_id: UserId,
-    token: String,
     ip_address: IpNetwork,
+    user_agent: String,
     created_at: DateTime<Utc>,
+    last_used_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
 }
 
 impl Session {
     #[inline]
-    pub fn user_id(&self) -> UserId {
-        self.user_id
+    pub fn id(&self) -> SessionId {
+        self.session_id
     }
 
     #[inline]
-    pub fn token(&self) -> &str {
-        &self.token
+    pub fn user_id(&self) -> UserId {
+        self.user_id
     }
 
     #[inline]
     pub fn ip_address(&self) -> IpNetwork {
         self.ip_address
     }
+
+    #[inline]
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    #[inline]
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    #[inline]
+    pub fn last_used_at(&self) -> DateTime<Utc> {
+        self.last_used_at
+    }
+
+    #[inline]
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
 }
 
+// Every column besides the token hash
+type SessionColumns = (
+    sessions::session_id,
+    sessions::user_id,
+    sessions::ip_address,
+    sessions::user_agent,
+    sessions::created_at,
+    sessions::last_used_at,
+    sessions::expires_at,
+);
+
+const SESSION_COLUMNS: SessionColumns = (
+    sessions::session_id,
+    sessions::user_id,
+    sessions::ip_address,
+    sessions::user_agent,
+    sessions::created_at,
+    sessions::last_used_at,
+    sessions::expires_at,
+);
+
 pub struct SessionService {
     conn: Arc<PgConnection>,
+    key: Box<[u8]>,
 }
 
 impl SessionService {
     #[inline]
-    pub fn new(conn: &Arc<PgConnection>) -> Self {
+    pub fn new(conn: &Arc<PgConnection>, key: &[u8]) -> Self {
         let conn = Arc::clone(conn);
-        SessionService { conn }
+        let key = Box::from(key);
+        SessionService { conn, key }
     }
 
-    pub fn get_session(&self, user_id: UserId) -> Result<Option<Session>> {
-        info!("Getting session information any for user ID {}", user_id);
+    pub fn get_session(&self, session_id: SessionId) -> Result<Option<Session>> {
+        info!("Getting session information for session ID {}", session_id);
 
-        let id: i64 = user_id.into();
+        let id: i64 = session_id.into();
         let session = sessions::table
             .find(id)
+            .filter(sessions::dsl::expires_at.gt(now))
+            .select(SESSION_COLUMNS)
             .first::<Session>(&*self.conn)
             .optional()?;
 
         Ok(session)
     }
 
-    pub fn get_token(&self, user_id: UserId) -> Result<Option<String>> {
-        debug!("Getting token (if any) for user ID {}", user_id);
+    /// Gets all of a user's sessions which haven't expired, most recently used first.
+    pub fn get_sessions(&self, user_id: UserId) -> Result<Vec<Session>> {
+        info!("Getting all sessions for user ID {}", user_id);
 
         let id: i64 = user_id.into();
-        let token = sessions::table
-            .find(id)
-            .select(sessions::dsl::token)
-            .first::<String>(&*self.conn)
-            .optional()?;
+        let sessions = sessions::table
+            .filter(sessions::dsl::user_id.eq(id))
+            .filter(sessions::dsl::expires_at.gt(now))
+            .order_by((
+                sessions::dsl::last_used_at.desc(),
+                sessions::dsl::session_id.desc(),
+            ))
+            .select(SESSION_COLUMNS)
+            .load::<Session>(&*self.conn)?;
 
-        Ok(token)
+        Ok(sessions)
     }
 
-    pub fn check_token(&self, user_id: UserId, token: &str) -> Result<()> {
+    /// Checks that the token belongs to one of the user's sessions which hasn't expired.
+    /// On success the session's expiry is pushed back, and the updated session is returned.
+    pub fn check_token(&self, user_id: UserId, token: &str) -> Result<Session> {
+        use self::sessions::dsl;
+
         debug!("Checking token for user ID {}", user_id);
 
+        // Only the keyed hash is matched in SQL, so timing reveals nothing about the token
         let id: i64 = user_id.into();
-        let result = sessions::table
-            .find(id)
-            .filter(sessions::dsl::token.eq(token))
-            .select(sessions::dsl::user_id)
-            .first::<UserId>(&*self.conn)
-            .optional()?;
+        let token_hash = hash_token(&self.key, token);
+        let session_id = dsl::sessions
+            .filter(dsl::token_hash.eq(&token_hash[..]))
+            .filter(dsl::user_id.eq(id))
+            .filter(dsl::expires_at.gt(now))
+            .select(dsl::session_id)
+            .first::<i64>(&*self.conn)
+            .optional()?
+            .ok_or(Error::InvalidToken)?;
+
+        trace!("Token matches session ID {}, extending expiry", session_id);
+        let expires_at = Utc::now() + Duration::days(SESSION_DURATION_DAYS);
+        let session = diesel::update(dsl::sessions.find(session_id))
+            .set((dsl::last_used_at.eq(now), dsl::expires_at.eq(expires_at)))
+            .returning(SESSION_COLUMNS)
+            .get_result::<Session>(&*self.conn)?;
 
-        match result {
-            Some(_) => Ok(()),
-            None => Err(Error::InvalidToken),
-        }
+        Ok(session)
     }
 
-    pub fn create_token(&self, user_id: UserId, ip_address: IpNetwork) -> Result<String> {
-        debug!("Creating token for user ID {}", user_id);
+    /// Starts a new session for a user, returning its ID and token.
+    pub fn create_session(
+        &self,
+        user_id: UserId,
+        ip_address: IpNetwork,
+        user_agent: &str,
+    ) -> Result<(SessionId, String)> {
+        debug!("Creating session for user ID {}", user_id);
 
         let token = generate_token();
+        let token_hash = hash_token(&self.key, &token);
         let model = NewSession {
             user_id: user_id.into(),
-            token: &token,
             ip_address,
+            user_agent,
+            expires_at: Utc::now() + Duration::days(SESSION_DURATION_DAYS),
+            token_hash: &token_hash,
         };
 
-        diesel::insert_into(sessions::table)
+        let session_id = diesel::insert_into(sessions::table)
             .values(&model)
-            .execute(&*self.conn)?;
+            .returning(sessions::dsl::session_id)
+            .get_result::<SessionId>(&*self.conn)?;
+
+        Ok((session_id, token))
+    }
+
+    /// Ends a single session. Returns false if there was no such session.
+    pub fn revoke_session(&self, session_id: SessionId) -> Result<bool> {
+        debug!("Revoking session ID {}", session_id);
 
-        Ok(token)
+        let id: i64 = session_id.into();
+        let rows = diesel::delete(sessions::table.find(id)).execute(&*self.conn)?;
+
+        Ok(rows_to_result(rows))
     }
 
-    pub fn revoke_token(&self, user_id: UserId) -> Result<bool> {
-        debug!("Revoking token for user ID {}", user_id);
+    /// Ends all of a user's sessions, except for `keep` if it is given.
+    /// Returns the number of sessions ended.
+    pub fn revoke_all(&self, user_id: UserId, keep: Option<SessionId>) -> Result<usize> {
+        use self::sessions::dsl;
+
+        debug!(
+            "Revoking all sessions for user ID {} (keeping {:?})",
+            user_id, keep
+        );
 
         let id: i64 = user_id.into();
-        let rows = diesel::delete(sessions::table)
-            .filter(sessions::dsl::user_id.eq(id))
+        let keep: i64 = keep.map(SessionId::into).unwrap_or(-1);
+        let rows = diesel::delete(
+            dsl::sessions
+                .filter(dsl::user_id.eq(id))
+                .filter(dsl::session_id.ne(keep)),
+        )
+        .execute(&*self.conn)?;
+
+        Ok(rows)
+    }
+
+    /// Deletes every session which has expired. Returns the number of sessions deleted.
+    pub fn purge_expired(&self) -> Result<usize> {
+        info!("Purging expired sessions");
+
+        let rows = diesel::delete(sessions::table.filter(sessions::dsl::expires_at.le(now)))
             .execute(&*self.conn)?;
 
-        Ok(rows_to_result(rows))
+        Ok(rows)
     }
 }
 
-fn generate_token() -> String {
-    iter::repeat(())
-        .map(|_| OsRng.sample(Alphanumeric))
-        .take(TOKEN_LENGTH)
-        .collect()
+impl Debug for SessionService {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SessionService")
+            .field("conn", &"PgConnection { .. }")
+            .finish()
+    }
 }
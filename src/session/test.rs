@@ -0,0 +1,45 @@
+/*
+ * session/test.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::crypto::{generate_token, hash_token};
+
+#[test]
+fn crypto() {
+    let key = b"session key";
+    let token = generate_token();
+    let hash = hash_token(key, &token);
+
+    assert_eq!(token.len(), 64);
+    assert_ne!(&hash[..], token.as_bytes());
+    assert_ne!(token, generate_token(), "Generated tokens are not random");
+
+    macro_rules! check {
+        ($key:expr, $token:expr, $expected:expr) => {{
+            println!("Checking token: '{}'", $token);
+            let actual = hash_token($key, $token) == hash;
+            assert_eq!(actual, $expected, "Token result mismatch");
+        }};
+    }
+
+    check!(key, "", false);
+    check!(key, &token, true);
+    check!(key, &token[1..], false);
+    check!(b"other key", &token, false);
+}
@@ -24,10 +24,6 @@ use crate::author::AuthorType;
 #[test]
 fn author_service() {
     run(|srv| {
-        let wiki_id = srv
-            .create_wiki("Test", "test", "example.org")
-            .expect("Unable to create wiki");
-
         let user_1 = {
             let user_id = srv
                 .create_user("superpersonyeah", "ralph@example.net", "blackmoonhowls")
@@ -53,11 +49,16 @@ fn author_service() {
             .expect("Unable to get user")
             .expect("Default user not found");
 
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user_1)
+            .expect("Unable to create wiki");
+
         let commit = PageCommit {
             wiki_id,
             slug: "scp-xxxx",
             message: "new scp!!",
             user: &user_1,
+            base: None,
         };
 
         let (page_id, _revision_id) = srv
@@ -65,6 +66,7 @@ fn author_service() {
                 commit,
                 b"item number spc-xxx\nobject: SUPER KETER",
                 &[],
+                &[],
                 "SCP-XXXX",
                 "Super-Keter",
             )
@@ -88,6 +90,7 @@ fn author_service() {
                 (user_2.id(), AuthorType::Translator, None),
                 (user_3.id(), AuthorType::Author, None),
             ],
+            &user_1,
         )
         .expect("Unable to add authors");
 
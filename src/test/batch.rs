@@ -0,0 +1,296 @@
+/*
+ * test/batch.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+#[test]
+fn batch_commit() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let mut removed_id = None;
+        for slug in &["scp-1000", "scp-1001", "scp-1002", "scp-1003"] {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New article!",
+                user: &user,
+                base: None,
+            };
+
+            let (page_id, _) = srv
+                .create_page(commit, b"Original", &[], &[], "SCP", "")
+                .expect("Unable to create page");
+
+            removed_id = Some(page_id);
+        }
+
+        let changes = [
+            BatchChange::Edit {
+                slug: "scp-1000",
+                content: Some(b"Edited"),
+                title: Some("SCP-1000"),
+                alt_title: None,
+            },
+            BatchChange::Tags {
+                slug: "scp-1001",
+                tags: &["scp", "keter"],
+            },
+            BatchChange::Rename {
+                old_slug: "scp-1002",
+                new_slug: "scp-1004",
+                redirect: true,
+            },
+            BatchChange::Remove { slug: "scp-1003" },
+            BatchChange::Create {
+                slug: "scp-1003",
+                content: b"Replacement",
+                tags: &[],
+                title: "SCP-1003",
+                alt_title: None,
+            },
+        ];
+
+        let revisions = srv
+            .commit_batch(wiki_id, &changes, "Mass update", &user)
+            .expect("Unable to commit batch");
+
+        assert_eq!(revisions.len(), changes.len());
+        assert_ne!(Some(revisions[4].0), removed_id);
+
+        // Every page gets its own revision, all in the same commit
+        let mut hashes = Vec::new();
+        for &(page_id, revision_id) in &revisions {
+            let history = srv
+                .get_page_history(Left(page_id), HistoryQuery::default())
+                .expect("Unable to get page history");
+
+            let entry = &history.entries[0];
+            assert_eq!(entry.revision_id, revision_id);
+            assert_eq!(entry.message, "Mass update");
+            hashes.push(entry.git_commit.clone());
+        }
+
+        assert!(hashes.iter().all(|hash| hash == &hashes[0]));
+
+        let contents = |slug| {
+            srv.get_page_contents(wiki_id, slug)
+                .unwrap()
+                .map(|(contents, _)| contents)
+        };
+
+        assert_eq!(contents("scp-1000").as_deref(), Some(&b"Edited"[..]));
+        assert_eq!(contents("scp-1003").as_deref(), Some(&b"Replacement"[..]));
+        assert_eq!(contents("scp-1004").as_deref(), Some(&b"Original"[..]));
+
+        let (page, _, redirect) = srv.get_page(wiki_id, "scp-1002").unwrap().unwrap();
+        assert_eq!(page.slug(), "scp-1004");
+        assert!(redirect.is_some());
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-1001").unwrap().unwrap();
+        assert_eq!(page.tags(), &["keter", "scp"]);
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+
+        // A failing change undoes the rest of the batch
+        let changes = [
+            BatchChange::Edit {
+                slug: "scp-1000",
+                content: Some(b"Vandalism"),
+                title: Some("Oops"),
+                alt_title: None,
+            },
+            BatchChange::Create {
+                slug: "scp-1004",
+                content: b"Spam",
+                tags: &[],
+                title: "Spam",
+                alt_title: None,
+            },
+        ];
+
+        match srv.commit_batch(wiki_id, &changes, "Vandalism", &user) {
+            Err(Error::PageExists) => (),
+            result => panic!("Conflicting batch succeeded: {:?}", result),
+        }
+
+        let changes = [
+            BatchChange::Edit {
+                slug: "scp-1000",
+                content: Some(b"Vandalism"),
+                title: Some("Oops"),
+                alt_title: None,
+            },
+            BatchChange::Remove { slug: "scp-1000" },
+        ];
+
+        match srv.commit_batch(wiki_id, &changes, "Vandalism", &user) {
+            Err(Error::DuplicateBatchPage) => (),
+            result => panic!("Batch changing a page twice succeeded: {:?}", result),
+        }
+
+        assert_eq!(contents("scp-1000").as_deref(), Some(&b"Edited"[..]));
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-1000").unwrap().unwrap();
+        assert_eq!(page.title(), "SCP-1000");
+
+        // Purging can't split up a commit shared with other pages
+        let (page_id, revision_id) = revisions[1];
+        match srv.purge_revisions(page_id, &[revision_id], &user, "Leak") {
+            Err(Error::SharedCommit) => (),
+            result => panic!("Purge of shared commit succeeded: {:?}", result),
+        }
+
+        match srv.purge_page(page_id, &user, "Leak") {
+            Err(Error::SharedCommit) => (),
+            result => panic!("Purge of page in shared commit succeeded: {:?}", result),
+        }
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
+
+#[test]
+fn batch_rename_history() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let mut pages = Vec::new();
+        for (slug, content) in &[("scp-1", "One"), ("scp-2", "Two"), ("scp-3", "Three")] {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New article!",
+                user: &user,
+                base: None,
+            };
+
+            let (page_id, revision_id) = srv
+                .create_page(commit, content.as_bytes(), &[], &[], "SCP", "")
+                .expect("Unable to create page");
+
+            pages.push((page_id, revision_id));
+        }
+
+        // Give two pages the same contents, so they can't be told apart by their files
+        for slug in &["scp-2", "scp-3"] {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "Merged",
+                user: &user,
+                base: None,
+            };
+
+            srv.edit_page(commit, Some(b"Same"), Some("SCP"), None)
+                .expect("Unable to edit page");
+        }
+
+        // Move a page away and create a new one in its place
+        let changes = [
+            BatchChange::Rename {
+                old_slug: "scp-1",
+                new_slug: "scp-9",
+                redirect: false,
+            },
+            BatchChange::Create {
+                slug: "scp-1",
+                content: b"Replacement",
+                tags: &[],
+                title: "SCP-1",
+                alt_title: None,
+            },
+            BatchChange::Rename {
+                old_slug: "scp-2",
+                new_slug: "scp-20",
+                redirect: false,
+            },
+            BatchChange::Rename {
+                old_slug: "scp-3",
+                new_slug: "scp-30",
+                redirect: false,
+            },
+        ];
+
+        let revisions = srv
+            .commit_batch(wiki_id, &changes, "Reorganize", &user)
+            .expect("Unable to commit batch");
+
+        let version = |(page_id, revision_id)| {
+            srv.get_page_version_by_id(page_id, Left(revision_id))
+                .expect("Unable to get page version")
+        };
+
+        assert_eq!(version(pages[0]).as_deref(), Some(&b"One"[..]));
+        assert_eq!(version(pages[1]).as_deref(), Some(&b"Two"[..]));
+        assert_eq!(version(pages[2]).as_deref(), Some(&b"Three"[..]));
+
+        let (page_id, created) = pages[1];
+        let diff = srv
+            .get_page_diff_by_id(page_id, Left(created), Left(revisions[2].1))
+            .expect("Unable to get diff");
+
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.files[0].path, "scp-20.ftml");
+        assert!(diff.files[0].old_blob.is_some());
+
+        let (page_id, created) = pages[0];
+        srv.get_page_blame_at(page_id, Left(created))
+            .expect("Unable to get blame")
+            .expect("Page not found in blame");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-9",
+            message: "Undo",
+            user: &user,
+            base: None,
+        };
+
+        srv.revert_page(commit, Left(created))
+            .expect("Unable to revert page");
+
+        let contents = srv.get_page_contents(wiki_id, "scp-9").unwrap();
+        assert_eq!(
+            contents.map(|(contents, _)| contents).as_deref(),
+            Some(&b"One"[..]),
+        );
+
+        // The revisions still match the repository
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
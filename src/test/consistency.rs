@@ -0,0 +1,206 @@
+/*
+ * test/consistency.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use diesel::{sql_query, RunQueryDsl};
+
+fn execute(srv: &Server, query: &str) {
+    sql_query(query)
+        .execute(srv.test_connection())
+        .expect("Unable to run query");
+}
+
+fn kinds(report: &ConsistencyReport) -> Vec<(Inconsistency, bool)> {
+    report
+        .issues
+        .iter()
+        .map(|issue| (issue.inconsistency.clone(), issue.repaired))
+        .collect()
+}
+
+#[test]
+fn consistency() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, _) = srv
+            .create_page(commit, b"**Item #:** SCP-XXXX", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Fixing class",
+            user: &user,
+            base: None,
+        };
+
+        let revision_id = srv
+            .edit_page(
+                commit,
+                Some(b"**Item #:** SCP-XXXX\n\nKeter"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        srv.set_page_tags(commit, &["scp", "keter"])
+            .expect("Unable to set page tags");
+
+        let report = srv
+            .check_wiki_consistency(wiki_id, false)
+            .expect("Unable to check consistency");
+
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+        assert_eq!(report.revisions_checked, 3);
+        assert_eq!(report.commits_checked, 4);
+
+        // Change type disagreement
+        execute(
+            srv,
+            &format!(
+                "UPDATE revisions SET change_type = 'rename' WHERE revision_id = {}",
+                revision_id,
+            ),
+        );
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert_eq!(report.issues.len(), 1);
+        assert!(!report.is_repaired());
+        match report.issues[0].inconsistency {
+            Inconsistency::ChangeTypeMismatch {
+                revision_id: id,
+                database: ChangeType::Rename,
+                git: ChangeType::Modify,
+                ..
+            } => assert_eq!(id, revision_id),
+            ref other => panic!("Unexpected inconsistency: {:?}", other),
+        }
+
+        let report = srv.check_wiki_consistency(wiki_id, true).unwrap();
+        assert!(report.is_repaired());
+        assert!(srv
+            .check_wiki_consistency(wiki_id, false)
+            .unwrap()
+            .is_consistent());
+
+        // Commit with no revision, and revision with no commit
+        execute(
+            srv,
+            &format!(
+                "DELETE FROM title_history WHERE revision_id = {}",
+                revision_id,
+            ),
+        );
+        execute(
+            srv,
+            &format!("DELETE FROM revisions WHERE revision_id = {}", revision_id),
+        );
+        execute(
+            srv,
+            &format!(
+                "INSERT INTO revisions (page_id, user_id, message, git_commit, change_type) \
+                 VALUES ({}, {}, '', '{}', 'modify')",
+                page_id,
+                user.id(),
+                "0123456789abcdef0123456789abcdef01234567",
+            ),
+        );
+
+        let report = srv.check_wiki_consistency(wiki_id, true).unwrap();
+        let issues = kinds(&report);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().any(|(issue, repaired)| match issue {
+            Inconsistency::MissingCommit { .. } => !repaired,
+            _ => false,
+        }));
+        assert!(issues.iter().any(|(issue, repaired)| match issue {
+            Inconsistency::UntrackedCommit {
+                change_type: ChangeType::Modify,
+                ..
+            } => *repaired,
+            _ => false,
+        }));
+
+        execute(
+            srv,
+            "DELETE FROM revisions \
+             WHERE git_commit = '0123456789abcdef0123456789abcdef01234567'",
+        );
+        assert!(srv
+            .check_wiki_consistency(wiki_id, false)
+            .unwrap()
+            .is_consistent());
+
+        // Page deleted without removing its file
+        execute(
+            srv,
+            &format!(
+                "UPDATE pages SET deleted_at = NOW() WHERE page_id = {}",
+                page_id,
+            ),
+        );
+
+        let report = srv.check_wiki_consistency(wiki_id, true).unwrap();
+        assert_eq!(
+            kinds(&report),
+            vec![(
+                Inconsistency::DeletedPageExists {
+                    page_id,
+                    slug: String::from("scp-xxxx"),
+                },
+                true,
+            )],
+        );
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents, None);
+        assert!(srv
+            .check_wiki_consistency(wiki_id, false)
+            .unwrap()
+            .is_consistent());
+    });
+}
@@ -0,0 +1,384 @@
+/*
+ * test/history.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use chrono::Duration;
+
+#[test]
+fn page_history() {
+    run(|srv| {
+        let user_1 = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user_2 = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user_1)
+            .expect("Unable to create wiki");
+
+        grant_all(srv, wiki_id, &user_1, &[user_2.id()]);
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user_1,
+            base: None,
+        };
+
+        let (page_id, _) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        for (i, &user) in [&user_1, &user_2, &user_1].iter().enumerate() {
+            let message = format!("Edit #{}", i + 1);
+            let content = format!("Object Class: Safe\n\nEdited {} times.\n", i + 1);
+            let commit = PageCommit {
+                wiki_id,
+                slug: "scp-xxxx",
+                message: &message,
+                user,
+                base: None,
+            };
+
+            srv.edit_page(commit, Some(content.as_bytes()), Some("SCP-XXXX"), None)
+                .expect("Unable to edit page");
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user_2,
+            base: None,
+        };
+
+        let tag_revision = srv
+            .set_page_tags(commit, &["scp", "safe"])
+            .expect("Unable to set tags");
+
+        // Full history
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .expect("Unable to get history");
+
+        let messages: Vec<_> = history.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["Tagging", "Edit #3", "Edit #2", "Edit #1", "New article!"],
+        );
+        assert_eq!(history.next, None);
+
+        let entry = &history.entries[0];
+        assert_eq!(entry.revision_id, tag_revision);
+        assert_eq!(entry.user_id, user_2.id());
+        assert_eq!(entry.change_type, ChangeType::Tags);
+        assert_eq!(entry.added_tags, ["safe", "scp"]);
+        assert!(entry.removed_tags.is_empty());
+        assert!(history.entries[1].added_tags.is_empty());
+        assert_eq!(history.entries[4].change_type, ChangeType::Create);
+
+        let version = srv
+            .get_page_version(wiki_id, "scp-xxxx", Right(&history.entries[2].git_commit))
+            .expect("Unable to get page version");
+        assert_eq!(
+            version.as_deref(),
+            Some(&b"Object Class: Safe\n\nEdited 2 times.\n"[..]),
+        );
+
+        // Pagination
+        let mut query = HistoryQuery {
+            limit: 2,
+            ..HistoryQuery::default()
+        };
+
+        let mut revisions = Vec::new();
+        loop {
+            let history = srv
+                .get_page_history(Right((wiki_id, "scp-xxxx")), query)
+                .expect("Unable to get history");
+
+            assert!(history.entries.len() <= 2);
+            revisions.extend(history.entries.iter().map(|entry| entry.revision_id));
+
+            match history.next {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        let all: Vec<_> = history.entries.iter().map(|e| e.revision_id).collect();
+        assert_eq!(revisions, all);
+
+        // Filters
+        let query = HistoryQuery {
+            user_id: Some(user_2.id()),
+            ..HistoryQuery::default()
+        };
+
+        let history = srv.get_page_history(Left(page_id), query).unwrap();
+        let messages: Vec<_> = history.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["Tagging", "Edit #2"]);
+
+        let query = HistoryQuery {
+            user_id: Some(user_1.id()),
+            change_type: Some(ChangeType::Modify),
+            ..HistoryQuery::default()
+        };
+
+        let history = srv.get_page_history(Left(page_id), query).unwrap();
+        let messages: Vec<_> = history.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["Edit #3", "Edit #1"]);
+
+        let created_at = history.entries[0].created_at;
+        let query = HistoryQuery {
+            start: Some(created_at - Duration::hours(1)),
+            end: Some(created_at + Duration::hours(1)),
+            ..HistoryQuery::default()
+        };
+
+        let history = srv.get_page_history(Left(page_id), query).unwrap();
+        assert_eq!(history.entries.len(), 5);
+
+        let query = HistoryQuery {
+            end: Some(created_at - Duration::hours(1)),
+            ..HistoryQuery::default()
+        };
+
+        let history = srv.get_page_history(Left(page_id), query).unwrap();
+        assert!(history.entries.is_empty());
+        assert_eq!(history.next, None);
+    });
+}
+
+#[test]
+fn history_across_renames() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, created) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        srv.rename_page(wiki_id, "scp-xxxx", "scp-1000", "Numbered", &user, false)
+            .expect("Unable to rename page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Reclassified",
+            user: &user,
+            base: None,
+        };
+
+        let edited = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Safe\nReclassified: Keter\n"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        srv.rename_page(wiki_id, "scp-1000", "scp-2000", "Renumbered", &user, true)
+            .expect("Unable to rename page");
+
+        // Slug lookups only see the current slug
+        let contents = srv
+            .get_page_version(wiki_id, "scp-2000", Left(created))
+            .unwrap();
+        assert_eq!(contents, None);
+
+        let contents = srv
+            .get_page_version_by_id(page_id, Left(created))
+            .expect("Unable to get page version");
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Safe\n"[..]));
+
+        let contents = srv.get_page_version_by_id(page_id, Left(edited)).unwrap();
+        assert_eq!(
+            contents.as_deref(),
+            Some(&b"Object Class: Safe\nReclassified: Keter\n"[..]),
+        );
+
+        let diff = srv
+            .get_page_diff_by_id(page_id, Left(created), Left(edited))
+            .expect("Unable to get diff");
+
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.files[0].path, "scp-1000.ftml");
+        assert!(diff.files[0].old_blob.is_some());
+
+        // Lines keep the revision which wrote them
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .unwrap();
+        let commit_of = |revision_id| {
+            history
+                .entries
+                .iter()
+                .find(|entry| entry.revision_id == revision_id)
+                .map(|entry| entry.git_commit.clone())
+                .unwrap()
+        };
+
+        let blame = srv
+            .get_page_blame_at(page_id, Left(history.entries[0].revision_id))
+            .expect("Unable to get blame")
+            .expect("Page not found in blame");
+
+        let commits = blame
+            .groups
+            .iter()
+            .flat_map(|group| group.lines.iter())
+            .map(|line| line.commit.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(commits, [commit_of(created), commit_of(edited)]);
+
+        // Reverting to before the renames keeps the current slug
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-2000",
+            message: "Undo",
+            user: &user,
+            base: None,
+        };
+
+        srv.revert_page(commit, Left(created))
+            .expect("Unable to revert page");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-2000")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Safe\n"[..]));
+    });
+}
+
+#[test]
+fn revision_messages() {
+    run(|srv| {
+        let admin = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let permset = Permset {
+            create: true,
+            edit: true,
+            ..Permset::default()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "member", permset, &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &admin)
+            .expect("Unable to add user to role");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &admin,
+            base: None,
+        };
+
+        let (page_id, created) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "tpyo",
+            user: &user,
+            base: None,
+        };
+
+        let edited = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Euclid\n"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        // Users can fix their own messages, but not anyone else's
+        srv.edit_revision(edited, "Fixing object class", &user)
+            .expect("Unable to edit own revision message");
+
+        match srv.edit_revision(created, "Vandalism", &user) {
+            Err(Error::PermissionDenied(Permission::Purge)) => (),
+            result => panic!("Other user's revision message was edited: {:?}", result),
+        }
+
+        srv.edit_revision(created, "Initial version", &admin)
+            .expect("Unable to edit other user's revision message");
+
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .expect("Unable to get history");
+
+        let messages: Vec<_> = history.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["Fixing object class", "Initial version"]);
+
+        match srv.edit_revision(RevisionId::from_raw(0), "Missing", &admin) {
+            Err(Error::RevisionNotFound) => (),
+            result => panic!("Missing revision was edited: {:?}", result),
+        }
+    });
+}
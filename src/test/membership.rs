@@ -0,0 +1,229 @@
+/*
+ * test/membership.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use chrono::{Duration, Utc};
+
+#[test]
+fn membership() {
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let admin = {
+            let user_id = srv
+                .create_user("admin", "admin@example.net", "adminpassword")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let user_1 = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found")
+            .id();
+
+        let user_2 = srv
+            .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+            .expect("Unable to create user");
+
+        let user_3 = srv
+            .create_user("bluesoul", "blue@example.net", "frostyolympics")
+            .expect("Unable to create user");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let user_ids = |memberships: Vec<Membership>| -> Vec<UserId> {
+            memberships
+                .iter()
+                .map(|membership| membership.user_id())
+                .collect()
+        };
+
+        match srv.check_membership(wiki_id, user_1) {
+            Err(Error::NotMember) => (),
+            result => panic!("Non-member passed membership check: {:?}", result),
+        }
+
+        // Applications
+        assert!(srv.apply_to_wiki(wiki_id, user_1).unwrap());
+        assert!(!srv.apply_to_wiki(wiki_id, user_1).unwrap());
+        assert!(srv.apply_to_wiki(wiki_id, user_2).unwrap());
+        assert!(srv.apply_to_wiki(wiki_id, user_3).unwrap());
+
+        let applicants = srv.get_wiki_applicants(wiki_id).unwrap();
+        assert_eq!(user_ids(applicants), vec![user_1, user_2, user_3]);
+
+        // Only users allowed to manage members can accept or reject them
+        let applicant = srv.get_user_from_id(user_3).expect("Unable to get user");
+
+        match srv.accept_wiki_application(wiki_id, user_3, &applicant) {
+            Err(Error::PermissionDenied(Permission::ManageMembers)) => (),
+            result => panic!("Applicant accepted themselves: {:?}", result),
+        }
+
+        match srv.reject_wiki_application(wiki_id, user_2, &applicant) {
+            Err(Error::PermissionDenied(Permission::ManageMembers)) => (),
+            result => panic!("Applicant rejected someone else: {:?}", result),
+        }
+
+        srv.accept_wiki_application(wiki_id, user_1, &admin)
+            .expect("Unable to accept application");
+        srv.accept_wiki_application(wiki_id, user_2, &admin)
+            .expect("Unable to accept application");
+        srv.reject_wiki_application(wiki_id, user_3, &admin)
+            .expect("Unable to reject application");
+
+        match srv.reject_wiki_application(wiki_id, user_1, &admin) {
+            Err(Error::ApplicationNotFound) => (),
+            result => panic!("Member's application was rejected: {:?}", result),
+        }
+
+        match srv.apply_to_wiki(wiki_id, user_1) {
+            Err(Error::AlreadyMember) => (),
+            result => panic!("Member was able to apply: {:?}", result),
+        }
+
+        assert!(srv.get_wiki_applicants(wiki_id).unwrap().is_empty());
+        assert_eq!(
+            user_ids(srv.get_wiki_members(wiki_id).unwrap()),
+            vec![user_1, user_2],
+        );
+
+        let membership = srv
+            .get_membership(wiki_id, user_1)
+            .expect("Unable to get membership")
+            .expect("No membership found");
+
+        assert!(membership.applied_at().is_some());
+        assert!(membership.joined_at().is_some());
+        assert!(membership.banned_at().is_none());
+
+        srv.check_membership(wiki_id, user_1)
+            .expect("Member failed membership check");
+
+        // Leaving takes away roles
+        let role_id = srv
+            .create_role(wiki_id, "member", Permset::default(), &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user_2, &admin)
+            .expect("Unable to add role");
+
+        srv.leave_wiki(wiki_id, user_2)
+            .expect("Unable to leave wiki");
+
+        assert!(srv.get_user_roles(wiki_id, user_2).unwrap().is_empty());
+
+        match srv.leave_wiki(wiki_id, user_2) {
+            Err(Error::NotMember) => (),
+            result => panic!("Non-member left wiki: {:?}", result),
+        }
+
+        // Bans
+        let member = srv.get_user_from_id(user_1).expect("Unable to get user");
+
+        match srv.ban_user(wiki_id, user_3, None, &member) {
+            Err(Error::PermissionDenied(Permission::ManageMembers)) => (),
+            result => panic!("Member without permission banned user: {:?}", result),
+        }
+
+        srv.ban_user(wiki_id, user_1, None, &admin)
+            .expect("Unable to ban user");
+
+        srv.ban_user(
+            wiki_id,
+            user_3,
+            Some(Utc::now() + Duration::days(7)),
+            &admin,
+        )
+        .expect("Unable to ban user");
+
+        match srv.ban_user(
+            wiki_id,
+            user_2,
+            Some(Utc::now() - Duration::days(1)),
+            &admin,
+        ) {
+            Err(Error::StaticMsg(_)) => (),
+            result => panic!("Ban ending in the past was allowed: {:?}", result),
+        }
+
+        assert!(srv.get_wiki_members(wiki_id).unwrap().is_empty());
+        assert_eq!(
+            user_ids(srv.get_wiki_bans(wiki_id).unwrap()),
+            vec![user_1, user_3],
+        );
+
+        for &user_id in &[user_1, user_3] {
+            match srv.check_not_banned(wiki_id, user_id) {
+                Err(Error::UserBanned) => (),
+                result => panic!("Banned user passed ban check: {:?}", result),
+            }
+
+            match srv.apply_to_wiki(wiki_id, user_id) {
+                Err(Error::UserBanned) => (),
+                result => panic!("Banned user was able to apply: {:?}", result),
+            }
+        }
+
+        srv.check_not_banned(wiki_id, user_2)
+            .expect("User who left failed ban check");
+
+        // Timed bans lift on their own
+        sql_query(format!(
+            "UPDATE wiki_membership SET banned_until = NOW() - INTERVAL '1 hour' \
+             WHERE wiki_id = {} AND user_id = {}",
+            wiki_id, user_3,
+        ))
+        .execute(srv.test_connection())
+        .expect("Unable to change ban time");
+
+        assert_eq!(user_ids(srv.get_wiki_bans(wiki_id).unwrap()), vec![user_1]);
+        srv.check_not_banned(wiki_id, user_3)
+            .expect("User with expired ban failed ban check");
+        assert!(!srv.unban_user(wiki_id, user_3, &admin).unwrap());
+        assert!(srv.apply_to_wiki(wiki_id, user_3).unwrap());
+
+        let membership = srv
+            .get_membership(wiki_id, user_3)
+            .expect("Unable to get membership")
+            .expect("No membership found");
+
+        assert!(membership.applied_at().is_some());
+        assert!(membership.banned_at().is_none());
+
+        match srv.unban_user(wiki_id, user_1, &member) {
+            Err(Error::PermissionDenied(Permission::ManageMembers)) => (),
+            result => panic!("Banned user unbanned themselves: {:?}", result),
+        }
+
+        assert!(srv.unban_user(wiki_id, user_1, &admin).unwrap());
+        assert!(!srv.unban_user(wiki_id, user_1, &admin).unwrap());
+        assert!(srv.get_wiki_bans(wiki_id).unwrap().is_empty());
+
+        match srv.check_membership(wiki_id, user_1) {
+            Err(Error::NotMember) => (),
+            result => panic!("Unbanned user is still a member: {:?}", result),
+        }
+    });
+}
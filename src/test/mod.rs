@@ -22,39 +22,72 @@ extern crate color_backtrace;
 extern crate tempfile;
 
 mod authors;
+mod batch;
+mod consistency;
+mod history;
+mod membership;
 mod page;
 mod password;
+mod purge;
+mod query;
+mod rebuild;
+mod redirect;
+mod revert;
+mod role;
+mod search;
+mod session;
+mod store;
 mod tags;
 mod user;
 mod wiki;
 
 use self::prelude::*;
 use std::env;
+use std::path::Path;
 use tempfile::tempdir;
 
 mod prelude {
-    pub use super::run;
+    pub use super::{grant_all, run, run_with_dir};
     pub use crate::prelude::*;
     pub use either::*;
 }
 
 pub fn run<F: FnOnce(&Server)>(f: F) {
+    run_with_dir(|srv, _| f(srv));
+}
+
+/// Like `run`, but also passes the directory the wikis' git repositories are kept in.
+pub fn run_with_dir<F: FnOnce(&Server, &Path)>(f: F) {
     color_backtrace::install();
 
     let database_url = &env::var("DATABASE_URL").expect("No DATABASE_URL specified!");
     let temp_dir = tempdir().expect("Unable to create temp dir");
-    let revisions_dir = temp_dir.path().into();
+    let revision_backend = RevisionBackend::Git(temp_dir.path().into());
 
     let config = ServerConfig {
         database_url,
-        revisions_dir,
+        revision_backend,
         password_blacklist: None,
+        session_key: b"test session key",
     };
 
     let server = Server::new(config).expect("Unable to create server");
 
     server.test_transaction(|| {
-        f(&server);
+        f(&server, temp_dir.path());
         Ok(())
     });
 }
+
+/// Gives users every permission in a wiki, since changing pages requires them.
+/// The administrator must be able to manage roles, e.g. by having created the wiki.
+pub fn grant_all(srv: &Server, wiki_id: WikiId, admin: &User, users: &[UserId]) {
+    let role_id = srv
+        .create_role(wiki_id, "editor", Permset::all(), admin)
+        .expect("Unable to create role");
+
+    for &user_id in users {
+        srv.add_user_role(role_id, user_id, admin)
+            .expect("Unable to add user to role");
+    }
+}
@@ -29,7 +29,7 @@ fn page_service() {
             .expect("Default user not found");
 
         let wiki_id = srv
-            .create_wiki("Test", "test", "example.org")
+            .create_wiki("Test", "test", "example.org", &user)
             .expect("Unable to create wiki");
 
         assert_eq!(srv.check_page(wiki_id, "tale-here").unwrap(), false);
@@ -39,10 +39,11 @@ fn page_service() {
             slug: &"tale-here",
             message: "new tale!",
             user: &user,
+            base: None,
         };
 
         let (_page_id, _revision_id) = srv
-            .create_page(commit, b"my great article here", &[], "Tale Thing", "")
+            .create_page(commit, b"my great article here", &[], &[], "Tale Thing", "")
             .expect("Unable to create page");
 
         assert_eq!(srv.check_page(wiki_id, "tale-here").unwrap(), true);
@@ -53,6 +54,7 @@ fn page_service() {
             "amazing-battle",
             "I like this name better",
             &user,
+            false,
         )
         .expect("Unable to rename page");
 
@@ -61,6 +63,7 @@ fn page_service() {
             slug: &"amazing-battle",
             message: "changing title",
             user: &user,
+            base: None,
         };
 
         srv.edit_page(
@@ -79,6 +82,7 @@ fn page_service() {
             slug: &"amazing-battle",
             message: "people keep downvoting :(",
             user: &user,
+            base: None,
         };
 
         srv.remove_page(commit).expect("Unable to remove page");
@@ -88,3 +92,405 @@ fn page_service() {
         assert_eq!(srv.check_page(wiki_id, "amazing-battle").unwrap(), false);
     });
 }
+
+#[test]
+fn failed_commit_rollback() {
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let ghost = {
+            let user_id = srv
+                .create_user("ghost", "ghost@example.net", "boooooooooo")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        grant_all(srv, wiki_id, &user, &[ghost.id()]);
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        srv.create_page(commit, b"Original", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        // Make revision inserts fail by removing the user they refer to,
+        // keeping their role so the permission checks still pass
+        sql_query("ALTER TABLE role_membership DROP CONSTRAINT role_membership_user_id_fkey")
+            .execute(srv.test_connection())
+            .expect("Unable to drop constraint");
+
+        for table in &["passwords", "users"] {
+            sql_query(format!(
+                "DELETE FROM {} WHERE user_id = {}",
+                table,
+                ghost.id(),
+            ))
+            .execute(srv.test_connection())
+            .expect("Unable to delete user");
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Vandalism",
+            user: &ghost,
+            base: None,
+        };
+
+        let result = srv.edit_page(commit, Some(b"Overwritten"), Some("Oops"), None);
+        assert!(result.is_err(), "Edit by missing user succeeded");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-yyyy",
+            message: "Spam",
+            user: &ghost,
+            base: None,
+        };
+
+        let result = srv.create_page(commit, b"Spam", &[], &[], "Spam", "");
+        assert!(result.is_err(), "Creation by missing user succeeded");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Original"[..]));
+        assert_eq!(srv.get_page_contents(wiki_id, "scp-yyyy").unwrap(), None);
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
+
+#[test]
+fn concurrent_edits() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (_, base) = srv
+            .create_page(
+                commit,
+                b"Object Class: Safe\n\nA cup.\n",
+                &[],
+                &[],
+                "SCP-XXXX",
+                "",
+            )
+            .expect("Unable to create page");
+
+        // Two edits starting from the same revision, to different lines
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Reclassify",
+            user: &user,
+            base: Some(base),
+        };
+
+        srv.edit_page(
+            commit,
+            Some(b"Object Class: Euclid\n\nA cup.\n"),
+            Some("SCP-XXXX"),
+            None,
+        )
+        .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Fix description",
+            user: &user,
+            base: Some(base),
+        };
+
+        srv.edit_page(
+            commit,
+            Some(b"Object Class: Safe\n\nA teacup.\n"),
+            Some("SCP-XXXX"),
+            None,
+        )
+        .expect("Unable to merge edit");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(
+            contents.as_deref(),
+            Some(&b"Object Class: Euclid\n\nA teacup.\n"[..]),
+        );
+
+        // An edit to a line which was changed after its base revision
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Reclassify again",
+            user: &user,
+            base: Some(base),
+        };
+
+        let result = srv.edit_page(
+            commit,
+            Some(b"Object Class: Keter\n\nA cup.\n"),
+            Some("SCP-XXXX"),
+            None,
+        );
+
+        match result {
+            Err(Error::EditConflict(conflicts)) => {
+                assert_eq!(conflicts.len(), 1);
+                assert_eq!(conflicts[0].current, "Object Class: Euclid\n");
+                assert_eq!(conflicts[0].edited, "Object Class: Keter\n");
+            }
+            _ => panic!("Conflicting edit did not fail: {:?}", result),
+        }
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(
+            contents.as_deref(),
+            Some(&b"Object Class: Euclid\n\nA teacup.\n"[..]),
+        );
+
+        // Edits based on a revision from before a rename are still merged
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Addendum",
+            user: &user,
+            base: None,
+        };
+
+        let base = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Euclid\n\nA teacup.\n\nAddendum: None.\n"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        srv.rename_page(wiki_id, "scp-xxxx", "scp-1000", "Numbered", &user, false)
+            .expect("Unable to rename page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Reclassify",
+            user: &user,
+            base: None,
+        };
+
+        srv.edit_page(
+            commit,
+            Some(b"Object Class: Keter\n\nA teacup.\n\nAddendum: None.\n"),
+            Some("SCP-1000"),
+            None,
+        )
+        .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Fill in addendum",
+            user: &user,
+            base: Some(base),
+        };
+
+        let base = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Euclid\n\nA teacup.\n\nAddendum: It's hot.\n"),
+                Some("SCP-1000"),
+                None,
+            )
+            .expect("Unable to merge edit across rename");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-1000")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(
+            contents.as_deref(),
+            Some(&b"Object Class: Keter\n\nA teacup.\n\nAddendum: It's hot.\n"[..]),
+        );
+
+        // Tag changes don't count as newer contents
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        srv.set_page_tags(commit, &["keter", "scp"])
+            .expect("Unable to set tags");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Shorter",
+            user: &user,
+            base: Some(base),
+        };
+
+        srv.edit_page(
+            commit,
+            Some(b"Object Class: Keter\n"),
+            Some("SCP-1000"),
+            None,
+        )
+        .expect("Unable to edit page after tagging");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-1000")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Keter\n"[..]));
+    });
+}
+
+#[test]
+fn restore_page() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, _) = srv
+            .create_page(commit, b"First draft", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Rewrite",
+            user: &user,
+            base: None,
+        };
+
+        srv.edit_page(commit, Some(b"Second draft"), Some("SCP-XXXX"), None)
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Deleting",
+            user: &user,
+            base: None,
+        };
+
+        srv.remove_page(commit).expect("Unable to remove page");
+
+        let deleted = srv.get_deleted_pages(wiki_id).unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].id(), page_id);
+        assert!(!deleted[0].exists());
+
+        // Another page takes the slug
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Replacement",
+            user: &user,
+            base: None,
+        };
+
+        let (new_id, _) = srv
+            .create_page(commit, b"Replacement", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page over deleted one");
+
+        match srv.restore_page(page_id, "Undelete", &user) {
+            Err(Error::PageExists) => (),
+            result => panic!("Restore over live page succeeded: {:?}", result),
+        }
+
+        match srv.restore_page(new_id, "Undelete", &user) {
+            Err(Error::PageExists) => (),
+            result => panic!("Restore of live page succeeded: {:?}", result),
+        }
+
+        srv.rename_page(
+            wiki_id,
+            "scp-xxxx",
+            "scp-xxxx-j",
+            "Never mind",
+            &user,
+            false,
+        )
+        .expect("Unable to rename page");
+
+        // Restore the original
+        let revision_id = srv
+            .restore_page(page_id, "Undelete", &user)
+            .expect("Unable to restore page");
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.id(), page_id);
+        assert!(page.exists());
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Second draft"[..]));
+
+        assert!(srv.get_deleted_pages(wiki_id).unwrap().is_empty());
+
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .unwrap();
+        assert_eq!(history.entries[0].revision_id, revision_id);
+        assert_eq!(history.entries[0].change_type, ChangeType::Restore);
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
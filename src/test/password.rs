@@ -86,3 +86,72 @@ fn password_default() {
         bad_password!(5, "blackmoon");
     });
 }
+
+#[test]
+fn password_rehash() {
+    use crypto::scrypt::{scrypt, ScryptParams};
+    use diesel::sql_types::{Bytea, Int8};
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let user_id = srv
+            .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+            .expect("Unable to create user");
+
+        // Replace the password with an old-style scrypt hash
+        let salt = vec![7; 16];
+        let mut hash = vec![0; 32];
+        scrypt(
+            b"blackmoonhowls",
+            &salt,
+            &ScryptParams::new(6, 8, 16),
+            &mut hash,
+        );
+
+        sql_query(
+            "UPDATE passwords SET hash = $1, salt = $2, logn = 6, param_r = 8, param_p = 16, \
+             algorithm = 'scrypt' WHERE user_id = $3",
+        )
+        .bind::<Bytea, _>(&hash)
+        .bind::<Bytea, _>(&salt)
+        .bind::<Int8, i64>(user_id.into())
+        .execute(srv.test_connection())
+        .expect("Unable to set scrypt password");
+
+        let algorithm = || {
+            sql_query(format!(
+                "SELECT algorithm FROM passwords WHERE user_id = {}",
+                user_id,
+            ))
+            .load::<AlgorithmRow>(srv.test_connection())
+            .expect("Unable to get password algorithm")
+            .remove(0)
+            .algorithm
+        };
+
+        // A failed login leaves the hash alone
+        match srv.validate_user_password(user_id, "blackmonhowls") {
+            Err(Error::AuthenticationFailed) => (),
+            result => panic!("Password matched when it shouldn't have: {:?}", result),
+        }
+        assert_eq!(algorithm(), "scrypt");
+
+        // A successful one upgrades it
+        srv.validate_user_password(user_id, "blackmoonhowls")
+            .expect("Password doesn't match");
+        assert_eq!(algorithm(), "argon2id");
+
+        srv.validate_user_password(user_id, "blackmoonhowls")
+            .expect("Password doesn't match after rehash");
+        match srv.validate_user_password(user_id, "blackmonhowls") {
+            Err(Error::AuthenticationFailed) => (),
+            result => panic!("Password matched when it shouldn't have: {:?}", result),
+        }
+    });
+}
+
+#[derive(QueryableByName)]
+struct AlgorithmRow {
+    #[sql_type = "diesel::sql_types::Varchar"]
+    algorithm: String,
+}
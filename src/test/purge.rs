@@ -0,0 +1,342 @@
+/*
+ * test/purge.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+#[test]
+fn purge() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let admin = {
+            let user_id = srv
+                .create_user("admin", "admin@example.net", "adminpassword")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let permset = Permset {
+            purge: false,
+            ..Permset::all()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "editor", permset, &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &admin)
+            .expect("Unable to add user to role");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, created) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        let tagged = srv
+            .set_page_tags(commit, &["scp"])
+            .expect("Unable to set tags");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Adding details",
+            user: &user,
+            base: None,
+        };
+
+        let leaked = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Safe\nAddress: 123 Fake Street\n"),
+                Some("Dr. Somebody's House"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Removing details",
+            user: &user,
+            base: None,
+        };
+
+        let fixed = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Euclid\n"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Restoring details",
+            user: &user,
+            base: None,
+        };
+
+        srv.revert_page(commit, Left(leaked))
+            .expect("Unable to revert page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-yyyy",
+            message: "Another article",
+            user: &user,
+            base: None,
+        };
+
+        let (other_id, _) = srv
+            .create_page(commit, b"Object Class: Keter\n", &[], &[], "SCP-YYYY", "")
+            .expect("Unable to create page");
+
+        // Purging requires its own permission
+        match srv.purge_revisions(page_id, &[leaked], &user, "Takedown") {
+            Err(Error::PermissionDenied(Permission::Purge)) => (),
+            result => panic!("Purging without permission did not fail: {:?}", result),
+        }
+
+        let permset = Permset {
+            purge: true,
+            ..Permset::default()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "admin", permset, &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &admin)
+            .expect("Unable to add user to role");
+
+        // Only edits, tag changes and reverts can be purged on their own
+        match srv.purge_revisions(page_id, &[created], &user, "Takedown") {
+            Err(Error::CannotPurgeRevision) => (),
+            result => panic!("Purging page creation did not fail: {:?}", result),
+        }
+
+        let other_history = srv
+            .get_page_history(Left(other_id), HistoryQuery::default())
+            .unwrap();
+
+        match srv.purge_revisions(page_id, &[other_history.entries[0].revision_id], &user, "") {
+            Err(Error::RevisionNotFound) => (),
+            result => panic!("Purging other page's revision did not fail: {:?}", result),
+        }
+
+        // Purging the edit also purges the revert back to it
+        srv.purge_revisions(page_id, &[leaked], &user, "Personal information")
+            .expect("Unable to purge revisions");
+
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .unwrap();
+
+        let revision_ids = history
+            .entries
+            .iter()
+            .map(|entry| entry.revision_id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(revision_ids, [fixed, tagged, created]);
+
+        let (page, _) = srv.get_page_by_id(page_id).unwrap().unwrap();
+        assert_eq!(page.title(), "SCP-XXXX");
+        assert_eq!(page.tags(), ["scp"]);
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Euclid\n"[..]));
+
+        // Other pages point to their rewritten commits
+        let new_history = srv
+            .get_page_history(Left(other_id), HistoryQuery::default())
+            .unwrap();
+
+        let old_commit = &other_history.entries[0].git_commit;
+        let new_commit = &new_history.entries[0].git_commit;
+        assert_ne!(old_commit, new_commit);
+
+        let contents = srv
+            .get_page_version(
+                wiki_id,
+                "scp-yyyy",
+                Left(new_history.entries[0].revision_id),
+            )
+            .unwrap();
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Keter\n"[..]));
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+
+        // Purges inside a transaction leave pruning to the caller
+        assert!(srv.prune_wiki_objects(wiki_id).unwrap() > 0);
+        assert_eq!(
+            srv.get_page_version(wiki_id, "scp-yyyy", Right(old_commit))
+                .unwrap_or_default(),
+            None,
+        );
+
+        // Purge the whole page
+        srv.set_rating(page_id, user.id(), 1)
+            .expect("Unable to set rating");
+
+        srv.purge_page(page_id, &user, "Takedown request")
+            .expect("Unable to purge page");
+
+        assert!(srv.get_page_by_id(page_id).unwrap().is_none());
+        assert_eq!(srv.get_page_contents(wiki_id, "scp-xxxx").unwrap(), None);
+        assert!(srv
+            .get_page_authors(Left(page_id))
+            .map(|authors| authors.is_empty())
+            .unwrap_or(true));
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-yyyy")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Keter\n"[..]));
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+        assert_eq!(report.revisions_checked, 1);
+
+        // Audit log
+        let log = srv.get_purge_log(wiki_id).expect("Unable to get purge log");
+        assert_eq!(log.len(), 2);
+
+        assert!(log[0].page_removed);
+        assert_eq!(log[0].page_id, page_id);
+        assert_eq!(log[0].slug, "scp-xxxx");
+        assert_eq!(log[0].reason, "Takedown request");
+        assert_eq!(log[0].revision_ids, [created, tagged, fixed]);
+        assert_eq!(log[0].commits_rewritten, 1);
+
+        assert!(!log[1].page_removed);
+        assert_eq!(log[1].user_id, user.id());
+        assert_eq!(log[1].reason, "Personal information");
+        assert_eq!(log[1].revision_ids.len(), 2);
+        assert_eq!(log[1].revision_ids[0], leaked);
+        assert_eq!(log[1].git_commits.len(), 2);
+        assert_eq!(log[1].commits_rewritten, 2);
+    });
+}
+
+#[test]
+fn purge_renamed() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let mut pages = Vec::new();
+        for (slug, content) in &[("scp-1", "Same"), ("scp-2", "Different")] {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New article!",
+                user: &user,
+                base: None,
+            };
+
+            let (page_id, _) = srv
+                .create_page(commit, content.as_bytes(), &[], &[], "SCP", "")
+                .expect("Unable to create page");
+
+            pages.push(page_id);
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-2",
+            message: "Copied",
+            user: &user,
+            base: None,
+        };
+
+        let copied = srv
+            .edit_page(commit, Some(b"Same"), Some("SCP"), None)
+            .expect("Unable to edit page");
+
+        // Both renamed files have the same contents, so they can't be told apart by them
+        let changes = [
+            BatchChange::Rename {
+                old_slug: "scp-1",
+                new_slug: "scp-10",
+                redirect: false,
+            },
+            BatchChange::Rename {
+                old_slug: "scp-2",
+                new_slug: "scp-20",
+                redirect: false,
+            },
+        ];
+
+        srv.commit_batch(wiki_id, &changes, "Reorganize", &user)
+            .expect("Unable to commit batch");
+
+        srv.purge_revisions(pages[1], &[copied], &user, "Copied content")
+            .expect("Unable to purge revision");
+
+        let contents = |slug| {
+            srv.get_page_contents(wiki_id, slug)
+                .unwrap()
+                .map(|(contents, _)| contents)
+        };
+
+        assert_eq!(contents("scp-10").as_deref(), Some(&b"Same"[..]));
+        assert_eq!(contents("scp-20").as_deref(), Some(&b"Different"[..]));
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
@@ -0,0 +1,275 @@
+/*
+ * test/query.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+#[test]
+fn list_pages() {
+    use chrono::{Duration, Utc};
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let user_1 = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user_2 = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user_1)
+            .expect("Unable to create wiki");
+
+        grant_all(srv, wiki_id, &user_1, &[user_2.id()]);
+
+        let pages: &[(&str, &User, &[&str], &str)] = &[
+            ("scp-001", &user_1, &["scp", "keter"], "SCP-001"),
+            ("scp-002", &user_2, &["scp", "euclid"], "SCP-002"),
+            ("scp-003", &user_1, &["scp", "safe", "_image"], "SCP-003"),
+            ("tale:midnight", &user_2, &["tale"], "Midnight"),
+            ("tale:dawn", &user_1, &["tale", "keter"], "Dawn"),
+            ("component:theme", &user_1, &[], "Theme"),
+        ];
+
+        let mut page_ids = Vec::new();
+        for (hours, &(slug, user, tags, title)) in pages.iter().enumerate() {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New page!",
+                user,
+                base: None,
+            };
+
+            let (page_id, _) = srv
+                .create_page(commit, b"Page contents", &[], tags, title, "")
+                .expect("Unable to create page");
+
+            // Every page in a transaction has the same time, so spread them out
+            sql_query(format!(
+                "UPDATE pages SET created_at = NOW() + INTERVAL '{} hours' WHERE page_id = {}",
+                hours, page_id,
+            ))
+            .execute(srv.test_connection())
+            .expect("Unable to change page time");
+
+            page_ids.push(page_id);
+        }
+
+        for &(index, user, rating) in &[
+            (0, &user_1, 1),
+            (0, &user_2, 1),
+            (1, &user_1, -1),
+            (2, &user_2, 1),
+            (3, &user_1, 1),
+            (3, &user_2, -1),
+        ] {
+            srv.set_rating(page_ids[index], user.id(), rating)
+                .expect("Unable to set rating");
+        }
+
+        sql_query(format!(
+            "INSERT INTO parents (page_id, parent_page_id, parented_by, parented_at) \
+             VALUES ({}, {}, {}, NOW())",
+            page_ids[4],
+            page_ids[3],
+            user_1.id(),
+        ))
+        .execute(srv.test_connection())
+        .expect("Unable to add parent");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "component:theme",
+            message: "Not needed",
+            user: &user_1,
+            base: None,
+        };
+
+        srv.remove_page(commit).expect("Unable to remove page");
+
+        let list = |query| -> Vec<&str> {
+            let result = srv
+                .list_pages(wiki_id, query)
+                .expect("Unable to list pages");
+
+            result
+                .pages
+                .iter()
+                .map(|(page, _)| {
+                    let index = page_ids
+                        .iter()
+                        .position(|&page_id| page_id == page.id())
+                        .expect("Unknown page returned");
+
+                    pages[index].0
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            list(PageQuery::default()),
+            vec![
+                "scp-001",
+                "scp-002",
+                "scp-003",
+                "tale:midnight",
+                "tale:dawn"
+            ],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                category: Some("tale"),
+                ..PageQuery::default()
+            }),
+            vec!["tale:midnight", "tale:dawn"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                category: Some("_default"),
+                descending: true,
+                ..PageQuery::default()
+            }),
+            vec!["scp-003", "scp-002", "scp-001"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                all_tags: &["scp", "keter"],
+                ..PageQuery::default()
+            }),
+            vec!["scp-001"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                any_tags: &["keter", "euclid"],
+                no_tags: &["tale"],
+                ..PageQuery::default()
+            }),
+            vec!["scp-001", "scp-002"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                created_by: Some(user_2.id()),
+                ..PageQuery::default()
+            }),
+            vec!["scp-002", "tale:midnight"],
+        );
+
+        let now = Utc::now();
+        assert_eq!(
+            list(PageQuery {
+                start: Some(now + Duration::minutes(30)),
+                end: Some(now + Duration::minutes(150)),
+                ..PageQuery::default()
+            }),
+            vec!["scp-002", "scp-003"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                min_rating: Some(0),
+                max_rating: Some(1),
+                ..PageQuery::default()
+            }),
+            vec!["scp-003", "tale:midnight", "tale:dawn"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                parent: Some(page_ids[3]),
+                ..PageQuery::default()
+            }),
+            vec!["tale:dawn"],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                order: PageOrder::Rating,
+                descending: true,
+                ..PageQuery::default()
+            }),
+            vec![
+                "scp-001",
+                "scp-003",
+                "tale:dawn",
+                "tale:midnight",
+                "scp-002"
+            ],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                order: PageOrder::Votes,
+                ..PageQuery::default()
+            }),
+            vec![
+                "tale:dawn",
+                "scp-002",
+                "scp-003",
+                "scp-001",
+                "tale:midnight"
+            ],
+        );
+
+        assert_eq!(
+            list(PageQuery {
+                order: PageOrder::Title,
+                ..PageQuery::default()
+            }),
+            vec![
+                "tale:dawn",
+                "tale:midnight",
+                "scp-001",
+                "scp-002",
+                "scp-003"
+            ],
+        );
+
+        let result = srv
+            .list_pages(
+                wiki_id,
+                PageQuery {
+                    any_tags: &["scp", "tale"],
+                    offset: 1,
+                    limit: 2,
+                    ..PageQuery::default()
+                },
+            )
+            .expect("Unable to list pages");
+
+        assert_eq!(result.total, 5);
+        assert_eq!(result.pages.len(), 2);
+        assert_eq!(result.pages[0].0.id(), page_ids[1]);
+        assert_eq!(result.pages[0].1.score(), -1);
+        assert_eq!(result.pages[0].1.votes(), 1);
+        assert_eq!(result.pages[1].0.id(), page_ids[2]);
+    });
+}
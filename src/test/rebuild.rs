@@ -0,0 +1,194 @@
+/*
+ * test/rebuild.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use diesel::{sql_query, RunQueryDsl};
+
+fn execute(srv: &Server, query: &str) {
+    sql_query(query)
+        .execute(srv.test_connection())
+        .expect("Unable to run query");
+}
+
+#[test]
+fn rebuild() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, created) = srv
+            .create_page(commit, b"**Item #:** SCP-XXXX", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        srv.set_page_tags(commit, &["scp", "keter"])
+            .expect("Unable to set page tags");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Untagging",
+            user: &user,
+            base: None,
+        };
+
+        srv.revert_page(commit, Left(created))
+            .expect("Unable to revert page");
+
+        srv.rename_page(wiki_id, "scp-xxxx", "scp-4999", "Numbered", &user, false)
+            .expect("Unable to rename page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "sandbox",
+            message: "Drafting",
+            user: &user,
+            base: None,
+        };
+
+        let (deleted_id, _) = srv
+            .create_page(commit, b"Draft", &[], &[], "Sandbox", "")
+            .expect("Unable to create page");
+
+        srv.remove_page(commit).expect("Unable to remove page");
+
+        let wiki = wiki_id.to_i64();
+        for table in &["tag_history", "title_history"] {
+            execute(
+                srv,
+                &format!(
+                    "DELETE FROM {} WHERE revision_id IN \
+                     (SELECT revision_id FROM revisions JOIN pages USING (page_id) \
+                     WHERE wiki_id = {})",
+                    table, wiki,
+                ),
+            );
+        }
+        execute(
+            srv,
+            &format!(
+                "DELETE FROM revisions WHERE page_id IN \
+                 (SELECT page_id FROM pages WHERE wiki_id = {})",
+                wiki,
+            ),
+        );
+        execute(
+            srv,
+            &format!(
+                "DELETE FROM authors WHERE page_id IN \
+                 (SELECT page_id FROM pages WHERE wiki_id = {})",
+                wiki,
+            ),
+        );
+        execute(srv, &format!("DELETE FROM pages WHERE wiki_id = {}", wiki));
+
+        assert!(srv.get_page_by_id(page_id).unwrap().is_none());
+
+        let report = srv
+            .rebuild_wiki_pages(wiki_id)
+            .expect("Unable to rebuild wiki");
+
+        assert_eq!(report.pages, 2);
+        assert_eq!(report.revisions, 6);
+        assert_eq!(report.issues.len(), 3);
+        assert_eq!(
+            report.issues[0],
+            RebuildIssue::MissingTitle {
+                page_id,
+                slug: String::from("scp-xxxx"),
+            },
+        );
+        match report.issues[1] {
+            RebuildIssue::MissingTags { page_id: id, .. } => assert_eq!(id, page_id),
+            ref other => panic!("Unexpected issue: {:?}", other),
+        }
+        assert_eq!(
+            report.issues[2],
+            RebuildIssue::MissingTitle {
+                page_id: deleted_id,
+                slug: String::from("sandbox"),
+            },
+        );
+
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .expect("Unable to get history");
+
+        let revert = &history.entries[1];
+        assert_eq!(revert.change_type, ChangeType::Revert);
+        assert_eq!(
+            revert.target_revision_id,
+            history.entries.last().map(|e| e.revision_id)
+        );
+
+        let (page, _) = srv.get_page_by_id(page_id).unwrap().unwrap();
+        assert_eq!(page.slug(), "scp-4999");
+        assert_eq!(page.title(), "");
+        assert!(page.exists());
+
+        let (page, _) = srv.get_page_by_id(deleted_id).unwrap().unwrap();
+        assert_eq!(page.slug(), "sandbox");
+        assert!(!page.exists());
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+
+        match srv.rebuild_wiki_pages(wiki_id) {
+            Err(Error::WikiNotEmpty) => (),
+            other => panic!("Rebuild of non-empty wiki succeeded: {:?}", other),
+        }
+
+        // New pages shouldn't conflict with rebuilt ones
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-5000",
+            message: "Why?",
+            user: &user,
+            base: None,
+        };
+
+        let (new_id, _) = srv
+            .create_page(commit, b"Why?", &[], &[], "SCP-5000", "")
+            .expect("Unable to create page after rebuild");
+
+        assert!(new_id > deleted_id);
+    });
+}
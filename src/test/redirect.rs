@@ -0,0 +1,152 @@
+/*
+ * test/redirect.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+fn redirect_slugs(srv: &Server, wiki_id: WikiId) -> Vec<String> {
+    srv.get_redirects(wiki_id)
+        .expect("Unable to get redirects")
+        .iter()
+        .map(|redirect| redirect.slug().to_string())
+        .collect()
+}
+
+#[test]
+fn redirects() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, _) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        srv.rename_page(wiki_id, "scp-xxxx", "scp-1000", "Numbered", &user, true)
+            .expect("Unable to rename page");
+
+        let (page, _, redirect) = srv
+            .get_page(wiki_id, "scp-xxxx")
+            .expect("Unable to get page")
+            .expect("Redirect not followed");
+
+        let redirect = redirect.expect("Redirect not reported");
+        assert_eq!(page.id(), page_id);
+        assert_eq!(page.slug(), "scp-1000");
+        assert_eq!(redirect.slug(), "scp-xxxx");
+        assert_eq!(redirect.page_id(), page_id);
+
+        let (contents, redirect) = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .expect("Redirect not followed");
+
+        assert_eq!(&*contents, b"Object Class: Safe\n");
+        assert!(redirect.is_some());
+
+        let (_, _, redirect) = srv.get_page(wiki_id, "scp-1000").unwrap().unwrap();
+        assert_eq!(redirect, None);
+
+        // Redirects lead straight to the page, not through each other
+        srv.rename_page(wiki_id, "scp-1000", "scp-2000", "Renumbered", &user, true)
+            .expect("Unable to rename page");
+
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-1000", "scp-xxxx"]);
+        for redirect in srv.get_redirects(wiki_id).unwrap() {
+            assert_eq!(redirect.page_id(), page_id);
+        }
+
+        // Renaming back to an old slug doesn't leave a loop
+        srv.rename_page(wiki_id, "scp-2000", "scp-xxxx", "Never mind", &user, true)
+            .expect("Unable to rename page");
+
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-1000", "scp-2000"]);
+
+        let (page, _, redirect) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.id(), page_id);
+        assert_eq!(redirect, None);
+
+        // New pages replace redirects
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1000",
+            message: "Bigfoot",
+            user: &user,
+            base: None,
+        };
+
+        let (other_id, _) = srv
+            .create_page(commit, b"Object Class: Keter\n", &[], &[], "SCP-1000", "")
+            .expect("Unable to create page");
+
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-2000"]);
+
+        let (page, _, redirect) = srv.get_page(wiki_id, "scp-1000").unwrap().unwrap();
+        assert_eq!(page.id(), other_id);
+        assert_eq!(redirect, None);
+
+        // Renames without redirects
+        srv.rename_page(wiki_id, "scp-1000", "scp-1001", "Off by one", &user, false)
+            .expect("Unable to rename page");
+
+        assert_eq!(srv.get_page(wiki_id, "scp-1000").unwrap(), None);
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-2000"]);
+
+        // Redirects to deleted pages aren't followed
+        srv.rename_page(wiki_id, "scp-1001", "scp-1002", "Again", &user, true)
+            .expect("Unable to rename page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1002",
+            message: "Deleting",
+            user: &user,
+            base: None,
+        };
+
+        srv.remove_page(commit).expect("Unable to remove page");
+
+        assert_eq!(srv.get_page(wiki_id, "scp-1001").unwrap(), None);
+        assert_eq!(srv.get_page_contents(wiki_id, "scp-1001").unwrap(), None);
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-1001", "scp-2000"]);
+
+        // Removing redirects
+        assert!(srv.remove_redirect(wiki_id, "scp-2000").unwrap());
+        assert!(!srv.remove_redirect(wiki_id, "scp-2000").unwrap());
+        assert_eq!(srv.get_page(wiki_id, "scp-2000").unwrap(), None);
+        assert_eq!(redirect_slugs(srv, wiki_id), ["scp-1001"]);
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
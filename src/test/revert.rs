@@ -0,0 +1,311 @@
+/*
+ * test/revert.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+#[test]
+fn revert_page() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        let (page_id, first) = srv
+            .create_page(commit, b"Object Class: Safe\n", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        srv.set_page_tags(commit, &["scp", "safe"])
+            .expect("Unable to set tags");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Improvements",
+            user: &user,
+            base: None,
+        };
+
+        let second = srv
+            .edit_page(
+                commit,
+                Some(b"Object Class: Euclid\n"),
+                Some("SCP-XXXX"),
+                Some("The Cup"),
+            )
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Vandalism",
+            user: &user,
+            base: None,
+        };
+
+        srv.edit_page(commit, Some(b"lol"), Some("lol"), Some(""))
+            .expect("Unable to edit page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Vandalism",
+            user: &user,
+            base: None,
+        };
+
+        srv.set_page_tags(commit, &["lol"])
+            .expect("Unable to set tags");
+
+        // Revert to before the vandalism
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Revert vandalism",
+            user: &user,
+            base: None,
+        };
+
+        let revert = srv
+            .revert_page(commit, Left(second))
+            .expect("Unable to revert page");
+
+        let (page, _, _) = srv
+            .get_page(wiki_id, "scp-xxxx")
+            .expect("Unable to get page")
+            .expect("Page not found");
+
+        assert_eq!(page.title(), "SCP-XXXX");
+        assert_eq!(page.alt_title(), Some("The Cup"));
+        assert_eq!(page.tags(), ["safe", "scp"]);
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Euclid\n"[..]));
+
+        let history = srv
+            .get_page_history(Left(page_id), HistoryQuery::default())
+            .expect("Unable to get history");
+
+        let entry = &history.entries[0];
+        assert_eq!(entry.revision_id, revert);
+        assert_eq!(entry.change_type, ChangeType::Revert);
+        assert_eq!(entry.target_revision_id, Some(second));
+        assert_eq!(entry.added_tags, ["safe", "scp"]);
+        assert_eq!(entry.removed_tags, ["lol"]);
+
+        // Revert by commit, to before the page had tags
+        let first_commit = history
+            .entries
+            .iter()
+            .find(|entry| entry.revision_id == first)
+            .map(|entry| entry.git_commit.clone())
+            .unwrap();
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Start over",
+            user: &user,
+            base: None,
+        };
+
+        srv.revert_page(commit, Right(&first_commit))
+            .expect("Unable to revert page");
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.alt_title(), None);
+        assert!(page.tags().is_empty());
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Safe\n"[..]));
+
+        // Revisions must belong to the page
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-yyyy",
+            message: "Another article",
+            user: &user,
+            base: None,
+        };
+
+        let (_, other) = srv
+            .create_page(commit, b"Object Class: Keter\n", &[], &[], "SCP-YYYY", "")
+            .expect("Unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Wrong page",
+            user: &user,
+            base: None,
+        };
+
+        match srv.revert_page(commit, Left(other)) {
+            Err(Error::RevisionNotFound) => (),
+            result => panic!("Revert to other page's revision did not fail: {:?}", result),
+        }
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
+
+#[test]
+fn revert_tags() {
+    run(|srv| {
+        let staff = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &staff)
+            .expect("Unable to create wiki");
+
+        let rules = TagRules {
+            staff_tags: true,
+            ..TagRules::default()
+        };
+
+        srv.set_tag_rules(wiki_id, &rules, &staff)
+            .expect("Unable to set tag rules");
+
+        let editor = Permset {
+            edit: true,
+            ..Permset::default()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "editor", editor, &staff)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &staff)
+            .expect("Unable to add user to role");
+
+        let commit = |user| PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Change",
+            user,
+            base: None,
+        };
+
+        let (_, created) = srv
+            .create_page(
+                commit(&staff),
+                b"Object Class: Keter\n",
+                &[],
+                &["scp", "_cc"],
+                "SCP-XXXX",
+                "",
+            )
+            .expect("Unable to create page");
+
+        let edited = srv
+            .edit_page(
+                commit(&staff),
+                Some(b"Object Class: Safe\n"),
+                Some("SCP-XXXX"),
+                None,
+            )
+            .expect("Unable to edit page");
+
+        srv.set_page_tags(commit(&staff), &["scp"])
+            .expect("Unable to set tags");
+
+        // Reverting which would change the tags needs permission to tag
+        match srv.revert_page(commit(&user), Left(created)) {
+            Err(Error::PermissionDenied(Permission::Tag)) => (),
+            result => panic!("Revert changed tags without permission: {:?}", result),
+        }
+
+        // Which still doesn't allow bringing back staff-only tags
+        srv.edit_role(
+            role_id,
+            None,
+            Some(Permset {
+                tag: true,
+                ..editor
+            }),
+            &staff,
+        )
+        .expect("Unable to edit role");
+
+        match srv.revert_page(commit(&user), Left(edited)) {
+            Err(Error::TagRulesViolated(violations)) => assert_eq!(
+                violations,
+                vec![TagRuleViolation::StaffOnly {
+                    tag: String::from("_cc"),
+                }],
+            ),
+            result => panic!("Revert restored staff-only tag: {:?}", result),
+        }
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.tags(), &["scp"]);
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Object Class: Safe\n"[..]));
+
+        // Staff can revert past their own tag changes
+        srv.revert_page(commit(&staff), Left(created))
+            .expect("Unable to revert page");
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.tags(), &["_cc", "scp"]);
+    });
+}
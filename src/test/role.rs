@@ -0,0 +1,418 @@
+/*
+ * test/role.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use crate::author::AuthorType;
+
+#[test]
+fn roles() {
+    run(|srv| {
+        let user_1 = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user_2 = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let admin = {
+            let user_id = srv
+                .create_user("admin", "admin@example.net", "adminpassword")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let other_wiki_id = srv
+            .create_wiki("Other", "other", "example.net", &admin)
+            .expect("Unable to create wiki");
+
+        let member = Permset {
+            create: true,
+            edit: true,
+            tag: true,
+            rate: true,
+            ..Permset::default()
+        };
+
+        let member_id = srv
+            .create_role(wiki_id, "member", member, &admin)
+            .expect("Unable to create role");
+
+        let moderator_id = srv
+            .create_role(wiki_id, "moderator", Permset::default(), &admin)
+            .expect("Unable to create role");
+
+        srv.create_role(other_wiki_id, "member", Permset::all(), &admin)
+            .expect("Unable to create role in another wiki");
+
+        match srv.create_role(wiki_id, "member", Permset::default(), &admin) {
+            Err(Error::RoleExists) => (),
+            result => panic!("Duplicate role was created: {:?}", result),
+        }
+
+        let names = srv
+            .get_roles(wiki_id)
+            .expect("Unable to get roles")
+            .iter()
+            .map(|role| role.name().to_owned())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["administrator", "member", "moderator"]);
+
+        // Nobody can do anything without a role
+        match srv.check_permission(wiki_id, user_1.id(), Permission::Edit) {
+            Err(Error::PermissionDenied(Permission::Edit)) => (),
+            result => panic!("Permission check didn't fail: {:?}", result),
+        }
+
+        assert!(srv.add_user_role(member_id, user_1.id(), &admin).unwrap());
+        assert!(!srv.add_user_role(member_id, user_1.id(), &admin).unwrap());
+        assert!(srv
+            .add_user_role(moderator_id, user_2.id(), &admin)
+            .unwrap());
+
+        srv.check_permission(wiki_id, user_1.id(), Permission::Edit)
+            .expect("Member can't edit");
+        srv.check_permission(wiki_id, user_1.id(), Permission::Rate)
+            .expect("Member can't rate");
+        match srv.check_permission(wiki_id, user_1.id(), Permission::Delete) {
+            Err(Error::PermissionDenied(Permission::Delete)) => (),
+            result => panic!("Member can delete: {:?}", result),
+        }
+
+        match srv.check_permission(other_wiki_id, user_1.id(), Permission::Edit) {
+            Err(Error::PermissionDenied(Permission::Edit)) => (),
+            result => panic!("Member can edit in another wiki: {:?}", result),
+        }
+
+        match srv.check_permission(wiki_id, user_2.id(), Permission::Purge) {
+            Err(Error::PermissionDenied(Permission::Purge)) => (),
+            result => panic!("Moderator can purge: {:?}", result),
+        }
+
+        let moderator = Permset {
+            delete: true,
+            rename: true,
+            manage_tags: true,
+            manage_authors: true,
+            ..Permset::default()
+        };
+
+        srv.edit_role(moderator_id, Some("staff"), Some(moderator), &admin)
+            .expect("Unable to edit role");
+
+        match srv.edit_role(moderator_id, Some("member"), None, &admin) {
+            Err(Error::RoleExists) => (),
+            result => panic!("Role was renamed to an existing name: {:?}", result),
+        }
+
+        let role = srv
+            .get_role(moderator_id)
+            .expect("Unable to get role")
+            .expect("Role not found");
+
+        assert_eq!(role.name(), "staff");
+        assert_eq!(role.permset(), moderator);
+
+        // Permissions from every role are combined
+        srv.add_user_role(moderator_id, user_1.id(), &admin)
+            .expect("Unable to add role");
+
+        let permset = srv
+            .get_user_permissions(wiki_id, user_1.id())
+            .expect("Unable to get permissions");
+
+        assert_eq!(permset, member.union(moderator));
+        assert!(permset.allows(Permission::Delete));
+        assert!(!permset.allows(Permission::ManageRoles));
+
+        let roles = srv
+            .get_user_roles(wiki_id, user_1.id())
+            .expect("Unable to get user roles");
+
+        assert_eq!(roles.len(), 2);
+
+        assert!(srv
+            .remove_user_role(member_id, user_1.id(), &admin)
+            .unwrap());
+        assert!(!srv
+            .remove_user_role(member_id, user_1.id(), &admin)
+            .unwrap());
+
+        match srv.check_permission(wiki_id, user_1.id(), Permission::Edit) {
+            Err(Error::PermissionDenied(Permission::Edit)) => (),
+            result => panic!("Removed member can edit: {:?}", result),
+        }
+
+        srv.delete_role(moderator_id, &admin)
+            .expect("Unable to delete role");
+
+        assert!(srv
+            .get_user_roles(wiki_id, user_2.id())
+            .expect("Unable to get user roles")
+            .is_empty());
+
+        match srv.delete_role(moderator_id, &admin) {
+            Err(Error::RoleNotFound) => (),
+            result => panic!("Deleted role was deleted again: {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn role_enforcement() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let admin = {
+            let user_id = srv
+                .create_user("admin", "admin@example.net", "adminpassword")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        match srv.create_page(commit, b"Contents", &[], &[], "SCP-XXXX", "") {
+            Err(Error::PermissionDenied(Permission::Create)) => (),
+            result => panic!("Page was created without a role: {:?}", result),
+        }
+
+        assert_eq!(srv.get_page_contents(wiki_id, "scp-xxxx").unwrap(), None);
+
+        let member = Permset {
+            create: true,
+            edit: true,
+            rate: true,
+            ..Permset::default()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "member", member, &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &admin)
+            .expect("Unable to add user to role");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        // Each extra part of a change needs its own permission
+        match srv.create_page(commit, b"Contents", &[], &["scp"], "SCP-XXXX", "") {
+            Err(Error::PermissionDenied(Permission::Tag)) => (),
+            result => panic!("Tagged page was created without permission: {:?}", result),
+        }
+
+        let (page_id, _) = srv
+            .create_page(commit, b"Contents", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        srv.set_rating(page_id, user.id(), 1)
+            .expect("Unable to rate page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user: &user,
+            base: None,
+        };
+
+        match srv.set_page_tags(commit, &["scp"]) {
+            Err(Error::PermissionDenied(Permission::Tag)) => (),
+            result => panic!("Page was tagged without permission: {:?}", result),
+        }
+
+        match srv.rename_page(wiki_id, "scp-xxxx", "scp-yyyy", "Moving", &user, false) {
+            Err(Error::PermissionDenied(Permission::Rename)) => (),
+            result => panic!("Page was renamed without permission: {:?}", result),
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Deleting",
+            user: &user,
+            base: None,
+        };
+
+        match srv.remove_page(commit) {
+            Err(Error::PermissionDenied(Permission::Delete)) => (),
+            result => panic!("Page was removed without permission: {:?}", result),
+        }
+
+        match srv.rename_tag(wiki_id, "scp", "euclid", "Retag", &user, false) {
+            Err(Error::PermissionDenied(Permission::ManageTags)) => (),
+            result => panic!("Tag was renamed without permission: {:?}", result),
+        }
+
+        let authors = [(user.id(), AuthorType::Translator, None)];
+        match srv.add_page_authors(Left(page_id), &authors, &user) {
+            Err(Error::PermissionDenied(Permission::ManageAuthors)) => (),
+            result => panic!("Author was added without permission: {:?}", result),
+        }
+
+        // Losing the role takes away what it allowed
+        srv.remove_user_role(role_id, user.id(), &admin)
+            .expect("Unable to remove user from role");
+
+        match srv.set_rating(page_id, user.id(), -1) {
+            Err(Error::PermissionDenied(Permission::Rate)) => (),
+            result => panic!("Page was rated without a role: {:?}", result),
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Editing",
+            user: &user,
+            base: None,
+        };
+
+        match srv.edit_page(commit, Some(b"Vandalism"), None, None) {
+            Err(Error::PermissionDenied(Permission::Edit)) => (),
+            result => panic!("Page was edited without a role: {:?}", result),
+        }
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Contents"[..]));
+    });
+}
+
+#[test]
+fn role_management() {
+    run(|srv| {
+        let admin = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &admin)
+            .expect("Unable to create wiki");
+
+        let admin_role_id = srv
+            .get_user_roles(wiki_id, admin.id())
+            .expect("Unable to get user roles")[0]
+            .id();
+
+        // Users can't hand themselves permissions they don't have
+        match srv.create_role(wiki_id, "takeover", Permset::all(), &user) {
+            Err(Error::PermissionDenied(Permission::ManageRoles)) => (),
+            result => panic!("Role was created without permission: {:?}", result),
+        }
+
+        match srv.add_user_role(admin_role_id, user.id(), &user) {
+            Err(Error::PermissionDenied(Permission::ManageRoles)) => (),
+            result => panic!("Role was given out without permission: {:?}", result),
+        }
+
+        let member_id = srv
+            .create_role(wiki_id, "member", Permset::default(), &admin)
+            .expect("Unable to create role");
+
+        srv.add_user_role(member_id, user.id(), &admin)
+            .expect("Unable to add user to role");
+
+        match srv.edit_role(member_id, None, Some(Permset::all()), &user) {
+            Err(Error::PermissionDenied(Permission::ManageRoles)) => (),
+            result => panic!("Role was edited without permission: {:?}", result),
+        }
+
+        match srv.remove_user_role(admin_role_id, admin.id(), &user) {
+            Err(Error::PermissionDenied(Permission::ManageRoles)) => (),
+            result => panic!("Role was taken away without permission: {:?}", result),
+        }
+
+        match srv.delete_role(admin_role_id, &user) {
+            Err(Error::PermissionDenied(Permission::ManageRoles)) => (),
+            result => panic!("Role was deleted without permission: {:?}", result),
+        }
+
+        let permset = srv
+            .get_user_permissions(wiki_id, user.id())
+            .expect("Unable to get permissions");
+        assert_eq!(permset, Permset::default());
+
+        let roles = srv.get_roles(wiki_id).expect("Unable to get roles");
+        assert_eq!(roles.len(), 2);
+
+        // Anyone with the permission can manage roles, not just the wiki's creator
+        let manager = Permset {
+            manage_roles: true,
+            ..Permset::default()
+        };
+
+        srv.edit_role(member_id, None, Some(manager), &admin)
+            .expect("Unable to edit role");
+
+        let role_id = srv
+            .create_role(wiki_id, "helper", Permset::default(), &user)
+            .expect("Unable to create role with permission");
+
+        srv.delete_role(role_id, &user)
+            .expect("Unable to delete role with permission");
+
+        match srv.delete_role(role_id, &user) {
+            Err(Error::RoleNotFound) => (),
+            result => panic!("Deleted role was deleted again: {:?}", result),
+        }
+    });
+}
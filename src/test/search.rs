@@ -0,0 +1,159 @@
+/*
+ * test/search.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+
+#[test]
+fn search() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let other_wiki_id = srv
+            .create_wiki("Other", "other", "example.net", &user)
+            .expect("Unable to create wiki");
+
+        let commit = |wiki_id, slug| PageCommit {
+            wiki_id,
+            slug,
+            message: "Search test",
+            user: &user,
+            base: None,
+        };
+
+        let pages: &[(&str, &[u8], &str)] = &[
+            (
+                "scp-1000",
+                b"Bigfoot are a species of primate. <They> live in forests.",
+                "SCP-1000",
+            ),
+            (
+                "scp-682",
+                b"A large reptile which adapts to any attempt at termination.",
+                "Hard-to-Destroy Reptile",
+            ),
+            (
+                "tale:forest",
+                b"Two researchers walk through the forest at midnight.",
+                "The Forest",
+            ),
+        ];
+
+        let mut page_ids = Vec::new();
+        for &(slug, content, title) in pages {
+            let (page_id, _) = srv
+                .create_page(commit(wiki_id, slug), content, &[], &[], title, "")
+                .expect("Unable to create page");
+
+            page_ids.push(page_id);
+        }
+
+        srv.create_page(
+            commit(other_wiki_id, "scp-1000"),
+            b"Bigfoot, but in another wiki.",
+            &[],
+            &[],
+            "SCP-1000",
+            "",
+        )
+        .expect("Unable to create page");
+
+        let search = |text| -> Vec<PageId> {
+            srv.search_pages(wiki_id, text, 0, 0)
+                .expect("Unable to search pages")
+                .results
+                .iter()
+                .map(|result| result.page_id)
+                .collect()
+        };
+
+        assert_eq!(search("bigfoot"), vec![page_ids[0]]);
+        assert_eq!(search("primates"), vec![page_ids[0]]);
+        assert_eq!(search("reptile adapts"), vec![page_ids[1]]);
+        assert_eq!(search("reptile forest"), vec![]);
+        assert_eq!(search("\"species of primate\""), vec![page_ids[0]]);
+        assert_eq!(search("\"primate species\""), vec![]);
+        assert_eq!(search("termin*"), vec![page_ids[1]]);
+        assert_eq!(search("hard-to-destroy"), vec![page_ids[1]]);
+        assert_eq!(search("!!! & | :*"), vec![]);
+        assert_eq!(search(""), vec![]);
+
+        // Title matches rank above matches in the contents
+        assert_eq!(search("forest"), vec![page_ids[2], page_ids[0]]);
+
+        let results = srv
+            .search_pages(wiki_id, "forest", 1, 1)
+            .expect("Unable to search pages");
+
+        assert_eq!(results.total, 2);
+        assert_eq!(results.results.len(), 1);
+
+        let result = &results.results[0];
+        assert_eq!(result.page_id, page_ids[0]);
+        assert_eq!(result.slug, "scp-1000");
+        assert_eq!(result.title, "SCP-1000");
+        assert!(result.snippet.contains("<b>forests</b>"));
+        assert!(result.snippet.contains("&lt;They&gt;"));
+
+        srv.edit_page(
+            commit(wiki_id, "scp-1000"),
+            Some(b"Bigfoot are a species of primate living in mountains."),
+            Some("SCP-1000"),
+            None,
+        )
+        .expect("Unable to edit page");
+
+        assert_eq!(search("forest"), vec![page_ids[2]]);
+        assert_eq!(search("mountain"), vec![page_ids[0]]);
+
+        srv.rename_page(wiki_id, "scp-682", "scp-682-j", "Joke", &user, false)
+            .expect("Unable to rename page");
+
+        let results = srv
+            .search_pages(wiki_id, "reptile", 0, 0)
+            .expect("Unable to search pages");
+
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].slug, "scp-682-j");
+
+        srv.remove_page(commit(wiki_id, "scp-682-j"))
+            .expect("Unable to remove page");
+
+        assert_eq!(search("reptile"), vec![]);
+
+        srv.restore_page(page_ids[1], "Restored", &user)
+            .expect("Unable to restore page");
+
+        assert_eq!(search("reptile"), vec![page_ids[1]]);
+
+        let indexed = srv
+            .rebuild_search_index(wiki_id)
+            .expect("Unable to rebuild search index");
+
+        assert_eq!(indexed, 3);
+        assert_eq!(search("bigfoot mountains"), vec![page_ids[0]]);
+    });
+}
@@ -0,0 +1,158 @@
+/*
+ * test/session.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use ipnetwork::IpNetwork;
+
+#[test]
+fn sessions() {
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let user_id = srv
+            .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+            .expect("Unable to create user");
+
+        let ip_1: IpNetwork = "127.0.0.1".parse().unwrap();
+        let ip_2: IpNetwork = "::1".parse().unwrap();
+
+        // Bad password
+        match srv.create_session(user_id, "wrongpassword", ip_1, "Firefox") {
+            Err(Error::AuthenticationFailed) => (),
+            result => panic!("Created session with invalid password: {:?}", result),
+        }
+
+        // Multiple sessions
+        let (session_1, token_1) = srv
+            .create_session(user_id, "blackmoonhowls", ip_1, "Firefox")
+            .expect("Unable to create session");
+        let (session_2, token_2) = srv
+            .create_session(user_id, "blackmoonhowls", ip_2, "curl")
+            .expect("Unable to create session");
+        let (session_3, token_3) = srv
+            .create_session(user_id, "blackmoonhowls", ip_1, "Chrome")
+            .expect("Unable to create session");
+
+        assert_ne!(session_1, session_2);
+        assert_ne!(token_1, token_2);
+
+        let session = srv
+            .get_session(session_2)
+            .expect("Unable to get session")
+            .expect("Session not found");
+
+        assert_eq!(session.id(), session_2);
+        assert_eq!(session.user_id(), user_id);
+        assert_eq!(session.ip_address(), ip_2);
+        assert_eq!(session.user_agent(), "curl");
+        assert!(session.expires_at() > session.created_at());
+
+        let sessions = srv.get_sessions(user_id).expect("Unable to get sessions");
+        assert_eq!(sessions.len(), 3);
+
+        // Token checks
+        let session = srv
+            .check_token(user_id, &token_1)
+            .expect("Token was not valid");
+        assert_eq!(session.id(), session_1);
+
+        match srv.check_token(user_id, "badtoken") {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Invalid token accepted: {:?}", result),
+        }
+
+        match srv.check_token(UserId::from_raw(0), &token_1) {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Token accepted for another user: {:?}", result),
+        }
+
+        // Per-session revocation
+        let ended = srv.end_session(session_2).expect("Unable to end session");
+        assert!(ended);
+
+        let ended = srv.end_session(session_2).expect("Unable to end session");
+        assert!(!ended);
+
+        match srv.check_token(user_id, &token_2) {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Revoked token accepted: {:?}", result),
+        }
+        srv.check_token(user_id, &token_3)
+            .expect("Token was not valid");
+
+        // Expiry
+        sql_query(format!(
+            "UPDATE sessions SET expires_at = NOW() - INTERVAL '1 hour' WHERE session_id = {}",
+            session_3,
+        ))
+        .execute(srv.test_connection())
+        .expect("Unable to expire session");
+
+        match srv.check_token(user_id, &token_3) {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Expired token accepted: {:?}", result),
+        }
+
+        let session = srv.get_session(session_3).expect("Unable to get session");
+        assert!(session.is_none());
+
+        let sessions = srv.get_sessions(user_id).expect("Unable to get sessions");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id(), session_1);
+
+        let purged = srv
+            .purge_expired_sessions()
+            .expect("Unable to purge sessions");
+        assert_eq!(purged, 1);
+
+        // Log out everywhere
+        let (session_4, token_4) = srv
+            .create_session(user_id, "blackmoonhowls", ip_2, "Safari")
+            .expect("Unable to create session");
+        let (_, token_5) = srv
+            .create_session(user_id, "blackmoonhowls", ip_2, "Edge")
+            .expect("Unable to create session");
+
+        let ended = srv
+            .end_all_sessions(user_id, Some(session_4))
+            .expect("Unable to end sessions");
+        assert_eq!(ended, 2);
+
+        match srv.check_token(user_id, &token_1) {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Revoked token accepted: {:?}", result),
+        }
+
+        match srv.check_token(user_id, &token_5) {
+            Err(Error::InvalidToken) => (),
+            result => panic!("Revoked token accepted: {:?}", result),
+        }
+        srv.check_token(user_id, &token_4)
+            .expect("Token was not valid");
+
+        let ended = srv
+            .end_all_sessions(user_id, None)
+            .expect("Unable to end sessions");
+        assert_eq!(ended, 1);
+
+        let sessions = srv.get_sessions(user_id).expect("Unable to get sessions");
+        assert!(sessions.is_empty());
+    });
+}
@@ -0,0 +1,192 @@
+/*
+ * test/wiki.rs
+ *
+ * deepwell - Database management and migrations service
+ * Copyright (C) 2019 Ammon Smith
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::prelude::*;
+use crate::revision::{CommitInfo, GitStore, RevisionStore};
+use std::fs;
+
+#[test]
+fn recover_store() {
+    run_with_dir(|srv, directory| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        srv.create_page(commit, b"Original", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let repo = directory.join("test");
+        let journal = repo.join(".git/DEEPWELL_JOURNAL");
+        let store = GitStore::new(&repo, "example.org");
+        let info = CommitInfo {
+            username: "unknown",
+            message: "Unfinished change",
+        };
+
+        // Commits which never made it into the database are undone
+        let previous = store.head().unwrap().expect("No commits in store");
+        store.begin_journal().expect("Unable to start journal");
+        store
+            .commit("scp-xxxx", Some(b"Half-written"), info)
+            .expect("Unable to commit");
+        store
+            .commit("scp-yyyy", Some(b"Also lost"), info)
+            .expect("Unable to commit");
+
+        assert!(journal.exists());
+        assert_ne!(store.head().unwrap().as_ref(), Some(&previous));
+
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load revision store");
+
+        assert_eq!(store.head().unwrap(), Some(previous));
+        assert!(!journal.exists(), "Journal was not removed");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Original"[..]));
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+
+        // Commits which were recorded are kept, only the journal is cleared
+        store.begin_journal().expect("Unable to start journal");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Finished change",
+            user: &user,
+            base: None,
+        };
+
+        srv.edit_page(commit, Some(b"Edited"), Some("SCP-XXXX"), None)
+            .expect("Unable to edit page");
+
+        let head = store.head().unwrap();
+        assert!(journal.exists());
+
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load revision store");
+
+        assert_eq!(store.head().unwrap(), head);
+        assert!(!journal.exists(), "Journal was not removed");
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Edited"[..]));
+    });
+}
+
+#[test]
+fn load_store() {
+    run_with_dir(|srv, directory| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let repo = directory.join("test");
+        let store = GitStore::new(&repo, "example.org");
+
+        // Valid repository
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "New article!",
+            user: &user,
+            base: None,
+        };
+
+        srv.create_page(commit, b"Original", &[], &[], "SCP-XXXX", "")
+            .expect("Unable to create page");
+
+        let head = store.head().unwrap();
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load valid revision store");
+
+        assert_eq!(store.head().unwrap(), head);
+
+        let contents = srv
+            .get_page_contents(wiki_id, "scp-xxxx")
+            .unwrap()
+            .map(|(contents, _)| contents);
+        assert_eq!(contents.as_deref(), Some(&b"Original"[..]));
+
+        // Missing directory
+        fs::remove_dir_all(&repo).unwrap();
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load missing revision store");
+
+        assert!(store.is_initialized().unwrap());
+
+        // Uninitialised directory
+        fs::remove_dir_all(&repo).unwrap();
+        fs::create_dir(&repo).unwrap();
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load uninitialised revision store");
+
+        assert!(store.is_initialized().unwrap());
+
+        // Files which are not a repository
+        fs::remove_dir_all(&repo).unwrap();
+        fs::create_dir(&repo).unwrap();
+        fs::write(repo.join("scp-xxxx"), b"Stray file").unwrap();
+
+        match srv.test_load_store(wiki_id) {
+            Err(Error::InvalidRevisionStore(path)) => assert_eq!(path, repo),
+            result => panic!("Loading non-repository succeeded: {:?}", result),
+        }
+
+        // Corrupted repository
+        fs::remove_dir_all(&repo).unwrap();
+        fs::create_dir(&repo).unwrap();
+        srv.test_load_store(wiki_id)
+            .expect("Unable to load uninitialised revision store");
+
+        fs::write(repo.join(".git/HEAD"), b"not a reference").unwrap();
+
+        match srv.test_load_store(wiki_id) {
+            Err(Error::InvalidRevisionStore(path)) => assert_eq!(path, repo),
+            result => panic!("Loading corrupted repository succeeded: {:?}", result),
+        }
+    });
+}
@@ -37,14 +37,17 @@ fn tags() {
         };
 
         let wiki_id = srv
-            .create_wiki("Test", "test", "example.org")
+            .create_wiki("Test", "test", "example.org", &user_1)
             .expect("Unable to create wiki");
 
+        grant_all(srv, wiki_id, &user_1, &[user_2.id()]);
+
         let commit = PageCommit {
             wiki_id,
             slug: "scp-xxxx",
             message: "New article!",
             user: &user_1,
+            base: None,
         };
 
         let (_page_id, _revision_id) = srv
@@ -52,6 +55,7 @@ fn tags() {
                 commit,
                 b"**Item #:** SCP-XXXX\n\n**Object Class:** Keter\n",
                 &[],
+                &[],
                 "SCP-XXXX",
                 "The Monster Behind the Door",
             )
@@ -62,6 +66,7 @@ fn tags() {
             slug: "scp-xxxx",
             message: "has image",
             user: &user_1,
+            base: None,
         };
 
         srv.set_page_tags(commit, &["_image"])
@@ -72,6 +77,7 @@ fn tags() {
             slug: "scp-xxxx",
             message: "initial tagging",
             user: &user_2,
+            base: None,
         };
 
         srv.set_page_tags(
@@ -85,6 +91,7 @@ fn tags() {
             slug: "scp-xxxx",
             message: "good image",
             user: &user_1,
+            base: None,
         };
 
         srv.set_page_tags(commit, &["scp", "keter", "artifact", "ontokinetic", "_cc"])
@@ -95,6 +102,7 @@ fn tags() {
             slug: "scp-xxxx",
             message: "goi tags",
             user: &user_2,
+            base: None,
         };
 
         srv.set_page_tags(
@@ -111,7 +119,7 @@ fn tags() {
         )
         .expect("Unable to set page tags");
 
-        let (page, _) = srv
+        let (page, _, _) = srv
             .get_page(wiki_id, "scp-xxxx")
             .expect("Unable to get page")
             .expect("No page found");
@@ -135,3 +143,441 @@ fn tags() {
         assert_eq!(&actual_tags, &expected_tags);
     });
 }
+
+#[test]
+fn tag_history() {
+    use chrono::{Duration, Utc};
+    use diesel::{sql_query, RunQueryDsl};
+
+    run(|srv| {
+        let user_1 = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user_2 = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user_1)
+            .expect("Unable to create wiki");
+
+        grant_all(srv, wiki_id, &user_1, &[user_2.id()]);
+
+        let mut page_ids = Vec::new();
+        for slug in &["scp-1000", "scp-1001"] {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New article!",
+                user: &user_1,
+                base: None,
+            };
+
+            let (page_id, _) = srv
+                .create_page(commit, b"Bigfoot", &[], &[], "SCP", "")
+                .expect("Unable to create page");
+
+            page_ids.push(page_id);
+        }
+
+        let commit = |slug, user| PageCommit {
+            wiki_id,
+            slug,
+            message: "Tagging",
+            user,
+            base: None,
+        };
+
+        // Spread the changes out, since every revision in a transaction has the same time
+        let later = |revision_id: RevisionId, hours| {
+            sql_query(format!(
+                "UPDATE revisions SET created_at = NOW() + INTERVAL '{} hours' \
+                 WHERE revision_id = {}",
+                hours, revision_id,
+            ))
+            .execute(srv.test_connection())
+            .expect("Unable to change revision time");
+        };
+
+        let added = srv
+            .set_page_tags(commit("scp-1000", &user_1), &["scp", "keter"])
+            .expect("Unable to set page tags");
+        later(added, 1);
+
+        let revision_id = srv
+            .set_page_tags(commit("scp-1001", &user_1), &["scp"])
+            .expect("Unable to set page tags");
+        later(revision_id, 1);
+
+        let removed = srv
+            .set_page_tags(commit("scp-1000", &user_2), &["scp"])
+            .expect("Unable to set page tags");
+        later(removed, 2);
+
+        let revision_id = srv
+            .remove_page(commit("scp-1001", &user_2))
+            .expect("Unable to remove page");
+        later(revision_id, 3);
+
+        let events = srv
+            .get_page_tag_history(Left(page_ids[0]), Some("keter"))
+            .expect("Unable to get tag history");
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].revision_id, added);
+        assert_eq!(events[0].user_id, user_1.id());
+        assert_eq!(events[0].action, TagAction::Added);
+        assert_eq!(events[1].revision_id, removed);
+        assert_eq!(events[1].user_id, user_2.id());
+        assert_eq!(events[1].action, TagAction::Removed);
+        assert!(events[0].created_at < events[1].created_at);
+
+        let timeline = srv
+            .get_page_tag_history(Right((wiki_id, "scp-1000")), None)
+            .expect("Unable to get tag history")
+            .into_iter()
+            .map(|event| (event.tag, event.action))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            timeline,
+            vec![
+                (String::from("keter"), TagAction::Added),
+                (String::from("scp"), TagAction::Added),
+                (String::from("keter"), TagAction::Removed),
+            ],
+        );
+
+        let tagged = |tag, minutes| {
+            let time = Utc::now() + Duration::minutes(minutes);
+
+            srv.get_tagged_pages_at(wiki_id, tag, time)
+                .expect("Unable to get tagged pages")
+        };
+
+        assert_eq!(tagged("keter", 30), vec![]);
+        assert_eq!(tagged("keter", 90), vec![page_ids[0]]);
+        assert_eq!(tagged("keter", 150), vec![]);
+        assert_eq!(tagged("scp", 150), page_ids);
+        assert_eq!(tagged("scp", 240), vec![page_ids[0]]);
+    });
+}
+
+#[test]
+fn tag_operations() {
+    run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &user)
+            .expect("Unable to create wiki");
+
+        let pages: [(&str, &[&str]); 4] = [
+            ("scp-1000", &["scp", "keter"]),
+            ("scp-1001", &["scp", "euclid"]),
+            ("tale-1000", &["tale"]),
+            ("scp-1002", &["scp", "keter"]),
+        ];
+
+        let mut page_ids = Vec::new();
+        for &(slug, tags) in &pages {
+            let commit = PageCommit {
+                wiki_id,
+                slug,
+                message: "New article!",
+                user: &user,
+                base: None,
+            };
+
+            let (page_id, _) = srv
+                .create_page(commit, b"Contents", &[], &[], "Page", "")
+                .expect("Unable to create page");
+
+            srv.set_page_tags(commit, tags)
+                .expect("Unable to set page tags");
+
+            page_ids.push(page_id);
+        }
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "scp-1002",
+            message: "Deleting",
+            user: &user,
+            base: None,
+        };
+
+        srv.remove_page(commit).expect("Unable to remove page");
+
+        let tags = |slug| {
+            let (page, _, _) = srv.get_page(wiki_id, slug).unwrap().unwrap();
+            page.tags().to_vec()
+        };
+
+        // Dry runs only report what would change
+        let report = srv
+            .rename_tag(wiki_id, "keter", "dangerous", "Renaming", &user, true)
+            .expect("Unable to rename tag");
+
+        assert!(report.dry_run);
+        assert_eq!(report.pages.len(), 1);
+        assert_eq!(report.pages[0].page_id, page_ids[0]);
+        assert_eq!(report.pages[0].new_tags, vec!["dangerous", "scp"]);
+        assert_eq!(report.pages[0].revision_id, None);
+        assert_eq!(tags("scp-1000"), vec!["keter", "scp"]);
+
+        match srv.rename_tag(wiki_id, "scp", "tale", "Renaming", &user, false) {
+            Err(Error::TagExists) => (),
+            result => panic!("Rename to existing tag succeeded: {:?}", result),
+        }
+
+        let report = srv
+            .rename_tag(wiki_id, "keter", "dangerous", "Renaming", &user, false)
+            .expect("Unable to rename tag");
+
+        assert!(!report.dry_run);
+        assert!(report.pages[0].revision_id.is_some());
+        assert_eq!(tags("scp-1000"), vec!["dangerous", "scp"]);
+
+        // Merged tags are changed in one commit, with a revision per page
+        let report = srv
+            .merge_tags(
+                wiki_id,
+                &["euclid", "dangerous"],
+                "object-class",
+                "Merging",
+                &user,
+                false,
+            )
+            .expect("Unable to merge tags");
+
+        let affected = report
+            .pages
+            .iter()
+            .map(|page| page.page_id)
+            .collect::<Vec<_>>();
+        assert_eq!(affected, &page_ids[..2]);
+        assert_eq!(tags("scp-1000"), vec!["object-class", "scp"]);
+        assert_eq!(tags("scp-1001"), vec!["object-class", "scp"]);
+
+        let mut hashes = Vec::new();
+        for page in &report.pages {
+            let history = srv
+                .get_page_history(Left(page.page_id), HistoryQuery::default())
+                .expect("Unable to get page history");
+
+            let entry = &history.entries[0];
+            assert_eq!(Some(entry.revision_id), page.revision_id);
+            assert_eq!(entry.change_type, ChangeType::Tags);
+            hashes.push(entry.git_commit.clone());
+        }
+
+        assert_eq!(hashes[0], hashes[1]);
+
+        let report = srv
+            .delete_tag(wiki_id, "scp", "Deleting", &user, false)
+            .expect("Unable to delete tag");
+
+        assert_eq!(report.pages.len(), 2);
+        assert_eq!(tags("scp-1000"), vec!["object-class"]);
+
+        let report = srv
+            .delete_tag(wiki_id, "nonexistent", "Deleting", &user, false)
+            .expect("Unable to delete tag");
+
+        assert!(report.pages.is_empty());
+
+        let events = srv
+            .get_page_tag_history(Left(page_ids[0]), None)
+            .expect("Unable to get tag history");
+        assert_eq!(events.len(), 7);
+
+        let report = srv.check_wiki_consistency(wiki_id, false).unwrap();
+        assert!(report.is_consistent(), "Unexpected issues: {:?}", report);
+    });
+}
+
+#[test]
+fn tag_rules() {
+    run(|srv| {
+        let staff = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let user = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &staff)
+            .expect("Unable to create wiki");
+
+        let permset = Permset {
+            create: true,
+            tag: true,
+            ..Permset::default()
+        };
+
+        let role_id = srv
+            .create_role(wiki_id, "member", permset, &staff)
+            .expect("Unable to create role");
+
+        srv.add_user_role(role_id, user.id(), &staff)
+            .expect("Unable to add member role");
+
+        let group = |name: &str, tags: &[&str], required| TagGroup {
+            name: name.into(),
+            tags: tags.iter().map(|&tag| tag.into()).collect(),
+            required,
+        };
+
+        let rules = TagRules {
+            allowed_tags: Some(
+                ["scp", "tale", "safe", "euclid", "keter", "_cc"]
+                    .iter()
+                    .map(|&tag| tag.into())
+                    .collect(),
+            ),
+            staff_tags: true,
+            groups: vec![
+                group("format", &["scp", "tale"], false),
+                group("object-class", &["safe", "euclid", "keter"], true),
+            ],
+        };
+
+        assert_eq!(srv.get_tag_rules(wiki_id).unwrap(), TagRules::default());
+
+        match srv.set_tag_rules(wiki_id, &rules, &user) {
+            Err(Error::PermissionDenied(Permission::ManageTags)) => (),
+            result => panic!(
+                "Setting tag rules without permission succeeded: {:?}",
+                result
+            ),
+        }
+
+        srv.set_tag_rules(wiki_id, &rules, &staff)
+            .expect("Unable to set tag rules");
+        assert_eq!(srv.get_tag_rules(wiki_id).unwrap(), rules);
+
+        let commit = |user| PageCommit {
+            wiki_id,
+            slug: "scp-xxxx",
+            message: "Tagging",
+            user,
+            base: None,
+        };
+
+        match srv.create_page(commit(&user), b"Contents", &[], &[], "SCP-XXXX", "") {
+            Err(Error::TagRulesViolated(violations)) => assert_eq!(
+                violations,
+                vec![TagRuleViolation::MissingFromGroup {
+                    group: String::from("object-class"),
+                }],
+            ),
+            result => panic!("Creating untagged page succeeded: {:?}", result),
+        }
+
+        srv.create_page(
+            commit(&user),
+            b"Contents",
+            &[],
+            &["scp", "safe"],
+            "SCP-XXXX",
+            "",
+        )
+        .expect("Unable to create page");
+
+        // Every broken rule is reported at once
+        let result = srv.set_page_tags(
+            commit(&user),
+            &["scp", "tale", "safe", "keter", "ontokinetic", "_cc"],
+        );
+
+        match result {
+            Err(Error::TagRulesViolated(violations)) => assert_eq!(
+                violations,
+                vec![
+                    TagRuleViolation::NotAllowed {
+                        tag: String::from("ontokinetic"),
+                    },
+                    TagRuleViolation::StaffOnly {
+                        tag: String::from("_cc")
+                    },
+                    TagRuleViolation::ConflictingTags {
+                        group: String::from("format"),
+                        tags: vec![String::from("scp"), String::from("tale")],
+                    },
+                    TagRuleViolation::ConflictingTags {
+                        group: String::from("object-class"),
+                        tags: vec![String::from("safe"), String::from("keter")],
+                    },
+                ],
+            ),
+            result => panic!("Breaking tag rules succeeded: {:?}", result),
+        }
+
+        // Staff-only tags can be kept by anyone, but only changed by staff
+        srv.set_page_tags(commit(&staff), &["scp", "keter", "_cc"])
+            .expect("Unable to set staff-only tag");
+
+        srv.set_page_tags(commit(&user), &["scp", "euclid", "_cc"])
+            .expect("Unable to keep staff-only tag");
+
+        match srv.set_page_tags(commit(&user), &["scp", "euclid"]) {
+            Err(Error::TagRulesViolated(violations)) => assert_eq!(
+                violations,
+                vec![TagRuleViolation::StaffOnly {
+                    tag: String::from("_cc")
+                }],
+            ),
+            result => panic!("Removing staff-only tag succeeded: {:?}", result),
+        }
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.tags(), &["_cc", "euclid", "scp"]);
+
+        // Reverting can't bring back tags the rules no longer allow
+        let history = srv
+            .get_page_history(Left(page.id()), HistoryQuery::default())
+            .expect("Unable to get history");
+        let euclid = history.entries[0].revision_id;
+
+        srv.set_page_tags(commit(&staff), &["scp", "keter", "_cc"])
+            .expect("Unable to set tags");
+
+        let mut rules = rules;
+        if let Some(ref mut allowed) = rules.allowed_tags {
+            allowed.retain(|tag| tag != "euclid");
+        }
+
+        srv.set_tag_rules(wiki_id, &rules, &staff)
+            .expect("Unable to set tag rules");
+
+        match srv.revert_page(commit(&staff), Left(euclid)) {
+            Err(Error::TagRulesViolated(violations)) => assert_eq!(
+                violations,
+                vec![TagRuleViolation::NotAllowed {
+                    tag: String::from("euclid"),
+                }],
+            ),
+            result => panic!("Reverting to disallowed tags succeeded: {:?}", result),
+        }
+
+        let (page, _, _) = srv.get_page(wiki_id, "scp-xxxx").unwrap().unwrap();
+        assert_eq!(page.tags(), &["_cc", "keter", "scp"]);
+    });
+}
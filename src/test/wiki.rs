@@ -23,8 +23,13 @@ use super::prelude::*;
 #[test]
 fn wiki_service() {
     run(|srv| {
+        let user = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
         let wiki_id = srv
-            .create_wiki("Test Wiki", "test", "example.com")
+            .create_wiki("Test Wiki", "test", "example.com", &user)
             .expect("Unable to create wiki");
 
         srv.rename_wiki(wiki_id, "NUTTEST")
@@ -47,3 +52,58 @@ fn wiki_service() {
         }
     });
 }
+
+#[test]
+fn wiki_creator() {
+    run(|srv| {
+        let creator = srv
+            .get_user_from_name("unknown")
+            .expect("Unable to get user")
+            .expect("Default user not found");
+
+        let other = {
+            let user_id = srv
+                .create_user("squirrelbird", "jenny@example.net", "blackmoonhowls")
+                .expect("Unable to create user");
+
+            srv.get_user_from_id(user_id).expect("Unable to get user")
+        };
+
+        let wiki_id = srv
+            .create_wiki("Test", "test", "example.org", &creator)
+            .expect("Unable to create wiki");
+
+        // The creator can do everything in a fresh wiki, but nobody else can
+        let roles = srv
+            .get_user_roles(wiki_id, creator.id())
+            .expect("Unable to get user roles");
+
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0].name(), "administrator");
+        assert_eq!(roles[0].permset(), Permset::all());
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "main",
+            message: "First page!",
+            user: &creator,
+            base: None,
+        };
+
+        srv.create_page(commit, b"Welcome!", &[], &["_home"], "Main Page", "")
+            .expect("Creator was unable to create page");
+
+        let commit = PageCommit {
+            wiki_id,
+            slug: "spam",
+            message: "Spam",
+            user: &other,
+            base: None,
+        };
+
+        match srv.create_page(commit, b"Spam", &[], &[], "Spam", "") {
+            Err(Error::PermissionDenied(Permission::Create)) => (),
+            result => panic!("Other user created page in new wiki: {:?}", result),
+        }
+    });
+}
@@ -123,6 +123,18 @@ impl WikiService {
         f(wiki)
     }
 
+    pub fn for_each<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Wiki) -> Result<()>,
+    {
+        let guard = self.wikis.read();
+        for wiki in guard.values() {
+            f(wiki)?;
+        }
+
+        Ok(())
+    }
+
     pub fn edit(&self, id: WikiId, model: UpdateWiki) -> Result<()> {
         use self::wikis::dsl;
 